@@ -0,0 +1,175 @@
+#[cfg(feature = "audio")]
+use std::{collections::HashMap, fs, io::Cursor, sync::Arc};
+
+#[cfg(feature = "audio")]
+use eframe::epaint::mutex::Mutex;
+#[cfg(feature = "audio")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "audio")]
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+
+#[cfg(feature = "audio")]
+use crate::{settings::settings, utils::resources_path};
+
+/// How long music takes to fade in or out in seconds
+#[cfg(feature = "audio")]
+const MUSIC_FADE_TIME: f32 = 2.0;
+
+/// The audio output, or `None` if there is no device to play on
+#[cfg(feature = "audio")]
+static OUTPUT: Lazy<Option<OutputStreamHandle>> = Lazy::new(|| {
+    let (stream, handle) = OutputStream::try_default()
+        .map_err(|e| tracing::warn!("Unable to play audio: {e}"))
+        .ok()?;
+    // The stream stops playing when dropped and can't be sent between threads,
+    // so it is kept alive for the rest of the program
+    std::mem::forget(stream);
+    Some(handle)
+});
+/// The bytes of an audio file, shared with the sources decoding it
+#[cfg(feature = "audio")]
+type SoundData = Arc<[u8]>;
+/// Loaded audio files, or `None` for files that failed to load
+#[cfg(feature = "audio")]
+static SOUNDS: Lazy<Mutex<HashMap<String, Option<SoundData>>>> = Lazy::new(Default::default);
+#[cfg(feature = "audio")]
+static MUSIC: Lazy<Mutex<Music>> = Lazy::new(Default::default);
+
+#[cfg(feature = "audio")]
+#[derive(Default)]
+struct Music {
+    /// The track that is playing and how far it has faded in
+    current: Option<(String, Sink, f32)>,
+    /// Tracks that are fading out and how loud they still are
+    fading: Vec<(Sink, f32)>,
+}
+
+/// Decode an audio file from `resources/audio/`
+#[cfg(feature = "audio")]
+fn decode(name: &str) -> Option<Decoder<Cursor<SoundData>>> {
+    let bytes = SOUNDS
+        .lock()
+        .entry(name.into())
+        .or_insert_with(|| {
+            let path = resources_path().join("audio").join(name);
+            fs::read(path)
+                .map(Into::into)
+                .map_err(|_| tracing::warn!("Failed to load audio: {name}"))
+                .ok()
+        })
+        .clone()?;
+    Decoder::new(Cursor::new(bytes))
+        .map_err(|e| tracing::warn!("Failed to decode audio {name}: {e}"))
+        .ok()
+}
+
+/// Play a sound effect once
+pub fn play_sfx(name: &str) {
+    play_sfx_pitched(name, 1.0);
+}
+
+/// Play a sound effect once, sped up or slowed down to change its pitch
+pub fn play_sfx_pitched(name: &str, pitch: f32) {
+    #[cfg(feature = "audio")]
+    {
+        let Some(output) = &*OUTPUT else {
+            return;
+        };
+        let Some(source) = decode(name) else {
+            return;
+        };
+        let source = (source.convert_samples::<f32>())
+            .speed(pitch)
+            .amplify(settings().sfx_volume);
+        if let Err(e) = output.play_raw(source) {
+            tracing::warn!("Unable to play {name}: {e}");
+        }
+    }
+    #[cfg(not(feature = "audio"))]
+    let _ = (name, pitch);
+}
+
+/// Crossfade to a music track, unless it is already playing
+pub fn play_music(name: &str, looped: bool) {
+    #[cfg(feature = "audio")]
+    {
+        let mut music = MUSIC.lock();
+        if music
+            .current
+            .as_ref()
+            .is_some_and(|(current, ..)| current == name)
+        {
+            return;
+        }
+        let Some(output) = &*OUTPUT else {
+            return;
+        };
+        let Some(source) = decode(name) else {
+            return;
+        };
+        let sink = match Sink::try_new(output) {
+            Ok(sink) => sink,
+            Err(e) => {
+                tracing::warn!("Unable to play {name}: {e}");
+                return;
+            }
+        };
+        sink.set_volume(0.0);
+        if looped {
+            sink.append(source.repeat_infinite());
+        } else {
+            sink.append(source);
+        }
+        if let Some((_, sink, volume)) = music.current.replace((name.into(), sink, 0.0)) {
+            music.fading.push((sink, volume));
+        }
+    }
+    #[cfg(not(feature = "audio"))]
+    let _ = (name, looped);
+}
+
+/// Fade out whatever music is playing
+pub fn stop_music() {
+    #[cfg(feature = "audio")]
+    {
+        let mut music = MUSIC.lock();
+        if let Some((_, sink, volume)) = music.current.take() {
+            music.fading.push((sink, volume));
+        }
+    }
+}
+
+/// Advance music fades and apply the volume setting
+///
+/// Returns whether music is still fading
+pub fn update_audio(dt: f32) -> bool {
+    #[cfg(feature = "audio")]
+    {
+        let volume = settings().music_volume;
+        let step = dt / MUSIC_FADE_TIME;
+        let mut music = MUSIC.lock();
+        let mut fading = false;
+        if let Some((_, sink, fade)) = &mut music.current {
+            *fade = (*fade + step).min(1.0);
+            sink.set_volume(*fade * volume);
+            fading |= *fade < 1.0;
+        }
+        music.fading.retain_mut(|(sink, fade)| {
+            *fade -= step;
+            sink.set_volume(fade.max(0.0) * volume);
+            *fade > 0.0
+        });
+        fading || !music.fading.is_empty()
+    }
+    #[cfg(not(feature = "audio"))]
+    {
+        let _ = dt;
+        false
+    }
+}
+
+/// Forget loaded audio files so that they are read again
+pub fn clear_audio_cache() {
+    #[cfg(feature = "audio")]
+    SOUNDS.lock().clear();
+}
@@ -0,0 +1,92 @@
+use eframe::epaint::{Pos2, Rect};
+
+use crate::{math::lerp, world::World};
+
+/// How quickly the camera catches up to the player
+const CAMERA_FOLLOW_RATE: f32 = 4.0;
+const MIN_CAMERA_ZOOM: f32 = 1.0;
+const MAX_CAMERA_ZOOM: f32 = 8.0;
+
+/// The part of the world that field plots show
+///
+/// All plots share one camera so that they stay spatially consistent
+pub struct Camera {
+    pub center: Pos2,
+    /// How many times smaller the range is than the range that fits the whole place
+    pub zoom: f32,
+    /// Whether to jump straight to the target on the next update
+    snap: bool,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            center: Pos2::ZERO,
+            zoom: 1.0,
+            snap: true,
+        }
+    }
+}
+
+impl Camera {
+    /// Get the distance from the center to the edge of the view
+    pub fn range(&self, world: &World) -> f32 {
+        world.max_rect().size().max_elem() * 0.5 / self.zoom
+    }
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(MIN_CAMERA_ZOOM, MAX_CAMERA_ZOOM);
+    }
+    /// Jump to the player on the next update instead of panning there
+    pub fn snap(&mut self) {
+        self.snap = true;
+    }
+    /// Move the camera toward the player
+    pub fn update(&mut self, world: &World, dt: f32) {
+        let target = self.target(world);
+        self.center = if self.snap {
+            target
+        } else {
+            let t = 1.0 - (-CAMERA_FOLLOW_RATE * dt).exp();
+            lerp(self.center.to_vec2(), target.to_vec2(), t).to_pos2()
+        };
+        self.snap = false;
+    }
+    /// Get the position closest to the player that doesn't show beyond the place
+    fn target(&self, world: &World) -> Pos2 {
+        let rect = world.max_rect();
+        let range = self.range(world);
+        let clamp_axis = |pos: f32, min: f32, max: f32| {
+            if max - min <= range * 2.0 {
+                (min + max) / 2.0
+            } else {
+                pos.clamp(min + range, max - range)
+            }
+        };
+        let player = world.player.person.pos;
+        let Rect { min, max } = rect;
+        Pos2::new(
+            clamp_axis(player.x, min.x, max.x),
+            clamp_axis(player.y, min.y, max.y),
+        )
+    }
+}
+
+#[test]
+fn camera_test() {
+    use crate::player::{Gender, Player};
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let mut camera = Camera::default();
+    // Fully zoomed out, the whole place is in view
+    camera.update(&world, 0.0);
+    assert_eq!(camera.center, world.max_rect().center());
+    // Zoomed in, the camera follows the player but stays inside the place
+    camera.zoom_by(4.0);
+    let rect = world.max_rect();
+    world.player.person.pos = rect.right_bottom();
+    for _ in 0..100 {
+        camera.update(&world, 0.1);
+    }
+    let range = camera.range(&world);
+    assert!((camera.center.x - (rect.max.x - range)).abs() < 0.01);
+    assert!(camera.center.x > rect.center().x);
+}
@@ -0,0 +1,132 @@
+use anyhow::bail;
+use eframe::epaint::{vec2, Pos2, Vec2};
+use rapier2d::prelude::*;
+use tracing::{debug, info};
+
+use crate::{
+    math::Convert,
+    world::{PlayerInput, World, PERSON_HEIGHT},
+};
+
+/// The heaviest object the player can pick up
+const MAX_CARRY_MASS: f32 = 0.3;
+/// How far from the player's hands an object can be picked up
+const CARRY_REACH: f32 = 2.0;
+/// How far in front of the player's hands carried objects are held
+const CARRY_DISTANCE: f32 = 0.6;
+/// How long a carried object takes to close most of the gap to where it is held
+const CARRY_FOLLOW_TIME: f32 = 0.1;
+/// How far a carried object can be pulled from where it is held before it is dropped
+const CARRY_BREAK_DISTANCE: f32 = 1.5;
+
+/// An object the player is holding
+#[derive(Debug, Clone, Copy)]
+pub struct Carried {
+    pub handle: RigidBodyHandle,
+    /// Which side of the player the object is held on, -1 or 1
+    side: f32,
+}
+
+impl World {
+    /// Where the player's hands are
+    fn player_hands(&self) -> Pos2 {
+        self.player.person.pos + vec2(0.0, PERSON_HEIGHT * 0.6)
+    }
+    /// Where a carried object is held
+    fn carry_point(&self, side: f32) -> Pos2 {
+        self.player_hands() + vec2(side * CARRY_DISTANCE, 0.0)
+    }
+    /// Pick up the dynamic object at a position
+    pub fn pick_up(&mut self, pos: Pos2) -> anyhow::Result<()> {
+        // Pick by where objects are rather than where they are drawn, so replays pick the same one
+        let render_alpha = self.render_alpha.take();
+        let found = self.dynamic_object_at(pos);
+        self.render_alpha = render_alpha;
+        let Some(handle) = found else {
+            bail!("There is nothing there to pick up");
+        };
+        let obj_pos = self.objects[&handle].pr.pos;
+        let hands = self.player_hands();
+        if obj_pos.distance(hands) > CARRY_REACH {
+            bail!("That is too far away to pick up");
+        }
+        if self.physics.bodies[handle].mass() > MAX_CARRY_MASS {
+            bail!("That is too heavy to pick up");
+        }
+        info!(?handle, "Picked up an object");
+        let side = if obj_pos.x < hands.x { -1.0 } else { 1.0 };
+        self.carried = Some(Carried { handle, side });
+        self.inputs.push(PlayerInput::PickUp([pos.x, pos.y]));
+        Ok(())
+    }
+    /// Let go of the carried object, throwing it toward the player's target
+    ///
+    /// An impulse of 0 just drops it
+    pub fn release_carried(&mut self, throw_impulse: f32) {
+        let Some(carried) = self.carried.take() else {
+            return;
+        };
+        self.inputs.push(PlayerInput::Release(throw_impulse));
+        let Some(obj) = self.objects.get(&carried.handle) else {
+            return;
+        };
+        // Without a target, objects are thrown away from the player
+        let target = (self.player.person.target).unwrap_or(obj.pr.pos + vec2(carried.side, 0.0));
+        let dir = (target - obj.pr.pos).normalized();
+        if throw_impulse > 0.0 && dir.is_finite() {
+            let impulse: Vector<Real> = (dir * throw_impulse).convert();
+            self.physics.bodies[carried.handle].apply_impulse(impulse, true);
+        }
+    }
+    /// Move the carried object toward where it is held, dropping it if it is caught on something
+    pub(crate) fn hold_carried(&mut self) {
+        let Some(carried) = self.carried else {
+            return;
+        };
+        let Some(obj) = self.objects.get(&carried.handle) else {
+            self.carried = None;
+            return;
+        };
+        let offset: Vec2 = self.carry_point(carried.side) - obj.pr.pos;
+        if offset.length() > CARRY_BREAK_DISTANCE {
+            debug!(handle = ?carried.handle, "A carried object was pulled away");
+            self.carried = None;
+            return;
+        }
+        let body = &mut self.physics.bodies[carried.handle];
+        body.set_linvel((offset / CARRY_FOLLOW_TIME).convert(), true);
+        body.set_angvel(0.0, true);
+    }
+}
+
+#[test]
+fn carry_test() {
+    use eframe::epaint::pos2;
+
+    use crate::{
+        field::VectorField,
+        player::{Gender, Player},
+    };
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    // The sofa is too heavy and the top of the cube stack is too far
+    assert!(world.pick_up(pos2(-0.5, 0.25)).is_err());
+    assert!(world.pick_up(pos2(2.8, 2.2)).is_err());
+    assert!(world.carried.is_none());
+    world.pick_up(pos2(-1.5, 0.2)).unwrap();
+    let handle = world.carried.unwrap().handle;
+    // The player's own force spells don't pull it out of their hands
+    world.push_test_force(VectorField::Uniform(vec2(0.0, 50.0)), None);
+    for _ in 0..60 {
+        world.update();
+    }
+    assert!(world.carried.is_some());
+    let held = world.objects[&handle].pr.pos;
+    assert!(held.distance(world.carry_point(-1.0)) < 0.2, "{held:?}");
+    // Throwing it sends it toward the target
+    world.player.person.active_spells = Default::default();
+    world.player.person.target = Some(pos2(-10.0, held.y));
+    world.release_carried(2.0);
+    world.update();
+    assert!(world.carried.is_none());
+    assert!(world.objects[&handle].vel.x < -5.0);
+}
@@ -1,7 +1,10 @@
-use std::ops::*;
+use std::{f32::consts::PI, ops::*};
 
-use eframe::epaint::{Color32, Hsva};
+use derive_more::Display;
+use eframe::epaint::{Color32, Hsva, Vec2};
+use enum_iterator::Sequence;
 use image::Rgba;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
@@ -87,3 +90,128 @@ impl From<Hsva> for Color {
         }
     }
 }
+
+/// How field values are turned into colors
+#[derive(Debug, Display, Default, Clone, Copy, PartialEq, Eq, Sequence, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    Default,
+    #[display(fmt = "Deuteranopia")]
+    Deuteranopia,
+    #[display(fmt = "High contrast")]
+    HighContrast,
+}
+
+/// What a scalar field's values mean, which some palettes color differently
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarScheme {
+    General,
+    Magic,
+    Light,
+    Temperature,
+}
+
+impl Palette {
+    /// Get the color of a scalar value, where `t` is 0.5 for 0 and approaches 0 and 1 for large values
+    pub fn scalar(self, scheme: ScalarScheme, t: f32) -> Color {
+        // Signed magnitude
+        let m = (t - 0.5) / 0.5;
+        match (self, scheme) {
+            (Palette::Default, ScalarScheme::General) => {
+                let h = 0.9 * (1.0 - t);
+                let v = m.abs().sqrt();
+                let s = v.powi(2);
+                Hsva::new(h, s, v, 1.0).into()
+            }
+            (Palette::Default, ScalarScheme::Magic) => Color::rgb(0.0, m * 0.5, m),
+            (Palette::Default, ScalarScheme::Light) => Color::rgb(m.powf(0.5), m.powf(0.6), m),
+            (Palette::Default, ScalarScheme::Temperature) => {
+                if m > 0.0 {
+                    Color::rgb(m, 0.25 - 0.5 * (m - 0.5).abs(), m * 0.2)
+                } else {
+                    Color::rgb(m.abs() * 0.5, m.abs() * 0.8, m.abs())
+                }
+            }
+            // Fields that are never negative fade in through viridis
+            (Palette::Deuteranopia, ScalarScheme::Magic | ScalarScheme::Light) => {
+                let m = m.max(0.0);
+                viridis(m) * m.sqrt()
+            }
+            (Palette::Deuteranopia, ScalarScheme::General | ScalarScheme::Temperature) => {
+                diverging(m, Color::rgb(1.0, 0.55, 0.1), Color::rgb(0.2, 0.5, 1.0))
+            }
+            (Palette::HighContrast, ScalarScheme::Magic | ScalarScheme::Light) => {
+                let v = m.max(0.0).sqrt();
+                Color::rgb(v, v, v)
+            }
+            (Palette::HighContrast, ScalarScheme::General | ScalarScheme::Temperature) => {
+                diverging(m, Color::rgb(1.0, 1.0, 0.0), Color::rgb(0.0, 1.0, 1.0))
+            }
+        }
+    }
+    /// Get the color of a vector value, where `t` is (0.5, 0.5) for 0
+    ///
+    /// The default palette colors direction by hue, rotated by `offset`.
+    /// Other palettes leave direction to the arrows and only color length.
+    pub fn vector(self, t: Vec2, offset: f32) -> Color {
+        let t = (t - Vec2::splat(0.5)) * 2.0;
+        let length = t.length();
+        match self {
+            Palette::Default => {
+                let v = 0.9 * length + 0.1;
+                let h = (t.angle() + PI) / (2.0 * PI);
+                let h = (h + offset) % 1.0;
+                Hsva::new(h, length, v, 1.0).into()
+            }
+            Palette::Deuteranopia => viridis(length) * (0.9 * length + 0.1),
+            Palette::HighContrast => {
+                let v = 0.3 + 0.7 * length.sqrt();
+                Color::rgb(v, v, v)
+            }
+        }
+    }
+}
+
+/// Black at 0, fading to one color for positive values and another for negative ones
+fn diverging(m: f32, positive: Color, negative: Color) -> Color {
+    let color = if m > 0.0 { positive } else { negative };
+    color * m.abs().sqrt()
+}
+
+/// An approximation of the viridis color map, for `t` from 0 to 1
+fn viridis(t: f32) -> Color {
+    const STOPS: [(f32, f32, f32); 5] = [
+        (0.267, 0.005, 0.329),
+        (0.229, 0.322, 0.546),
+        (0.128, 0.567, 0.551),
+        (0.369, 0.789, 0.383),
+        (0.993, 0.906, 0.144),
+    ];
+    let t = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let i = (t as usize).min(STOPS.len() - 2);
+    let f = t - i as f32;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    Color::rgb(
+        a.0 + (b.0 - a.0) * f,
+        a.1 + (b.1 - a.1) * f,
+        a.2 + (b.2 - a.2) * f,
+    )
+}
+
+#[test]
+fn palette_test() {
+    for palette in enum_iterator::all::<Palette>() {
+        // Zero is dark so that empty fields don't cover the background
+        let zero = palette.scalar(ScalarScheme::General, 0.5);
+        assert!(zero.r + zero.g + zero.b < 0.01, "{palette:?}");
+        // Opposite values look different
+        let (low, high) = (
+            palette.scalar(ScalarScheme::Temperature, 0.0),
+            palette.scalar(ScalarScheme::Temperature, 1.0),
+        );
+        assert!(
+            (low.r - high.r).abs() + (low.b - high.b).abs() > 0.5,
+            "{palette:?}"
+        );
+    }
+}
@@ -1,27 +1,43 @@
+use serde::{Deserialize, Serialize};
+
 use crate::word::Word;
 
+/// The number of conduit slots a new player has
+pub const STARTING_CONDUIT_SLOTS: usize = 2;
+
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct ConduitRack {
     pub conduits: Vec<ConduitStone>,
 }
 
-impl ConduitRack {
-    pub fn new(size: usize) -> Self {
-        ConduitRack {
-            conduits: vec![ConduitStone::default(); size],
-        }
-    }
-}
-
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct ConduitStone {
     pub words: Vec<Word>,
+    #[serde(default)]
+    pub name: String,
 }
 
 impl ConduitStone {
+    pub fn erase(&mut self) {
+        self.words.clear();
+        self.name.clear();
+    }
     pub fn etch(&mut self, words: impl IntoIterator<Item = Word>) {
         self.words = words.into_iter().filter(Word::etchable).collect();
     }
+    /// Format the stone's name, or its words if it has no name
     pub fn format(&self, max_length: usize) -> String {
+        if self.name.is_empty() {
+            return self.format_words(max_length);
+        }
+        if self.name.chars().count() > max_length {
+            let mut s: String = self.name.chars().take(max_length).collect();
+            s.push_str("...");
+            return s;
+        }
+        self.name.clone()
+    }
+    pub fn format_words(&self, max_length: usize) -> String {
         if self.words.is_empty() {
             return "...".into();
         }
@@ -143,7 +143,7 @@ impl Widget for SeparatorButton {
             let stroke = if hilight
                 && ui
                     .input(|input| input.pointer.interact_pos())
-                    .map_or(false, |pos| rect.contains(pos))
+                    .is_some_and(|pos| rect.contains(pos))
             {
                 ui.visuals().selection.stroke
             } else if response.hovered() || response.has_focus() {
@@ -1,19 +1,34 @@
-use std::{borrow::Cow, collections::HashMap, fs};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    fs,
+    mem::replace,
+};
 
 use anyhow::{anyhow, bail};
 use chumsky::{prelude::*, text::whitespace};
-use eframe::{egui::*, epaint::ahash::HashSet};
+use eframe::{
+    egui::*,
+    epaint::{ahash::HashSet, text::LayoutJob},
+};
 use enum_iterator::all;
 use indexmap::IndexMap;
 use itertools::Itertools;
 use once_cell::sync::Lazy;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
 
 use crate::{
-    field::InputFieldKind,
+    audio::play_sfx,
+    field::{InputFieldKind, OutputFieldKind, ScalarFieldKind},
     game::Game,
-    image::{image_plot, ImagePlotKind},
+    gamepad::GamepadNavigable,
+    image::{image_exists, image_plot, Background, ImagePlotKind},
+    keybindings::Action,
+    object::pos2_as_array,
     player::Gender,
+    quest::{Quest, QUESTS},
+    settings::settings,
     utils::{fatal_error, resources_path},
     word::Word,
     world::World,
@@ -33,7 +48,7 @@ fn load_scenes() -> anyhow::Result<DialogScenes> {
         let entry = entry.unwrap();
         if entry.file_type()?.is_file() {
             let path = entry.path();
-            if path.extension().map_or(false, |ext| ext == "yaml") {
+            if path.extension().is_some_and(|ext| ext == "yaml") {
                 let yaml = fs::read_to_string(&path)?;
                 let name = path.file_stem().unwrap().to_string_lossy().into_owned();
                 let scene: DialogScene<SerializedLine> = serde_yaml::from_str(&yaml)
@@ -46,6 +61,7 @@ fn load_scenes() -> anyhow::Result<DialogScenes> {
                     .map_err(|e| anyhow!("Error parsing fragment in {name}: {e}"))?;
                 for (node_name, node) in &scene.nodes {
                     validate_children(&name, &scene, node_name, &node.children)?;
+                    validate_quests(&name, node_name, &node.lines)?;
                 }
                 map.insert(name, scene);
             }
@@ -62,6 +78,7 @@ fn validate_children(
 ) -> anyhow::Result<()> {
     let child_nodes = match children {
         NodeChildren::Choices(choices) => choices.keys().collect_vec(),
+        NodeChildren::Options { options } => options.iter().map(|option| &option.jump).collect(),
         NodeChildren::Jump { jump } => vec![jump],
         NodeChildren::Condition { then, els, .. } => {
             validate_children(scene_name, scene, node_name, then)?;
@@ -85,6 +102,33 @@ fn validate_children(
     Ok(())
 }
 
+/// Check that the quests a node's commands refer to exist
+fn validate_quests(
+    scene_name: &str,
+    node_name: &str,
+    lines: &[Line<DeserializedLine>],
+) -> anyhow::Result<()> {
+    for line in lines {
+        let (id, step) = match line {
+            Line::Command(DialogCommand::StartQuest(id)) => (id, None),
+            Line::Command(DialogCommand::CompleteStep { quest, step }) => (quest, Some(*step)),
+            _ => continue,
+        };
+        let Some(def) = QUESTS.get(id) else {
+            bail!("In {scene_name} scene, node {node_name} refers to unknown quest {id}")
+        };
+        if step.is_some_and(|step| step >= def.steps.len()) {
+            bail!(
+                "In {scene_name} scene, node {node_name} completes step {} of quest {id}, \
+                which only has {} steps",
+                step.unwrap(),
+                def.steps.len()
+            )
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(transparent)]
 pub struct DialogScene<T> {
@@ -114,6 +158,10 @@ pub enum NodeChildren<T> {
         condition: WaitCondition,
         then: String,
     },
+    // This must come before `Choices`, which would otherwise match it
+    Options {
+        options: Vec<ChoiceOption<T>>,
+    },
     Choices(IndexMap<String, Vec<T>>),
     Jump {
         jump: String,
@@ -128,16 +176,64 @@ impl<T> Default for NodeChildren<T> {
     }
 }
 
+/// A choice that may only be available under some condition
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChoiceOption<T> {
+    pub text: T,
+    #[serde(rename = "if", default)]
+    pub condition: Option<Condition>,
+    pub jump: String,
+    /// Effects applied when the option is chosen
+    #[serde(default)]
+    pub effects: Vec<DialogEffect>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Condition {
     FieldKnown(InputFieldKind),
+    WordKnown(Word),
+    /// A flag set in the current dialog
     Flag(String),
+    /// A flag set in the player's progression
+    ProgressionFlag(String),
+    MaxManaAtLeast(f32),
+    /// A field sampled at a point has at least some value
+    FieldAtLeast {
+        kind: ScalarFieldKind,
+        #[serde(deserialize_with = "pos2_as_array")]
+        pos: Pos2,
+        value: f32,
+    },
+    /// The player is sustaining a spell of some kind
+    ActiveSpell(OutputFieldKind),
+    StackNotEmpty,
     Not(Box<Self>),
     And(Vec<Self>),
     Or(Vec<Self>),
 }
 
+impl Condition {
+    /// Describe what is needed for the condition to be met
+    fn requirement(&self) -> String {
+        match self {
+            Condition::FieldKnown(kind) => format!("Requires knowing {kind}"),
+            Condition::WordKnown(word) => format!("Requires knowing {word}"),
+            Condition::Flag(_) | Condition::ProgressionFlag(_) | Condition::Not(_) => {
+                "Not available yet".into()
+            }
+            Condition::MaxManaAtLeast(mana) => format!("Requires at least {mana} max mana"),
+            Condition::FieldAtLeast { kind, value, .. } => {
+                format!("Requires {kind} of at least {value}")
+            }
+            Condition::ActiveSpell(kind) => format!("Requires an active {kind} spell"),
+            Condition::StackNotEmpty => "Requires something on the stack".into(),
+            Condition::And(conditions) => conditions.iter().map(Self::requirement).join("\n"),
+            Condition::Or(conditions) => conditions.iter().map(Self::requirement).join("\nor\n"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum WaitCondition {
@@ -150,6 +246,7 @@ pub enum WaitCondition {
 #[serde(untagged)]
 pub enum Line<T> {
     Command(DialogCommand),
+    Effect(DialogEffect),
     Text(T),
 }
 
@@ -165,16 +262,32 @@ enum SerializedLine {
 pub enum DialogCommand {
     Left(Option<Speaker>),
     Right(Option<Speaker>),
-    Background(Option<String>),
+    Background(Option<Background>),
     Speaker(Option<CurrentSpeaker>),
-    RevealWord(Word),
     RevealAllWords,
     RevealManaBar,
+    RevealHealthBar,
     RevealFree,
+    RevealRearrange,
     RevealConduit,
-    RevealField(InputFieldKind),
+    AddConduitSlots(usize),
     Set(String),
     Unset(String),
+    StartQuest(String),
+    CompleteStep { quest: String, step: usize },
+}
+
+/// A change to the player's progression, from a dialog line or a choice
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DialogEffect {
+    #[serde(alias = "reveal_word")]
+    GiveWord(Word),
+    RevealField(InputFieldKind),
+    AddMaxMana(f32),
+    SetFlag(String),
+    /// Start a dialog scene once the current one ends
+    StartDialog(String),
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -191,7 +304,7 @@ impl Speaker {
             Speaker::Image { name, .. } => name,
         }
     }
-    fn image(&self) -> Cow<str> {
+    fn image(&self) -> Cow<'_, str> {
         match self {
             Speaker::Npc(name) => Cow::Owned(format!("{}.png", name)),
             Speaker::Image { image, .. } => image.into(),
@@ -203,6 +316,7 @@ impl Speaker {
 #[serde(untagged)]
 pub enum CurrentSpeaker {
     Stranger { stranger: String },
+    Image { name: String, image: String },
     Npc(String),
 }
 
@@ -210,15 +324,35 @@ impl CurrentSpeaker {
     fn name(&self) -> &str {
         match self {
             CurrentSpeaker::Stranger { stranger } => stranger,
+            CurrentSpeaker::Image { name, .. } => name,
             CurrentSpeaker::Npc(name) => name,
         }
     }
     fn display(&self) -> &str {
         match self {
             CurrentSpeaker::Stranger { .. } => "Stranger",
+            CurrentSpeaker::Image { name, .. } => name,
             CurrentSpeaker::Npc(name) => name,
         }
     }
+    fn speaker(&self) -> Speaker {
+        match self {
+            CurrentSpeaker::Image { name, image } => Speaker::Image {
+                name: name.clone(),
+                image: image.clone(),
+            },
+            _ => Speaker::Npc(self.name().into()),
+        }
+    }
+}
+
+fn portrait_id(name: &str) -> Id {
+    Id::new(("portrait", name))
+}
+
+/// Identifies the fade between the previous background and the current one
+pub fn background_fade_id() -> Id {
+    Id::new("background fade")
 }
 
 #[derive(Debug, Clone)]
@@ -269,6 +403,7 @@ impl TryFrom<DialogScene<SerializedLine>> for DialogScene<DeserializedLine> {
                         parser.parse(text).map_err(|mut e| anyhow!(e.remove(0)))?
                     }
                     Line::Command(com) => Line::Command(com),
+                    Line::Effect(effect) => Line::Effect(effect),
                     Line::Text(SerializedLine::Catch(value)) => {
                         bail!(
                             "`{}` is not a valid command",
@@ -309,6 +444,26 @@ impl TryFrom<NodeChildren<SerializedLine>> for NodeChildren<DeserializedLine> {
                 }
                 NodeChildren::Choices(children)
             }
+            NodeChildren::Options { options } => NodeChildren::Options {
+                options: (options.into_iter())
+                    .map(|option| {
+                        let SerializedLine::String(text) = option.text else {
+                            bail!("Choice text must be a string");
+                        };
+                        let Line::Text(text) =
+                            parser.parse(text).map_err(|mut e| anyhow!(e.remove(0)))?
+                        else {
+                            bail!("Choice text must not be a command");
+                        };
+                        Ok(ChoiceOption {
+                            text,
+                            condition: option.condition,
+                            jump: option.jump,
+                            effects: option.effects,
+                        })
+                    })
+                    .try_collect()?,
+            },
             NodeChildren::Jump { jump } => NodeChildren::Jump { jump },
             NodeChildren::Condition {
                 condition,
@@ -390,15 +545,59 @@ pub struct DialogState {
     scene: String,
     node: String,
     line: usize,
-    character: usize,
+    /// How many characters of the current line have been revealed
+    character: f32,
+    /// How fast characters are revealed
+    chars_per_second: f32,
     left_speaker: Option<Speaker>,
     right_speaker: Option<Speaker>,
     speaker: Option<CurrentSpeaker>,
     can_cast: bool,
     flags: HashSet<String>,
+    /// The node and line last added to the history
+    recorded: Option<(String, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DialogSettings {
+    /// How fast dialog text is revealed
+    pub chars_per_second: f32,
 }
 
-const DIALOG_SPEED: usize = 3;
+impl Default for DialogSettings {
+    fn default() -> Self {
+        DialogSettings {
+            chars_per_second: 20.0,
+        }
+    }
+}
+
+/// The most lines the dialog history keeps
+pub const MAX_DIALOG_HISTORY: usize = 300;
+
+pub enum DialogHistoryEntry {
+    Line {
+        speaker: Option<String>,
+        text: String,
+    },
+    Choice(String),
+}
+
+/// A transcript of shown dialog lines and the choices the player made
+#[derive(Default)]
+pub struct DialogHistory {
+    entries: VecDeque<DialogHistoryEntry>,
+}
+
+impl DialogHistory {
+    fn push(&mut self, entry: DialogHistoryEntry) {
+        self.entries.push_back(entry);
+        if self.entries.len() > MAX_DIALOG_HISTORY {
+            self.entries.pop_front();
+        }
+    }
+}
 
 impl DialogState {
     pub fn allows_casting(&self) -> bool {
@@ -409,40 +608,61 @@ impl DialogState {
         self.line == node.lines.len() - 1 && node.children.enables_casting()
     }
     pub fn speakers_ui(&self, ui: &mut Ui) -> bool {
-        if self.left_speaker.is_none() && self.right_speaker.is_none() {
+        // The current speaker is shown on the left if they aren't already placed
+        let placed = |name: &str| {
+            [&self.left_speaker, &self.right_speaker]
+                .into_iter()
+                .flatten()
+                .any(|speaker| speaker.name() == name)
+        };
+        let unplaced_speaker = (self.speaker.as_ref())
+            .filter(|speaker| !placed(speaker.name()))
+            .map(CurrentSpeaker::speaker)
+            .filter(|speaker| image_exists(&speaker.image()));
+        let left_speaker = self.left_speaker.clone().or(unplaced_speaker);
+        if left_speaker.is_none() && self.right_speaker.is_none() {
             return false;
         }
-        const PORTRAIT_HEIGHT: f32 = 200.0;
-        if let Some(speaker) = &self.left_speaker {
-            let focused = self
-                .speaker
-                .as_ref()
-                .map_or(true, |curr| curr.name() == speaker.name());
+        if let Some(speaker) = &left_speaker {
             ui.with_layout(Layout::bottom_up(Align::Min), |ui| {
-                image_plot(
-                    ui,
-                    &speaker.image(),
-                    Vec2::splat(PORTRAIT_HEIGHT),
-                    ImagePlotKind::Portrait(focused),
-                );
+                self.portrait_ui(ui, speaker)
             });
         }
         if let Some(speaker) = &self.right_speaker {
-            let focused = self
-                .speaker
-                .as_ref()
-                .map_or(true, |curr| curr.name() == speaker.name());
             ui.with_layout(Layout::bottom_up(Align::Max), |ui| {
-                image_plot(
-                    ui,
-                    &speaker.image(),
-                    Vec2::splat(PORTRAIT_HEIGHT),
-                    ImagePlotKind::Portrait(focused),
-                );
+                self.portrait_ui(ui, speaker)
             });
         }
         true
     }
+    /// Show a speaker's name plate and portrait, if they have one
+    fn portrait_ui(&self, ui: &mut Ui, speaker: &Speaker) {
+        const PORTRAIT_HEIGHT: f32 = 200.0;
+        let focused = self
+            .speaker
+            .as_ref()
+            .is_none_or(|curr| curr.name() == speaker.name());
+        let ctx = ui.ctx().clone();
+        let visibility = ctx.animate_bool_with_time(portrait_id(speaker.name()), true, 0.5);
+        let focus =
+            ctx.animate_bool_with_time(portrait_id(speaker.name()).with("focus"), focused, 0.3);
+        let mut name = RichText::new(speaker.name()).heading();
+        if !focused {
+            name = name.weak();
+        }
+        ui.label(name);
+        let image = speaker.image();
+        if image_exists(&image) {
+            let size = PORTRAIT_HEIGHT * (0.75 + 0.25 * focus);
+            let opacity = visibility * (0.1 + 0.7 * focus);
+            image_plot(
+                ui,
+                &image,
+                Vec2::splat(size),
+                ImagePlotKind::Portrait(opacity),
+            );
+        }
+    }
 }
 
 impl NodeChildren<DeserializedLine> {
@@ -457,6 +677,7 @@ impl NodeChildren<DeserializedLine> {
                 WaitCondition::EmptyStack => true,
             },
             NodeChildren::Choices(_) => false,
+            NodeChildren::Options { .. } => false,
             NodeChildren::Jump { .. } => false,
             NodeChildren::List(list) => list.iter().any(Self::enables_casting),
             NodeChildren::Next(_) => false,
@@ -465,10 +686,27 @@ impl NodeChildren<DeserializedLine> {
 }
 
 impl DialogState {
+    /// Go to the start of another node in the scene
+    fn jump(&mut self, node: String) {
+        debug!(scene = %self.scene, %node, "Dialog jumped");
+        self.node = node;
+        self.line = 0;
+        self.character = 0.0;
+    }
     fn check_condition(&self, world: &World, condition: &Condition) -> bool {
         match condition {
             Condition::FieldKnown(kind) => world.player.progression.known_fields.contains(kind),
+            Condition::WordKnown(word) => world.player.progression.known_words.contains(word),
             Condition::Flag(flag) => self.flags.contains(flag),
+            Condition::ProgressionFlag(flag) => world.player.progression.flags.contains(flag),
+            Condition::MaxManaAtLeast(mana) => world.player.person.max_mana >= *mana,
+            Condition::FieldAtLeast { kind, pos, value } => {
+                world.sample_scalar_field(*kind, *pos, true) >= *value
+            }
+            Condition::ActiveSpell(kind) => {
+                world.player.person.active_spells.spell_words(*kind).len() > 0
+            }
+            Condition::StackNotEmpty => !world.player.person.stack.is_empty(),
             Condition::Not(inner) => !self.check_condition(world, inner),
             Condition::And(conditions) => conditions
                 .iter()
@@ -482,44 +720,94 @@ impl DialogState {
 
 impl Game {
     pub fn set_dialog(&mut self, scene_name: &str) {
-        let scene = &DIALOG_SCENES[scene_name];
+        let Some(scene) = DIALOG_SCENES.get(scene_name) else {
+            warn!("Unknown dialog scene {scene_name}");
+            return;
+        };
+        debug!(scene = scene_name, "Dialog started");
         let dialog = DialogState {
             scene: scene_name.into(),
             node: scene.nodes.first().unwrap().0.clone(),
             line: 0,
-            character: 0,
+            character: 0.0,
+            chars_per_second: settings().dialog.chars_per_second,
             speaker: None,
             can_cast: false,
             left_speaker: None,
             right_speaker: None,
             flags: HashSet::default(),
+            recorded: None,
         };
         self.ui_state.dialog = Some(dialog);
     }
     pub fn dialog_ui(&mut self, ui: &mut Ui) {
         if self.ui_state.dialog.is_none() {
+            if let Some(scene) = self.ui_state.queued_dialog.take() {
+                self.set_dialog(&scene);
+                return;
+            }
+            // Prompt to talk to nearby npcs
+            if let Some((npc_id, dialog)) = self.world.npc_dialog_in_range() {
+                if !dialog.auto {
+                    let key = self.ui_state.keybindings.get(Action::Talk);
+                    ui.group(|ui| ui.label(format!("Press {key} to talk to {npc_id:?}")));
+                }
+            }
             return;
         }
+        ui.toggle_value(&mut self.ui_state.history_open, "📜")
+            .on_hover_text("History");
         ui.group(|ui| self.dialog_ui_impl(ui));
     }
+    pub fn dialog_history_ui(&mut self, ctx: &Context) {
+        Window::new("History")
+            .open(&mut self.ui_state.history_open)
+            .default_height(300.0)
+            .show(ctx, |ui| {
+                ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        for entry in &self.ui_state.dialog_history.entries {
+                            match entry {
+                                DialogHistoryEntry::Line { speaker, text } => {
+                                    ui.horizontal_wrapped(|ui| {
+                                        if let Some(speaker) = speaker {
+                                            ui.strong(format!("{speaker}:"));
+                                        }
+                                        ui.label(text);
+                                    });
+                                }
+                                DialogHistoryEntry::Choice(text) => {
+                                    let color = ui.visuals().hyperlink_color;
+                                    ui.colored_label(
+                                        color,
+                                        RichText::new(format!("> {text}")).italics(),
+                                    );
+                                }
+                            }
+                        }
+                    });
+            });
+    }
     fn progress_dialog(&mut self) {
         let Some(dialog) = &mut self.ui_state.dialog else {
             return;
         };
+        play_sfx("dialog.ogg");
         let scene = &DIALOG_SCENES[&dialog.scene];
         let node = &scene.nodes[&dialog.node];
 
         if dialog.line < node.lines.len().saturating_sub(1) {
             dialog.line += 1;
-            dialog.character = 0;
+            dialog.character = 0.0;
         } else {
             let node_index = scene.nodes.get_index_of(&dialog.node).unwrap();
             if let Some((node_name, _)) = scene.nodes.get_index(node_index + 1) {
-                dialog.node = node_name.clone();
-                dialog.line = 0;
-                dialog.character = 0;
+                dialog.jump(node_name.clone());
             } else if matches!(&node.children, NodeChildren::Choices(choices) if choices.is_empty())
             {
+                debug!(scene = %dialog.scene, "Dialog ended");
                 self.ui_state.dialog = None;
             }
         }
@@ -542,86 +830,209 @@ impl Game {
                 // Space the group
                 ui.allocate_at_least(vec2(ui.max_rect().width(), 0.0), Sense::hover());
                 let line_text = self.world.format_dialog_fragments(fragments);
-                let char_indices = line_text.char_indices().collect_vec();
-                let char_index = dialog.character / DIALOG_SPEED;
-                ui.horizontal(|ui| {
-                    // Show speaker
-                    if let Some(speaker) = &dialog.speaker {
-                        ui.heading(format!("{}:", speaker.display()));
-                    }
-                    // Show line text
+                // Record the line the first time it is shown
+                let position = (dialog.node.clone(), dialog.line);
+                if dialog.recorded.as_ref() != Some(&position) {
+                    dialog.recorded = Some(position);
                     if !line_text.is_empty() {
-                        let line_text = &line_text[..=char_indices[char_index].0];
-                        ui.horizontal_wrapped(|ui| ui.heading(line_text));
+                        self.ui_state.dialog_history.push(DialogHistoryEntry::Line {
+                            speaker: (dialog.speaker.as_ref())
+                                .map(|speaker| speaker.display().into()),
+                            text: line_text.clone(),
+                        });
                     }
-                });
+                }
+                let char_count = line_text.chars().count() as f32;
+                let revealed = dialog.character.min(char_count) as usize;
+                let split =
+                    (line_text.char_indices().nth(revealed)).map_or(line_text.len(), |(i, _)| i);
+                let clicked = ui
+                    .horizontal(|ui| {
+                        // Show speaker
+                        let mut resp = if let Some(speaker) = &dialog.speaker {
+                            ui.add(
+                                Label::new(
+                                    RichText::new(format!("{}:", speaker.display())).heading(),
+                                )
+                                .sense(Sense::click()),
+                            )
+                        } else {
+                            ui.allocate_response(Vec2::ZERO, Sense::click())
+                        };
+                        // Show line text
+                        // The whole line is laid out so that wrapping doesn't change as it is revealed
+                        if !line_text.is_empty() {
+                            let font_id = TextStyle::Heading.resolve(ui.style());
+                            let color = ui.visuals().text_color();
+                            let mut job = LayoutJob::default();
+                            job.append(
+                                &line_text[..split],
+                                0.0,
+                                TextFormat::simple(font_id.clone(), color),
+                            );
+                            job.append(
+                                &line_text[split..],
+                                0.0,
+                                TextFormat::simple(font_id, Color32::TRANSPARENT),
+                            );
+                            resp |= ui.add(Label::new(job).wrap(true).sense(Sense::click()));
+                        }
+                        resp.clicked()
+                    })
+                    .inner;
+                // Clicking the text or pressing the advance key acts like the Next button
+                let keybindings = &self.ui_state.keybindings;
+                let skip = clicked
+                    || ui.ctx().memory(|memory| memory.focus().is_none())
+                        && ui.input(|input| keybindings.pressed(input, Action::AdvanceDialog));
                 // Show continue or choices
-                let max_dialog_char = (char_indices.len().saturating_sub(1)) * DIALOG_SPEED;
-                dialog.character = (dialog.character + 1).min(max_dialog_char);
+                let revealing = dialog.character < char_count;
+                dialog.character = (dialog.character
+                    + ui.input(|input| input.stable_dt) * dialog.chars_per_second)
+                    .min(char_count);
                 let mut next = || {
                     ui.with_layout(Layout::bottom_up(Align::Max), |ui| {
-                        ui.button("Next").clicked()
+                        ui.button("Next").gamepad_nav().clicked()
                     })
                     .inner
+                        || skip
                 };
-                if dialog.character < max_dialog_char {
+                if revealing {
                     // Revealing the text
                     if next() {
-                        dialog.character = max_dialog_char;
+                        dialog.character = char_count;
                     }
                 } else if dialog.line < node.lines.len() - 1 {
                     if next() {
                         self.progress_dialog();
                     }
                 } else {
-                    self.node_children_ui(ui, line_text, node.children.clone());
+                    self.node_children_ui(ui, line_text, skip, node.children.clone());
                 }
             }
             Line::Command(command) => {
                 let progression = &mut self.world.player.progression;
                 match command {
-                    DialogCommand::Left(speaker) => dialog.left_speaker = speaker.clone(),
-                    DialogCommand::Right(speaker) => dialog.right_speaker = speaker.clone(),
-                    DialogCommand::Background(image) => self.ui_state.background = image.clone(),
-                    DialogCommand::Speaker(speaker) => dialog.speaker = speaker.clone(),
-                    DialogCommand::RevealWord(word) => {
-                        progression.known_words.insert(*word);
+                    DialogCommand::Left(speaker) | DialogCommand::Right(speaker) => {
+                        // Start the portrait invisible so that it fades in
+                        if let Some(speaker) = speaker {
+                            let id = portrait_id(speaker.name());
+                            ui.ctx().animate_bool_with_time(id, false, 0.0);
+                        }
+                        if let DialogCommand::Left(_) = command {
+                            dialog.left_speaker = speaker.clone();
+                        } else {
+                            dialog.right_speaker = speaker.clone();
+                        }
+                    }
+                    DialogCommand::Background(background) => {
+                        // Fade from the current background to the new one
+                        ui.ctx()
+                            .animate_bool_with_time(background_fade_id(), false, 0.0);
+                        self.ui_state.previous_background =
+                            replace(&mut self.ui_state.background, background.clone());
                     }
+                    DialogCommand::Speaker(speaker) => dialog.speaker = speaker.clone(),
                     DialogCommand::RevealAllWords => progression.known_words.extend(all::<Word>()),
                     DialogCommand::RevealManaBar => progression.mana_bar = true,
-                    DialogCommand::RevealField(kind) => {
-                        progression.known_fields.insert(*kind);
-                        self.ui_state.fields_display.insert(
-                            (*kind).into(),
-                            self.ui_state.default_field_display((*kind).into()),
-                        );
-                    }
+                    DialogCommand::RevealHealthBar => progression.health_bar = true,
                     DialogCommand::RevealFree => progression.free = true,
+                    DialogCommand::RevealRearrange => progression.rearrange = true,
                     DialogCommand::RevealConduit => progression.conduit = true,
+                    DialogCommand::AddConduitSlots(n) => progression.conduit_slots += n,
                     DialogCommand::Set(flag) => {
                         dialog.flags.insert(flag.clone());
                     }
                     DialogCommand::Unset(flag) => {
                         dialog.flags.remove(flag);
                     }
+                    DialogCommand::StartQuest(id) => self.start_quest(id),
+                    DialogCommand::CompleteStep { quest, step } => {
+                        self.complete_quest_step(quest, *step)
+                    }
                 }
                 self.progress_dialog();
                 self.dialog_ui_impl(ui);
             }
+            Line::Effect(effect) => {
+                let effect = effect.clone();
+                self.apply_dialog_effect(&effect);
+                self.progress_dialog();
+                self.dialog_ui_impl(ui);
+            }
+        }
+    }
+    fn apply_dialog_effect(&mut self, effect: &DialogEffect) {
+        let player = &mut self.world.player;
+        match effect {
+            DialogEffect::GiveWord(word) => {
+                player.progression.known_words.insert(*word);
+            }
+            DialogEffect::RevealField(kind) => {
+                player.progression.known_fields.insert(*kind);
+                self.ui_state.fields_display.insert(
+                    (*kind).into(),
+                    self.ui_state.default_field_display((*kind).into()),
+                );
+            }
+            DialogEffect::AddMaxMana(mana) => {
+                player.grant_max_mana(*mana);
+                self.ui_state.toast(format!("Max mana +{mana}"));
+                // The other effects only change what the player can do from the ui,
+                // which replays already record as it is done
+                self.note_unrecorded("max mana from dialog");
+            }
+            DialogEffect::SetFlag(flag) => {
+                player.progression.flags.insert(flag.clone());
+            }
+            DialogEffect::StartDialog(scene) => self.ui_state.queued_dialog = Some(scene.clone()),
+        }
+    }
+    fn start_quest(&mut self, id: &str) {
+        let quests = &mut self.world.player.progression.quests;
+        if quests.iter().any(|quest| quest.id == id) {
+            return;
+        }
+        let Some(def) = QUESTS.get(id) else {
+            warn!("Unknown quest: {id}");
+            return;
+        };
+        quests.push(Quest::new(id, def));
+        self.ui_state.toast(format!("New quest: {}", def.title));
+    }
+    pub fn complete_quest_step(&mut self, id: &str, step: usize) {
+        let quests = &mut self.world.player.progression.quests;
+        let Some(quest) = quests.iter_mut().find(|quest| quest.id == id) else {
+            warn!("Quest {id} has not been started");
+            return;
+        };
+        if !quest.complete_step(step) {
+            return;
+        }
+        let title = quest.title.clone();
+        if let Some(def) = QUESTS.get(id) {
+            for effect in &def.rewards {
+                self.apply_dialog_effect(effect);
+            }
+        } else {
+            warn!("Quest {id} no longer exists, so it has no rewards");
         }
+        self.ui_state.toast(format!("Quest complete: {title}"));
     }
     fn node_children_ui(
         &mut self,
         ui: &mut Ui,
         line_text: String,
+        skip: bool,
         children: NodeChildren<DeserializedLine>,
     ) {
         let dialog = self.ui_state.dialog.as_mut().unwrap();
         let mut next = || {
             ui.with_layout(Layout::bottom_up(Align::Max), |ui| {
-                ui.button("Next").clicked()
+                ui.button("Next").gamepad_nav().clicked()
             })
             .inner
+                || skip
         };
         match children {
             NodeChildren::Choices(choices) if choices.is_empty() => {
@@ -635,26 +1046,50 @@ impl Game {
                 ui.with_layout(Layout::bottom_up(Align::Min), |ui| {
                     for (name, fragments) in choices.iter().rev() {
                         for fragments in fragments.iter().rev() {
-                            if ui
-                                .button(
-                                    RichText::new(self.world.format_dialog_fragments(fragments))
-                                        .heading(),
-                                )
-                                .clicked()
-                            {
-                                dialog.node = name.clone();
-                                dialog.line = 0;
-                                dialog.character = 0;
+                            let text = self.world.format_dialog_fragments(fragments);
+                            let resp = ui.button(RichText::new(&text).heading());
+                            if resp.gamepad_nav().clicked() {
+                                self.ui_state
+                                    .dialog_history
+                                    .push(DialogHistoryEntry::Choice(text));
+                                dialog.jump(name.clone());
                             }
                         }
                     }
                 });
             }
+            NodeChildren::Options { options } => {
+                let mut chosen = None;
+                ui.with_layout(Layout::bottom_up(Align::Min), |ui| {
+                    for option in options.iter().rev() {
+                        let enabled = (option.condition.as_ref())
+                            .is_none_or(|condition| dialog.check_condition(&self.world, condition));
+                        let text = self.world.format_dialog_fragments(&option.text);
+                        let mut resp =
+                            ui.add_enabled(enabled, Button::new(RichText::new(text).heading()));
+                        if let Some(condition) = &option.condition {
+                            resp = resp.on_disabled_hover_text(condition.requirement());
+                        }
+                        if resp.gamepad_nav().clicked() {
+                            chosen = Some(option);
+                        }
+                    }
+                });
+                if let Some(option) = chosen {
+                    let text = self.world.format_dialog_fragments(&option.text);
+                    self.ui_state
+                        .dialog_history
+                        .push(DialogHistoryEntry::Choice(text));
+                    for effect in &option.effects {
+                        self.apply_dialog_effect(effect);
+                    }
+                    let dialog = self.ui_state.dialog.as_mut().unwrap();
+                    dialog.jump(option.jump.clone());
+                }
+            }
             NodeChildren::Jump { jump } => {
                 if next() {
-                    dialog.node = jump;
-                    dialog.line = 0;
-                    dialog.character = 0;
+                    dialog.jump(jump);
                 }
             }
             NodeChildren::Condition {
@@ -663,9 +1098,9 @@ impl Game {
                 els,
             } => {
                 if dialog.check_condition(&self.world, &condition) {
-                    self.node_children_ui(ui, line_text, *then)
+                    self.node_children_ui(ui, line_text, skip, *then)
                 } else {
-                    self.node_children_ui(ui, line_text, *els)
+                    self.node_children_ui(ui, line_text, skip, *els)
                 }
             }
             NodeChildren::Wait {
@@ -673,15 +1108,13 @@ impl Game {
                 then: node,
             } => {
                 if self.world.wait_condition(&condition) {
-                    dialog.node = node;
-                    dialog.line = 0;
-                    dialog.character = 0;
+                    dialog.jump(node);
                 }
                 ui.allocate_exact_size(ui.available_size(), Sense::hover());
             }
             NodeChildren::List(list) => {
                 for children in list {
-                    self.node_children_ui(ui, line_text.clone(), children);
+                    self.node_children_ui(ui, line_text.clone(), skip, children);
                 }
             }
             NodeChildren::Next(fragments) => {
@@ -692,6 +1125,7 @@ impl Game {
                                 RichText::new(self.world.format_dialog_fragments(fragments))
                                     .heading(),
                             )
+                            .gamepad_nav()
                             .clicked()
                         })
                     })
@@ -767,3 +1201,90 @@ impl World {
         formatted
     }
 }
+
+#[test]
+fn dialog_files_test() {
+    let scenes = load_scenes().unwrap();
+    let end = &scenes["intro"].nodes["end"];
+    assert!(matches!(end.children, NodeChildren::Options { .. }));
+}
+
+#[test]
+fn dialog_effect_test() {
+    let parse = |yaml: &str| -> anyhow::Result<DialogScene<DeserializedLine>> {
+        let scene: DialogScene<SerializedLine> = serde_yaml::from_str(yaml)?;
+        scene.try_into()
+    };
+    let scene = parse("start:\n  lines:\n    - give_word: Te\n    - add_max_mana: 5\n").unwrap();
+    let lines = &scene.nodes["start"].lines;
+    assert!(matches!(
+        lines[0],
+        Line::Effect(DialogEffect::GiveWord(Word::Te))
+    ));
+    assert!(matches!(
+        lines[1],
+        Line::Effect(DialogEffect::AddMaxMana(_))
+    ));
+    // Unknown words and fields are caught when loading
+    assert!(parse("start:\n  lines:\n    - give_word: Sila\n").is_err());
+    assert!(parse("start:\n  lines:\n    - reveal_field: Smell\n").is_err());
+    // So are unknown quests and steps
+    let quests = |yaml: &str| {
+        let scene = parse(yaml).unwrap();
+        validate_quests("test", "start", &scene.nodes["start"].lines)
+    };
+    assert!(quests("start:\n  lines:\n    - start_quest: settling_in\n").is_ok());
+    assert!(quests("start:\n  lines:\n    - start_quest: settling_out\n").is_err());
+    assert!(
+        quests("start:\n  lines:\n    - complete_step: {quest: settling_in, step: 9}\n").is_err()
+    );
+}
+
+#[test]
+fn world_condition_test() {
+    use crate::{
+        field::{ScalarInputFieldKind, VectorOutputFieldKind},
+        person::PersonId,
+        player::Player,
+    };
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let dialog = DialogState {
+        scene: String::new(),
+        node: String::new(),
+        line: 0,
+        character: 0.0,
+        chars_per_second: DialogSettings::default().chars_per_second,
+        left_speaker: None,
+        right_speaker: None,
+        speaker: None,
+        can_cast: false,
+        flags: HashSet::default(),
+        recorded: None,
+    };
+    let condition = |yaml: &str| -> Condition { serde_yaml::from_str(yaml).unwrap() };
+    // Field values
+    let warm = condition("field_at_least: {kind: Temperature, pos: [0, 4], value: -100}");
+    let hot = condition("field_at_least: {kind: Temperature, pos: [0, 4], value: 1000}");
+    assert!(matches!(
+        warm,
+        Condition::FieldAtLeast {
+            kind: ScalarFieldKind::Input(ScalarInputFieldKind::Temperature),
+            ..
+        }
+    ));
+    assert!(dialog.check_condition(&world, &warm));
+    assert!(!dialog.check_condition(&world, &hot));
+    // Stack and active spells
+    let stack_not_empty = condition("stack_not_empty");
+    let gravity = condition("active_spell: Gravity");
+    assert!(!dialog.check_condition(&world, &stack_not_empty));
+    assert!(!dialog.check_condition(&world, &gravity));
+    world.player.progression.known_words.extend(all::<Word>());
+    world.say(PersonId::Player, Word::Ki).unwrap();
+    assert!(dialog.check_condition(&world, &stack_not_empty));
+    world.say(PersonId::Player, Word::Vu).unwrap();
+    assert!(!dialog.check_condition(&world, &stack_not_empty));
+    assert!(dialog.check_condition(&world, &gravity));
+    let kind = OutputFieldKind::from(VectorOutputFieldKind::Gravity);
+    assert_eq!(world.player.person.active_spells.spell_words(kind).len(), 1);
+}
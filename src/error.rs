@@ -1,6 +1,9 @@
 use std::{borrow::Cow, error::Error, fmt};
 
-use crate::{field::Type, function::*};
+use crate::{
+    field::{Type, MAX_FIELD_COMPLEXITY},
+    function::*,
+};
 
 #[derive(Debug)]
 pub enum EidosError {
@@ -15,6 +18,14 @@ pub enum EidosError {
         expected: usize,
         stack_size: usize,
     },
+    NonUniformArgument {
+        function: Function,
+        position: usize,
+    },
+    TooComplex {
+        function: Function,
+        complexity: usize,
+    },
 }
 
 impl fmt::Display for EidosError {
@@ -43,13 +54,25 @@ impl fmt::Display for EidosError {
                     n => format!("only has {n} values"),
                 }
             ),
+            EidosError::NonUniformArgument { function, position } => write!(
+                f,
+                "Invalid argument {position} to {function}. Expected a constant."
+            ),
+            EidosError::TooComplex {
+                function,
+                complexity,
+            } => write!(
+                f,
+                "{function} would make a field that is too complex. \
+                It would have a complexity of {complexity}, but the limit is {MAX_FIELD_COMPLEXITY}."
+            ),
         }
     }
 }
 
 impl Error for EidosError {}
 
-fn _plural(s: &str, n: usize) -> Cow<str> {
+fn _plural(s: &str, n: usize) -> Cow<'_, str> {
     if n == 1 {
         Cow::Borrowed(s)
     } else {
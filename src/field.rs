@@ -1,11 +1,19 @@
 use derive_more::{Display, From};
 use eframe::epaint::{Pos2, Vec2};
 use enum_iterator::Sequence;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{function::*, person::PersonId, world::World};
+use crate::{
+    function::*,
+    math::rotate,
+    object::{vec2_as_array, vec2_to_array},
+    person::PersonId,
+    world::{World, HEAT_GRID_RESOLUTION, MEMORY_GRID_RESOLUTION},
+};
 
-#[derive(Debug, Clone, From)]
+const GRADIENT_RANGE: f32 = 0.1;
+
+#[derive(Debug, Clone, From, Serialize, Deserialize)]
 pub enum Field {
     #[from(types(f32))]
     Scalar(ScalarField),
@@ -26,6 +34,30 @@ impl Field {
             Field::Vector(field) => field.controls(),
         }
     }
+    pub fn complexity(&self) -> usize {
+        match self {
+            Field::Scalar(field) => field.complexity(),
+            Field::Vector(field) => field.complexity(),
+        }
+    }
+}
+
+/// The most complex a field can be before sampling it gets too slow
+pub const MAX_FIELD_COMPLEXITY: usize = 200;
+/// The number of times derivatives sample the field they are taken of
+pub const DERIVATIVE_SAMPLES: usize = 4;
+/// The largest value a field can have
+///
+/// Fields like the reciprocal of zero are clamped to this so that they never become infinite
+pub const MAX_FIELD_VALUE: f32 = 1e6;
+
+/// Clamp a field value to a finite range, treating NaN as zero
+fn finite(v: f32) -> f32 {
+    if v.is_nan() {
+        0.0
+    } else {
+        v.clamp(-MAX_FIELD_VALUE, MAX_FIELD_VALUE)
+    }
 }
 
 #[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -34,7 +66,7 @@ pub enum Type {
     Vector,
 }
 
-#[derive(Debug, Clone, From)]
+#[derive(Debug, Clone, From, Serialize, Deserialize)]
 pub enum ScalarField {
     #[from]
     Uniform(f32),
@@ -42,10 +74,13 @@ pub enum ScalarField {
     Y,
     TargetX(PersonId),
     TargetY(PersonId),
+    Proximity(PersonId),
     ScalarUn(TypedUnOp<ScalarUnOp>, Box<Self>),
     VectorUn(VectorUnScalarOp, Box<VectorField>),
     Bin(TypedBinOp<HomoBinOp>, Box<Self>, Box<Self>),
     Index(Box<VectorField>, Box<Self>),
+    Dot(Box<VectorField>, Box<VectorField>),
+    Select(Box<Self>, Box<Self>, Box<Self>),
     #[from]
     Input(ScalarInputFieldKind),
     #[from]
@@ -53,20 +88,24 @@ pub enum ScalarField {
     Variable,
 }
 
-#[derive(Debug, Clone, From)]
+#[derive(Debug, Clone, From, Serialize, Deserialize)]
 pub enum VectorField {
-    Uniform(Vec2),
+    Uniform(#[serde(serialize_with = "vec2_to_array", deserialize_with = "vec2_as_array")] Vec2),
     VectorUn(TypedUnOp<VectorUnVectorOp>, Box<Self>),
     ScalarUn(ScalarUnVectorOp, Box<ScalarField>),
     BinSV(TypedBinOp<NoOp<Vec2>>, ScalarField, Box<Self>),
     BinVS(TypedBinOp<NoOp<Vec2>>, Box<Self>, ScalarField),
     BinVV(TypedBinOp<HomoBinOp>, Box<Self>, Box<Self>),
     Index(Box<Self>, Box<Self>),
+    Rotate(Box<Self>, Box<ScalarField>),
+    Select(Box<ScalarField>, Box<Self>, Box<Self>),
     Input(VectorInputFieldKind),
     Variable,
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, From, Sequence, Deserialize)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, Hash, From, Sequence, Serialize, Deserialize,
+)]
 #[serde(untagged)]
 pub enum FieldKind {
     #[from(types(ScalarInputFieldKind, ScalarOutputFieldKind))]
@@ -113,35 +152,43 @@ impl From<FieldKind> for IoFieldKind {
     }
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, From, Sequence, Deserialize)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, Hash, From, Sequence, Serialize, Deserialize,
+)]
 #[serde(untagged)]
 pub enum InputFieldKind {
     Scalar(ScalarInputFieldKind),
     Vector(VectorInputFieldKind),
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, From, Sequence, Deserialize)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, Hash, From, Sequence, Serialize, Deserialize,
+)]
 #[serde(untagged)]
 pub enum OutputFieldKind {
     Scalar(ScalarOutputFieldKind),
     Vector(VectorOutputFieldKind),
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, From, Sequence, Deserialize)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, Hash, From, Sequence, Serialize, Deserialize,
+)]
 #[serde(untagged)]
 pub enum ScalarFieldKind {
     Input(ScalarInputFieldKind),
     Output(ScalarOutputFieldKind),
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, From, Sequence, Deserialize)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, Hash, From, Sequence, Serialize, Deserialize,
+)]
 #[serde(untagged)]
 pub enum VectorFieldKind {
     Output(VectorOutputFieldKind),
     Input(VectorInputFieldKind),
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, Sequence, Deserialize)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, Sequence, Serialize, Deserialize)]
 pub enum ScalarInputFieldKind {
     #[display(fmt = "ρ Density")]
     Density,
@@ -157,12 +204,34 @@ pub enum ScalarInputFieldKind {
     Magic,
     #[display(fmt = "🕯Light")]
     Light,
+    #[display(fmt = "🎲Noise")]
+    Noise,
+    #[display(fmt = "⏳Time")]
+    Time,
+    #[display(fmt = "🧱Material")]
+    Material,
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, Sequence, Deserialize)]
-pub enum VectorInputFieldKind {}
+impl ScalarInputFieldKind {
+    /// The distance over which the field's value is constant
+    pub fn resolution(&self) -> f32 {
+        match self {
+            ScalarInputFieldKind::Temperature => HEAT_GRID_RESOLUTION,
+            ScalarInputFieldKind::Memory => MEMORY_GRID_RESOLUTION,
+            _ => 0.0,
+        }
+    }
+}
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, Sequence, Deserialize)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, Sequence, Serialize, Deserialize)]
+pub enum VectorInputFieldKind {
+    #[display(fmt = "🌬Wind")]
+    Wind,
+    #[display(fmt = "🏃Velocity")]
+    Velocity,
+}
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, Sequence, Serialize, Deserialize)]
 pub enum ScalarOutputFieldKind {
     #[display(fmt = "🔥Heat")]
     Heat,
@@ -172,7 +241,7 @@ pub enum ScalarOutputFieldKind {
     Anchor,
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, Sequence, Deserialize)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, Sequence, Serialize, Deserialize)]
 pub enum VectorOutputFieldKind {
     #[display(fmt = "⬇ Gravity")]
     Gravity,
@@ -182,7 +251,9 @@ pub enum VectorOutputFieldKind {
     Write,
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
 pub enum ControlKind {
     XSlider,
     YSlider,
@@ -191,32 +262,45 @@ pub enum ControlKind {
 }
 
 impl ScalarField {
+    /// Sample the field at a position, always getting a finite value
     pub fn sample(&self, world: &World, pos: Pos2, allow_recursion: bool) -> f32 {
+        finite(self.sample_impl(world, pos, allow_recursion))
+    }
+    fn sample_impl(&self, world: &World, pos: Pos2, allow_recursion: bool) -> f32 {
         puffin::profile_function!();
         match self {
             ScalarField::Uniform(v) => *v,
             ScalarField::X => pos.x,
             ScalarField::Y => pos.y,
             ScalarField::TargetX(person_id) => {
-                let person = world.person(*person_id);
-                let Some(target) = person.target else {
+                let Some(target) = world.person(*person_id).and_then(|person| person.target) else {
                     return 0.0;
                 };
                 target.x - pos.x
             }
             ScalarField::TargetY(person_id) => {
-                let person = world.person(*person_id);
-                let Some(target) = person.target else {
+                let Some(target) = world.person(*person_id).and_then(|person| person.target) else {
                     return 0.0;
                 };
                 target.y - pos.y
             }
+            ScalarField::Proximity(person_id) => world
+                .person(*person_id)
+                .map_or(0.0, |person| person.pos.distance(pos)),
             ScalarField::ScalarUn(op, field) => {
                 op.operate(field.sample(world, pos, allow_recursion))
             }
-            ScalarField::VectorUn(op, field) => {
-                op.operate(field.sample(world, pos, allow_recursion))
-            }
+            ScalarField::VectorUn(op, field) => match op {
+                VectorUnScalarOp::Divergence => {
+                    let [dx, dy] = field.partials_at(world, pos, allow_recursion);
+                    dx.x + dy.y
+                }
+                VectorUnScalarOp::Curl => {
+                    let [dx, dy] = field.partials_at(world, pos, allow_recursion);
+                    dx.y - dy.x
+                }
+                _ => op.operate(field.sample(world, pos, allow_recursion)),
+            },
             ScalarField::Bin(op, a, b) => op.operate(
                 a.sample(world, pos, allow_recursion),
                 b.sample(world, pos, allow_recursion),
@@ -226,6 +310,17 @@ impl ScalarField {
                 index.sample(world, pos, allow_recursion).to_pos2(),
                 allow_recursion,
             ),
+            ScalarField::Dot(a, b) => {
+                a.sample(world, pos, allow_recursion)
+                    .dot(b.sample(world, pos, allow_recursion))
+            }
+            ScalarField::Select(condition, a, b) => {
+                if condition.sample(world, pos, allow_recursion) > 0.0 {
+                    a.sample(world, pos, allow_recursion)
+                } else {
+                    b.sample(world, pos, allow_recursion)
+                }
+            }
             ScalarField::Input(kind) => {
                 world.sample_input_scalar_field(*kind, pos, allow_recursion)
             }
@@ -233,35 +328,69 @@ impl ScalarField {
             ScalarField::Variable => pos.to_vec2().length(),
         }
     }
-    fn uniform(&self) -> Option<f32> {
+    pub fn uniform(&self) -> Option<f32> {
         match self {
             ScalarField::Uniform(n) => Some(*n),
             _ => None,
         }
     }
+    /// Get the input field that this field reads, possibly clamped by a uniform with min or max
+    pub fn clamped_input(&self) -> Option<ScalarInputFieldKind> {
+        match self {
+            ScalarField::Input(kind) => Some(*kind),
+            ScalarField::Bin(TypedBinOp::Typed(HomoBinOp::Min | HomoBinOp::Max), a, b) => {
+                match (a.uniform(), b.uniform()) {
+                    (Some(_), None) => b.clamped_input(),
+                    (None, Some(_)) => a.clamped_input(),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
     pub fn reduce(self) -> Self {
         match self {
-            ScalarField::ScalarUn(op, field) => {
+            ScalarField::ScalarUn(op, field) => match (op, *field) {
+                (op, ScalarField::Uniform(n)) => ScalarField::Uniform(op.operate(n)),
+                // Double negation
+                (
+                    TypedUnOp::Math(MathUnOp::Neg),
+                    ScalarField::ScalarUn(TypedUnOp::Math(MathUnOp::Neg), field),
+                ) => *field,
+                (op, field) => ScalarField::ScalarUn(op, field.into()),
+            },
+            ScalarField::Bin(op, a, b) => match (op, a.uniform(), b.uniform()) {
+                (op, Some(a), Some(b)) => ScalarField::Uniform(op.operate(a, b)),
+                // Identities
+                (TypedBinOp::Typed(HomoBinOp::Add), Some(0.0), None) => *b,
+                (TypedBinOp::Typed(HomoBinOp::Add | HomoBinOp::Sub), None, Some(0.0)) => *a,
+                (TypedBinOp::Hetero(HeteroBinOp::Mul), Some(1.0), None) => *b,
+                (
+                    TypedBinOp::Hetero(HeteroBinOp::Mul | HeteroBinOp::Div | HeteroBinOp::Pow),
+                    None,
+                    Some(1.0),
+                ) => *a,
+                (op, ..) => ScalarField::Bin(op, a, b),
+            },
+            ScalarField::VectorUn(op, field) => {
                 if let Some(n) = field.uniform() {
                     ScalarField::Uniform(op.operate(n))
                 } else {
-                    ScalarField::ScalarUn(op, field)
+                    ScalarField::VectorUn(op, field)
                 }
             }
-            ScalarField::Bin(op, a, b) => {
+            ScalarField::Dot(a, b) => {
                 if let (Some(a), Some(b)) = (a.uniform(), b.uniform()) {
-                    ScalarField::Uniform(op.operate(a, b))
-                } else {
-                    ScalarField::Bin(op, a, b)
-                }
-            }
-            ScalarField::VectorUn(op, field) => {
-                if let Some(n) = field.uniform() {
-                    ScalarField::Uniform(op.operate(n))
+                    ScalarField::Uniform(a.dot(b))
                 } else {
-                    ScalarField::VectorUn(op, field)
+                    ScalarField::Dot(a, b)
                 }
             }
+            ScalarField::Select(condition, a, b) => match condition.uniform() {
+                Some(c) if c > 0.0 => *a,
+                Some(_) => *b,
+                None => ScalarField::Select(condition, a, b),
+            },
             field => field,
         }
     }
@@ -275,22 +404,51 @@ impl ScalarField {
             ScalarField::Index(a, b) => {
                 [a.controls(), b.controls()].into_iter().flatten().collect()
             }
+            ScalarField::Dot(a, b) => [a.controls(), b.controls()].into_iter().flatten().collect(),
+            ScalarField::Select(c, a, b) => [c.controls(), a.controls(), b.controls()]
+                .into_iter()
+                .flatten()
+                .collect(),
             ScalarField::Control(kind) => vec![*kind],
             _ => Vec::new(),
         }
     }
-    pub fn derivative_at(&self, world: &World, pos: Pos2, allow_recursion: bool) -> Vec2 {
-        const RANGE: f32 = 0.1;
-        let left_x = self.sample(world, pos - Vec2::X * RANGE, allow_recursion);
-        let right_x = self.sample(world, pos + Vec2::X * RANGE, allow_recursion);
-        let down_y = self.sample(world, pos - Vec2::Y * RANGE, allow_recursion);
-        let up_y = self.sample(world, pos + Vec2::Y * RANGE, allow_recursion);
-        Vec2::new(right_x - left_x, up_y - down_y) / (2.0 * RANGE)
+    /// Roughly how many nodes are evaluated when sampling the field once
+    pub fn complexity(&self) -> usize {
+        1 + match self {
+            ScalarField::ScalarUn(_, field) => field.complexity(),
+            ScalarField::VectorUn(VectorUnScalarOp::Divergence | VectorUnScalarOp::Curl, field) => {
+                DERIVATIVE_SAMPLES * field.complexity()
+            }
+            ScalarField::VectorUn(_, field) => field.complexity(),
+            ScalarField::Bin(_, a, b) => a.complexity() + b.complexity(),
+            ScalarField::Index(a, b) => a.complexity() + b.complexity(),
+            ScalarField::Dot(a, b) => a.complexity() + b.complexity(),
+            ScalarField::Select(c, a, b) => c.complexity() + a.complexity() + b.complexity(),
+            _ => 0,
+        }
+    }
+    pub fn gradient_at(&self, world: &World, pos: Pos2, allow_recursion: bool) -> Vec2 {
+        // Grid-backed and stepped inputs need a wider range to not read the same cell twice
+        let range = match self {
+            ScalarField::Input(kind) => kind.resolution().max(GRADIENT_RANGE),
+            _ => GRADIENT_RANGE,
+        };
+        let left_x = self.sample(world, pos - Vec2::X * range, allow_recursion);
+        let right_x = self.sample(world, pos + Vec2::X * range, allow_recursion);
+        let down_y = self.sample(world, pos - Vec2::Y * range, allow_recursion);
+        let up_y = self.sample(world, pos + Vec2::Y * range, allow_recursion);
+        Vec2::new(right_x - left_x, up_y - down_y) / (2.0 * range)
     }
 }
 
 impl VectorField {
+    /// Sample the field at a position, always getting a finite value
     pub fn sample(&self, world: &World, pos: Pos2, allow_recursion: bool) -> Vec2 {
+        let v = self.sample_impl(world, pos, allow_recursion);
+        Vec2::new(finite(v.x), finite(v.y))
+    }
+    fn sample_impl(&self, world: &World, pos: Pos2, allow_recursion: bool) -> Vec2 {
         puffin::profile_function!();
         match self {
             VectorField::Uniform(v) => *v,
@@ -298,7 +456,7 @@ impl VectorField {
                 op.operate(field.sample(world, pos, allow_recursion))
             }
             VectorField::ScalarUn(op, field) => match op {
-                ScalarUnVectorOp::Derivative => field.derivative_at(world, pos, allow_recursion),
+                ScalarUnVectorOp::Gradient => field.gradient_at(world, pos, allow_recursion),
             },
             VectorField::BinSV(op, a, b) => op.operate(
                 a.sample(world, pos, allow_recursion),
@@ -317,10 +475,32 @@ impl VectorField {
                 index.sample(world, pos, allow_recursion).to_pos2(),
                 allow_recursion,
             ),
+            VectorField::Rotate(field, angle) => rotate(
+                field.sample(world, pos, allow_recursion),
+                angle.sample(world, pos, allow_recursion),
+            ),
+            VectorField::Select(condition, a, b) => {
+                if condition.sample(world, pos, allow_recursion) > 0.0 {
+                    a.sample(world, pos, allow_recursion)
+                } else {
+                    b.sample(world, pos, allow_recursion)
+                }
+            }
             VectorField::Input(kind) => world.sample_input_vector_field(*kind, pos),
             VectorField::Variable => pos.to_vec2(),
         }
     }
+    /// Get the partial derivatives of the field with respect to x and y
+    pub fn partials_at(&self, world: &World, pos: Pos2, allow_recursion: bool) -> [Vec2; 2] {
+        let left_x = self.sample(world, pos - Vec2::X * GRADIENT_RANGE, allow_recursion);
+        let right_x = self.sample(world, pos + Vec2::X * GRADIENT_RANGE, allow_recursion);
+        let down_y = self.sample(world, pos - Vec2::Y * GRADIENT_RANGE, allow_recursion);
+        let up_y = self.sample(world, pos + Vec2::Y * GRADIENT_RANGE, allow_recursion);
+        [
+            (right_x - left_x) / (2.0 * GRADIENT_RANGE),
+            (up_y - down_y) / (2.0 * GRADIENT_RANGE),
+        ]
+    }
     fn uniform(&self) -> Option<Vec2> {
         match self {
             VectorField::Uniform(v) => Some(*v),
@@ -329,40 +509,83 @@ impl VectorField {
     }
     pub fn reduce(self) -> Self {
         match self {
-            VectorField::VectorUn(op, field) => {
-                if let Some(v) = field.uniform() {
-                    VectorField::Uniform(op.operate(v))
-                } else {
-                    VectorField::VectorUn(op, field)
-                }
-            }
-            VectorField::BinSV(op, a, b) => {
-                if let (Some(a), Some(b)) = (a.uniform(), b.uniform()) {
-                    VectorField::Uniform(op.operate(a, b))
-                } else {
-                    VectorField::BinSV(op, a, b)
-                }
-            }
-            VectorField::BinVV(op, a, b) => {
-                if let (Some(a), Some(b)) = (a.uniform(), b.uniform()) {
-                    VectorField::Uniform(op.operate(a, b))
-                } else {
-                    VectorField::BinVV(op, a, b)
+            VectorField::VectorUn(op, field) => match (op, *field) {
+                (op, VectorField::Uniform(v)) => VectorField::Uniform(op.operate(v)),
+                // Double negation
+                (
+                    TypedUnOp::Math(MathUnOp::Neg),
+                    VectorField::VectorUn(TypedUnOp::Math(MathUnOp::Neg), field),
+                ) => *field,
+                (op, field) => VectorField::VectorUn(op, field.into()),
+            },
+            VectorField::BinSV(op, a, b) => match (op, a.uniform(), b.uniform()) {
+                (op, Some(a), Some(b)) => VectorField::Uniform(op.operate(a, b)),
+                // Identities
+                (TypedBinOp::Hetero(HeteroBinOp::Mul), Some(1.0), None) => *b,
+                (op, ..) => VectorField::BinSV(op, a, b),
+            },
+            VectorField::BinVV(op, a, b) => match (op, a.uniform(), b.uniform()) {
+                (op, Some(a), Some(b)) => VectorField::Uniform(op.operate(a, b)),
+                // Identities
+                (TypedBinOp::Typed(HomoBinOp::Add), Some(v), None) if v == Vec2::ZERO => *b,
+                (TypedBinOp::Typed(HomoBinOp::Add | HomoBinOp::Sub), None, Some(v))
+                    if v == Vec2::ZERO =>
+                {
+                    *a
                 }
+                (op, ..) => VectorField::BinVV(op, a, b),
+            },
+            VectorField::BinVS(op, a, b) => match (op, a.uniform(), b.uniform()) {
+                (op, Some(a), Some(b)) => VectorField::Uniform(op.operate(a, b)),
+                // Identities
+                (
+                    TypedBinOp::Hetero(HeteroBinOp::Mul | HeteroBinOp::Div | HeteroBinOp::Pow),
+                    None,
+                    Some(1.0),
+                ) => *a,
+                (op, ..) => VectorField::BinVS(op, a, b),
+            },
+            // The gradient of a uniform field is zero
+            VectorField::ScalarUn(ScalarUnVectorOp::Gradient, field)
+                if field.uniform().is_some() =>
+            {
+                VectorField::Uniform(Vec2::ZERO)
             }
-            VectorField::BinVS(op, a, b) => {
-                if let (Some(a), Some(b)) = (a.uniform(), b.uniform()) {
-                    VectorField::Uniform(op.operate(a, b))
+            VectorField::Rotate(field, angle) => {
+                if let (Some(v), Some(angle)) = (field.uniform(), angle.uniform()) {
+                    VectorField::Uniform(rotate(v, angle))
                 } else {
-                    VectorField::BinVS(op, a, b)
+                    VectorField::Rotate(field, angle)
                 }
             }
+            VectorField::Select(condition, a, b) => match condition.uniform() {
+                Some(c) if c > 0.0 => *a,
+                Some(_) => *b,
+                None => VectorField::Select(condition, a, b),
+            },
             field => field,
         }
     }
+    /// Roughly how many nodes are evaluated when sampling the field once
+    pub fn complexity(&self) -> usize {
+        1 + match self {
+            VectorField::VectorUn(_, field) => field.complexity(),
+            VectorField::ScalarUn(ScalarUnVectorOp::Gradient, field) => {
+                DERIVATIVE_SAMPLES * field.complexity()
+            }
+            VectorField::BinSV(_, a, b) => a.complexity() + b.complexity(),
+            VectorField::BinVS(_, a, b) => a.complexity() + b.complexity(),
+            VectorField::BinVV(_, a, b) => a.complexity() + b.complexity(),
+            VectorField::Index(a, b) => a.complexity() + b.complexity(),
+            VectorField::Rotate(a, b) => a.complexity() + b.complexity(),
+            VectorField::Select(c, a, b) => c.complexity() + a.complexity() + b.complexity(),
+            _ => 0,
+        }
+    }
     pub fn controls(&self) -> Vec<ControlKind> {
         match self {
             VectorField::VectorUn(_, field) => field.controls(),
+            VectorField::ScalarUn(_, field) => field.controls(),
             VectorField::BinSV(_, a, b) => {
                 [a.controls(), b.controls()].into_iter().flatten().collect()
             }
@@ -375,7 +598,109 @@ impl VectorField {
             VectorField::Index(a, b) => {
                 [a.controls(), b.controls()].into_iter().flatten().collect()
             }
+            VectorField::Rotate(a, b) => {
+                [a.controls(), b.controls()].into_iter().flatten().collect()
+            }
+            VectorField::Select(c, a, b) => [c.controls(), a.controls(), b.controls()]
+                .into_iter()
+                .flatten()
+                .collect(),
             _ => Vec::new(),
         }
     }
 }
+
+#[test]
+fn pow_mod_test() {
+    use crate::player::{Gender, Player};
+    let world = World::new(Player::new("Test".into(), Gender::Enby));
+    let bin = |op: HeteroBinOp, a: ScalarField, b: f32| {
+        ScalarField::Bin(
+            TypedBinOp::Hetero(op),
+            a.into(),
+            ScalarField::Uniform(b).into(),
+        )
+    };
+    let sample = |field: &ScalarField, x: f32| field.sample(&world, Pos2::new(x, 0.0), true);
+    // Power
+    let pow = bin(HeteroBinOp::Pow, ScalarField::X, 2.0);
+    assert_eq!(sample(&pow, 3.0), 9.0);
+    assert_eq!(sample(&pow, -3.0), 9.0);
+    let root = bin(HeteroBinOp::Pow, ScalarField::X, 0.5);
+    assert_eq!(sample(&root, 4.0), 2.0);
+    assert_eq!(sample(&root, -4.0), 0.0);
+    // Modulo
+    let modulo = bin(HeteroBinOp::Mod, ScalarField::X, 2.0);
+    assert_eq!(sample(&modulo, 5.0), 1.0);
+    assert_eq!(sample(&modulo, -0.5), 1.5);
+    let by_zero = bin(HeteroBinOp::Mod, ScalarField::X, 0.0);
+    assert_eq!(sample(&by_zero, 5.0), 5.0);
+}
+
+#[test]
+fn component_round_trip_test() {
+    use crate::player::{Gender, Player};
+    let world = World::new(Player::new("Test".into(), Gender::Enby));
+    let mul = TypedBinOp::Hetero(HeteroBinOp::Mul);
+    let component = |op: ToScalarOp, field: &VectorField| {
+        ScalarField::VectorUn(VectorUnScalarOp::ToScalar(op), field.clone().into())
+    };
+    let field = VectorField::BinSV(
+        mul,
+        ScalarField::X,
+        VectorField::Uniform(Vec2::new(2.0, -1.0)).into(),
+    );
+    let x = component(ToScalarOp::XComponent, &field);
+    let y = component(ToScalarOp::YComponent, &field);
+    let recombined = VectorField::BinVV(
+        TypedBinOp::Typed(HomoBinOp::Add),
+        VectorField::BinSV(mul, x, VectorField::Uniform(Vec2::X).into()).into(),
+        VectorField::BinSV(mul, y, VectorField::Uniform(Vec2::Y).into()).into(),
+    );
+    for i in -5..=5 {
+        let pos = Pos2::new(i as f32, 1.0);
+        assert_eq!(
+            field.sample(&world, pos, true),
+            recombined.sample(&world, pos, true)
+        );
+    }
+}
+
+#[test]
+fn reduce_test() {
+    let uniform = |n: f32| Box::new(ScalarField::Uniform(n));
+    let add = TypedBinOp::Typed(HomoBinOp::Add);
+    let mul = TypedBinOp::Hetero(HeteroBinOp::Mul);
+    let neg = TypedUnOp::Math(MathUnOp::Neg);
+    // Uniform operands fold
+    let field = ScalarField::Bin(
+        add,
+        uniform(1.0),
+        ScalarField::Bin(mul, uniform(2.0), uniform(3.0))
+            .reduce()
+            .into(),
+    )
+    .reduce();
+    assert!(matches!(field, ScalarField::Uniform(n) if n == 7.0));
+    // Identities and double negation disappear
+    let field = ScalarField::Bin(add, uniform(0.0), ScalarField::X.into()).reduce();
+    assert!(matches!(field, ScalarField::X));
+    let field = ScalarField::Bin(mul, ScalarField::X.into(), uniform(1.0)).reduce();
+    assert!(matches!(field, ScalarField::X));
+    let negated = ScalarField::ScalarUn(neg, ScalarField::Y.into()).reduce();
+    let field = ScalarField::ScalarUn(neg, negated.into()).reduce();
+    assert!(matches!(field, ScalarField::Y));
+    // Multiplying by zero is left alone because the other side might not be finite
+    let field = ScalarField::Bin(mul, ScalarField::X.into(), uniform(0.0)).reduce();
+    assert!(matches!(field, ScalarField::Bin(..)));
+    // Vectors
+    let field = VectorField::BinVS(
+        TypedBinOp::Hetero(HeteroBinOp::Div),
+        VectorField::Uniform(Vec2::new(4.0, 2.0)).into(),
+        ScalarField::Bin(add, uniform(1.0), uniform(1.0)).reduce(),
+    )
+    .reduce();
+    assert!(matches!(field, VectorField::Uniform(v) if v == Vec2::new(2.0, 1.0)));
+    let field = VectorField::ScalarUn(ScalarUnVectorOp::Gradient, uniform(5.0)).reduce();
+    assert!(matches!(field, VectorField::Uniform(Vec2::ZERO)));
+}
@@ -1,8 +1,9 @@
-use std::{collections::HashMap, marker::PhantomData, ops::*};
+use std::{collections::HashMap, marker::PhantomData};
 
 use derive_more::{Display, From};
 use eframe::epaint::{vec2, Vec2};
 use enum_iterator::Sequence;
+use serde::{Deserialize, Serialize};
 
 use crate::{error::EidosError, field::*, person::PersonId, stack::Stack};
 
@@ -18,14 +19,28 @@ pub enum Function {
     Nullary(Nullary),
     #[from(types(HeteroBinOp, HomoBinOp))]
     Bin(BinOp),
-    #[from(types(MathUnOp, ScalarUnOp, ToScalarOp, ScalarUnVectorOp))]
+    #[from(types(
+        MathUnOp,
+        ScalarUnOp,
+        ToScalarOp,
+        ScalarUnVectorOp,
+        VectorUnScalarOp,
+        VectorUnVectorOp
+    ))]
     Un(UnOp),
     #[from]
+    Ternary(TernOp),
+    #[from]
     Combinator1(Combinator1),
     #[from]
     Combinator2(Combinator2),
     #[from]
+    Combinator3(Combinator3),
+    #[from]
     Variable(Variable),
+    #[from]
+    Modifier(Modifier),
+    Dispel,
 }
 
 #[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Sequence)]
@@ -34,6 +49,16 @@ pub enum Variable {
     Vector,
 }
 
+/// Modifies the next function that consumes it rather than producing a field
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Sequence)]
+pub enum Modifier {
+    IncludeSelf,
+    Confine,
+    ExcludeSelf,
+    /// Makes the spell last as many seconds as a constant on the stack
+    Duration,
+}
+
 #[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Sequence)]
 pub enum Nullary {
     Zero,
@@ -48,6 +73,7 @@ pub enum Nullary {
     Y,
     TargetX,
     TargetY,
+    Proximity,
 }
 
 impl Nullary {
@@ -65,6 +91,7 @@ impl Nullary {
             Nullary::Y => ScalarField::Y.into(),
             Nullary::TargetX => ScalarField::TargetX(caster).into(),
             Nullary::TargetY => ScalarField::TargetY(caster).into(),
+            Nullary::Proximity => ScalarField::Proximity(caster).into(),
         }
     }
 }
@@ -81,6 +108,12 @@ pub enum Combinator2 {
     Over,
 }
 
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Sequence)]
+pub enum Combinator3 {
+    Rot,
+    UnRot,
+}
+
 pub trait UnOperator<T> {
     type Output;
     fn operate(&self, v: T) -> Self::Output;
@@ -96,20 +129,28 @@ pub enum UnOp {
     ToScalar(ToScalarOp),
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence, Serialize, Deserialize,
+)]
 pub enum TypedUnOp<T> {
     Math(MathUnOp),
     Typed(T),
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence, Serialize, Deserialize,
+)]
 pub enum MathUnOp {
     Neg,
+    #[display(fmt = "|Abs|")]
     Abs,
+    #[display(fmt = "±Sign")]
     Sign,
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence, Serialize, Deserialize,
+)]
 pub enum ScalarUnOp {
     Sin,
     Cos,
@@ -119,25 +160,40 @@ pub enum ScalarUnOp {
     ToScalar(ToScalarOp),
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence, Serialize, Deserialize,
+)]
 pub enum VectorUnScalarOp {
     Length,
+    #[display(fmt = "∇·Divergence")]
+    Divergence,
+    #[display(fmt = "∇×Curl")]
+    Curl,
     ToScalar(ToScalarOp),
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence, Serialize, Deserialize,
+)]
 pub enum ScalarUnVectorOp {
-    Derivative,
+    #[display(fmt = "∇Gradient")]
+    Gradient,
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence, Serialize, Deserialize,
+)]
 pub enum VectorUnVectorOp {
-    Unit,
+    Normalize,
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence, Serialize, Deserialize,
+)]
 pub enum ToScalarOp {
     Magnitude,
+    XComponent,
+    YComponent,
 }
 
 impl<A, T> UnOperator<A> for TypedUnOp<T>
@@ -168,7 +224,10 @@ impl UnOperator<f32> for MathUnOp {
 impl UnOperator<Vec2> for MathUnOp {
     type Output = Vec2;
     fn operate(&self, v: Vec2) -> Self::Output {
-        vec2(self.operate(v.x), self.operate(v.y))
+        match self {
+            MathUnOp::Sign => v.normalized(),
+            _ => vec2(self.operate(v.x), self.operate(v.y)),
+        }
     }
 }
 
@@ -185,6 +244,8 @@ impl UnOperator<f32> for ScalarUnOp {
             ScalarUnOp::Sqrt => v.sqrt(),
             ScalarUnOp::ToScalar(op) => match op {
                 ToScalarOp::Magnitude => v.abs(),
+                ToScalarOp::XComponent => v,
+                ToScalarOp::YComponent => 0.0,
             },
         }
     }
@@ -195,8 +256,12 @@ impl UnOperator<Vec2> for VectorUnScalarOp {
     fn operate(&self, v: Vec2) -> Self::Output {
         match self {
             VectorUnScalarOp::Length => v.length(),
+            // The derivatives of a uniform field are zero
+            VectorUnScalarOp::Divergence | VectorUnScalarOp::Curl => 0.0,
             VectorUnScalarOp::ToScalar(op) => match op {
                 ToScalarOp::Magnitude => v.length(),
+                ToScalarOp::XComponent => v.x,
+                ToScalarOp::YComponent => v.y,
             },
         }
     }
@@ -206,7 +271,7 @@ impl UnOperator<Vec2> for VectorUnVectorOp {
     type Output = Vec2;
     fn operate(&self, v: Vec2) -> Self::Output {
         match self {
-            VectorUnVectorOp::Unit => v.normalized(),
+            VectorUnVectorOp::Normalize => v.normalized(),
         }
     }
 }
@@ -222,23 +287,37 @@ pub enum BinOp {
     Homo(HomoBinOp),
     #[display(fmt = "🔀Index")]
     Index,
+    #[display(fmt = "🔄Rotate")]
+    Rotate,
+    #[display(fmt = "·Dot")]
+    Dot,
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence, Serialize, Deserialize,
+)]
 pub enum TypedBinOp<T> {
     Hetero(HeteroBinOp),
     Typed(T),
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence, Serialize, Deserialize,
+)]
 pub enum HeteroBinOp {
     #[display(fmt = "×")]
     Mul,
     #[display(fmt = "÷")]
     Div,
+    #[display(fmt = "^")]
+    Pow,
+    #[display(fmt = "%")]
+    Mod,
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence, Serialize, Deserialize,
+)]
 pub enum HomoBinOp {
     #[display(fmt = "+")]
     Add,
@@ -250,9 +329,19 @@ pub enum HomoBinOp {
     Max,
 }
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence, Serialize, Deserialize,
+)]
 pub struct NoOp<T>(PhantomData<T>);
 
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+pub enum TernOp {
+    #[display(fmt = "❓Select")]
+    Select,
+    #[display(fmt = "📍Sample")]
+    Sample,
+}
+
 impl<A, B, T> BinOperator<A, B> for TypedBinOp<T>
 where
     HeteroBinOp: BinOperator<A, B, Output = T::Output>,
@@ -277,14 +366,31 @@ impl<A, B, T> BinOperator<A, B> for NoOp<T> {
 impl BinOperator<f32, f32> for HeteroBinOp {
     type Output = f32;
     fn operate(&self, a: f32, b: f32) -> Self::Output {
-        self.homo_operate(a, b)
+        match self {
+            HeteroBinOp::Mul => a * b,
+            HeteroBinOp::Div => a / b,
+            HeteroBinOp::Pow => {
+                if a < 0.0 && b.fract() != 0.0 {
+                    0.0
+                } else {
+                    a.powf(b)
+                }
+            }
+            HeteroBinOp::Mod => {
+                if b == 0.0 {
+                    a
+                } else {
+                    a.rem_euclid(b)
+                }
+            }
+        }
     }
 }
 
 impl BinOperator<Vec2, Vec2> for HeteroBinOp {
     type Output = Vec2;
     fn operate(&self, a: Vec2, b: Vec2) -> Self::Output {
-        self.homo_operate(a, b)
+        vec2(self.operate(a.x, b.x), self.operate(a.y, b.y))
     }
 }
 
@@ -302,21 +408,6 @@ impl BinOperator<Vec2, f32> for HeteroBinOp {
     }
 }
 
-impl HeteroBinOp {
-    fn homo_operate<T>(&self, a: T, b: T) -> T
-    where
-        T: Add<Output = T>,
-        T: Sub<Output = T>,
-        T: Mul<Output = T>,
-        T: Div<Output = T>,
-    {
-        match self {
-            HeteroBinOp::Mul => a * b,
-            HeteroBinOp::Div => a / b,
-        }
-    }
-}
-
 impl BinOperator<f32, f32> for HomoBinOp {
     type Output = f32;
     fn operate(&self, a: f32, b: f32) -> Self::Output {
@@ -384,6 +475,66 @@ impl ValueConstraint {
 }
 
 impl Function {
+    /// Describe what the function takes from the top of the stack and what it leaves there
+    ///
+    /// Letters stand for fields of any type and `T` for fields that must share a type.
+    pub fn signature(&self) -> String {
+        let (inputs, outputs): (&[&str], &[&str]) = match self {
+            Function::ReadField(InputFieldKind::Scalar(_)) => (&[], &["scalar"]),
+            Function::ReadField(InputFieldKind::Vector(_)) => (&[], &["vector"]),
+            Function::WriteField(OutputFieldKind::Scalar(_)) => (&["scalar"], &["spell"]),
+            Function::WriteField(OutputFieldKind::Vector(_)) => (&["vector"], &["spell"]),
+            Function::Control(_) => (&[], &["scalar"]),
+            Function::Nullary(Nullary::ZeroVector | Nullary::OneX | Nullary::OneY) => {
+                (&[], &["vector"])
+            }
+            Function::Nullary(_) => (&[], &["scalar"]),
+            Function::Bin(op) => match op {
+                BinOp::Math(_) => (&["a", "b"], &["vector unless both are scalars"]),
+                BinOp::Homo(_) => (&["T", "T"], &["T"]),
+                BinOp::Index => (&["vector", "a"], &["a"]),
+                BinOp::Rotate => (&["vector", "scalar"], &["vector"]),
+                BinOp::Dot => (&["vector", "vector"], &["scalar"]),
+            },
+            Function::Un(op) => match op {
+                UnOp::Math(_) => (&["a"], &["a"]),
+                UnOp::Scalar(_) => (&["scalar"], &["scalar"]),
+                UnOp::ScalarVector(_) => (&["scalar"], &["vector"]),
+                UnOp::VectorScalar(_) => (&["vector"], &["scalar"]),
+                UnOp::VectorVector(_) => (&["vector"], &["vector"]),
+                UnOp::ToScalar(_) => (&["a"], &["scalar"]),
+            },
+            Function::Ternary(op) => match op {
+                TernOp::Select => (&["T", "T", "scalar"], &["T"]),
+                TernOp::Sample => (&["scalar", "scalar", "scalar"], &["scalar"]),
+            },
+            Function::Combinator1(com) => match com {
+                Combinator1::Duplicate => (&["a"], &["a", "a"]),
+                Combinator1::Drop => (&["a"], &[]),
+            },
+            Function::Combinator2(com) => match com {
+                Combinator2::Swap => (&["a", "b"], &["b", "a"]),
+                Combinator2::Over => (&["a", "b"], &["a", "b", "a"]),
+            },
+            Function::Combinator3(com) => match com {
+                Combinator3::Rot => (&["a", "b", "c"], &["b", "c", "a"]),
+                Combinator3::UnRot => (&["a", "b", "c"], &["c", "a", "b"]),
+            },
+            Function::Variable(Variable::Scalar) => (&[], &["scalar"]),
+            Function::Variable(Variable::Vector) => (&[], &["vector"]),
+            Function::Modifier(Modifier::Duration) => (&["scalar"], &["modifier"]),
+            Function::Modifier(_) => (&[], &["modifier"]),
+            Function::Dispel => (&[], &[]),
+        };
+        let side = |names: &[&str]| {
+            if names.is_empty() {
+                "nothing".into()
+            } else {
+                names.join(" ")
+            }
+        };
+        format!("{} → {}", side(inputs), side(outputs))
+    }
     pub fn validate_use(&self, stack: &Stack) -> Result<(), EidosError> {
         // Collect constraints
         use TypeConstraint::*;
@@ -399,6 +550,7 @@ impl Function {
             },
             Function::Combinator1(_) => vec![Any],
             Function::Combinator2(_) => vec![Any; 2],
+            Function::Combinator3(_) => vec![Any; 3],
             Function::Un(op) => match op {
                 UnOp::Math(_) => vec![Any],
                 UnOp::Scalar(_) | UnOp::ScalarVector(_) => {
@@ -418,8 +570,24 @@ impl Function {
                     Constrain(ValueConstraint::Group(0)),
                 ],
                 BinOp::Index => vec![Constrain(ValueConstraint::Exact(Type::Vector)), Any],
+                BinOp::Rotate => vec![
+                    Constrain(ValueConstraint::Exact(Type::Vector)),
+                    Constrain(ValueConstraint::Exact(Type::Scalar)),
+                ],
+                BinOp::Dot => vec![Constrain(ValueConstraint::Exact(Type::Vector)); 2],
+            },
+            Function::Ternary(op) => match op {
+                TernOp::Select => vec![
+                    Constrain(ValueConstraint::Group(0)),
+                    Constrain(ValueConstraint::Group(0)),
+                    Constrain(ValueConstraint::Exact(Type::Scalar)),
+                ],
+                TernOp::Sample => vec![Constrain(ValueConstraint::Exact(Type::Scalar)); 3],
             },
-            Function::Variable(_) => vec![],
+            Function::Modifier(Modifier::Duration) => {
+                vec![Constrain(ValueConstraint::Exact(Type::Scalar))]
+            }
+            Function::Variable(_) | Function::Modifier(_) | Function::Dispel => vec![],
         };
         // Validate stack size
         if stack.len() < constraints.len() {
@@ -447,6 +615,50 @@ impl Function {
                 });
             }
         }
+        // Validate that durations are constant
+        if let Function::Modifier(Modifier::Duration) = self {
+            let item = stack.iter().last().unwrap();
+            if !matches!(&item.field, Field::Scalar(f) if f.uniform().is_some()) {
+                return Err(EidosError::NonUniformArgument {
+                    function: *self,
+                    position: 1,
+                });
+            }
+        }
+        // Validate that coordinates are constant
+        if let Function::Ternary(TernOp::Sample) = self {
+            for (i, item) in stack.iter().rev().take(2).enumerate() {
+                if !matches!(&item.field, Field::Scalar(f) if f.uniform().is_some()) {
+                    return Err(EidosError::NonUniformArgument {
+                        function: *self,
+                        position: 3 - i,
+                    });
+                }
+            }
+        }
+        // Validate that the resulting field is not too slow to sample
+        let (arity, multiplier) = match self {
+            Function::Un(
+                UnOp::ScalarVector(ScalarUnVectorOp::Gradient)
+                | UnOp::VectorScalar(VectorUnScalarOp::Divergence | VectorUnScalarOp::Curl),
+            ) => (1, DERIVATIVE_SAMPLES),
+            Function::Un(_) => (1, 1),
+            Function::Bin(_) => (2, 1),
+            Function::Ternary(TernOp::Select) => (3, 1),
+            _ => (0, 1),
+        };
+        if arity > 0 {
+            let from_args: usize = (stack.iter().rev().take(arity))
+                .map(|item| item.field.complexity())
+                .sum();
+            let complexity = 1 + multiplier * from_args;
+            if complexity > MAX_FIELD_COMPLEXITY {
+                return Err(EidosError::TooComplex {
+                    function: *self,
+                    complexity,
+                });
+            }
+        }
         Ok(())
     }
 }
@@ -1,22 +1,42 @@
-use std::{collections::BTreeSet, time::Instant};
+use std::{collections::BTreeSet, mem::take, path::PathBuf, time::Instant};
 
-use eframe::egui::{style::Margin, *};
+use anyhow::{anyhow, bail};
+use eframe::{
+    egui::{style::Margin, *},
+    epaint::util::hash,
+};
 use enum_iterator::all;
 use indexmap::IndexMap;
 use itertools::Itertools;
+use tracing::{info, warn, Level};
 
 use crate::{
-    color::Color,
+    audio::{clear_audio_cache, play_music, play_sfx, play_sfx_pitched, stop_music},
+    camera::Camera,
+    color::{Color, Palette, ScalarScheme},
+    conduit::ConduitStone,
     controls::{apply_color_fading, FadeButton},
-    dialog::DialogState,
+    dialog::{background_fade_id, DialogHistory, DialogState, DIALOG_SCENES},
     field::*,
-    function::Function,
-    image::{image_plot, ImagePlotKind},
-    person::PersonId,
+    function::{Function, MathUnOp, TypedUnOp},
+    gamepad::{begin_nav_frame, Gamepad, GamepadNavigable},
+    image::{clear_image_caches, image_plot, Background, ImagePlotKind},
+    keybindings::{Action, KeyBinding, Keybindings},
+    logging::{clear_recent_logs, recent_logs},
+    npc::NpcId,
+    object::Region,
+    person::{PersonId, SpellBounds},
     player::Player,
     plot::*,
+    quest::QUESTS,
+    replay::{recording_message, ReplayMode},
+    save::default_save_path,
+    settings::{set_settings, settings, Settings, TICK_RATES},
+    snapshot::WorldSnapshot,
+    spellbook::SpellbookEntry,
     word::*,
-    world::{Controls, World},
+    world::{Controls, World, WorldEvent},
+    world_view::{NPC_COLOR, PLAYER_COLOR},
     GameState,
 };
 
@@ -25,18 +45,41 @@ pub struct Game {
     pub ui_state: UiState,
     last_time: Instant,
     ticker: f32,
+    /// How many times the world updated during the last frame
+    updates_per_frame: usize,
+    /// The world state saved with the quicksave key
+    quicksave: Option<WorldSnapshot>,
+    /// The replay being recorded or played back
+    pub replay: Option<ReplayMode>,
+    gamepad: Gamepad,
+    /// How far between the last two world steps fields are drawn
+    render_alpha: f32,
+    /// Where the game is saved, or `None` for sessions that must not touch a save
+    pub save_path: Option<PathBuf>,
 }
 
 impl Game {
     pub fn new(player: Player) -> Self {
-        let mut game = Game {
+        let mut game = Game::from_player(player);
+        game.set_dialog("intro");
+        game
+    }
+    /// Create a game without starting the intro
+    pub fn from_player(player: Player) -> Self {
+        let mut ui_state = UiState::default();
+        ui_state.load_layout();
+        Game {
             world: World::new(player),
-            ui_state: UiState::default(),
+            ui_state,
             last_time: Instant::now(),
             ticker: 0.0,
-        };
-        game.set_dialog("intro");
-        game
+            updates_per_frame: 0,
+            quicksave: None,
+            replay: None,
+            gamepad: Gamepad::new(),
+            render_alpha: 1.0,
+            save_path: Some(default_save_path()),
+        }
     }
 }
 
@@ -46,13 +89,64 @@ pub struct UiState {
     last_stack_len: usize,
     paused: bool,
     next_player_target: Option<Pos2>,
-    pub background: Option<String>,
+    /// The world position under the cursor in a field window this frame
+    hovered_field_pos: Option<Pos2>,
+    pub background: Option<Background>,
+    /// The background being faded out
+    pub previous_background: Option<Background>,
+    /// The place the UI last showed
+    place: String,
+    /// How dark the screen is while changing places or after dying
+    fade: f32,
+    pub keybindings: Keybindings,
+    /// Whether the controls window is open
+    controls_open: bool,
+    /// The action waiting for a key to be bound to it
+    rebinding: Option<Action>,
+    /// The result of the last save
+    save_message: Option<String>,
+    /// Short messages shown in the corner
+    toasts: Vec<(String, Instant)>,
+    spellbook_open: bool,
+    /// The name for the next spellbook entry
+    new_spell_name: String,
+    /// The index of the spellbook entry being edited
+    editing_spell: Option<usize>,
+    /// The index of the conduit stone being renamed
+    renaming_stone: Option<usize>,
+    /// The index of the conduit stone waiting for erase confirmation
+    erasing_stone: Option<usize>,
+    /// The spell code being imported
+    spell_code: String,
+    spell_code_error: Option<String>,
+    pub dialog_history: DialogHistory,
+    /// Whether the dialog history window is open
+    pub history_open: bool,
+    /// A dialog scene to start once the current one ends
+    pub queued_dialog: Option<String>,
+    /// The npc whose dialog was in range last frame
+    npc_in_range: Option<NpcId>,
+    /// Whether to outline sensors on field plots, for place authors
+    show_sensors: bool,
+    pub camera: Camera,
+    settings_open: bool,
+    active_spells_open: bool,
+    /// The index of the stack item being dragged
+    dragged_stack_item: Option<usize>,
 }
 
 pub struct FieldDisplay {
     pub visible: bool,
     pub pos: Vec2,
     pub size: f32,
+    /// How far the display's view is moved from the camera's center, in world units
+    pub center_offset: Vec2,
+    /// How many times closer the display's view is than the camera's
+    pub zoom: f32,
+    /// Whether to show what the display's colors mean
+    pub legend: bool,
+    pub scalar_mode: ScalarPlotMode,
+    pub vector_mode: VectorPlotMode,
 }
 
 #[allow(clippy::derivable_impls)]
@@ -64,7 +158,32 @@ impl Default for UiState {
             last_stack_len: 0,
             paused: false,
             next_player_target: None,
+            hovered_field_pos: None,
             background: None,
+            previous_background: None,
+            place: String::new(),
+            fade: 0.0,
+            keybindings: Keybindings::load(),
+            controls_open: false,
+            rebinding: None,
+            dialog_history: DialogHistory::default(),
+            history_open: false,
+            queued_dialog: None,
+            npc_in_range: None,
+            show_sensors: false,
+            camera: Camera::default(),
+            settings_open: false,
+            active_spells_open: false,
+            dragged_stack_item: None,
+            save_message: None,
+            toasts: Vec::new(),
+            spellbook_open: false,
+            new_spell_name: String::new(),
+            editing_spell: None,
+            renaming_stone: None,
+            erasing_stone: None,
+            spell_code: String::new(),
+            spell_code_error: None,
         }
     }
 }
@@ -86,8 +205,17 @@ impl UiState {
             visible: true,
             pos: vec2(x, y),
             size: 0.35,
+            center_offset: Vec2::ZERO,
+            zoom: 1.0,
+            legend: true,
+            scalar_mode: ScalarPlotMode::default(),
+            vector_mode: VectorPlotMode::default(),
         }
     }
+    /// Show a short message in the corner
+    pub fn toast(&mut self, message: impl Into<String>) {
+        self.toasts.push((message.into(), Instant::now()));
+    }
     pub fn field_display(&mut self, kind: FieldKind) -> &mut FieldDisplay {
         if !self.fields_display.contains_key(&kind) {
             self.fields_display
@@ -98,6 +226,27 @@ impl UiState {
 }
 
 const SMALL_PLOT_SIZE: f32 = 100.0;
+const MAX_FIELD_DISPLAY_ZOOM: f32 = 16.0;
+/// The most that zooming into a field display increases its precision
+const MAX_ZOOM_PRECISION_SCALE: f32 = 1.5;
+/// Why a stack can't be etched after items were removed or reordered by hand
+const REARRANGED_ETCH_MESSAGE: &str = "A rearranged stack can't be etched";
+/// The largest the minimap can be in points
+const MINIMAP_MAX_SIZE: Vec2 = vec2(180.0, 120.0);
+/// How long toasts are shown in seconds
+const TOAST_DURATION: f32 = 5.0;
+/// How long it takes for the screen to fade in after changing places
+const PLACE_FADE_TIME: f32 = 0.5;
+/// How long the mana bar is in points per point of max mana
+const MANA_BAR_WIDTH_PER_MANA: f32 = 6.0;
+/// The longest the mana bar gets in points, however much max mana there is
+const MAX_MANA_BAR_WIDTH: f32 = 600.0;
+/// How long the mana bar takes to grow after an upgrade in seconds
+const MANA_BAR_GROW_TIME: f32 = 1.0;
+/// How long the health bar is in points
+const HEALTH_BAR_WIDTH: f32 = 150.0;
+/// The most time in seconds the world catches up on in one frame
+const MAX_TICKER: f32 = 0.25;
 
 impl Game {
     pub fn show(&mut self, ctx: &Context) -> Option<GameState> {
@@ -105,24 +254,48 @@ impl Game {
 
         let mut res = None;
 
+        // Read the gamepad, whose sliders and activations only change when it is used
+        let pad = self.gamepad.poll();
+        begin_nav_frame(ctx, pad.navigate, pad.confirm);
+        let controls = &mut self.world.controls;
+        controls.x_slider = pad.x_slider.or(controls.x_slider);
+        controls.y_slider = pad.y_slider.or(controls.y_slider);
+        controls.activation1 = pad.activation1.unwrap_or(controls.activation1);
+        controls.activation2 = pad.activation2.unwrap_or(controls.activation2);
+
         // Set player target
         self.world.player.person.target = self.ui_state.next_player_target.take();
+        self.ui_state.hovered_field_pos = None;
 
         // Set animation time
+        let animation_time = settings().animation_time;
         let mut style = (*ctx.style()).clone();
-        style.animation_time = 2.0;
+        style.animation_time = animation_time.unwrap_or(2.0);
         ctx.set_style(style.clone());
 
         // Show central UI
         let rect = ctx.available_rect();
         CentralPanel::default().show(ctx, |ui| {
-            // Show background
-            ui.allocate_ui_at_rect(rect, |ui| {
-                if let Some(background) = &self.ui_state.background {
-                    let max_size = ui.available_size_before_wrap();
-                    image_plot(ui, background, max_size, ImagePlotKind::Background);
+            // Show background, crossfading from the previous one
+            if !settings().plot.world_view {
+                let fade = ctx.animate_bool(background_fade_id(), true);
+                let time = ctx.input(|input| input.time) as f32;
+                for (background, opacity) in [
+                    (&self.ui_state.previous_background, 1.0 - fade),
+                    (&self.ui_state.background, fade),
+                ] {
+                    let Some(image) = (background.as_ref())
+                        .filter(|_| opacity > 0.0)
+                        .and_then(|background| background.image_at(time))
+                    else {
+                        continue;
+                    };
+                    ui.allocate_ui_at_rect(rect, |ui| {
+                        let max_size = ui.available_size_before_wrap();
+                        image_plot(ui, &image, max_size, ImagePlotKind::Background(opacity));
+                    });
                 }
-            });
+            }
             // Show top bar and fields
             ui.allocate_ui_at_rect(rect.shrink(10.0), |ui| {
                 self.top_ui(ui);
@@ -130,13 +303,22 @@ impl Game {
             });
         });
 
+        // Show minimap
+        if settings().show_minimap {
+            self.minimap_ui(ctx);
+        }
+
+        // Bind the next key pressed
+        self.capture_rebinding(ctx);
+
         // Show pause menu
-        if ctx.input(|input| input.key_pressed(Key::Escape)) {
+        if pad.pause || ctx.input(|input| self.ui_state.keybindings.pressed(input, Action::Pause)) {
             self.ui_state.paused = !self.ui_state.paused;
+            self.ui_state.save_message = None;
         }
 
         // Set animation time
-        style.animation_time = 0.5;
+        style.animation_time = animation_time.unwrap_or(0.5);
         ctx.set_style(style.clone());
 
         SidePanel::right("pause")
@@ -155,16 +337,88 @@ impl Game {
                 {
                     self.ui_state.paused = false;
                 }
+                if ui
+                    .selectable_label(false, RichText::new("Save").heading())
+                    .clicked()
+                {
+                    self.ui_state.save_message = Some(match self.save() {
+                        Ok(()) => "Saved".into(),
+                        Err(e) => format!("Unable to save: {e}"),
+                    });
+                }
+                if ui
+                    .selectable_label(
+                        self.ui_state.history_open,
+                        RichText::new("History").heading(),
+                    )
+                    .clicked()
+                {
+                    self.ui_state.history_open = !self.ui_state.history_open;
+                }
+                if ui
+                    .selectable_label(
+                        self.ui_state.controls_open,
+                        RichText::new("Controls").heading(),
+                    )
+                    .clicked()
+                {
+                    self.ui_state.controls_open = !self.ui_state.controls_open;
+                }
+                if ui
+                    .selectable_label(
+                        self.ui_state.settings_open,
+                        RichText::new("Settings").heading(),
+                    )
+                    .clicked()
+                {
+                    self.ui_state.settings_open = !self.ui_state.settings_open;
+                }
+                let recording = matches!(self.replay, Some(ReplayMode::Recording(_)));
+                if ui
+                    .selectable_label(recording, RichText::new("Record").heading())
+                    .on_hover_text("Restarts the place from its saved state and records from there")
+                    .clicked()
+                {
+                    self.ui_state.save_message = Some(if recording {
+                        self.finish_recording()
+                            .map(recording_message)
+                            .unwrap_or_default()
+                    } else {
+                        match self.start_recording() {
+                            Ok(()) => "Recording".into(),
+                            Err(e) => format!("Unable to record: {e}"),
+                        }
+                    });
+                }
                 if ui
                     .selectable_label(false, RichText::new("Main Menu").heading())
                     .clicked()
                 {
+                    if let Some(message) = self.finish_recording().map(recording_message) {
+                        info!("{message}");
+                    }
                     res = Some(GameState::MainMenu);
                 }
+                if let Some(message) = &self.ui_state.save_message {
+                    ui.label(message);
+                }
+                self.journal_ui(ui);
+            });
+
+        SidePanel::right("active spells")
+            .resizable(false)
+            .min_width(200.0)
+            .frame(Frame {
+                inner_margin: Margin::same(20.0),
+                fill: style.visuals.faint_bg_color,
+                ..Frame::side_top_panel(&style)
+            })
+            .show_animated(ctx, self.ui_state.active_spells_open, |ui| {
+                ScrollArea::vertical().show(ui, |ui| self.active_spells_ui(ui));
             });
 
         // Set animation time
-        style.animation_time = 2.0;
+        style.animation_time = animation_time.unwrap_or(2.0);
         ctx.set_style(style);
 
         // Show bottom UIs
@@ -199,47 +453,278 @@ impl Game {
                     .ui_state
                     .dialog
                     .as_ref()
-                    .map_or(false, |dialog| dialog.speakers_ui(ui));
+                    .is_some_and(|dialog| dialog.speakers_ui(ui));
                 if !showed_speakers_ui {
                     self.stack_ui(ui);
                 }
             });
 
         // Update world
+        self.world.particles.enabled = settings().particles;
+        // Replays keep the update length they were recorded with
+        let dt = (self.replay.as_ref()).map_or_else(|| settings().tick_dt(), ReplayMode::dt);
+        self.world.physics.set_dt(dt);
+        // Drop time beyond a limit, so that a long frame doesn't make the next one longer
+        self.ticker = self.ticker.min(MAX_TICKER);
+        self.updates_per_frame = 0;
         while self.ticker >= self.world.physics.dt() {
-            self.world.update();
+            self.update_world();
             self.ticker -= self.world.physics.dt();
+            self.updates_per_frame += 1;
+        }
+        // Fields are drawn between the last two steps until the next update
+        self.render_alpha = self.ticker / self.world.physics.dt();
+        self.handle_world_events();
+        let dt = ctx.input(|input| input.stable_dt);
+        self.ui_state.camera.update(&self.world, dt);
+
+        self.spellbook_ui(ctx);
+        self.resource_errors_ui(ctx);
+        self.log_ui(ctx);
+        self.dialog_history_ui(ctx);
+        self.keybindings_ui(ctx);
+        self.settings_ui(ctx);
+
+        // Quicksave, quickload and other keys that work anywhere
+        if ctx.memory(|mem| mem.focus().is_none()) {
+            let keybindings = &self.ui_state.keybindings;
+            let pressed = |action| ctx.input(|input| keybindings.pressed(input, action));
+            let (quicksave, reload, show_sensors, quickload, carry) = (
+                pressed(Action::Quicksave),
+                pressed(Action::ReloadResources),
+                pressed(Action::ShowSensors),
+                pressed(Action::Quickload),
+                pressed(Action::Carry),
+            );
+            if quicksave {
+                self.quicksave = Some(self.world.snapshot());
+                self.ui_state.toast("Quicksaved");
+            }
+            if reload {
+                self.reload_resources();
+            }
+            if show_sensors {
+                self.ui_state.show_sensors = !self.ui_state.show_sensors;
+            }
+            if quickload {
+                if let Some(snapshot) = &self.quicksave {
+                    self.world.restore(snapshot);
+                    self.ui_state.toast("Quickloaded");
+                }
+            }
+            if carry {
+                self.carry();
+            }
+        }
+
+        // Talk to nearby npcs
+        // Auto dialogs only start on getting close, so they don't restart as soon as they end
+        let in_range = self.world.npc_dialog_in_range();
+        let approached =
+            in_range.is_some_and(|(npc_id, _)| self.ui_state.npc_in_range != Some(npc_id));
+        self.ui_state.npc_in_range = in_range.map(|(npc_id, _)| npc_id);
+        if self.ui_state.dialog.is_none() {
+            if let Some((_, dialog)) = in_range {
+                let talk = dialog.auto && approached
+                    || ctx.memory(|mem| mem.focus().is_none())
+                        && ctx
+                            .input(|input| self.ui_state.keybindings.pressed(input, Action::Talk));
+                if talk {
+                    let scene = dialog.scene.clone();
+                    self.world
+                        .player
+                        .progression
+                        .seen_dialogs
+                        .insert(scene.clone());
+                    self.set_dialog(&scene);
+                }
+            }
+        }
+
+        // Show toasts
+        (self.ui_state.toasts)
+            .retain(|(_, created)| created.elapsed().as_secs_f32() < TOAST_DURATION);
+        if !self.ui_state.toasts.is_empty() {
+            Area::new("toasts")
+                .anchor(Align2::RIGHT_TOP, vec2(-20.0, 40.0))
+                .show(ctx, |ui| {
+                    for (message, _) in &self.ui_state.toasts {
+                        Frame::popup(ui.style()).show(ui, |ui| ui.label(message));
+                    }
+                });
+        }
+
+        // Fade in after changing places
+        if self.ui_state.place != self.world.place {
+            if !self.ui_state.place.is_empty() {
+                self.ui_state.fade = 1.0;
+            }
+            self.ui_state.place = self.world.place.clone();
+            self.ui_state.camera.snap();
+            let place = self.world.resources.places.get(&self.world.place);
+            if let Some(background) = place.and_then(|place| place.background.clone()) {
+                self.ui_state.background = Some(background);
+                self.ui_state.previous_background = None;
+            }
+            match place.and_then(|place| place.music.as_deref()) {
+                Some(music) => play_music(music, true),
+                None => stop_music(),
+            }
+        }
+        if self.ui_state.fade > 0.0 {
+            let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("place fade")));
+            let alpha = (self.ui_state.fade * 255.0) as u8;
+            painter.rect_filled(ctx.screen_rect(), 0.0, Color32::from_black_alpha(alpha));
+            let dt = ctx.input(|input| input.stable_dt);
+            self.ui_state.fade = (self.ui_state.fade - dt / PLACE_FADE_TIME).max(0.0);
+            ctx.request_repaint();
         }
 
         res
     }
+    /// Show the whole place from afar, and aim at wherever it is clicked
+    fn minimap_ui(&mut self, ctx: &Context) {
+        puffin::profile_function!();
+        let world = &self.world;
+        let world_rect = world.max_rect();
+        let scale = (MINIMAP_MAX_SIZE / world_rect.size()).min_elem();
+        let to_map = |rect: Rect, pos: Pos2| {
+            rect.left_top() + vec2(pos.x - world_rect.min.x, world_rect.max.y - pos.y) * scale
+        };
+        let to_map_rect = |rect: Rect, world: Rect| {
+            Rect::from_two_pos(to_map(rect, world.min), to_map(rect, world.max))
+        };
+        let Some(place) = world.resources.places.get(&world.place) else {
+            return;
+        };
+        let clicked = Area::new("minimap")
+            .anchor(Align2::RIGHT_BOTTOM, vec2(-10.0, -10.0))
+            .show(ctx, |ui| {
+                Frame::popup(ui.style())
+                    .show(ui, |ui| {
+                        let (rect, response) =
+                            ui.allocate_exact_size(world_rect.size() * scale, Sense::click());
+                        let painter = ui.painter_at(rect);
+                        // Place bounds
+                        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+                        // Transitions, with the one the player is in highlighted
+                        let active = world.player_transition_region();
+                        for (i, transition) in place.transitions.iter().enumerate() {
+                            let alpha = if active == Some(i) { 160 } else { 60 };
+                            let color = Color32::from_rgba_unmultiplied(80, 220, 120, alpha);
+                            match &transition.region {
+                                Region::Rect { min, max } => painter.rect_filled(
+                                    to_map_rect(rect, Rect::from_two_pos(*min, *max)),
+                                    0.0,
+                                    color,
+                                ),
+                                Region::Circle { center, radius } => painter.circle_filled(
+                                    to_map(rect, *center),
+                                    radius * scale,
+                                    color,
+                                ),
+                            }
+                        }
+                        // Fixed objects as their bounds and dynamic objects as dots
+                        for obj in world.objects.values() {
+                            if obj.def.ty.is_fixed() {
+                                if let Some(bounds) = world.object_bounds(obj) {
+                                    let bounds = to_map_rect(rect, bounds.intersect(world_rect));
+                                    painter.rect_filled(bounds, 1.0, Color32::GRAY);
+                                }
+                            } else {
+                                let pos = to_map(rect, obj.pr.pos);
+                                painter.circle_filled(pos, 1.5, Color32::LIGHT_GRAY);
+                            }
+                        }
+                        // The part of the place the field plots show
+                        let camera = &self.ui_state.camera;
+                        let view = Rect::from_center_size(
+                            camera.center,
+                            Vec2::splat(camera.range(world) * 2.0),
+                        );
+                        let stroke = Stroke::new(1.0, ui.visuals().strong_text_color());
+                        painter.rect_stroke(to_map_rect(rect, view), 0.0, stroke);
+                        // People
+                        for npc in world.npcs.values() {
+                            painter.circle_filled(to_map(rect, npc.person.pos), 3.0, NPC_COLOR);
+                        }
+                        let player_pos = to_map(rect, world.player.person.pos);
+                        painter.circle_filled(player_pos, 4.0, PLAYER_COLOR);
+                        let response = response.on_hover_text("Click to aim here");
+                        response
+                            .clicked()
+                            .then(|| response.interact_pointer_pos())
+                            .flatten()
+                            .map(|pos| {
+                                let offset = (pos - rect.left_top()) / scale;
+                                pos2(world_rect.min.x + offset.x, world_rect.max.y - offset.y)
+                            })
+                    })
+                    .inner
+            })
+            .inner;
+        if let Some(pos) = clicked {
+            self.ui_state.next_player_target = Some(pos);
+        }
+    }
     fn top_ui(&mut self, ui: &mut Ui) {
         puffin::profile_function!();
         ui.horizontal(|ui| {
             // Mana bar
             ui.scope(|ui| {
-                let reserved = self.world.player.person.reserved_mana();
-                let capped = self.world.person(PersonId::Player).max_mana - reserved;
+                let max_mana = self.world.player.person.max_mana;
+                let reserved = self.world.player.person.reserved_mana().min(max_mana);
+                let capped = self.world.player.person.capped_mana().max(0.0);
                 let color = Rgba::from_rgb(0.1, 0.1, 0.9).into();
                 ui.visuals_mut().selection.bg_fill = color;
                 let id = ui.make_persistent_id("mana bar");
                 let length_mul = ui
                     .ctx()
                     .animate_bool(id, self.world.player.progression.mana_bar);
+                // The bar grows with max mana, so upgrades can be seen landing
+                let shown_max_mana =
+                    ui.ctx()
+                        .animate_value_with_time(id.with("max"), max_mana, MANA_BAR_GROW_TIME);
+                let width =
+                    (shown_max_mana * MANA_BAR_WIDTH_PER_MANA).min(MAX_MANA_BAR_WIDTH) * length_mul;
+                let per_mana = width / max_mana.max(1.0);
                 if length_mul > 0.0 {
                     ProgressBar::new(1.0)
-                        .text(format!("{capped:.0}"))
-                        .desired_width(capped * 10.0 * length_mul)
+                        .text(format!("{capped:.0} / {max_mana:.0}"))
+                        .desired_width(capped * per_mana)
                         .ui(ui);
                     if reserved > 0.0 {
                         ui.visuals_mut().selection.bg_fill = Rgba::from_rgb(0.2, 0.2, 0.9).into();
                         ProgressBar::new(1.0)
                             .text(format!("{reserved:.0}"))
-                            .desired_width(reserved * 10.0 * length_mul)
+                            .desired_width(reserved * per_mana)
                             .ui(ui);
                     }
                 }
+            })
+            .response
+            .on_hover_ui(|ui| self.mana_breakdown_ui(ui));
+            // Health bar
+            ui.scope(|ui| {
+                let person = &self.world.player.person;
+                ui.visuals_mut().selection.bg_fill = Rgba::from_rgb(0.8, 0.1, 0.1).into();
+                let id = ui.make_persistent_id("health bar");
+                let length_mul = ui
+                    .ctx()
+                    .animate_bool(id, self.world.player.progression.health_bar);
+                if length_mul > 0.0 {
+                    ProgressBar::new((person.health / person.max_health).clamp(0.0, 1.0))
+                        .text(format!("{:.0}", person.health))
+                        .desired_width(HEALTH_BAR_WIDTH * length_mul)
+                        .ui(ui);
+                }
             });
+            // Active spells toggle
+            if self.world.player.progression.mana_bar {
+                ui.toggle_value(&mut self.ui_state.active_spells_open, "✨")
+                    .on_hover_text("Active spells");
+            }
             // Fps
             let now = Instant::now();
             let dt = (now - self.last_time).as_secs_f32();
@@ -247,7 +732,13 @@ impl Game {
                 self.ticker += dt;
             }
             self.last_time = now;
-            ui.small(format!("{} fps", (1.0 / dt).round()));
+            if settings().show_fps {
+                ui.small(format!(
+                    "{} fps, {} updates/frame",
+                    (1.0 / dt).round(),
+                    self.updates_per_frame
+                ));
+            }
         });
     }
     fn fields_ui(&mut self, ui: &mut Ui) {
@@ -258,6 +749,8 @@ impl Game {
         let mut drag_released = None;
         let mut hovered = Vec::new();
         let mut double_clicked = Vec::new();
+        let mut panned = Vec::new();
+        let panning = ui.input(|input| input.modifiers.ctrl);
         // Input fields
         for kind in all::<InputFieldKind>() {
             let known = self.world.player.progression.known_fields.contains(&kind);
@@ -275,9 +768,12 @@ impl Game {
                     Vec2::splat(size),
                 );
                 ui.allocate_ui_at_rect(plot_rect, |ui| {
-                    let plot_resp = self.plot_io_field(ui, size, alpha, kind);
+                    let plot_resp =
+                        self.interpolated(|game| game.plot_io_field(ui, size, alpha, kind));
                     if plot_resp.response.double_clicked_by(PointerButton::Middle) {
                         double_clicked.push(kind);
+                    } else if plot_resp.response.dragged_by(PointerButton::Middle) && panning {
+                        panned.push((kind, plot_resp.response.drag_delta() / size));
                     } else if plot_resp.response.dragged_by(PointerButton::Middle) {
                         dragged.push((kind, plot_resp.response.drag_delta()));
                     } else if plot_resp.response.drag_released() {
@@ -291,12 +787,17 @@ impl Game {
         }
         // Output fields
         for output_kind in all::<OutputFieldKind>() {
-            let player_person = &self.world.player.person;
-            if player_person.active_spells.contains(output_kind) {
+            if self
+                .world
+                .people()
+                .any(|person| person.active_spells.contains(output_kind))
+            {
                 let kind = FieldKind::from(output_kind);
                 let display = self.ui_state.field_display(kind);
-                if display.visible && player_person.active_spells.spell_words(output_kind).len() > 0
-                {
+                let spell_count: usize = (self.world.people())
+                    .map(|person| person.active_spells.spell_words(output_kind).len())
+                    .sum();
+                if display.visible && spell_count > 0 {
                     let size = full_rect.size().min_elem() * display.size;
                     let center = full_rect.min + display.pos * full_rect.size();
                     let plot_rect = Rect::from_min_max(
@@ -305,20 +806,36 @@ impl Game {
                     );
                     ui.allocate_ui_at_rect(plot_rect, |ui| {
                         ui.horizontal_wrapped(|ui| {
-                            let plot_resp = self.plot_io_field(ui, size, 1.0, kind);
+                            let plot_resp =
+                                self.interpolated(|game| game.plot_io_field(ui, size, 1.0, kind));
                             let player_person = &mut self.world.player.person;
                             let words = player_person.active_spells.spell_words(output_kind);
                             let mut to_dispel = None;
                             for (i, words) in words.enumerate() {
-                                if Self::spell_words_ui(ui, words, size, true) {
+                                let lifetime = player_person.active_spells.lifetime(output_kind, i);
+                                if Self::spell_words_ui(ui, words, lifetime, size, true) {
                                     to_dispel = Some(i);
                                 }
                             }
                             if let Some(i) = to_dispel {
-                                player_person.active_spells.remove(output_kind, i);
+                                play_sfx("dispel.ogg");
+                                self.world.dispel_player_spell(output_kind, i);
+                            }
+                            // Npc spells can't be dispelled from here
+                            for npc in self.world.npcs.values() {
+                                let active_spells = &npc.person.active_spells;
+                                for (i, words) in active_spells.spell_words(output_kind).enumerate()
+                                {
+                                    let lifetime = active_spells.lifetime(output_kind, i);
+                                    Self::spell_words_ui(ui, words, lifetime, size, false);
+                                }
                             }
                             if plot_resp.response.double_clicked_by(PointerButton::Middle) {
                                 double_clicked.push(kind);
+                            } else if plot_resp.response.dragged_by(PointerButton::Middle)
+                                && panning
+                            {
+                                panned.push((kind, plot_resp.response.drag_delta() / size));
                             } else if plot_resp.response.dragged_by(PointerButton::Middle) {
                                 dragged.push((kind, plot_resp.response.drag_delta()));
                             } else if plot_resp.response.drag_released() {
@@ -341,14 +858,16 @@ impl Game {
                         continue;
                     }
                     let kind = FieldKind::from(kind);
-                    let enabled = &mut self.ui_state.field_display(kind).visible;
-                    ui.toggle_value(enabled, kind.to_string());
+                    let display = self.ui_state.field_display(kind);
+                    Self::field_toggle_ui(ui, kind, display);
                 }
                 for output_kind in all::<OutputFieldKind>() {
-                    if self.world.player.person.active_spells.contains(output_kind) {
+                    if (self.world.people())
+                        .any(|person| person.active_spells.contains(output_kind))
+                    {
                         let kind = FieldKind::from(output_kind);
-                        let enabled = &mut self.ui_state.field_display(kind).visible;
-                        ui.toggle_value(enabled, kind.to_string());
+                        let display = self.ui_state.field_display(kind);
+                        Self::field_toggle_ui(ui, kind, display);
                     }
                 }
             });
@@ -361,9 +880,19 @@ impl Game {
         if let Some((kind, delta)) = dragged.pop() {
             self.ui_state.fields_display.get_mut(&kind).unwrap().pos += delta / full_rect.size();
         }
+        // Pan and zoom the view of a field display
+        if let Some((kind, delta)) = panned.pop() {
+            let range = self.ui_state.camera.range(&self.world);
+            let display = self.ui_state.fields_display.get_mut(&kind).unwrap();
+            display.center_offset += vec2(-delta.x, delta.y) * 2.0 * range / display.zoom;
+        }
         if let Some(kind) = hovered.pop() {
-            let size = &mut self.ui_state.fields_display.get_mut(&kind).unwrap().size;
+            let display = self.ui_state.fields_display.get_mut(&kind).unwrap();
+            let size = &mut display.size;
             *size = (*size + ui.input(|input| input.scroll_delta.y) / 1000.0).clamp(0.1, 1.0);
+            // Ctrl and the scroll wheel zoom
+            let zoom = ui.input(|input| input.zoom_delta());
+            display.zoom = (display.zoom * zoom).clamp(1.0, MAX_FIELD_DISPLAY_ZOOM);
         }
         if let Some(kind) = drag_released {
             let pos = &mut self.ui_state.fields_display.get_mut(&kind).unwrap().pos;
@@ -371,20 +900,54 @@ impl Game {
             pos.y = (pos.y * 20.0).round() / 20.0;
         }
     }
-    fn spell_words_ui(ui: &mut Ui, words: &[Word], max_height: f32, can_dispel: bool) -> bool {
+    /// Show a button that toggles a field display, with its options in a context menu
+    fn field_toggle_ui(ui: &mut Ui, kind: FieldKind, display: &mut FieldDisplay) {
+        (ui.toggle_value(&mut display.visible, kind.to_string())).context_menu(|ui| {
+            ui.checkbox(&mut display.legend, "Legend");
+            ui.separator();
+            match kind {
+                FieldKind::Scalar(_) => {
+                    for mode in all::<ScalarPlotMode>() {
+                        ui.radio_value(&mut display.scalar_mode, mode, mode.to_string());
+                    }
+                }
+                FieldKind::Vector(_) => {
+                    for mode in all::<VectorPlotMode>() {
+                        ui.radio_value(&mut display.vector_mode, mode, mode.to_string());
+                    }
+                }
+            }
+        });
+    }
+    /// Show a spell's words, with how long it has left if it has a duration
+    ///
+    /// Returns whether it was dispelled
+    fn spell_words_ui(
+        ui: &mut Ui,
+        words: &[Word],
+        lifetime: Option<f32>,
+        max_height: f32,
+        can_dispel: bool,
+    ) -> bool {
         puffin::profile_function!();
         let font_id = &ui.style().text_styles[&TextStyle::Body];
         let row_height = ui.fonts(|input| input.row_height(font_id));
         let vert_spacing = ui.spacing().item_spacing.y;
         const MARGIN: f32 = 4.0;
         ui.vertical(|ui| {
-            let (dispel_height, dispelled) = if can_dispel {
-                let resp = ui.button("Dispel");
-                (resp.rect.height(), resp.clicked())
-            } else {
-                (0.0, false)
-            };
-            let non_word_space = max_height - dispel_height - vert_spacing - MARGIN * 2.0;
+            let buttons = ui.horizontal(|ui| {
+                let dispelled = can_dispel && ui.button("Dispel").clicked();
+                if ui.small_button("📋").on_hover_text("Copy spell").clicked() {
+                    ui.output_mut(|output| output.copied_text = spell_code(words));
+                }
+                if let Some(lifetime) = lifetime {
+                    ui.small(format!("⏳{:.0}s", lifetime.ceil()))
+                        .on_hover_text("Time until the spell fades");
+                }
+                dispelled
+            });
+            let (buttons_height, dispelled) = (buttons.response.rect.height(), buttons.inner);
+            let non_word_space = max_height - buttons_height - vert_spacing - MARGIN * 2.0;
             let words_per_column =
                 ((non_word_space / (row_height + vert_spacing)).ceil() as usize).max(1);
             if words.len() < words_per_column {
@@ -418,22 +981,174 @@ impl Game {
         })
         .inner
     }
+    /// Explain where the player's mana is going
+    fn mana_breakdown_ui(&self, ui: &mut Ui) {
+        const SHOWN_WORDS: usize = 3;
+        let person = &self.world.player.person;
+        Grid::new("mana breakdown").show(ui, |ui| {
+            ui.label("Max mana");
+            ui.label(format!("{:.1}", person.max_mana));
+            ui.end_row();
+            // Mana spent on work comes back over time
+            if person.mana < person.max_mana {
+                ui.label("Current mana");
+                ui.label(format!("{:.1}", person.mana));
+                ui.end_row();
+            }
+            for reservation in person.reservation_breakdown() {
+                let mut words = reservation.words.iter().take(SHOWN_WORDS).join(" ");
+                if reservation.words.len() > SHOWN_WORDS {
+                    words.push_str(" …");
+                }
+                ui.label(format!("{}: {words}", reservation.kind));
+                ui.label(format!("-{:.1}", reservation.mana));
+                ui.end_row();
+            }
+            let from_stack: f32 = person.stack.words().map(|word| word.cost()).sum();
+            if from_stack > 0.0 {
+                ui.label("Stack");
+                ui.label(format!("-{from_stack:.1}"));
+                ui.end_row();
+            }
+            ui.label("Available");
+            ui.label(format!(
+                "{:.1}",
+                self.world.available_mana(PersonId::Player)
+            ));
+            ui.end_row();
+            ui.label("Regeneration");
+            ui.label(format!(
+                "+{:.2}/s",
+                self.world.mana_regen_rate(PersonId::Player)
+            ));
+            ui.end_row();
+        });
+        if self.world.available_mana(PersonId::Player) < person.capped_mana() {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "Too deep under water to draw mana",
+            );
+        }
+    }
+    /// List the player's active spells and the npc spells affecting them
+    fn active_spells_ui(&mut self, ui: &mut Ui) {
+        puffin::profile_function!();
+        const SPELL_WORDS_HEIGHT: f32 = 80.0;
+        let player_person = &mut self.world.player.person;
+        let reserved = player_person.reserved_mana();
+        let max_mana = player_person.max_mana;
+        ui.heading("Active spells");
+        ProgressBar::new((reserved / max_mana).clamp(0.0, 1.0))
+            .text(format!("{reserved:.0} / {max_mana:.0} reserved"))
+            .ui(ui);
+        let mut to_dispel = None;
+        for kind in all::<OutputFieldKind>() {
+            for (i, words) in player_person.active_spells.spell_words(kind).enumerate() {
+                ui.separator();
+                let cost: f32 = words.iter().map(|word| word.cost()).sum();
+                let affects_caster = player_person.active_spells.affects_caster(kind, i);
+                let work = player_person.active_spells.work(kind, i);
+                ui.horizontal(|ui| {
+                    ui.strong(kind.to_string());
+                    ui.label(format!("{cost:.1} mana"));
+                    if work > 0.0 {
+                        ui.label(format!("{work:.0} J"))
+                            .on_hover_text("Work done on objects since it was cast");
+                    }
+                    if !affects_caster {
+                        ui.label("🛡").on_hover_text("Doesn't affect you");
+                    }
+                });
+                let lifetime = player_person.active_spells.lifetime(kind, i);
+                if Self::spell_words_ui(ui, words, lifetime, SPELL_WORDS_HEIGHT, true) {
+                    to_dispel = Some((kind, i));
+                }
+            }
+        }
+        if let Some((kind, i)) = to_dispel {
+            play_sfx("dispel.ogg");
+            self.world.dispel_player_spell(kind, i);
+        }
+        // Npc spells that reach the player are shown for awareness, but can't be dispelled
+        let player_pos = self.world.player.person.pos;
+        ui.add_enabled_ui(false, |ui| {
+            for (id, npc) in &self.world.npcs {
+                for kind in all::<OutputFieldKind>() {
+                    for words in npc.person.active_spells.spell_words_at(kind, player_pos) {
+                        ui.separator();
+                        ui.horizontal(|ui| {
+                            ui.strong(kind.to_string());
+                            ui.label(format!("from {id:?}"));
+                        });
+                        Self::spell_words_ui(ui, words, None, SPELL_WORDS_HEIGHT, false);
+                    }
+                }
+            }
+        });
+    }
     fn stack_ui(&mut self, ui: &mut Ui) {
         puffin::profile_function!();
         ScrollArea::horizontal().show(ui, |ui| {
             ui.horizontal(|ui| {
                 ui.allocate_exact_size(vec2(0.0, SMALL_PLOT_SIZE), Sense::hover());
-                for item in self.world.player.person.stack.iter().collect_vec() {
-                    let plot_resp = self.plot_stack_field(ui, SMALL_PLOT_SIZE, 1.0, &item.field);
-                    Self::handle_plot_response_impl(
-                        ui,
-                        &mut self.ui_state,
-                        &mut self.world.controls,
-                        plot_resp,
-                    );
-                    Self::spell_words_ui(ui, &item.words, SMALL_PLOT_SIZE, false);
+                // Items can be removed with a right click and reordered by middle dragging
+                let can_rearrange = self.world.player.progression.rearrange
+                    && (self.ui_state.dialog.as_ref()).is_none_or(|dialog| dialog.allows_casting());
+                let mut item_rects = Vec::new();
+                let mut to_remove = None;
+                let mut dropped = None;
+                let items = self.world.player.person.stack.iter().cloned().collect_vec();
+                for (i, item) in items.into_iter().enumerate() {
+                    let plot_resp = self.interpolated(|game| {
+                        game.plot_stack_field(ui, SMALL_PLOT_SIZE, 1.0, &item.field)
+                    });
+                    let response = plot_resp.response.clone();
+                    // Clicks on stack plots rearrange the stack instead of activating spells
+                    // when rearranging is allowed
+                    let controls = (!can_rearrange).then_some(&mut self.world.controls);
+                    Self::handle_plot_response_impl(ui, &mut self.ui_state, controls, plot_resp);
+                    if can_rearrange {
+                        // Plots only sense drags, so clicks are read from the pointer
+                        let right_clicked = ui
+                            .input(|input| input.pointer.button_clicked(PointerButton::Secondary));
+                        if response.hovered() && right_clicked {
+                            to_remove = Some(i);
+                        }
+                        if response.drag_started_by(PointerButton::Middle) {
+                            self.ui_state.dragged_stack_item = Some(i);
+                        }
+                        if response.drag_released() {
+                            dropped = response.interact_pointer_pos();
+                        }
+                        if self.ui_state.dragged_stack_item == Some(i) {
+                            ui.painter().rect_stroke(
+                                response.rect,
+                                4.0,
+                                ui.visuals().selection.stroke,
+                            );
+                        }
+                    }
+                    item_rects.push(response.rect);
+                    Self::spell_words_ui(ui, &item.words, None, SMALL_PLOT_SIZE, false);
+                }
+                if let Some(i) = to_remove {
+                    self.world.remove_stack_item(i);
+                }
+                if let Some(pos) = dropped {
+                    let from = self.ui_state.dragged_stack_item.take();
+                    let to = item_rects.iter().position(|rect| rect.contains(pos));
+                    if let Some((from, to)) = from.zip(to).filter(|(from, to)| from != to) {
+                        self.world.swap_stack_items(from, to);
+                    }
+                }
+                if !ui.input(|input| input.pointer.middle_down()) {
+                    self.ui_state.dragged_stack_item = None;
                 }
                 let stack = &self.world.player.person.stack;
+                let modifiers = stack.modifier_words().collect_vec();
+                if !modifiers.is_empty() {
+                    Self::spell_words_ui(ui, &modifiers, None, SMALL_PLOT_SIZE, false);
+                }
                 if self.ui_state.last_stack_len != stack.len() {
                     ui.scroll_to_cursor(None);
                     self.ui_state.last_stack_len = stack.len();
@@ -446,41 +1161,414 @@ impl Game {
         ui.horizontal_top(|ui| {
             self.words_grid(ui);
             self.conduit_ui(ui);
+            if self.world.player.progression.conduit {
+                ui.toggle_value(&mut self.ui_state.spellbook_open, "📖")
+                    .on_hover_text("Spellbook");
+            }
         });
     }
+    /// React to things that happened during the world's updates
+    fn handle_world_events(&mut self) {
+        // The queue is taken and given back so that it keeps its allocation
+        let mut events = take(&mut self.world.events);
+        for event in events.drain(..) {
+            match event {
+                WorldEvent::SpellCast { .. } => play_sfx("cast.ogg"),
+                WorldEvent::SpellsDispelled { .. } => play_sfx("dispel.ogg"),
+                WorldEvent::SpellsExpired {
+                    caster: PersonId::Player,
+                } => play_sfx("dispel.ogg"),
+                WorldEvent::SpellsDestabilized { caster } => {
+                    play_sfx("dispel.ogg");
+                    if caster == PersonId::Player {
+                        self.ui_state.toast("Your spell destabilized");
+                    }
+                }
+                WorldEvent::PlayerDied => {
+                    self.ui_state.fade = 1.0;
+                    self.ui_state.camera.snap();
+                    self.ui_state.toast("You blacked out");
+                    let place = self.world.resources.places.get(&self.world.place);
+                    if let Some(scene) = place.and_then(|place| place.on_death.clone()) {
+                        if DIALOG_SCENES.contains_key(&scene) {
+                            self.ui_state.queued_dialog = Some(scene);
+                        } else {
+                            warn!(place = self.world.place, "Unknown death dialog {scene}");
+                        }
+                    }
+                }
+                _ => {}
+            }
+            // Complete quest steps triggered by the event
+            // Quests from saves may no longer exist, which is warned about when loading
+            let completed = (self.world.player.progression.quests.iter())
+                .filter_map(|quest| Some((quest, QUESTS.get(&quest.id)?)))
+                .flat_map(|(quest, def)| {
+                    (quest.steps.iter().zip(&def.steps).enumerate())
+                        .filter(|(_, (step, def))| {
+                            !step.done && def.trigger().is_some_and(|when| when.matches(&event))
+                        })
+                        .map(|(i, _)| (quest.id.clone(), i))
+                })
+                .collect_vec();
+            for (id, step) in completed {
+                self.complete_quest_step(&id, step);
+            }
+            // Start the first unseen dialog the event triggers in this place
+            let progression = &mut self.world.player.progression;
+            let triggered = (self.world.resources.places.get(&self.world.place))
+                .into_iter()
+                .flat_map(|place| &place.triggers)
+                .find(|trigger| {
+                    trigger.when.matches(&event)
+                        && !progression.seen_dialogs.contains(&trigger.scene)
+                });
+            if let Some(trigger) = triggered {
+                progression.seen_dialogs.insert(trigger.scene.clone());
+                self.ui_state.queued_dialog = Some(trigger.scene.clone());
+            }
+        }
+        self.world.events = events;
+    }
+    fn journal_ui(&self, ui: &mut Ui) {
+        let quests = &self.world.player.progression.quests;
+        if quests.is_empty() {
+            return;
+        }
+        ui.separator();
+        ui.heading("Journal");
+        // Active quests are listed before completed ones
+        let (completed, active): (Vec<_>, Vec<_>) =
+            quests.iter().partition(|quest| quest.is_complete());
+        for quest in active.into_iter().chain(completed) {
+            if quest.is_complete() {
+                ui.label(RichText::new(format!("☑ {}", quest.title)).weak());
+                continue;
+            }
+            ui.label(RichText::new(&quest.title).strong());
+            for step in &quest.steps {
+                let check = if step.done { "☑" } else { "☐" };
+                ui.label(format!("{check} {}", step.text));
+            }
+        }
+    }
+    fn resource_errors_ui(&mut self, ctx: &Context) {
+        let errors = &self.world.resources.errors;
+        if errors.is_empty() {
+            return;
+        }
+        let mut reload = false;
+        Window::new("Resource errors")
+            .default_width(400.0)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for error in &errors.errors {
+                        let file = match error.location {
+                            Some((line, column)) => format!("{}:{line}:{column}", error.file),
+                            None => error.file.clone(),
+                        };
+                        ui.label(RichText::new(file).monospace().strong());
+                        ui.colored_label(ui.visuals().error_fg_color, &error.message);
+                        ui.separator();
+                    }
+                });
+                reload = ui.button("Reload resources").clicked();
+            });
+        if reload {
+            self.reload_resources();
+        }
+    }
+    /// Show recent warnings and errors
+    fn log_ui(&mut self, ctx: &Context) {
+        let logs = recent_logs();
+        if logs.is_empty() {
+            return;
+        }
+        let mut clear = false;
+        Window::new(format!("Log ({})", logs.len()))
+            .id(Id::new("log"))
+            .default_open(false)
+            .default_width(400.0)
+            .show(ctx, |ui| {
+                ScrollArea::vertical()
+                    .max_height(300.0)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for entry in &logs {
+                            let color = if entry.level == Level::ERROR {
+                                ui.visuals().error_fg_color
+                            } else {
+                                ui.visuals().warn_fg_color
+                            };
+                            ui.colored_label(color, &entry.message);
+                        }
+                    });
+                clear = ui.button("Clear").clicked();
+            });
+        if clear {
+            clear_recent_logs();
+        }
+    }
+    fn reload_resources(&mut self) {
+        self.world.reload_resources();
+        clear_image_caches();
+        clear_audio_cache();
+        // Show the place's background again without fading
+        self.ui_state.place.clear();
+        self.ui_state.toast("Reloaded resources");
+    }
+    fn spellbook_ui(&mut self, ctx: &Context) {
+        let mut open = self.ui_state.spellbook_open;
+        Window::new("Spellbook")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                // Write down the stack
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.ui_state.new_spell_name)
+                        .on_hover_text("Name");
+                    let stack = &self.world.player.person.stack;
+                    let name = self.ui_state.new_spell_name.trim();
+                    let rearranged = stack.is_rearranged();
+                    let can_write = !stack.is_empty() && !rearranged && !name.is_empty();
+                    let mut resp = ui.add_enabled(can_write, Button::new("Write stack"));
+                    if rearranged {
+                        resp = resp.on_disabled_hover_text(REARRANGED_ETCH_MESSAGE);
+                    }
+                    if resp.clicked() {
+                        self.world.player.spellbook.entries.push(SpellbookEntry {
+                            name: name.into(),
+                            words: stack.words().collect(),
+                            note: String::new(),
+                        });
+                        self.ui_state.new_spell_name.clear();
+                    }
+                });
+                ui.separator();
+                // Entries
+                let results: Vec<_> = (self.world.player.spellbook.entries.iter())
+                    .map(|entry| self.world.preview_player_words(&entry.words).1)
+                    .collect();
+                let mut to_remove = None;
+                let mut to_cast = None;
+                Grid::new("spellbook").show(ui, |ui| {
+                    let entries = &mut self.world.player.spellbook.entries;
+                    for (i, (entry, res)) in entries.iter_mut().zip(results).enumerate() {
+                        if self.ui_state.editing_spell == Some(i) {
+                            ui.vertical(|ui| {
+                                ui.text_edit_singleline(&mut entry.name);
+                                ui.text_edit_multiline(&mut entry.note);
+                            });
+                        } else {
+                            let button = Button::new(&entry.name);
+                            let on_hover = |ui: &mut Ui| {
+                                ui.label(spell_code(&entry.words));
+                                if !entry.note.is_empty() {
+                                    ui.label(&entry.note);
+                                }
+                                if let Err(err) = &res {
+                                    ui.label(err.to_string());
+                                }
+                            };
+                            if res.is_ok() {
+                                if button.ui(ui).on_hover_ui(on_hover).clicked() {
+                                    to_cast = Some(entry.words.clone());
+                                }
+                            } else {
+                                ui.add_enabled(false, button).on_disabled_hover_ui(on_hover);
+                            }
+                        }
+                        let editing = self.ui_state.editing_spell == Some(i);
+                        if ui
+                            .selectable_label(editing, "✏")
+                            .on_hover_text("Edit")
+                            .clicked()
+                        {
+                            self.ui_state.editing_spell = (!editing).then_some(i);
+                        }
+                        if ui.button("📋").on_hover_text("Copy spell").clicked() {
+                            ui.output_mut(|output| output.copied_text = spell_code(&entry.words));
+                        }
+                        if ui.button("🗑").on_hover_text("Erase").clicked() {
+                            to_remove = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(words) = to_cast {
+                    self.world.push_player_words(&words);
+                }
+                if let Some(i) = to_remove {
+                    self.world.player.spellbook.entries.remove(i);
+                    self.ui_state.editing_spell = None;
+                }
+                ui.separator();
+                // Import
+                ui.label("Spell code");
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.ui_state.spell_code);
+                    if ui.button("Cast").clicked() {
+                        let code = self.ui_state.spell_code.clone();
+                        self.ui_state.spell_code_error =
+                            self.cast_spell_code(&code).err().map(|e| e.to_string());
+                    }
+                });
+                if let Some(error) = &self.ui_state.spell_code_error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            });
+        self.ui_state.spellbook_open = open;
+    }
+    /// Say the words of a spell code, only once all of them are known to be sayable
+    fn cast_spell_code(&mut self, code: &str) -> anyhow::Result<()> {
+        let words = parse_spell_code(code)?;
+        // Spells cast or dispelled partway through can't be taken back,
+        // so the whole code is said on a copy of the stack first
+        let mut preview = self.world.player.person.stack.clone();
+        let mut cost = 0.0;
+        for &word in &words {
+            if !self.world.player.progression.known_words.contains(&word) {
+                bail!("You don't know {word}");
+            }
+            cost += word.cost();
+            if self.world.available_mana(PersonId::Player) < cost {
+                bail!("Not enough mana to say {word}");
+            }
+            (preview.say(&self.world, PersonId::Player, word, None))
+                .map_err(|e| anyhow!("Unable to say {word}: {e}"))?;
+        }
+        let stack = self.world.player.person.stack.clone();
+        let input_count = self.world.inputs.len();
+        let res = words.into_iter().try_for_each(|word| {
+            (self.world.say(PersonId::Player, word))
+                .map_err(|e| anyhow!("Unable to say {word}: {e}"))
+        });
+        if res.is_err() {
+            self.world.player.person.stack = stack;
+            if self.world.inputs.len() > input_count {
+                self.note_unrecorded("spell codes that fail partway");
+            }
+        }
+        res
+    }
     fn conduit_ui(&mut self, ui: &mut Ui) {
         if !self.world.player.progression.conduit {
             return;
         }
+        let results: Vec<_> = (self.world.player.person.rack.conduits)
+            .iter()
+            .map(|stone| self.world.preview_player_words(&stone.words).1)
+            .collect();
+        let mut to_swap = None;
+        let mut to_cast = None;
+        let mut etched = false;
         Grid::new("conduits").show(ui, |ui| {
-            for stone in &mut self.world.player.person.rack.conduits {
-                let mut stack = self.world.player.person.stack.clone();
+            let conduits = &mut self.world.player.person.rack.conduits;
+            let count = conduits.len();
+            for (i, (stone, res)) in conduits.iter_mut().zip(results).enumerate() {
                 let button = Button::new(stone.format(16));
-                let mut res = Ok(());
-                for word in &stone.words {
-                    res = stack.say(PersonId::Player, *word, None);
-                    if res.is_err() {
-                        break;
-                    }
-                }
                 let on_hover = |ui: &mut Ui| {
-                    ui.label(stone.format(usize::MAX));
+                    ui.label(stone.format_words(usize::MAX));
+                    if let Err(err) = &res {
+                        ui.label(err.to_string());
+                    }
                 };
-                if res.is_ok() {
+                if self.ui_state.renaming_stone == Some(i) {
+                    let resp = TextEdit::singleline(&mut stone.name)
+                        .desired_width(100.0)
+                        .ui(ui);
+                    if resp.lost_focus() {
+                        self.ui_state.renaming_stone = None;
+                    } else if !resp.has_focus() {
+                        resp.request_focus();
+                    }
+                } else if res.is_ok() {
                     if button.ui(ui).on_hover_ui(on_hover).clicked() {
-                        self.world.player.person.stack = stack;
+                        to_cast = Some(stone.words.clone());
                     }
                 } else {
                     ui.add_enabled(false, button).on_disabled_hover_ui(on_hover);
                 }
-                let can_add = !self.world.player.person.stack.is_empty();
-                if ui.add_enabled(can_add, Button::new("+")).clicked() {
+                let stack = &self.world.player.person.stack;
+                let can_add = !stack.is_empty() && !stack.is_rearranged();
+                if (ui.add_enabled(can_add, Button::new("+")))
+                    .on_disabled_hover_text(REARRANGED_ETCH_MESSAGE)
+                    .clicked()
+                {
                     stone.etch(self.world.player.person.stack.words());
-                    self.world.player.person.stack.clear();
+                    etched = true;
+                }
+                let can_copy = !stone.words.is_empty();
+                if (ui.add_enabled(can_copy, Button::new("📋")))
+                    .on_hover_text("Copy spell")
+                    .clicked()
+                {
+                    ui.output_mut(|output| output.copied_text = spell_code(&stone.words));
+                }
+                // Stone management
+                ui.menu_button("…", |ui| {
+                    if ui.button("Rename").clicked() {
+                        self.ui_state.renaming_stone = Some(i);
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(i > 0, Button::new("Move up")).clicked() {
+                        to_swap = Some((i, i - 1));
+                        ui.close_menu();
+                    }
+                    if (ui.add_enabled(i + 1 < count, Button::new("Move down"))).clicked() {
+                        to_swap = Some((i, i + 1));
+                        ui.close_menu();
+                    }
+                    // Erasing only clears the stone, it does not dispel anything cast from it
+                    if self.ui_state.erasing_stone == Some(i) {
+                        ui.label("Erase this stone?");
+                        ui.horizontal(|ui| {
+                            if ui.button("Erase").clicked() {
+                                stone.erase();
+                                self.ui_state.erasing_stone = None;
+                                ui.close_menu();
+                            }
+                            if ui.button("Keep").clicked() {
+                                self.ui_state.erasing_stone = None;
+                                ui.close_menu();
+                            }
+                        });
+                    } else if (ui.add_enabled(!stone.words.is_empty(), Button::new("Erase")))
+                        .clicked()
+                    {
+                        self.ui_state.erasing_stone = Some(i);
+                    }
+                });
+                ui.end_row();
+            }
+            // Empty slots
+            let slots = self.world.player.progression.conduit_slots;
+            for _ in conduits.len()..slots {
+                ui.add_enabled(false, Button::new("..."));
+                let stack = &self.world.player.person.stack;
+                let can_add = !stack.is_empty() && !stack.is_rearranged();
+                if (ui.add_enabled(can_add, Button::new("+")))
+                    .on_hover_text("Inscribe a new stone")
+                    .on_disabled_hover_text(REARRANGED_ETCH_MESSAGE)
+                    .clicked()
+                {
+                    let mut stone = ConduitStone::default();
+                    stone.etch(stack.words());
+                    conduits.push(stone);
+                    etched = true;
                 }
                 ui.end_row();
             }
         });
+        if let Some(words) = to_cast {
+            self.world.push_player_words(&words);
+        }
+        if etched {
+            self.world.free_stack();
+        }
+        if let Some((a, b)) = to_swap {
+            self.world.player.person.rack.conduits.swap(a, b);
+        }
     }
     fn words_grid(&mut self, ui: &mut Ui) {
         Grid::new("words").min_col_width(10.0).show(ui, |ui| {
@@ -489,8 +1577,8 @@ impl Game {
                 .ui_state
                 .dialog
                 .as_ref()
-                .map_or(true, |dialog| dialog.allows_casting());
-            let available_mana = self.world.player.person.capped_mana();
+                .is_none_or(|dialog| dialog.allows_casting());
+            let available_mana = self.world.available_mana(PersonId::Player);
             // Rows
             for (i, row) in WORD_GRID.iter().enumerate() {
                 // Words in the row
@@ -498,30 +1586,73 @@ impl Game {
                     let player_person = &self.world.player.person;
                     let f = word.function();
                     let known = self.world.player.progression.known_words.contains(word);
+                    let validation = player_person.stack.validate_function_use(f);
                     let enabled = dialog_allows_casting
                         && known
-                        && player_person.stack.validate_function_use(f).is_ok()
+                        && validation.is_ok()
                         && available_mana >= word.cost();
+                    let hilight = matches!(f, Function::WriteField(_));
+                    let max_mana = player_person.max_mana;
+                    let predicted = hilight.then(|| player_person.predicted_reservation(*word));
+                    // Writing releases the rest of the stack, so it may need less than it costs
+                    let capped = player_person.capped_mana();
+                    let reserved = player_person.reserved_mana();
+                    let over_budget =
+                        predicted.is_some_and(|predicted| predicted - reserved > capped);
                     ui.scope(|ui| {
-                        let hilight = matches!(f, Function::WriteField(_));
-                        if enabled {
+                        if known && over_budget {
+                            ui.visuals_mut().override_text_color = Some(ui.visuals().warn_fg_color);
+                        } else if enabled {
                             ui.visuals_mut().override_text_color =
                                 word.text_color().map(Into::into);
                         }
                         let button =
                             FadeButton::new(word, known, word.to_string()).hilight(hilight);
-                        if ui.add_enabled(enabled, button).clicked() {
-                            let player_person = &mut self.world.player.person;
-                            let mut say = || {
-                                player_person
-                                    .stack
-                                    .say(
-                                        PersonId::Player,
-                                        *word,
-                                        Some(&mut player_person.active_spells),
-                                    )
-                                    .err()
-                            };
+                        let disabled_reason = if !known {
+                            Some("You don't know this word yet".to_string())
+                        } else if !dialog_allows_casting {
+                            Some("You can't cast right now".into())
+                        } else if let Err(err) = &validation {
+                            Some(err.to_string())
+                        } else if available_mana < word.cost() {
+                            Some(format!(
+                                "Not enough mana: needs {:.1}, but only {available_mana:.1} is available",
+                                word.cost()
+                            ))
+                        } else {
+                            None
+                        };
+                        let tooltip = |ui: &mut Ui| {
+                            if known {
+                                ui.horizontal(|ui| {
+                                    ui.strong(format!("{word}: {f}"));
+                                    ui.label(format!("{:.1} mana", word.cost()));
+                                });
+                                ui.monospace(f.signature());
+                                if let Some(description) = word.description() {
+                                    ui.label(description);
+                                }
+                            }
+                            if let Some(predicted) = predicted.filter(|_| known) {
+                                let text = format!(
+                                    "Casting will reserve {predicted:.1} of {max_mana:.1} mana"
+                                );
+                                if over_budget {
+                                    ui.colored_label(ui.visuals().warn_fg_color, text);
+                                } else {
+                                    ui.label(text);
+                                }
+                            }
+                            if let Some(reason) = &disabled_reason {
+                                ui.colored_label(ui.visuals().warn_fg_color, reason);
+                            }
+                        };
+                        let resp = (ui.add_enabled(enabled, button).gamepad_nav())
+                            .on_hover_ui(tooltip)
+                            .on_disabled_hover_ui(tooltip);
+                        if resp.clicked() {
+                            // Costlier words sound deeper
+                            play_sfx_pitched("word.ogg", 1.0 / (1.0 + word.cost() * 0.05));
                             let _err = if let Function::ReadField(kind) = f {
                                 if self.world.player.progression.known_fields.insert(kind) {
                                     // Reveal the relevant field if this is the first time its word is said
@@ -531,10 +1662,10 @@ impl Game {
                                     );
                                     None
                                 } else {
-                                    say()
+                                    self.world.say(PersonId::Player, *word).err()
                                 }
                             } else {
-                                say()
+                                self.world.say(PersonId::Player, *word).err()
                             };
                         }
                     });
@@ -546,9 +1677,33 @@ impl Game {
                     let visibility = ui.ctx().animate_bool(id, show_free);
                     if show_free {
                         apply_color_fading(ui.visuals_mut(), visibility);
-                        if ui.button("Free").clicked() {
-                            self.world.player.person.stack.clear();
-                        }
+                        let (free_key, undo_key) = if ui.memory(|mem| mem.focus().is_none()) {
+                            ui.input(|input| {
+                                let keybindings = &self.ui_state.keybindings;
+                                (
+                                    keybindings.pressed(input, Action::Free),
+                                    keybindings.pressed(input, Action::Undo),
+                                )
+                            })
+                        } else {
+                            (false, false)
+                        };
+                        ui.horizontal(|ui| {
+                            if ui.button("Free").gamepad_nav().clicked() || free_key {
+                                self.world.free_stack();
+                            }
+                            let can_undo = self.world.can_undo_word();
+                            let undo = ui
+                                .add_enabled(can_undo, Button::new("Undo"))
+                                .gamepad_nav()
+                                .on_hover_text("Take back the last word")
+                                .on_disabled_hover_text(
+                                    "Nothing to undo since the last spell was cast",
+                                );
+                            if undo.clicked() || undo_key {
+                                self.world.undo_word();
+                            }
+                        });
                     } else {
                         ui.label("");
                     }
@@ -584,20 +1739,9 @@ impl Game {
         if used_controls.contains(&ControlKind::YSlider) {
             let value = self.world.controls.y_slider.get_or_insert(0.0);
             if ui.memory(|mem| mem.focus().is_none()) {
-                if let Some(i) = [
-                    Key::Num0,
-                    Key::Num1,
-                    Key::Num2,
-                    Key::Num3,
-                    Key::Num4,
-                    Key::Num5,
-                    Key::Num6,
-                    Key::Num7,
-                    Key::Num8,
-                    Key::Num9,
-                ]
-                .into_iter()
-                .position(|key| ui.input(|input| input.key_pressed(key)))
+                let keybindings = &self.ui_state.keybindings;
+                if let Some(i) = (Action::Y_SLIDER.into_iter())
+                    .position(|action| ui.input(|input| keybindings.pressed(input, action)))
                 {
                     *value = i as f32 / 9.0;
                 }
@@ -615,13 +1759,17 @@ impl Game {
             // Horizontal slider
             if used_controls.contains(&ControlKind::XSlider) {
                 let value = self.world.controls.x_slider.get_or_insert(0.0);
+                let keybindings = &self.ui_state.keybindings;
                 ui.input(|input| {
-                    if input.key_down(Key::D) || input.key_down(Key::A) {
+                    let left = keybindings.down(input, Action::XSliderLeft);
+                    let right = keybindings.down(input, Action::XSliderRight);
+                    if left || right {
                         if !something_focused {
-                            *value = input.key_down(Key::D) as u8 as f32
-                                - input.key_down(Key::A) as u8 as f32;
+                            *value = right as u8 as f32 - left as u8 as f32;
                         }
-                    } else if input.key_released(Key::D) || input.key_released(Key::A) {
+                    } else if keybindings.released(input, Action::XSliderLeft)
+                        || keybindings.released(input, Action::XSliderRight)
+                    {
                         *value = 0.0;
                     }
                 });
@@ -633,28 +1781,29 @@ impl Game {
                 self.world.controls.x_slider = None;
             }
             // Activators
-            for (word, kind, value, require_shift) in [
+            let keybindings = &self.ui_state.keybindings;
+            for (word, kind, value, action) in [
                 (
                     Word::Ve,
                     ControlKind::Activation1,
                     &mut self.world.controls.activation1,
-                    false,
+                    Action::Activation1,
                 ),
                 (
                     Word::Vi,
                     ControlKind::Activation2,
                     &mut self.world.controls.activation2,
-                    true,
+                    Action::Activation2,
                 ),
             ] {
                 if used_controls.contains(&kind) {
                     ui.toggle_value(value, word.to_string());
                     ui.input(|input| {
-                        if input.key_down(Key::Space) && require_shift != input.modifiers.shift {
+                        if keybindings.down(input, action) {
                             if !something_focused {
                                 *value = true;
                             }
-                        } else if input.key_released(Key::Space) {
+                        } else if keybindings.released(input, action) {
                             *value = false;
                         }
                     });
@@ -664,27 +1813,259 @@ impl Game {
             }
         });
     }
+    /// Show the window for rebinding keys
+    fn keybindings_ui(&mut self, ctx: &Context) {
+        let ui_state = &mut self.ui_state;
+        let mut open = ui_state.controls_open;
+        Window::new("Controls").open(&mut open).show(ctx, |ui| {
+            Grid::new("keybindings").striped(true).show(ui, |ui| {
+                for action in all::<Action>() {
+                    ui.label(action.to_string());
+                    let binding = ui_state.keybindings.get(action);
+                    let text = if ui_state.rebinding == Some(action) {
+                        RichText::new("Press a key…").italics()
+                    } else if ui_state.keybindings.conflicts(action).next().is_some() {
+                        RichText::new(binding.to_string()).color(ui.visuals().warn_fg_color)
+                    } else {
+                        RichText::new(binding.to_string())
+                    };
+                    let conflicts = ui_state.keybindings.conflicts(action).join(", ");
+                    let mut resp = ui.button(text);
+                    if !conflicts.is_empty() {
+                        resp = resp.on_hover_text(format!("Also bound to {conflicts}"));
+                    }
+                    if resp.clicked() {
+                        ui_state.rebinding = Some(action);
+                    }
+                    ui.end_row();
+                }
+            });
+            if ui.button("Reset to defaults").clicked() {
+                ui_state.keybindings = Keybindings::default();
+                if let Err(e) = ui_state.keybindings.save() {
+                    ui_state.toast(format!("Unable to save keybindings: {e}"));
+                }
+            }
+        });
+        ui_state.controls_open = open;
+        if !open {
+            ui_state.rebinding = None;
+        }
+    }
+    fn settings_ui(&mut self, ctx: &Context) {
+        let mut settings = settings();
+        Window::new("Settings")
+            .open(&mut self.ui_state.settings_open)
+            .show(ctx, |ui| {
+                Grid::new("settings").striped(true).show(ui, |ui| {
+                    ui.label("UI scale");
+                    ui.add(Slider::new(&mut settings.ui_scale, 0.5..=2.0));
+                    ui.end_row();
+                    ui.label("Field precision");
+                    ui.add(Slider::new(&mut settings.plot.precision, 0.25..=3.0));
+                    ui.end_row();
+                    ui.label("Field quality range").on_hover_text(format!(
+                        "Fields are sampled less densely when frames are slow\nCurrently {:.0}%",
+                        plot_quality() * 100.0
+                    ));
+                    ui.horizontal(|ui| {
+                        let plot = &mut settings.plot;
+                        ui.add(Slider::new(&mut plot.min_quality, 0.1..=1.0).text("min"));
+                        ui.add(Slider::new(&mut plot.max_quality, 0.1..=2.0).text("max"));
+                        plot.max_quality = plot.max_quality.max(plot.min_quality);
+                    });
+                    ui.end_row();
+                    ui.label("Field colors");
+                    ComboBox::from_id_source("palette")
+                        .selected_text(settings.plot.palette.to_string())
+                        .show_ui(ui, |ui| {
+                            for palette in all::<Palette>() {
+                                ui.selectable_value(
+                                    &mut settings.plot.palette,
+                                    palette,
+                                    palette.to_string(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("Show minimap");
+                    ui.checkbox(&mut settings.show_minimap, "");
+                    ui.end_row();
+                    ui.label("Show particles");
+                    ui.checkbox(&mut settings.particles, "");
+                    ui.end_row();
+                    ui.label("Music volume");
+                    ui.add(Slider::new(&mut settings.music_volume, 0.0..=1.0));
+                    ui.end_row();
+                    ui.label("Sound effect volume");
+                    ui.add(Slider::new(&mut settings.sfx_volume, 0.0..=1.0));
+                    ui.end_row();
+                    ui.label("Throw strength");
+                    ui.add(Slider::new(&mut settings.throw_impulse, 0.5..=5.0));
+                    ui.end_row();
+                    ui.label("Show field values on hover");
+                    ui.checkbox(&mut settings.plot.hover_readout, "");
+                    ui.end_row();
+                    ui.label("Draw objects in the density field");
+                    ui.checkbox(&mut settings.plot.world_view, "")
+                        .on_hover_text("Replaces the place's background");
+                    ui.end_row();
+                    ui.label("Dialog text speed");
+                    ui.add(
+                        Slider::new(&mut settings.dialog.chars_per_second, 5.0..=100.0)
+                            .suffix(" chars/s"),
+                    );
+                    ui.end_row();
+                    ui.label("Animation time");
+                    ui.horizontal(|ui| {
+                        let mut overridden = settings.animation_time.is_some();
+                        ui.checkbox(&mut overridden, "");
+                        let mut time = settings.animation_time.unwrap_or(0.5);
+                        ui.add_enabled(overridden, Slider::new(&mut time, 0.0..=3.0).suffix(" s"));
+                        settings.animation_time = overridden.then_some(time);
+                    });
+                    ui.end_row();
+                    ui.label("FPS cap");
+                    ui.horizontal(|ui| {
+                        let mut capped = settings.fps_cap.is_some();
+                        ui.checkbox(&mut capped, "");
+                        let mut fps = settings.fps_cap.unwrap_or(60);
+                        ui.add_enabled(capped, Slider::new(&mut fps, 10..=240));
+                        settings.fps_cap = capped.then_some(fps);
+                    });
+                    ui.end_row();
+                    ui.label("Vsync");
+                    ui.checkbox(&mut settings.vsync, "")
+                        .on_hover_text("Takes effect after restarting");
+                    ui.end_row();
+                    ui.label("Show FPS");
+                    ui.checkbox(&mut settings.show_fps, "");
+                    ui.end_row();
+                    ui.label("Simulation rate");
+                    ui.horizontal(|ui| {
+                        for rate in TICK_RATES {
+                            ui.selectable_value(
+                                &mut settings.tick_rate,
+                                rate,
+                                format!("{rate} Hz"),
+                            );
+                        }
+                    });
+                    ui.end_row();
+                });
+                if ui.button("Reset to defaults").clicked() {
+                    settings = Settings::default();
+                }
+            });
+        set_settings(settings);
+    }
+    /// Bind the next key pressed to the action waiting for one
+    fn capture_rebinding(&mut self, ctx: &Context) {
+        let Some(action) = self.ui_state.rebinding else {
+            return;
+        };
+        let pressed = ctx.input(|input| {
+            input.events.iter().find_map(|event| match event {
+                Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => Some(KeyBinding {
+                    key: *key,
+                    shift: modifiers.shift,
+                    ctrl: modifiers.ctrl,
+                    alt: modifiers.alt,
+                }),
+                _ => None,
+            })
+        });
+        let Some(binding) = pressed else {
+            return;
+        };
+        // The key shouldn't also do anything else this frame
+        ctx.input_mut(|input| {
+            input.events.clear();
+            input.keys_down.remove(&binding.key);
+        });
+        self.ui_state.keybindings.set(action, binding);
+        self.ui_state.rebinding = None;
+        if let Err(e) = self.ui_state.keybindings.save() {
+            self.ui_state
+                .toast(format!("Unable to save keybindings: {e}"));
+        }
+    }
+    /// Pick up the object under the cursor in a field window, or let go of the carried one,
+    /// throwing it if an activation control is held
+    fn carry(&mut self) {
+        let world = &mut self.world;
+        if world.carried.is_some() {
+            let throw = world.controls.activation1 || world.controls.activation2;
+            world.release_carried(if throw { settings().throw_impulse } else { 0.0 });
+        } else if let Some(pos) = self.ui_state.hovered_field_pos {
+            if let Err(e) = world.pick_up(pos) {
+                self.ui_state.toast(e.to_string());
+            }
+        } else {
+            self.ui_state
+                .toast("Hover over an object in a field to pick it up");
+        }
+    }
     fn handle_plot_response(&mut self, ui: &Ui, plot_resp: PlotResponse) {
-        Self::handle_plot_response_impl(ui, &mut self.ui_state, &mut self.world.controls, plot_resp)
+        if plot_resp.hovered_pos.is_some() {
+            self.ui_state.hovered_field_pos = plot_resp.hovered_pos;
+        }
+        let controls = Some(&mut self.world.controls);
+        Self::handle_plot_response_impl(ui, &mut self.ui_state, controls, plot_resp)
     }
+    /// Aim and zoom with a hovered plot, and activate with its clicks if there are controls
     fn handle_plot_response_impl(
         ui: &Ui,
         ui_state: &mut UiState,
-        controls: &mut Controls,
+        controls: Option<&mut Controls>,
         plot_resp: PlotResponse,
     ) {
         if ui_state.next_player_target.is_none() {
             ui_state.next_player_target = plot_resp.hovered_pos;
         }
         if plot_resp.response.hovered() {
-            controls.activation1 = ui.input(|input| input.pointer.primary_down());
-            controls.activation2 = ui.input(|input| input.pointer.secondary_down());
+            // Shift and the scroll wheel zoom the camera
+            // Egui turns shifted vertical scrolling into horizontal scrolling
+            let (shift, scroll) = ui.input(|input| (input.modifiers.shift, input.scroll_delta.x));
+            if shift && scroll != 0.0 {
+                ui_state.camera.zoom_by((scroll / 200.0).exp());
+            }
+            if let Some(controls) = controls {
+                controls.activation1 = ui.input(|input| input.pointer.primary_down());
+                controls.activation2 = ui.input(|input| input.pointer.secondary_down());
+            }
         }
     }
-    fn init_plot(&self, size: f32, global_alpha: f32) -> FieldPlot {
-        let rect = self.world.max_rect();
-        let range = rect.size().max_elem() * 0.5;
-        FieldPlot::new(&self.world, rect.center(), range, size, global_alpha)
+    fn init_plot(
+        &self,
+        size: f32,
+        global_alpha: f32,
+        center_offset: Vec2,
+        zoom: f32,
+    ) -> FieldPlot<'_> {
+        let camera = &self.ui_state.camera;
+        let range = camera.range(&self.world) / zoom;
+        let center = camera.center + center_offset;
+        let plot_settings = settings().plot;
+        FieldPlot::new(&self.world, center, range, size, global_alpha)
+            .show_sensors(self.ui_state.show_sensors)
+            .hover_readout(plot_settings.hover_readout)
+            .palette(plot_settings.palette)
+            .precision_scale(zoom.sqrt().min(MAX_ZOOM_PRECISION_SCALE) * plot_settings.precision)
+    }
+    /// Draw with objects between the last two world steps
+    ///
+    /// Only drawing sees the interpolated transforms, so the world acts on where objects are
+    fn interpolated<R>(&mut self, draw: impl FnOnce(&Self) -> R) -> R {
+        self.world.render_alpha = Some(self.render_alpha);
+        let res = draw(self);
+        self.world.render_alpha = None;
+        res
     }
     #[must_use]
     pub fn plot_stack_field(
@@ -694,7 +2075,10 @@ impl Game {
         global_alpha: f32,
         field: &Field,
     ) -> PlotResponse {
-        let plot = self.init_plot(size, global_alpha);
+        // Stack plots are too small for a readout
+        let plot = self
+            .init_plot(size, global_alpha, Vec2::ZERO, 1.0)
+            .hover_readout(false);
         match field {
             Field::Scalar(ScalarField::Uniform(n)) => {
                 FieldPlot::show_number(ui, size, global_alpha, *n)
@@ -711,7 +2095,25 @@ impl Game {
         global_alpha: f32,
         kind: FieldKind,
     ) -> PlotResponse {
-        let plot = self.init_plot(size, global_alpha);
+        let display = self.ui_state.fields_display.get(&kind);
+        let mut plot = self.init_plot(
+            size,
+            global_alpha,
+            display.map_or(Vec2::ZERO, |display| display.center_offset),
+            display.map_or(1.0, |display| display.zoom),
+        );
+        if let Some(display) = display {
+            plot = plot
+                .legend(display.legend)
+                .scalar_mode(display.scalar_mode)
+                .vector_mode(display.vector_mode);
+        }
+        let plot = plot
+            .world_view(
+                settings().plot.world_view
+                    && kind == FieldKind::Scalar(ScalarInputFieldKind::Density.into()),
+            )
+            .particles(settings().particles);
         match kind {
             FieldKind::Scalar(kind) => plot.show(ui, &kind),
             FieldKind::Vector(kind) => plot.show(ui, &kind),
@@ -721,6 +2123,8 @@ impl Game {
 
 const DEFAULT_SCALAR_PRECISION: f32 = 0.6;
 const DEFAULT_VECTOR_PRECISION: f32 = 0.2;
+/// How far input vector field hues are rotated in the default palette
+const INPUT_VECTOR_HUE_OFFSET: f32 = 0.75;
 
 /// For rendering scalar stack fields
 impl FieldPlottable for ScalarField {
@@ -729,8 +2133,11 @@ impl FieldPlottable for ScalarField {
         DEFAULT_SCALAR_PRECISION
     }
     fn color_midpoint(&self) -> f32 {
-        if let ScalarField::Input(kind) = self {
-            ScalarFieldKind::Input(*kind).color_midpoint()
+        if let Some(kind) = self.clamped_input() {
+            ScalarFieldKind::Input(kind).color_midpoint()
+        } else if let ScalarField::ScalarUn(TypedUnOp::Math(MathUnOp::Sign), _) = self {
+            // Sign fields are only ever -1, 0, or 1, so make those distinct
+            0.5
         } else {
             1.0
         }
@@ -738,10 +2145,14 @@ impl FieldPlottable for ScalarField {
     fn get_z(&self, world: &World, pos: Pos2) -> Self::Value {
         self.sample(world, pos, true)
     }
-    fn get_color(&self, t: Self::Value) -> Color {
-        match self {
-            ScalarField::Input(kind) => ScalarFieldKind::Input(*kind).get_color(t),
-            _ => default_scalar_color(t),
+    fn cache_id(&self) -> Option<u64> {
+        // Stack fields are pure, so they can be identified by their structure
+        Some(hash(format!("{self:?}")))
+    }
+    fn get_color(&self, palette: Palette, t: Self::Value) -> Color {
+        match self.clamped_input() {
+            Some(kind) => ScalarFieldKind::Input(kind).get_color(palette, t),
+            None => palette.scalar(ScalarScheme::General, t),
         }
     }
 }
@@ -758,8 +2169,12 @@ impl FieldPlottable for VectorField {
     fn get_z(&self, world: &World, pos: Pos2) -> Self::Value {
         self.sample(world, pos, true)
     }
-    fn get_color(&self, t: Self::Value) -> Color {
-        default_vector_color(t)
+    fn cache_id(&self) -> Option<u64> {
+        // Stack fields are pure, so they can be identified by their structure
+        Some(hash(format!("{self:?}")))
+    }
+    fn get_color(&self, palette: Palette, t: Self::Value) -> Color {
+        palette.vector(t, INPUT_VECTOR_HUE_OFFSET)
     }
 }
 
@@ -782,6 +2197,9 @@ impl FieldPlottable for ScalarFieldKind {
             ScalarFieldKind::Input(ScalarInputFieldKind::Light) => 5.0,
             ScalarFieldKind::Input(ScalarInputFieldKind::Disorder) => 2.0,
             ScalarFieldKind::Input(ScalarInputFieldKind::Memory) => 1.0,
+            ScalarFieldKind::Input(ScalarInputFieldKind::Noise) => 0.3,
+            ScalarFieldKind::Input(ScalarInputFieldKind::Time) => 10.0,
+            ScalarFieldKind::Input(ScalarInputFieldKind::Material) => 1.0,
             ScalarFieldKind::Input(ScalarInputFieldKind::Temperature)
             | ScalarFieldKind::Output(ScalarOutputFieldKind::Heat) => 20.0,
             ScalarFieldKind::Output(ScalarOutputFieldKind::Order) => 1.0,
@@ -791,27 +2209,28 @@ impl FieldPlottable for ScalarFieldKind {
     fn get_z(&self, world: &World, pos: Pos2) -> Self::Value {
         world.sample_scalar_field(*self, pos, true)
     }
-    fn get_color(&self, t: Self::Value) -> Color {
-        match self {
-            ScalarFieldKind::Input(ScalarInputFieldKind::Magic) => {
-                let t = (t - 0.5) / 0.5;
-                Color::rgb(0.0, t * 0.5, t)
-            }
-            ScalarFieldKind::Input(ScalarInputFieldKind::Light) => {
-                let t = (t - 0.5) / 0.5;
-                Color::rgb(t.powf(0.5), t.powf(0.6), t)
-            }
+    fn bounds(&self, world: &World) -> Vec<SpellBounds> {
+        let ScalarFieldKind::Output(kind) = self else {
+            return Vec::new();
+        };
+        (world.people())
+            .filter_map(|person| person.active_spells.scalars.get(kind))
+            .flatten()
+            .filter_map(|spell| spell.bounds)
+            .collect()
+    }
+    fn cache_id(&self) -> Option<u64> {
+        Some(hash(self))
+    }
+    fn get_color(&self, palette: Palette, t: Self::Value) -> Color {
+        let scheme = match self {
+            ScalarFieldKind::Input(ScalarInputFieldKind::Magic) => ScalarScheme::Magic,
+            ScalarFieldKind::Input(ScalarInputFieldKind::Light) => ScalarScheme::Light,
             ScalarFieldKind::Input(ScalarInputFieldKind::Temperature)
-            | ScalarFieldKind::Output(ScalarOutputFieldKind::Heat) => {
-                let t = (t - 0.5) / 0.5;
-                if t > 0.0 {
-                    Color::rgb(t, 0.25 - 0.5 * (t - 0.5).abs(), t * 0.2)
-                } else {
-                    Color::rgb(t.abs() * 0.5, t.abs() * 0.8, t.abs())
-                }
-            }
-            _ => default_scalar_color(t),
-        }
+            | ScalarFieldKind::Output(ScalarOutputFieldKind::Heat) => ScalarScheme::Temperature,
+            _ => ScalarScheme::General,
+        };
+        palette.scalar(scheme, t)
     }
 }
 
@@ -827,14 +2246,48 @@ impl FieldPlottable for VectorFieldKind {
     fn get_z(&self, world: &World, pos: Pos2) -> Self::Value {
         world.sample_vector_field(*self, pos, true)
     }
-    fn get_color(&self, t: Self::Value) -> Color {
+    fn bounds(&self, world: &World) -> Vec<SpellBounds> {
+        let VectorFieldKind::Output(kind) = self else {
+            return Vec::new();
+        };
+        (world.people())
+            .filter_map(|person| person.active_spells.vectors.get(kind))
+            .flatten()
+            .filter_map(|spell| spell.bounds)
+            .collect()
+    }
+    fn cache_id(&self) -> Option<u64> {
+        Some(hash(self))
+    }
+    fn get_color(&self, palette: Palette, t: Self::Value) -> Color {
         match self {
-            VectorFieldKind::Input(_) => default_vector_color(t),
+            VectorFieldKind::Input(_) => palette.vector(t, INPUT_VECTOR_HUE_OFFSET),
             VectorFieldKind::Output(kind) => match kind {
-                VectorOutputFieldKind::Gravity => simple_vector_color(t, 0.5),
-                VectorOutputFieldKind::Force => simple_vector_color(t, 0.5),
-                VectorOutputFieldKind::Write => simple_vector_color(t, 0.5),
+                VectorOutputFieldKind::Gravity => palette.vector(t, 0.5),
+                VectorOutputFieldKind::Force => palette.vector(t, 0.5),
+                VectorOutputFieldKind::Write => palette.vector(t, 0.5),
             },
         }
     }
 }
+
+#[test]
+fn spell_code_cast_test() {
+    use crate::player::Gender;
+    use enum_iterator::all;
+    let mut game = Game::from_player(Player::new("Test".into(), Gender::Enby));
+    game.world
+        .player
+        .progression
+        .known_words
+        .extend(all::<Word>());
+    let force = OutputFieldKind::from(VectorOutputFieldKind::Force);
+    // A code that fails partway doesn't cast the spells before the failure
+    assert!(game.cast_spell_code("Ki-Wu-Wu").is_err());
+    let person = &game.world.player.person;
+    assert_eq!(person.active_spells.spell_words(force).len(), 0);
+    assert!(person.stack.is_empty());
+    game.cast_spell_code("Ki-Wu").unwrap();
+    let person = &game.world.player.person;
+    assert_eq!(person.active_spells.spell_words(force).len(), 1);
+}
@@ -0,0 +1,158 @@
+use eframe::egui::*;
+
+/// How far a stick has to move from the center before it counts
+#[cfg(feature = "gamepad")]
+const STICK_DEADZONE: f32 = 0.15;
+
+/// What the gamepad did since the last frame
+///
+/// Sliders and activations are only set when they change so that the keyboard can still be used.
+/// The activations are on the bottom and right face buttons,
+/// so confirming in menus is on the left face button to keep it from activating spells.
+#[derive(Debug, Default)]
+pub struct GamepadInput {
+    pub x_slider: Option<f32>,
+    pub y_slider: Option<f32>,
+    pub activation1: Option<bool>,
+    pub activation2: Option<bool>,
+    pub pause: bool,
+    /// How many widgets to move focus by
+    pub navigate: i32,
+    pub confirm: bool,
+}
+
+/// Reads gamepads if the game was built with the `gamepad` feature
+pub struct Gamepad {
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Gamepad {
+    pub fn new() -> Self {
+        Gamepad {
+            #[cfg(feature = "gamepad")]
+            gilrs: gilrs::Gilrs::new()
+                .map_err(|e| tracing::warn!("Unable to read gamepads: {e}"))
+                .ok(),
+        }
+    }
+    #[cfg(not(feature = "gamepad"))]
+    pub fn poll(&mut self) -> GamepadInput {
+        GamepadInput::default()
+    }
+    #[cfg(feature = "gamepad")]
+    pub fn poll(&mut self) -> GamepadInput {
+        use gilrs::{Axis, Button, EventType};
+        let mut input = GamepadInput::default();
+        let Some(gilrs) = &mut self.gilrs else {
+            return input;
+        };
+        let stick = |value: f32| {
+            if value.abs() < STICK_DEADZONE {
+                0.0
+            } else {
+                value
+            }
+        };
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                EventType::AxisChanged(Axis::LeftStickX, value, _) => {
+                    input.x_slider = Some(stick(value));
+                }
+                EventType::AxisChanged(Axis::RightStickY, value, _) => {
+                    input.y_slider = Some(stick(value) * 0.5 + 0.5);
+                }
+                EventType::ButtonChanged(Button::RightTrigger2, value, _) => {
+                    input.y_slider = Some(value);
+                }
+                EventType::ButtonPressed(button, _) => match button {
+                    Button::South => input.activation1 = Some(true),
+                    Button::East => input.activation2 = Some(true),
+                    Button::West => input.confirm = true,
+                    Button::Start => input.pause = true,
+                    Button::DPadDown | Button::DPadRight => input.navigate += 1,
+                    Button::DPadUp | Button::DPadLeft => input.navigate -= 1,
+                    _ => {}
+                },
+                EventType::ButtonReleased(Button::South, _) => input.activation1 = Some(false),
+                EventType::ButtonReleased(Button::East, _) => input.activation2 = Some(false),
+                _ => {}
+            }
+        }
+        input
+    }
+}
+
+/// Moves egui's focus between the widgets registered with [`GamepadNavigable::gamepad_nav`]
+#[derive(Debug, Clone, Default)]
+struct GamepadNav {
+    /// How many widgets have been registered this frame
+    count: usize,
+    /// How many widgets were registered last frame
+    last_count: usize,
+    /// The index of the focused widget last frame
+    focused: Option<usize>,
+    /// The index of the widget to focus this frame
+    target: Option<usize>,
+}
+
+fn nav_id() -> Id {
+    Id::new("gamepad nav")
+}
+
+/// Start a frame of gamepad focus navigation
+///
+/// Confirming presses Enter, which clicks the focused widget or advances dialog
+pub fn begin_nav_frame(ctx: &Context, navigate: i32, confirm: bool) {
+    ctx.data_mut(|data| {
+        let nav = data.get_temp_mut_or_default::<GamepadNav>(nav_id());
+        nav.last_count = nav.count;
+        nav.count = 0;
+        nav.target = None;
+        if navigate != 0 && nav.last_count > 0 {
+            let count = nav.last_count as i32;
+            let current = nav
+                .focused
+                .map_or(if navigate > 0 { -1 } else { 0 }, |i| i as i32);
+            nav.target = Some((current + navigate).rem_euclid(count) as usize);
+        }
+        nav.focused = None;
+    });
+    if confirm {
+        ctx.input_mut(|input| {
+            input.events.push(Event::Key {
+                key: Key::Enter,
+                pressed: true,
+                repeat: false,
+                modifiers: Modifiers::default(),
+            })
+        });
+    }
+}
+
+pub trait GamepadNavigable {
+    /// Let the widget be focused with the gamepad
+    fn gamepad_nav(self) -> Self;
+}
+
+impl GamepadNavigable for Response {
+    fn gamepad_nav(self) -> Self {
+        if !self.enabled {
+            return self;
+        }
+        let has_focus = self.has_focus();
+        let is_target = self.ctx.data_mut(|data| {
+            let nav = data.get_temp_mut_or_default::<GamepadNav>(nav_id());
+            let i = nav.count;
+            nav.count += 1;
+            if has_focus {
+                nav.focused = Some(i);
+            }
+            nav.target == Some(i)
+        });
+        if is_target {
+            self.request_focus();
+        }
+        self
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, f64};
+use std::{collections::HashMap, f64, fs, sync::Arc};
 
 use eframe::{
     egui::*,
@@ -8,118 +8,253 @@ use image::RgbaImage;
 use once_cell::sync::Lazy;
 use rand::prelude::*;
 use rayon::prelude::*;
+use serde::Deserialize;
+use tracing::warn;
 
-use crate::{
-    color::Color,
-    plot::{time, Plottable},
-    utils::resources_path,
-};
+use crate::{color::Color, plot::time, utils::resources_path};
 
-static IMAGES: Lazy<Mutex<HashMap<String, RgbaImage>>> = Lazy::new(Default::default);
+/// Loaded images, or `None` for images that failed to load
+static IMAGES: Lazy<Mutex<HashMap<String, Option<RgbaImage>>>> = Lazy::new(Default::default);
+/// Images uploaded to the GPU, or `None` for images that failed to load
+static IMAGE_TEXTURES: Lazy<Mutex<HashMap<String, Option<TextureHandle>>>> =
+    Lazy::new(Default::default);
+/// Sampled image plot points by image name
+static IMAGE_SAMPLES: Lazy<Mutex<HashMap<String, CachedImageSamples>>> =
+    Lazy::new(Default::default);
+/// The frames in each directory of animation frames
+static ANIMATIONS: Lazy<Mutex<HashMap<String, Vec<String>>>> = Lazy::new(Default::default);
+static MISSING_IMAGE: Lazy<RgbaImage> = Lazy::new(|| RgbaImage::new(1, 1));
 
-pub fn use_image<F, T>(name: &str, mut f: F) -> T
-where
-    F: FnMut(&RgbaImage) -> T,
-{
+fn with_loaded_image<T>(name: &str, f: impl FnOnce(Option<&RgbaImage>) -> T) -> T {
     let mut images = IMAGES.lock();
     let image = images.entry(name.into()).or_insert_with(|| {
         let path = resources_path().join("images").join(name);
         image::open(path)
             .map(|image| image.to_rgba8())
-            .unwrap_or_else(|_| {
-                eprintln!("Failed to load image: {}", name);
-                RgbaImage::new(1, 1)
+            .map_err(|_| warn!("Failed to load image: {}", name))
+            .ok()
+    });
+    f(image.as_ref())
+}
+
+pub fn use_image<F, T>(name: &str, mut f: F) -> T
+where
+    F: FnMut(&RgbaImage) -> T,
+{
+    with_loaded_image(name, |image| f(image.unwrap_or(&MISSING_IMAGE)))
+}
+
+/// Whether an image can be loaded
+pub fn image_exists(name: &str) -> bool {
+    with_loaded_image(name, |image| image.is_some())
+}
+
+/// Get an image as a texture that can be painted
+pub fn image_texture(ctx: &Context, name: &str) -> Option<TextureId> {
+    let mut textures = IMAGE_TEXTURES.lock();
+    let texture = textures.entry(name.into()).or_insert_with(|| {
+        with_loaded_image(name, |image| {
+            image.map(|image| {
+                let size = [image.width() as usize, image.height() as usize];
+                let image_data = ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+                ctx.load_texture(name, image_data, TextureOptions::default())
             })
+        })
     });
-    f(image)
+    texture.as_ref().map(TextureHandle::id)
 }
 
-#[derive(Clone, Copy)]
-pub enum ImagePlotKind {
-    Portrait(bool),
-    Background,
+/// A background image, or a directory of frames shown one after another
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Background {
+    Image(String),
+    Animated {
+        /// The directory of frames, shown in order of their names
+        frames: String,
+        /// How many frames are shown per second
+        fps: f32,
+    },
 }
 
-pub fn image_plot(ui: &mut Ui, name: &str, max_size: Vec2, kind: ImagePlotKind) {
-    puffin::profile_function!();
-    use_image(name, |image| {
-        let image_aspect = image.width() as f32 / image.height() as f32;
-        let max_size_aspect = max_size.x / max_size.y;
-        let size = match kind {
-            ImagePlotKind::Portrait(_) => {
-                if image_aspect > max_size_aspect {
-                    Vec2::new(max_size.x, max_size.x / image_aspect)
-                } else {
-                    Vec2::new(max_size.y * image_aspect, max_size.y)
-                }
-            }
-            ImagePlotKind::Background => {
-                if image_aspect > max_size_aspect {
-                    Vec2::new(max_size.y * image_aspect, max_size.y)
-                } else {
-                    Vec2::new(max_size.x, max_size.x / image_aspect)
-                }
+impl Background {
+    /// Get the name of the image to show at a time in seconds
+    pub fn image_at(&self, time: f32) -> Option<String> {
+        match self {
+            Background::Image(name) => Some(name.clone()),
+            Background::Animated { frames, fps } => {
+                let frames = animation_frames(frames);
+                let i = (time * fps).max(0.0) as usize;
+                (!frames.is_empty()).then(|| frames[i % frames.len()].clone())
             }
+        }
+    }
+}
+
+/// Get the names of the images in a directory of frames
+fn animation_frames(dir: &str) -> Vec<String> {
+    let mut animations = ANIMATIONS.lock();
+    let frames = animations.entry(dir.into()).or_insert_with(|| {
+        let path = resources_path().join("images").join(dir);
+        let Ok(entries) = fs::read_dir(path) else {
+            warn!("Failed to load animation: {}", dir);
+            return Vec::new();
         };
-        let alpha = match kind {
-            ImagePlotKind::Portrait(true) => 0.8,
-            ImagePlotKind::Portrait(false) => 0.1,
-            _ => 1.0,
-        };
-        let step = match kind {
-            ImagePlotKind::Portrait(_) => 3.0,
-            ImagePlotKind::Background => 5.0,
-        };
-        let wiggle_range = match kind {
-            ImagePlotKind::Portrait(_) => step,
-            ImagePlotKind::Background => step * 0.3,
-        };
-        let color_mul = match kind {
-            ImagePlotKind::Portrait(_) => 1.0,
-            ImagePlotKind::Background => 0.6,
-        };
-        let time = time();
-        let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
-        let max_i = (size.x / step) as usize;
-        let max_j = (size.y / step) as usize;
-        let mut points = Vec::with_capacity(max_i * max_j);
-        points.par_extend((0..max_i).par_bridge().flat_map(|i| {
+        let mut names: Vec<String> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| format!("{dir}/{name}"))
+            .collect()
+    });
+    frames.clone()
+}
+
+/// Forget every loaded image so that they are read from disk again
+pub fn clear_image_caches() {
+    IMAGES.lock().clear();
+    IMAGE_TEXTURES.lock().clear();
+    IMAGE_SAMPLES.lock().clear();
+    ANIMATIONS.lock().clear();
+}
+
+#[derive(Clone, Copy)]
+pub enum ImagePlotKind {
+    /// A portrait with an opacity
+    Portrait(f32),
+    /// A background with an opacity
+    Background(f32),
+}
+
+/// The position, wiggle phases, and color of a point in an image plot
+type ImageSample = (Vec2, [f64; 2], Color);
+
+/// Image plot points that are reused until the image or the plot's size changes
+struct CachedImageSamples {
+    key: u64,
+    samples: Arc<Vec<ImageSample>>,
+}
+
+/// Sample an image's colors on a grid over the size it is drawn at
+fn image_samples(name: &str, size: Vec2, step: f32) -> Arc<Vec<ImageSample>> {
+    let mut cache = IMAGE_SAMPLES.lock();
+    let key = hash((size.x.to_bits(), size.y.to_bits(), step.to_bits()));
+    if let Some(cached) = cache.get(name).filter(|cached| cached.key == key) {
+        return cached.samples.clone();
+    }
+    let max_i = (size.x / step) as usize;
+    let max_j = (size.y / step) as usize;
+    let samples = use_image(name, |image| {
+        let mut samples = Vec::with_capacity(max_i * max_j);
+        samples.par_extend((0..max_i).into_par_iter().flat_map_iter(|i| {
             let x = i as f32 * step;
-            (0..max_j).par_bridge().filter_map(move |j| {
+            (0..max_j).map(move |j| {
                 let y = j as f32 * step;
                 let mut rng = SmallRng::seed_from_u64(hash((i, j)));
-                let mut color = Color::from(*image.get_pixel(
+                let color = Color::from(*image.get_pixel(
                     (x / size.x * image.width() as f32) as u32,
                     (y / size.y * image.height() as f32) as u32,
                 ));
-                let dx = wiggle_range * (time + rng.gen_range(0.0..=f64::consts::TAU)).sin() as f32;
-                let dy = wiggle_range * (time + rng.gen_range(0.0..=f64::consts::TAU)).sin() as f32;
-                let dropoff = match kind {
-                    ImagePlotKind::Portrait(_) => 1.0,
-                    ImagePlotKind::Background => {
-                        let dist_from_center =
-                            (Vec2::new(x + dx, y + dy) - max_size * 0.5).length();
-                        1.0 - dist_from_center / ((size.x + size.y) * 0.25)
-                    }
-                };
-                color.a *= alpha * dropoff;
-                if color.a < 1.0 / 255.0 {
-                    return None;
-                }
-                let color_mul = color_mul * dropoff;
-                Some((
-                    pos2(x, y) + rect.left_top().to_vec2(),
-                    vec2(dx, dy),
-                    color * color_mul,
-                ))
+                let phases = [
+                    rng.gen_range(0.0..=f64::consts::TAU),
+                    rng.gen_range(0.0..=f64::consts::TAU),
+                ];
+                (vec2(x, y), phases, color)
             })
         }));
-        points.par_sort_by(|(a, ..), (b, ..)| {
-            <f32 as Plottable>::cmp(&a.x, &b.x).then_with(|| <f32 as Plottable>::cmp(&a.y, &b.y))
-        });
-        let painter = ui.painter();
-        for (point, offset, color) in points {
-            painter.circle_filled(point + offset, step * 0.5, color);
-        }
+        samples
     });
+    let samples = Arc::new(samples);
+    let cached = CachedImageSamples {
+        key,
+        samples: samples.clone(),
+    };
+    cache.insert(name.into(), cached);
+    samples
+}
+
+pub fn image_plot(ui: &mut Ui, name: &str, max_size: Vec2, kind: ImagePlotKind) {
+    puffin::profile_function!();
+    let (width, height) = use_image(name, |image| image.dimensions());
+    let image_aspect = width as f32 / height as f32;
+    let max_size_aspect = max_size.x / max_size.y;
+    let size = match kind {
+        ImagePlotKind::Portrait(_) => {
+            if image_aspect > max_size_aspect {
+                Vec2::new(max_size.x, max_size.x / image_aspect)
+            } else {
+                Vec2::new(max_size.y * image_aspect, max_size.y)
+            }
+        }
+        ImagePlotKind::Background(_) => {
+            if image_aspect > max_size_aspect {
+                Vec2::new(max_size.y * image_aspect, max_size.y)
+            } else {
+                Vec2::new(max_size.x, max_size.x / image_aspect)
+            }
+        }
+    };
+    let alpha = match kind {
+        ImagePlotKind::Portrait(opacity) | ImagePlotKind::Background(opacity) => opacity,
+    };
+    let step = match kind {
+        ImagePlotKind::Portrait(_) => 3.0,
+        ImagePlotKind::Background(_) => 5.0,
+    };
+    let wiggle_range = match kind {
+        ImagePlotKind::Portrait(_) => step,
+        ImagePlotKind::Background(_) => step * 0.3,
+    };
+    let color_mul = match kind {
+        ImagePlotKind::Portrait(_) => 1.0,
+        ImagePlotKind::Background(_) => 0.6,
+    };
+    let time = time();
+    let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+    let samples = image_samples(name, size, step);
+    let points: Vec<_> = samples
+        .par_iter()
+        .filter_map(|&(pos, [phase_x, phase_y], mut color)| {
+            let dx = wiggle_range * (time + phase_x).sin() as f32;
+            let dy = wiggle_range * (time + phase_y).sin() as f32;
+            let dropoff = match kind {
+                ImagePlotKind::Portrait(_) => 1.0,
+                ImagePlotKind::Background(_) => {
+                    let dist_from_center = (pos + vec2(dx, dy) - max_size * 0.5).length();
+                    1.0 - dist_from_center / ((size.x + size.y) * 0.25)
+                }
+            };
+            color.a *= alpha * dropoff;
+            if color.a < 1.0 / 255.0 {
+                return None;
+            }
+            let color_mul = color_mul * dropoff;
+            Some((rect.left_top() + pos + vec2(dx, dy), color * color_mul))
+        })
+        .collect();
+    let painter = ui.painter();
+    for (point, color) in points {
+        painter.circle_filled(point, step * 0.5, color);
+    }
+}
+
+#[test]
+fn background_test() {
+    // Backgrounds can be a single image or a directory of frames
+    let image: Background = serde_yaml::from_str("school1.png").unwrap();
+    assert_eq!(image.image_at(3.0).as_deref(), Some("school1.png"));
+    let animated: Background = serde_yaml::from_str("{frames: missing, fps: 8}").unwrap();
+    assert_eq!(
+        animated,
+        Background::Animated {
+            frames: "missing".into(),
+            fps: 8.0
+        }
+    );
+    // Animations without frames show nothing
+    assert_eq!(animated.image_at(3.0), None);
 }
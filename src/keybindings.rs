@@ -0,0 +1,347 @@
+use std::{fmt, fs, path::PathBuf};
+
+use anyhow::Context as _;
+use derive_more::Display;
+use eframe::egui::*;
+use enum_iterator::{all, Sequence};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::data_path;
+
+/// Something the player can do with a key
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, Sequence, Serialize, Deserialize)]
+pub enum Action {
+    #[display(fmt = "X slider left")]
+    XSliderLeft,
+    #[display(fmt = "X slider right")]
+    XSliderRight,
+    #[display(fmt = "Y slider 0")]
+    YSlider0,
+    #[display(fmt = "Y slider 1")]
+    YSlider1,
+    #[display(fmt = "Y slider 2")]
+    YSlider2,
+    #[display(fmt = "Y slider 3")]
+    YSlider3,
+    #[display(fmt = "Y slider 4")]
+    YSlider4,
+    #[display(fmt = "Y slider 5")]
+    YSlider5,
+    #[display(fmt = "Y slider 6")]
+    YSlider6,
+    #[display(fmt = "Y slider 7")]
+    YSlider7,
+    #[display(fmt = "Y slider 8")]
+    YSlider8,
+    #[display(fmt = "Y slider 9")]
+    YSlider9,
+    #[display(fmt = "Activation 1")]
+    Activation1,
+    #[display(fmt = "Activation 2")]
+    Activation2,
+    Free,
+    Undo,
+    Talk,
+    #[display(fmt = "Advance dialog")]
+    AdvanceDialog,
+    /// Pick up the hovered object, or drop or throw the carried one
+    Carry,
+    Pause,
+    Quicksave,
+    Quickload,
+    #[display(fmt = "Reload resources")]
+    ReloadResources,
+    #[display(fmt = "Show sensors")]
+    ShowSensors,
+}
+
+impl Action {
+    /// The y slider actions, in order of the value they set
+    pub const Y_SLIDER: [Action; 10] = [
+        Action::YSlider0,
+        Action::YSlider1,
+        Action::YSlider2,
+        Action::YSlider3,
+        Action::YSlider4,
+        Action::YSlider5,
+        Action::YSlider6,
+        Action::YSlider7,
+        Action::YSlider8,
+        Action::YSlider9,
+    ];
+    fn default_binding(&self) -> KeyBinding {
+        match self {
+            Action::XSliderLeft => Key::A.into(),
+            Action::XSliderRight => Key::D.into(),
+            Action::YSlider0 => Key::Num0.into(),
+            Action::YSlider1 => Key::Num1.into(),
+            Action::YSlider2 => Key::Num2.into(),
+            Action::YSlider3 => Key::Num3.into(),
+            Action::YSlider4 => Key::Num4.into(),
+            Action::YSlider5 => Key::Num5.into(),
+            Action::YSlider6 => Key::Num6.into(),
+            Action::YSlider7 => Key::Num7.into(),
+            Action::YSlider8 => Key::Num8.into(),
+            Action::YSlider9 => Key::Num9.into(),
+            Action::Activation1 => Key::Space.into(),
+            Action::Activation2 => KeyBinding {
+                shift: true,
+                ..Key::Space.into()
+            },
+            Action::Free => Key::Backspace.into(),
+            Action::Undo => KeyBinding {
+                ctrl: true,
+                ..Key::Z.into()
+            },
+            Action::Talk => Key::E.into(),
+            Action::AdvanceDialog => Key::Enter.into(),
+            Action::Carry => Key::F.into(),
+            Action::Pause => Key::Escape.into(),
+            Action::Quicksave => Key::F5.into(),
+            Action::Quickload => Key::F9.into(),
+            Action::ReloadResources => Key::F6.into(),
+            Action::ShowSensors => Key::F3.into(),
+        }
+    }
+}
+
+/// A key and the modifiers that must be held with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyBinding {
+    #[serde(with = "key_name")]
+    pub key: Key,
+    #[serde(default)]
+    pub shift: bool,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub alt: bool,
+}
+
+impl From<Key> for KeyBinding {
+    fn from(key: Key) -> Self {
+        KeyBinding {
+            key,
+            shift: false,
+            ctrl: false,
+            alt: false,
+        }
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "Ctrl"),
+            (self.shift, "Shift"),
+            (self.alt, "Alt"),
+        ] {
+            if held {
+                write!(f, "{name}+")?;
+            }
+        }
+        write!(f, "{}", self.key.name())
+    }
+}
+
+impl KeyBinding {
+    fn modifiers_match(&self, modifiers: Modifiers) -> bool {
+        self.shift == modifiers.shift && self.ctrl == modifiers.ctrl && self.alt == modifiers.alt
+    }
+}
+
+pub struct Keybindings {
+    bindings: IndexMap<Action, KeyBinding>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Keybindings {
+            bindings: all::<Action>()
+                .map(|action| (action, action.default_binding()))
+                .collect(),
+        }
+    }
+}
+
+fn keybindings_path() -> PathBuf {
+    data_path().join("keybindings.yaml")
+}
+
+impl Keybindings {
+    pub fn get(&self, action: Action) -> KeyBinding {
+        self.bindings[&action]
+    }
+    pub fn set(&mut self, action: Action, binding: KeyBinding) {
+        self.bindings.insert(action, binding);
+    }
+    /// Get the other actions bound to the same key as an action
+    pub fn conflicts(&self, action: Action) -> impl Iterator<Item = Action> + '_ {
+        let binding = self.get(action);
+        (self.bindings.iter())
+            .filter(move |(other, other_binding)| **other != action && **other_binding == binding)
+            .map(|(other, _)| *other)
+    }
+    /// Whether an action's key was pressed this frame with its modifiers
+    pub fn pressed(&self, input: &InputState, action: Action) -> bool {
+        let binding = self.get(action);
+        input.key_pressed(binding.key) && binding.modifiers_match(input.modifiers)
+    }
+    /// Whether an action's key is held with its modifiers
+    pub fn down(&self, input: &InputState, action: Action) -> bool {
+        let binding = self.get(action);
+        input.key_down(binding.key) && binding.modifiers_match(input.modifiers)
+    }
+    /// Whether an action's key was released this frame, regardless of modifiers
+    pub fn released(&self, input: &InputState, action: Action) -> bool {
+        input.key_released(self.get(action).key)
+    }
+    /// Load the keybindings, using defaults for any that aren't in the file
+    pub fn load() -> Self {
+        let mut keybindings = Keybindings::default();
+        let Ok(yaml) = fs::read_to_string(keybindings_path()) else {
+            return keybindings;
+        };
+        let Ok(serde_yaml::Value::Mapping(bindings)) = serde_yaml::from_str(&yaml) else {
+            return keybindings;
+        };
+        for (action, binding) in bindings {
+            if let (Ok(action), Ok(binding)) = (
+                serde_yaml::from_value::<Action>(action),
+                serde_yaml::from_value::<KeyBinding>(binding),
+            ) {
+                keybindings.set(action, binding);
+            }
+        }
+        keybindings
+    }
+    pub fn save(&self) -> anyhow::Result<()> {
+        let yaml = serde_yaml::to_string(&self.bindings)?;
+        let path = keybindings_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, yaml).with_context(|| format!("Unable to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Every key that can be bound, used to read keys by name
+const KEYS: [Key; 73] = [
+    Key::ArrowDown,
+    Key::ArrowLeft,
+    Key::ArrowRight,
+    Key::ArrowUp,
+    Key::Escape,
+    Key::Tab,
+    Key::Backspace,
+    Key::Enter,
+    Key::Space,
+    Key::Insert,
+    Key::Delete,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+    Key::Minus,
+    Key::PlusEquals,
+    Key::Num0,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+    Key::F13,
+    Key::F14,
+    Key::F15,
+    Key::F16,
+    Key::F17,
+    Key::F18,
+    Key::F19,
+    Key::F20,
+];
+
+/// Keys are stored by their names
+mod key_name {
+    use eframe::egui::Key;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::KEYS;
+
+    pub fn serialize<S>(key: &Key, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(key.name())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Key, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        (KEYS.into_iter())
+            .find(|key| key.name() == name)
+            .ok_or_else(|| D::Error::custom(format!("Unknown key {name}")))
+    }
+}
+
+#[test]
+fn keybindings_test() {
+    let mut keybindings = Keybindings::default();
+    assert!(all::<Action>().all(|action| keybindings.conflicts(action).count() == 0));
+    // Bindings round trip through their file format
+    let binding = KeyBinding {
+        ctrl: true,
+        ..Key::ArrowUp.into()
+    };
+    let yaml = serde_yaml::to_string(&binding).unwrap();
+    assert_eq!(serde_yaml::from_str::<KeyBinding>(&yaml).unwrap(), binding);
+    // Conflicting bindings are allowed but reported
+    keybindings.set(Action::Free, Key::Space.into());
+    let conflicts: Vec<_> = keybindings.conflicts(Action::Activation1).collect();
+    assert_eq!(conflicts, [Action::Free]);
+}
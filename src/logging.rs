@@ -0,0 +1,122 @@
+use std::{collections::VecDeque, env, fmt::Write};
+
+use eframe::epaint::mutex::Mutex;
+use once_cell::sync::Lazy;
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    filter::Targets,
+    fmt,
+    layer::{Context, SubscriberExt},
+    util::SubscriberInitExt,
+    Layer,
+};
+
+/// The environment variable that sets which logs are shown, like `eidos=debug,warn`
+const LOG_ENV_VAR: &str = "EIDOS_LOG";
+/// How many warnings and errors are kept to show in the game
+const MAX_RECENT_LOGS: usize = 50;
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub level: Level,
+    pub message: String,
+}
+
+static RECENT_LOGS: Lazy<Mutex<VecDeque<LogEntry>>> = Lazy::new(Default::default);
+
+/// Start logging to stderr
+///
+/// The log level is taken from `EIDOS_LOG`, unless `verbose` asks for debug logs
+pub fn init_logging(verbose: bool) {
+    let default = Targets::new()
+        .with_default(Level::WARN)
+        .with_target("eidos", if verbose { Level::DEBUG } else { Level::INFO });
+    let mut invalid = None;
+    let targets = match env::var(LOG_ENV_VAR) {
+        Ok(var) if !verbose => var.parse().unwrap_or_else(|e| {
+            invalid = Some(e);
+            default
+        }),
+        _ => default,
+    };
+    tracing_subscriber::registry()
+        .with(targets)
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .with(RecentLogs)
+        .init();
+    if let Some(e) = invalid {
+        tracing::warn!("Invalid {LOG_ENV_VAR}: {e}");
+    }
+}
+
+/// The most recent warnings and errors, oldest first
+pub fn recent_logs() -> Vec<LogEntry> {
+    RECENT_LOGS.lock().iter().cloned().collect()
+}
+
+pub fn clear_recent_logs() {
+    RECENT_LOGS.lock().clear();
+}
+
+/// Keeps warnings and errors to show in the game
+struct RecentLogs;
+
+impl<S: Subscriber> Layer<S> for RecentLogs {
+    fn on_event(&self, event: &Event, _: Context<S>) {
+        let level = *event.metadata().level();
+        if level > Level::WARN {
+            return;
+        }
+        let mut message = Message::default();
+        event.record(&mut message);
+        let mut logs = RECENT_LOGS.lock();
+        if logs.len() == MAX_RECENT_LOGS {
+            logs.pop_front();
+        }
+        logs.push_back(LogEntry {
+            level,
+            message: message.0,
+        });
+    }
+}
+
+/// Formats an event's message followed by its other fields
+#[derive(Default)]
+struct Message(String);
+
+impl Visit for Message {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0.insert_str(0, value);
+        } else {
+            let _ = write!(self.0, " {}={value}", field.name());
+        }
+    }
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, &format!("{value:?}"));
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+#[test]
+fn recent_logs_test() {
+    let subscriber = tracing_subscriber::registry().with(RecentLogs);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("Not kept");
+        tracing::warn!(place = "nowhere", words = ?["Se", "Vu"], "Something went wrong");
+    });
+    let logs = recent_logs();
+    let entry = logs.last().unwrap();
+    assert_eq!(entry.level, Level::WARN);
+    assert_eq!(
+        entry.message,
+        r#"Something went wrong place=nowhere words=["Se", "Vu"]"#
+    );
+    assert!(logs.iter().all(|entry| entry.message != "Not kept"));
+}
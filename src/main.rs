@@ -1,5 +1,8 @@
 #![windows_subsystem = "windows"]
 
+mod audio;
+mod camera;
+mod carry;
 mod color;
 mod conduit;
 mod controls;
@@ -8,39 +11,169 @@ mod error;
 mod field;
 mod function;
 mod game;
+mod gamepad;
 mod image;
+mod keybindings;
+mod logging;
 mod main_menu;
 mod math;
 mod new_game;
 mod npc;
 mod object;
+mod particle;
 mod person;
 mod physics;
 mod player;
 mod plot;
+mod quest;
+mod replay;
+mod resources;
+mod save;
+mod settings;
+mod snapshot;
+mod spellbook;
 mod stack;
 mod texture;
 mod utils;
 mod word;
 mod world;
+mod world_view;
 
+use std::{env, path::PathBuf, process::exit, time::Duration};
+
+use audio::update_audio;
 use dialog::DIALOG_SCENES;
 use eframe::egui::*;
 use game::Game;
+use logging::init_logging;
 use main_menu::main_menu;
 use new_game::NewGame;
 use npc::NPCS;
-use object::{OBJECTS, PLACES};
 use once_cell::sync::Lazy;
 use player::{Gender, Player};
+use plot::adapt_plot_quality;
+use replay::{recording_message, Replay};
+use settings::{load_settings, save_settings, settings};
 use texture::load_textures;
+use tracing::{error, info};
+
+/// Options for development, given on the command line
+#[derive(Default)]
+struct Args {
+    /// A replay to play back before continuing normally
+    replay: Option<PathBuf>,
+    /// A save file to load instead of starting a new game
+    save: Option<PathBuf>,
+    /// The place to start in
+    place: Option<String>,
+    skip_intro: bool,
+    unlock_all: bool,
+    /// Whether to show debug logs
+    verbose: bool,
+}
+
+impl Args {
+    /// Whether any option that starts a game was given
+    fn starts_game(&self) -> bool {
+        self.replay.is_some()
+            || self.save.is_some()
+            || self.place.is_some()
+            || self.skip_intro
+            || self.unlock_all
+    }
+}
+
+fn parse_args() -> Args {
+    let mut args = Args::default();
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = |what: &str| {
+            iter.next().unwrap_or_else(|| {
+                eprintln!("{arg} needs {what}");
+                exit(1);
+            })
+        };
+        match arg.as_str() {
+            "--replay" => args.replay = Some(value("a file").into()),
+            "--save" => args.save = Some(value("a file").into()),
+            "--place" => args.place = Some(value("a place name")),
+            "--skip-intro" => args.skip_intro = true,
+            "--unlock-all" => args.unlock_all = true,
+            "--verbose" => args.verbose = true,
+            _ => {
+                eprintln!("Unknown argument: {arg}");
+                exit(1);
+            }
+        }
+    }
+    // A replay has to start exactly as it was recorded
+    let replay = args.replay.take();
+    if replay.is_some() && args.starts_game() {
+        eprintln!("--replay can't be used with other options");
+        exit(1);
+    }
+    args.replay = replay;
+    args
+}
+
+/// Start the game the arguments ask for, exiting if that isn't possible
+fn start_game(args: Args) -> Game {
+    let mut game = if let Some(path) = &args.replay {
+        Replay::load(path)
+            .and_then(Game::from_replay)
+            .unwrap_or_else(|e| {
+                eprintln!("Unable to play {}: {e}", path.display());
+                exit(1);
+            })
+    } else if let Some(path) = &args.save {
+        Game::load_from(path).unwrap_or_else(|e| {
+            eprintln!("Unable to load {}: {e}", path.display());
+            exit(1);
+        })
+    } else {
+        let player = Player::new("Kai".into(), Gender::Male);
+        if args.skip_intro {
+            Game::from_player(player)
+        } else {
+            Game::new(player)
+        }
+    };
+    if let Some(place) = &args.place {
+        if !game.world.resources.places.contains_key(place) {
+            let mut places: Vec<&str> = game
+                .world
+                .resources
+                .places
+                .keys()
+                .map(String::as_str)
+                .collect();
+            places.sort_unstable();
+            eprintln!("Unknown place {place}. Places: {}", places.join(", "));
+            exit(1);
+        }
+        if let Err(e) = game.world.load_place(place) {
+            eprintln!("Unable to load {place}: {e}");
+            exit(1);
+        }
+    }
+    if args.unlock_all {
+        game.world.player.progression.unlock_all();
+    }
+    // Only a save given on its own is saved back to, so that dev sessions can't leak into saves
+    if args.save.is_none() || args.place.is_some() || args.unlock_all {
+        game.save_path = None;
+    }
+    game
+}
 
 fn main() {
+    let args = parse_args();
+    init_logging(args.verbose);
     // Load resources
     Lazy::force(&DIALOG_SCENES);
-    Lazy::force(&OBJECTS);
-    Lazy::force(&PLACES);
     Lazy::force(&NPCS);
+    load_settings();
+    let started_game = args.starts_game().then(|| start_game(args));
     // Enable profiling
     puffin::set_scopes_on(cfg!(all(feature = "profile", not(debug_assertions))));
     // Run
@@ -48,6 +181,7 @@ fn main() {
         "Eidos",
         eframe::NativeOptions {
             initial_window_size: Some(Vec2::new(1280.0, 800.0)),
+            vsync: settings().vsync,
             ..Default::default()
         },
         Box::new(|cc| {
@@ -67,7 +201,9 @@ fn main() {
                 .push("emoji".into());
             ctx.set_fonts(fonts);
 
-            Box::new(if cfg!(feature = "title") {
+            Box::new(if let Some(game) = started_game {
+                GameState::Game(game.into())
+            } else if cfg!(feature = "title") {
                 GameState::MainMenu
             } else {
                 GameState::Game(Game::new(Player::new("Kai".into(), Gender::Male)).into())
@@ -102,11 +238,19 @@ impl eframe::App for GameState {
         };
         let ppp_divider = 700.0 / ppp_scale;
         let target_ppp = ((window_size.x * window_size.y).sqrt() / ppp_divider)
-            .clamp(1.2 * ppp_scale, 3.0 * ppp_scale);
+            .clamp(1.2 * ppp_scale, 3.0 * ppp_scale)
+            * settings().ui_scale;
         if (target_ppp - ctx.pixels_per_point()).abs() > 0.001 {
             ctx.set_pixels_per_point(target_ppp);
         }
 
+        if let Some(cpu_usage) = frame.info().cpu_usage {
+            adapt_plot_quality(cpu_usage);
+        }
+        if update_audio(ctx.input(|input| input.stable_dt)) {
+            ctx.request_repaint();
+        }
+
         let new_state = match self {
             GameState::MainMenu => main_menu(ctx),
             GameState::NewGame(new_game) => new_game.show(ctx),
@@ -121,6 +265,29 @@ impl eframe::App for GameState {
             *self = new_state;
         }
 
-        ctx.request_repaint();
+        if let Some(fps) = settings().fps_cap {
+            ctx.request_repaint_after(Duration::from_secs_f32(1.0 / fps as f32));
+        } else {
+            ctx.request_repaint();
+        }
+    }
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if let Err(e) = save_settings() {
+            error!("Unable to save settings: {e}");
+        }
+        // Save automatically when quitting
+        if let GameState::Game(game) = self {
+            if let Some(message) = game.finish_recording().map(recording_message) {
+                info!("{message}");
+            }
+            if game.save_path.is_some() {
+                if let Err(e) = game.save() {
+                    error!("Unable to save: {e}");
+                }
+            }
+            if let Err(e) = game.ui_state.save_layout() {
+                error!("Unable to save the field layout: {e}");
+            }
+        }
     }
 }
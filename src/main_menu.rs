@@ -4,7 +4,7 @@ use eframe::egui::{plot::*, *};
 use once_cell::sync::Lazy;
 use rand::prelude::*;
 
-use crate::{new_game::NewGame, plot::time, GameState};
+use crate::{game::Game, new_game::NewGame, plot::time, save::save_exists, GameState};
 
 const LOGO_ASCII: &str = "
    ▄████████   ▄█   ████████▄    ▄██████▄      ▄████████
@@ -61,6 +61,16 @@ fn main_menu_ui(ui: &mut Ui) -> Option<GameState> {
     let mut res = None;
     ui.with_layout(Layout::top_down(Align::Center), |ui| {
         ui.spacing_mut().item_spacing.y = 20.0;
+        let error_id = Id::new("load error");
+        if save_exists() && ui.button(RichText::new("Continue").heading()).clicked() {
+            match Game::load() {
+                Ok(game) => res = Some(GameState::Game(game.into())),
+                Err(e) => ui.data_mut(|data| data.insert_temp(error_id, e.to_string())),
+            }
+        }
+        if let Some(error) = ui.data(|data| data.get_temp::<String>(error_id)) {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
         if ui.button(RichText::new("New Game").heading()).clicked() {
             res = Some(GameState::NewGame(NewGame::default()));
         }
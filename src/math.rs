@@ -5,7 +5,8 @@ use std::{
     ops::*,
 };
 
-use eframe::epaint::{pos2, vec2, Pos2, Vec2};
+use eframe::epaint::{pos2, util::hash, vec2, Pos2, Vec2};
+use rand::prelude::*;
 use rapier2d::{na::Vector2, prelude::*};
 
 pub fn go_to(from: f32, to: f32, delta: f32) -> f32 {
@@ -24,6 +25,23 @@ where
     a * (1.0 - t) + b * t
 }
 
+pub fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Smooth value noise in the range [-1, 1] with features about 1 unit wide
+pub fn value_noise(seed: u64, pos: Pos2) -> f32 {
+    let lattice =
+        |i: i64, j: i64| SmallRng::seed_from_u64(hash((seed, i, j))).gen_range(-1f32..=1.0);
+    let i = pos.x.floor() as i64;
+    let j = pos.y.floor() as i64;
+    let tx = smoothstep(pos.x - i as f32);
+    let ty = smoothstep(pos.y - j as f32);
+    let bottom = lerp(lattice(i, j), lattice(i + 1, j), tx);
+    let top = lerp(lattice(i, j + 1), lattice(i + 1, j + 1), tx);
+    lerp(bottom, top, ty)
+}
+
 pub fn approach_one(x: f32, mid: f32) -> f32 {
     x.signum() * (1.0 - 2f32.powf(-x.abs() / mid))
 }
@@ -186,3 +204,17 @@ fn polygon_contains_test() {
     assert!(!polygon_contains(&rectangle, pos2(-1.0, 1.0)));
     assert!(!polygon_contains(&rectangle, pos2(-1.5, 2.0)));
 }
+
+#[test]
+fn value_noise_test() {
+    for i in 0..100 {
+        let pos = pos2(i as f32 * 0.37 - 20.0, i as f32 * -0.71 + 5.0);
+        let n = value_noise(0, pos);
+        assert!((-1.0..=1.0).contains(&n));
+        assert_eq!(n, value_noise(0, pos));
+    }
+    assert_ne!(
+        value_noise(0, pos2(0.5, 0.5)),
+        value_noise(1, pos2(0.5, 0.5))
+    );
+}
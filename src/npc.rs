@@ -1,15 +1,19 @@
 use std::{collections::HashMap, fs};
 
+use eframe::epaint::Pos2;
 use enum_iterator::Sequence;
 use once_cell::sync::Lazy;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    field::OutputFieldKind,
+    object::pos2_as_array,
     person::Person,
     utils::{fatal_error, resources_path},
+    word::Word,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Sequence, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Sequence, Serialize, Deserialize)]
 pub enum NpcId {
     Leavy,
 }
@@ -17,10 +21,108 @@ pub enum NpcId {
 #[derive(Debug, Deserialize)]
 pub struct NpcDef {
     pub max_mana: f32,
+    #[serde(default)]
+    pub spells: Vec<ScriptedSpell>,
+}
+
+/// A spell an npc casts when its trigger is met
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScriptedSpell {
+    pub words: Vec<Word>,
+    pub trigger: SpellTrigger,
+}
+
+/// A condition for an npc to cast a spell
+///
+/// Spells are cast when their trigger starts being met, not while it stays met
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpellTrigger {
+    /// The player comes within a distance of the npc
+    PlayerWithin(f32),
+    /// The player has an active spell of a kind
+    PlayerCasting(OutputFieldKind),
+    /// Some seconds have passed since the spell was last cast
+    Every(f32),
+}
+
+/// How an npc moves around
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Movement {
+    #[default]
+    Idle,
+    /// Walk to random points within a horizontal radius of where the npc spawned
+    Wander { radius: f32 },
+    /// Walk to a point and stop there
+    WalkTo {
+        #[serde(deserialize_with = "pos2_as_array")]
+        pos: Pos2,
+    },
+    /// Stay close to the player
+    FollowPlayer,
+}
+
+/// A dialog started by getting close to an npc
+#[derive(Debug, Clone, Deserialize)]
+pub struct NpcDialog {
+    /// The name of the dialog scene
+    pub scene: String,
+    /// How close the player has to be to talk
+    #[serde(default = "default_talk_radius")]
+    pub radius: f32,
+    /// Start the dialog when the player comes close rather than waiting for the talk key
+    #[serde(default)]
+    pub auto: bool,
+    /// Only start the dialog once per game
+    #[serde(default)]
+    pub once: bool,
+}
+
+fn default_talk_radius() -> f32 {
+    1.5
 }
 
 pub struct Npc {
     pub person: Person,
+    pub movement: Movement,
+    /// Where the npc spawned
+    pub home: Pos2,
+    /// Where the npc is currently walking to
+    pub goal: Option<Pos2>,
+    /// How long the npc has been unable to get closer to its goal
+    pub stuck_time: f32,
+    /// The dialog started by talking to this npc
+    pub dialog: Option<NpcDialog>,
+    pub spells: Vec<NpcSpell>,
+}
+
+pub struct NpcSpell {
+    pub script: ScriptedSpell,
+    /// Whether the trigger was met on the last update
+    pub triggered: bool,
+    pub last_cast: f32,
+}
+
+impl Npc {
+    pub fn new(id: NpcId) -> Self {
+        let def = &NPCS[&id];
+        Npc {
+            person: Person::new(def.max_mana),
+            movement: Movement::Idle,
+            home: Pos2::ZERO,
+            goal: None,
+            stuck_time: 0.0,
+            dialog: None,
+            spells: (def.spells.iter())
+                .map(|script| NpcSpell {
+                    script: script.clone(),
+                    triggered: false,
+                    last_cast: 0.0,
+                })
+                .collect(),
+        }
+    }
 }
 
 pub static NPCS: Lazy<HashMap<NpcId, NpcDef>> =
@@ -1,34 +1,102 @@
-use std::{collections::HashMap, fs};
-
-use anyhow::{anyhow, bail};
 use eframe::egui::*;
-use once_cell::sync::Lazy;
 use rapier2d::prelude::*;
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
-    math::rotate,
-    utils::{fatal_error, resources_path},
+    image::Background,
+    math::{angle_diff, rotate},
+    npc::{Movement, NpcDialog, NpcId},
+    quest::QuestTrigger,
 };
 
 pub struct Object {
     pub kind: ObjectKind,
     pub def: ObjectDef,
     pub pr: PosRot,
+    /// Where the object was before the last physics step
+    pub prev_pr: PosRot,
     pub ordered_pr: PosRot,
     pub vel: Vec2,
     pub heat: f32,
+    pub entropy: f32,
+    /// Accumulated impulse from strong impacts
+    pub damage: f32,
     pub body_handle: RigidBodyHandle,
     pub foreground_handles: Vec<ColliderHandle>,
     pub background_handles: Vec<ColliderHandle>,
+    /// How the object moves, if it is a moving platform
+    pub motion: Option<PlacedMotion>,
+}
+
+/// How a kinematic object moves, relative to where it was placed
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Motion {
+    /// Move through each offset in turn and back to the start, looping every period
+    Waypoints { points: Vec<[f32; 2]>, period: f32 },
+    /// Swing back and forth along an offset, once every period
+    Sine {
+        #[serde(deserialize_with = "vec2_as_array")]
+        amplitude: Vec2,
+        period: f32,
+    },
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Motion {
+    /// Get the offset from the object's placed position at a time
+    pub fn offset(&self, time: f32) -> Vec2 {
+        match self {
+            Motion::Waypoints { points, period } => {
+                if *period <= 0.0 {
+                    return Vec2::ZERO;
+                }
+                let stops = [Vec2::ZERO]
+                    .into_iter()
+                    .chain(points.iter().map(|&[x, y]| vec2(x, y)))
+                    .collect::<Vec<_>>();
+                let t = (time / period).rem_euclid(1.0) * stops.len() as f32;
+                let i = t as usize % stops.len();
+                let (a, b) = (stops[i], stops[(i + 1) % stops.len()]);
+                a + (b - a) * t.fract()
+            }
+            Motion::Sine { amplitude, period } => {
+                if *period <= 0.0 {
+                    return Vec2::ZERO;
+                }
+                *amplitude * (time * std::f32::consts::TAU / period).sin()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlacedMotion {
+    pub origin: Pos2,
+    pub motion: Motion,
+    /// The world time when the object was placed, which its motion starts from
+    pub start_time: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PosRot {
     pub pos: Pos2,
     pub rot: f32,
 }
 
+impl PosRot {
+    /// Transform a point into the space of shapes placed at this position and rotation
+    pub fn transform_point(&self, pos: Pos2) -> Pos2 {
+        rotate(pos.to_vec2() - self.pos.to_vec2(), -self.rot).to_pos2()
+    }
+    /// Blend toward another position and rotation, turning the short way around
+    pub fn lerp(&self, other: PosRot, t: f32) -> PosRot {
+        PosRot {
+            pos: self.pos.lerp(other.pos, t),
+            rot: self.rot + angle_diff(self.rot, other.rot) * t,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ObjectKind {
     Object,
@@ -39,8 +107,25 @@ pub enum ObjectKind {
 #[serde(default)]
 pub struct ObjectProperties {
     pub magic: f32,
+    /// How brightly the object glows
+    #[serde(alias = "emission")]
     pub light: f32,
     pub constant_heat: Option<f32>,
+    /// Starting entropy, which adds to disorder
+    pub disorder: f32,
+    /// How much faster the object soaks up heat
+    pub flammable: f32,
+    /// How well the object carries current to objects it touches
+    pub conductive: f32,
+    /// How much extra force the object feels from the force field
+    pub magnetic: f32,
+}
+
+impl ObjectProperties {
+    /// How strongly the object's material reacts to spells
+    pub fn material(&self) -> f32 {
+        self.flammable + self.conductive + self.magnetic
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -50,6 +135,11 @@ pub struct OffsetShape {
     pub offset: Vec2,
     #[serde(default = "default_density")]
     pub density: f32,
+    #[serde(default)]
+    pub friction: Option<f32>,
+    /// Overrides the restitution of the collider
+    #[serde(default)]
+    pub restitution: Option<f32>,
 }
 
 fn default_density() -> f32 {
@@ -86,6 +176,8 @@ impl GraphicalShape {
             shape: self,
             offset,
             density: 1.0,
+            friction: None,
+            restitution: None,
         }
     }
     pub fn contains(&self, pos: Pos2) -> bool {
@@ -108,14 +200,18 @@ impl GraphicalShape {
 impl Object {
     /// Transform a point so that it can be checked against this object's shapes
     pub fn transform_point(&self, pos: Pos2) -> Pos2 {
-        rotate(pos.to_vec2() - self.pr.pos.to_vec2(), -self.pr.rot).to_pos2()
+        self.pr.transform_point(pos)
     }
     pub fn transform_point_as_ordered(&self, pos: Pos2) -> Pos2 {
-        rotate(
-            pos.to_vec2() - self.ordered_pr.pos.to_vec2(),
-            -self.ordered_pr.rot,
-        )
-        .to_pos2()
+        self.ordered_pr.transform_point(pos)
+    }
+    /// Whether the last physics step moved the object
+    pub fn moved(&self) -> bool {
+        self.prev_pr != self.pr
+    }
+    /// Where the object is shown, a fraction of the way from its previous to its current transform
+    pub fn render_pr(&self, alpha: f32) -> PosRot {
+        self.prev_pr.lerp(self.pr, alpha)
     }
 }
 
@@ -135,6 +231,8 @@ impl IntoShapes for GraphicalShape {
             shape: self,
             offset: Vec2::ZERO,
             density: 1.0,
+            friction: None,
+            restitution: None,
         }]
     }
 }
@@ -147,7 +245,7 @@ impl IntoShapes for Vec<OffsetShape> {
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ObjectDef {
-    #[serde(rename = "type")]
+    #[serde(rename = "type", deserialize_with = "body_type")]
     pub ty: RigidBodyType,
     #[serde(default)]
     pub shapes: Vec<OffsetShape>,
@@ -159,6 +257,33 @@ pub struct ObjectDef {
     pub restitution: f32,
     #[serde(default)]
     pub props: ObjectProperties,
+    /// How much damage the object can take before it breaks
+    #[serde(default)]
+    pub durability: Option<f32>,
+    /// The names of the objects left behind when the object breaks
+    #[serde(default)]
+    pub breaks_into: Vec<String>,
+    #[serde(default)]
+    pub collision_group: CollisionGroup,
+    /// The RGB color the object is drawn with in the world view
+    #[serde(default)]
+    pub color: Option<[u8; 3]>,
+    /// The name of an image drawn over the object's shapes in the world view
+    #[serde(default)]
+    pub texture: Option<String>,
+}
+
+/// What an object's shapes collide with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionGroup {
+    /// Collides with everything solid
+    #[default]
+    Scenery,
+    /// Collides with scenery and the ground, but not with other characters
+    Character,
+    /// Collides with nothing, but is still affected by fields
+    Ethereal,
 }
 
 fn default_restitution() -> f32 {
@@ -174,6 +299,11 @@ impl ObjectDef {
             far: Vec::new(),
             restitution: default_restitution(),
             props: ObjectProperties::default(),
+            durability: None,
+            breaks_into: Vec::new(),
+            collision_group: CollisionGroup::Scenery,
+            color: None,
+            texture: None,
         }
     }
     pub fn shapes(self, shapes: impl IntoShapes) -> Self {
@@ -199,17 +329,6 @@ impl ObjectDef {
     }
 }
 
-pub static OBJECTS: Lazy<HashMap<String, ObjectDef>> = Lazy::new(|| {
-    let yaml = fs::read_to_string(resources_path().join("objects.yaml"));
-    let yaml = yaml
-        .as_deref()
-        .unwrap_or(include_str!("../resources/objects.yaml"));
-    match serde_yaml::from_str::<HashMap<String, ObjectDef>>(yaml) {
-        Ok(objects) => objects,
-        Err(e) => fatal_error(format!("Unable to read objects file: {e}")),
-    }
-});
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct PlacedObject {
     pub name: String,
@@ -217,6 +336,23 @@ pub struct PlacedObject {
     pub pos: Pos2,
     #[serde(default)]
     pub replication: Option<Replication>,
+    /// How the object moves, if it is kinematic
+    #[serde(default)]
+    pub motion: Option<Motion>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlacedNpc {
+    pub id: NpcId,
+    #[serde(deserialize_with = "pos2_as_array")]
+    pub pos: Pos2,
+    /// Overrides the npc's default max mana
+    #[serde(default)]
+    pub max_mana: Option<f32>,
+    #[serde(default)]
+    pub dialog: Option<NpcDialog>,
+    #[serde(default)]
+    pub movement: Movement,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -231,6 +367,133 @@ pub struct Replication {
 pub struct Place {
     pub objects: Vec<PlacedObject>,
     pub bounds: Bounds,
+    #[serde(default)]
+    pub wind: Wind,
+    #[serde(default)]
+    pub npcs: Vec<PlacedNpc>,
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+    /// The image shown behind the place
+    #[serde(default)]
+    pub background: Option<Background>,
+    /// The music that loops while the player is here, from `resources/audio/`
+    #[serde(default)]
+    pub music: Option<String>,
+    #[serde(default)]
+    pub sensors: Vec<Sensor>,
+    #[serde(default)]
+    pub water: Vec<Water>,
+    /// The dialog scene started when the player dies here
+    #[serde(default)]
+    pub on_death: Option<String>,
+    #[serde(default)]
+    pub triggers: Vec<DialogTrigger>,
+}
+
+/// A dialog scene started the first time something happens in a place
+#[derive(Debug, Clone, Deserialize)]
+pub struct DialogTrigger {
+    pub when: QuestTrigger,
+    pub scene: String,
+}
+
+impl Place {
+    /// Where the player appears when the place is loaded or when they die in it
+    pub fn spawn(&self) -> Pos2 {
+        pos2((self.bounds.left + self.bounds.right) / 2.0, 0.0)
+    }
+}
+
+/// A body of fluid that objects float in
+#[derive(Debug, Clone, Deserialize)]
+pub struct Water {
+    pub region: Region,
+    #[serde(default = "default_fluid_density")]
+    pub density: f32,
+}
+
+fn default_fluid_density() -> f32 {
+    1.0
+}
+
+impl Water {
+    /// Get how far below the surface a position is, if it is in the water
+    pub fn depth(&self, pos: Pos2) -> Option<f32> {
+        let surface = match &self.region {
+            Region::Rect { min, max } => min.y.max(max.y),
+            Region::Circle { center, radius } => center.y + radius,
+        };
+        self.region.contains(pos).then_some(surface - pos.y)
+    }
+}
+
+/// A named region that reports people entering and leaving it
+#[derive(Debug, Clone, Deserialize)]
+pub struct Sensor {
+    pub name: String,
+    pub region: Region,
+}
+
+/// A region that takes the player to another place
+#[derive(Debug, Clone, Deserialize)]
+pub struct Transition {
+    pub region: Region,
+    /// The name of the place to go to
+    pub to: String,
+    /// Where the player appears in the new place
+    #[serde(deserialize_with = "pos2_as_array")]
+    pub spawn: Pos2,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Region {
+    Rect {
+        #[serde(deserialize_with = "pos2_as_array")]
+        min: Pos2,
+        #[serde(deserialize_with = "pos2_as_array")]
+        max: Pos2,
+    },
+    Circle {
+        #[serde(deserialize_with = "pos2_as_array")]
+        center: Pos2,
+        radius: f32,
+    },
+}
+
+impl Region {
+    pub fn contains(&self, pos: Pos2) -> bool {
+        match self {
+            Region::Rect { min, max } => Rect::from_min_max(*min, *max).contains(pos),
+            Region::Circle { center, radius } => center.distance(pos) <= *radius,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Wind {
+    #[serde(default, deserialize_with = "vec2_as_array")]
+    pub base: Vec2,
+    #[serde(default)]
+    pub gust: Option<Gust>,
+}
+
+/// A sinusoidal variation in wind strength
+#[derive(Debug, Clone, Deserialize)]
+pub struct Gust {
+    /// The fraction of the base wind that is added or removed at the peak of a gust
+    pub amplitude: f32,
+    /// The time in seconds between gusts
+    pub period: f32,
+}
+
+impl Wind {
+    pub fn at(&self, time: f32) -> Vec2 {
+        let gust = self.gust.as_ref().map_or(0.0, |gust| {
+            gust.amplitude * (time * std::f32::consts::TAU / gust.period).sin()
+        });
+        self.base * (1.0 + gust)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -246,35 +509,32 @@ fn default_bottom() -> f32 {
     -1.0
 }
 
-pub static PLACES: Lazy<HashMap<String, Place>> =
-    Lazy::new(|| load_places().unwrap_or_else(|e| fatal_error(e)));
-
-fn load_places() -> anyhow::Result<HashMap<String, Place>> {
-    let mut map = HashMap::new();
-    for entry in fs::read_dir(resources_path().join("places"))
-        .map_err(|e| anyhow!("Unable to open places directory: {e}"))?
-    {
-        let entry = entry.unwrap();
-        if entry.file_type()?.is_file() {
-            let path = entry.path();
-            if path.extension().map_or(false, |ext| ext == "yaml") {
-                let yaml = fs::read_to_string(&path)?;
-                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
-                let place: Place = serde_yaml::from_str(&yaml)
-                    .map_err(|e| anyhow!("Unable to read {name} place: {e}"))?;
-                for po in &place.objects {
-                    if !OBJECTS.contains_key(&po.name) {
-                        bail!("Error in {name} place");
-                    }
-                }
-                map.insert(name, place);
-            }
+/// Read a body type by either its rapier name or a snake case name
+fn body_type<'de, D>(deserializer: D) -> Result<RigidBodyType, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+    Ok(match name.as_str() {
+        "Dynamic" | "dynamic" => RigidBodyType::Dynamic,
+        "Fixed" | "fixed" => RigidBodyType::Fixed,
+        "KinematicPositionBased" | "kinematic_position" => RigidBodyType::KinematicPositionBased,
+        "KinematicVelocityBased" | "kinematic_velocity" => RigidBodyType::KinematicVelocityBased,
+        _ => {
+            return Err(serde::de::Error::unknown_variant(
+                &name,
+                &[
+                    "dynamic",
+                    "fixed",
+                    "kinematic_position",
+                    "kinematic_velocity",
+                ],
+            ))
         }
-    }
-    Ok(map)
+    })
 }
 
-fn vec2_as_array<'de, D>(deserializer: D) -> Result<Vec2, D::Error>
+pub fn vec2_as_array<'de, D>(deserializer: D) -> Result<Vec2, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -282,10 +542,24 @@ where
     Ok(vec2(x, y))
 }
 
-fn pos2_as_array<'de, D>(deserializer: D) -> Result<Pos2, D::Error>
+pub fn pos2_as_array<'de, D>(deserializer: D) -> Result<Pos2, D::Error>
 where
     D: Deserializer<'de>,
 {
     let [x, y] = <[f32; 2]>::deserialize(deserializer)?;
     Ok(pos2(x, y))
 }
+
+pub fn vec2_to_array<S>(v: &Vec2, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    [v.x, v.y].serialize(serializer)
+}
+
+pub fn pos2_to_array<S>(pos: &Pos2, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    [pos.x, pos.y].serialize(serializer)
+}
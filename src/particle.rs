@@ -0,0 +1,108 @@
+use std::f32::consts::TAU;
+
+use eframe::egui::*;
+use rand::prelude::*;
+
+use crate::color::Color;
+
+/// The most particles that can exist at once
+const MAX_PARTICLES: usize = 500;
+/// How much of its velocity a particle keeps each second
+const PARTICLE_DRAG: f32 = 0.2;
+
+#[derive(Debug, Clone)]
+pub struct Particle {
+    pub pos: Pos2,
+    pub vel: Vec2,
+    /// How long the particle has existed in seconds
+    pub age: f32,
+    /// How long the particle lasts in seconds
+    pub lifetime: f32,
+    pub color: Color,
+}
+
+impl Particle {
+    /// How opaque the particle is, fading out over its lifetime
+    pub fn alpha(&self) -> f32 {
+        (1.0 - self.age / self.lifetime).clamp(0.0, 1.0) * self.color.a
+    }
+}
+
+/// Purely visual particles that show spell activity
+pub struct Particles {
+    particles: Vec<Particle>,
+    /// Whether new particles are emitted
+    pub enabled: bool,
+}
+
+impl Default for Particles {
+    fn default() -> Self {
+        Particles {
+            particles: Vec::new(),
+            enabled: true,
+        }
+    }
+}
+
+impl Particles {
+    pub fn iter(&self) -> impl Iterator<Item = &Particle> {
+        self.particles.iter()
+    }
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+    /// Add a particle if there is room for it
+    pub fn emit(&mut self, particle: Particle) {
+        if self.enabled && self.particles.len() < MAX_PARTICLES {
+            self.particles.push(particle);
+        }
+    }
+    /// Emit particles in random directions from a point
+    pub fn burst(&mut self, pos: Pos2, count: usize, speed: f32, lifetime: f32, color: Color) {
+        let mut rng = thread_rng();
+        for _ in 0..count {
+            let angle = rng.gen_range(0.0..TAU);
+            let speed = speed * rng.gen_range(0.5..=1.0);
+            self.emit(Particle {
+                pos,
+                vel: vec2(angle.cos(), angle.sin()) * speed,
+                age: 0.0,
+                lifetime: lifetime * rng.gen_range(0.7..=1.0),
+                color,
+            });
+        }
+    }
+    /// Move and age particles, removing the ones that have faded out
+    pub fn update(&mut self, dt: f32) {
+        if !self.enabled {
+            self.particles.clear();
+            return;
+        }
+        let drag = PARTICLE_DRAG.powf(dt);
+        for particle in &mut self.particles {
+            particle.pos += particle.vel * dt;
+            particle.vel *= drag;
+            particle.age += dt;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+    }
+}
+
+#[test]
+fn particles_test() {
+    let mut particles = Particles::default();
+    let color = Color::rgb(1.0, 1.0, 1.0);
+    // Particles are capped
+    particles.burst(Pos2::ZERO, MAX_PARTICLES + 10, 1.0, 1.0, color);
+    assert_eq!(particles.iter().count(), MAX_PARTICLES);
+    // Particles fade out and are removed
+    particles.update(0.5);
+    assert!(particles.iter().all(|particle| particle.alpha() < 1.0));
+    particles.update(1.0);
+    assert_eq!(particles.iter().count(), 0);
+    // Disabled particles are never emitted
+    particles.enabled = false;
+    particles.burst(Pos2::ZERO, 10, 1.0, 1.0, color);
+    assert_eq!(particles.iter().count(), 0);
+}
@@ -1,19 +1,56 @@
-use std::{collections::HashMap, iter::empty};
+use std::iter::empty;
 
 use derive_more::From;
-use eframe::epaint::Pos2;
+use eframe::epaint::{vec2, Pos2, Rect};
 use enum_iterator::Sequence;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 
-use crate::{conduit::ConduitRack, field::*, npc::NpcId, stack::Stack, word::Word};
+use crate::{
+    conduit::ConduitRack,
+    field::*,
+    function::Function,
+    npc::NpcId,
+    object::{pos2_as_array, pos2_to_array},
+    stack::Stack,
+    word::Word,
+    world::{PERSON_HEIGHT, PERSON_WIDTH},
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, From, Sequence)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, From, Sequence, Serialize, Deserialize)]
 pub enum PersonId {
     Player,
     Npc(NpcId),
 }
 
+/// How much mana each joule of work done by a person's spells costs them
+///
+/// This tunes how quickly sustaining forceful spells drains mana overall
+pub const WORK_MANA_MULTIPLIER: f32 = 0.005;
+/// How much work a person's spells do between each `WorkDone` world event
+pub const WORK_MILESTONE: f32 = 1000.0;
+/// How much mana a person regains per second while they sustain no spells and there is no magic
+pub const MANA_REGEN_RATE: f32 = 2.0;
+/// How much magic around a person doubles their mana regeneration
+const MAGIC_REGEN_SCALE: f32 = 10.0;
+/// The slowest regeneration in a dead-magic zone, as a fraction of the usual rate
+const DEAD_MAGIC_REGEN: f32 = 0.1;
+/// The fastest regeneration in a high-magic zone, as a multiple of the usual rate
+const MAX_MAGIC_REGEN: f32 = 4.0;
+/// The fraction of the usual regeneration that trickles in while sustaining spells that reserve nothing
+const TRICKLE_REGEN: f32 = 0.25;
+/// How much health people start with
+pub const STARTING_MAX_HEALTH: f32 = 100.0;
+
 pub struct Person {
+    /// The mana the person has left, spent by the work their spells do
+    pub mana: f32,
     pub max_mana: f32,
+    /// All the work the person's spells have done
+    pub total_work: f32,
+    pub health: f32,
+    pub max_health: f32,
+    pub pos: Pos2,
     pub target: Option<Pos2>,
     pub stack: Stack,
     pub rack: ConduitRack,
@@ -23,57 +60,180 @@ pub struct Person {
 impl Person {
     pub fn new(max_mana: f32) -> Person {
         Person {
+            mana: max_mana,
             max_mana,
+            total_work: 0.0,
+            health: STARTING_MAX_HEALTH,
+            max_health: STARTING_MAX_HEALTH,
+            pos: Pos2::ZERO,
             target: None,
             stack: Stack::default(),
-            rack: ConduitRack::new(5),
+            rack: ConduitRack::default(),
             active_spells: ActiveSpells::default(),
         }
     }
+    /// Lose some health, down to none
+    pub fn hurt(&mut self, damage: f32) {
+        if damage > 0.0 {
+            self.health = (self.health - damage).max(0.0);
+        }
+    }
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0.0
+    }
+    /// Whether a position is inside the person's body
+    pub fn body_contains(&self, pos: Pos2) -> bool {
+        let min = self.pos - vec2(PERSON_WIDTH / 2.0, 0.0);
+        let max = self.pos + vec2(PERSON_WIDTH / 2.0, PERSON_HEIGHT);
+        Rect::from_min_max(min, max).contains(pos)
+    }
     pub fn reserved_mana(&self) -> f32 {
-        let from_scalars: f32 = self
-            .active_spells
-            .scalars
-            .values()
-            .flatten()
-            .flat_map(|spell| &spell.words)
-            .map(|word| word.cost())
-            .sum();
-        let from_vectors: f32 = self
-            .active_spells
-            .vectors
-            .values()
-            .flatten()
-            .flat_map(|spell| &spell.words)
-            .map(|word| word.cost())
-            .sum();
-        let from_stack: f32 = self
-            .stack
-            .iter()
-            .flat_map(|item| &item.words)
-            .map(|word| word.cost())
-            .sum();
-        from_scalars + from_vectors + from_stack
+        let from_stack: f32 = self.stack.words().map(|word| word.cost()).sum();
+        self.reserved_by_spells() + from_stack
+    }
+    fn reserved_by_spells(&self) -> f32 {
+        self.reservation_breakdown()
+            .map(|reservation| reservation.mana)
+            .sum()
+    }
+    /// Get the mana reserved by each active spell
+    pub fn reservation_breakdown(&self) -> impl Iterator<Item = SpellReservation<'_>> {
+        let scalars = (self.active_spells.scalars.iter()).flat_map(|(kind, spells)| {
+            spells.iter().map(|spell| SpellReservation {
+                kind: (*kind).into(),
+                words: &spell.words,
+                mana: spell.reserved,
+            })
+        });
+        let vectors = (self.active_spells.vectors.iter()).flat_map(|(kind, spells)| {
+            spells.iter().map(|spell| SpellReservation {
+                kind: (*kind).into(),
+                words: &spell.words,
+                mana: spell.reserved,
+            })
+        });
+        scalars.chain(vectors)
+    }
+    /// Get what the reserved mana will be after saying a word
+    pub fn predicted_reservation(&self, word: Word) -> f32 {
+        if let Function::WriteField(_) = word.function() {
+            // Writing keeps the top item and modifiers as a spell and clears the rest of the stack
+            let from_top: f32 = (self.stack.iter().last())
+                .map(|item| item.words.iter().map(|word| word.cost()).sum())
+                .unwrap_or(0.0);
+            let from_modifiers: f32 = self.stack.modifier_words().map(|word| word.cost()).sum();
+            self.reserved_by_spells() + from_top + from_modifiers + word.cost()
+        } else {
+            self.reserved_mana() + word.cost()
+        }
     }
     pub fn capped_mana(&self) -> f32 {
-        self.max_mana - self.reserved_mana()
+        (self.max_mana - self.reserved_mana()).min(self.mana)
+    }
+    /// Pay for the work done by one of the person's spells
+    ///
+    /// Returns the person's total work if it just passed a multiple of [`WORK_MILESTONE`].
+    pub fn do_work(&mut self, work: f32) -> Option<f32> {
+        self.mana = (self.mana - work * WORK_MANA_MULTIPLIER).max(0.0);
+        let milestones = (self.total_work / WORK_MILESTONE).floor();
+        self.total_work += work;
+        ((self.total_work / WORK_MILESTONE).floor() > milestones).then_some(self.total_work)
+    }
+    /// Get how much mana the person regains per second with some magic around them
+    ///
+    /// Magic speeds regeneration up, and negative magic slows it down.
+    /// Sustaining spells only lets a trickle through, which shrinks as more mana is reserved.
+    pub fn mana_regen_rate(&self, magic: f32) -> f32 {
+        let from_magic = (1.0 + magic / MAGIC_REGEN_SCALE).clamp(DEAD_MAGIC_REGEN, MAX_MAGIC_REGEN);
+        let from_spells = if self.active_spells.is_empty() {
+            1.0
+        } else {
+            let unreserved = 1.0 - self.reserved_mana() / self.max_mana.max(1.0);
+            TRICKLE_REGEN * unreserved.clamp(0.0, 1.0)
+        };
+        MANA_REGEN_RATE * from_magic * from_spells
+    }
+    /// Regain some mana, up to the max
+    pub fn regen_mana(&mut self, regen: f32) {
+        self.mana = (self.mana + regen).min(self.max_mana);
     }
 }
 
-type TypedActiveSpells<K, V> = HashMap<K, Vec<ActiveSpell<V>>>;
+/// Spells are kept in the order they were cast, so that updates are deterministic
+type TypedActiveSpells<K, V> = IndexMap<K, Vec<ActiveSpell<V>>>;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ActiveSpells {
     pub scalars: TypedActiveSpells<ScalarOutputFieldKind, ScalarField>,
     pub vectors: TypedActiveSpells<VectorOutputFieldKind, VectorField>,
 }
 
+#[derive(Clone)]
 pub struct ActiveSpell<T> {
     pub field: T,
     pub words: Vec<Word>,
+    /// The mana reserved when the spell was cast
+    pub reserved: f32,
+    pub bounds: Option<SpellBounds>,
+    /// Whether the spell has any effect on the person who cast it
+    pub affects_caster: bool,
+    /// How many more seconds the spell lasts, or `None` if it lasts until dispelled
+    pub lifetime: Option<f32>,
+    /// The work the spell's forces have done on objects since it was cast
+    pub work: f32,
+}
+
+/// The mana reserved by one active spell
+pub struct SpellReservation<'a> {
+    pub kind: OutputFieldKind,
+    pub words: &'a [Word],
+    pub mana: f32,
+}
+
+impl<T> ActiveSpell<T> {
+    /// Create a spell that affects everything, including its caster, until it is dispelled
+    pub fn new(field: T, words: Vec<Word>, reserved: f32) -> Self {
+        ActiveSpell {
+            field,
+            words,
+            reserved,
+            bounds: None,
+            affects_caster: true,
+            lifetime: None,
+            work: 0.0,
+        }
+    }
+    /// Whether the spell has any effect at a position
+    pub fn affects(&self, pos: Pos2) -> bool {
+        self.bounds.is_none_or(|bounds| bounds.contains(pos))
+    }
+    /// Whether the spell has any effect at a position, leaving out its caster's body if it should
+    pub fn affects_from(&self, caster: &Person, pos: Pos2) -> bool {
+        self.affects(pos) && (self.affects_caster || !caster.body_contains(pos))
+    }
+}
+
+/// The radius of a spell cast with the Confine modifier
+pub const CONFINED_SPELL_RADIUS: f32 = 3.0;
+
+/// A circle outside of which a spell has no effect
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SpellBounds {
+    #[serde(serialize_with = "pos2_to_array", deserialize_with = "pos2_as_array")]
+    pub center: Pos2,
+    pub radius: f32,
+}
+
+impl SpellBounds {
+    pub fn contains(&self, pos: Pos2) -> bool {
+        self.center.distance(pos) <= self.radius
+    }
 }
 
 impl ActiveSpells {
+    pub fn is_empty(&self) -> bool {
+        self.scalars.values().all(Vec::is_empty) && self.vectors.values().all(Vec::is_empty)
+    }
     pub fn contains(&self, kind: OutputFieldKind) -> bool {
         match kind {
             OutputFieldKind::Scalar(kind) => self.scalars.contains_key(&kind),
@@ -90,6 +250,50 @@ impl ActiveSpells {
             }
         }
     }
+    /// Whether the `i`th spell of a kind has any effect on its caster
+    pub fn affects_caster(&self, kind: OutputFieldKind, i: usize) -> bool {
+        match kind {
+            OutputFieldKind::Scalar(kind) => self.scalars[&kind][i].affects_caster,
+            OutputFieldKind::Vector(kind) => self.vectors[&kind][i].affects_caster,
+        }
+    }
+    /// How many more seconds the `i`th spell of a kind lasts, if it has a duration
+    pub fn lifetime(&self, kind: OutputFieldKind, i: usize) -> Option<f32> {
+        match kind {
+            OutputFieldKind::Scalar(kind) => self.scalars[&kind][i].lifetime,
+            OutputFieldKind::Vector(kind) => self.vectors[&kind][i].lifetime,
+        }
+    }
+    /// The work the `i`th spell of a kind has done on objects
+    pub fn work(&self, kind: OutputFieldKind, i: usize) -> f32 {
+        match kind {
+            OutputFieldKind::Scalar(kind) => self.scalars[&kind][i].work,
+            OutputFieldKind::Vector(kind) => self.vectors[&kind][i].work,
+        }
+    }
+    /// Count down spell durations and remove expired spells, returning how many were removed
+    pub fn expire(&mut self, dt: f32) -> usize {
+        fn tick<T>(spell: &mut ActiveSpell<T>, dt: f32) -> bool {
+            let Some(lifetime) = &mut spell.lifetime else {
+                return true;
+            };
+            *lifetime -= dt;
+            *lifetime > 0.0
+        }
+        let before = self.count();
+        for spells in self.scalars.values_mut() {
+            spells.retain_mut(|spell| tick(spell, dt));
+        }
+        for spells in self.vectors.values_mut() {
+            spells.retain_mut(|spell| tick(spell, dt));
+        }
+        before - self.count()
+    }
+    fn count(&self) -> usize {
+        let scalars: usize = self.scalars.values().map(Vec::len).sum();
+        let vectors: usize = self.vectors.values().map(Vec::len).sum();
+        scalars + vectors
+    }
     /// Get an iterator over all the words of all the active spells of a given kind.
     pub fn spell_words(
         &self,
@@ -110,4 +314,23 @@ impl ActiveSpells {
             }
         }
     }
+    /// Get the words of the active spells of a given kind that have an effect at a position
+    pub fn spell_words_at(
+        &self,
+        kind: OutputFieldKind,
+        pos: Pos2,
+    ) -> Box<dyn Iterator<Item = &[Word]> + '_> {
+        match kind {
+            OutputFieldKind::Scalar(kind) => Box::new(
+                (self.scalars.get(&kind).into_iter().flatten())
+                    .filter(move |spell| spell.affects(pos))
+                    .map(|spell| spell.words.as_slice()),
+            ),
+            OutputFieldKind::Vector(kind) => Box::new(
+                (self.vectors.get(&kind).into_iter().flatten())
+                    .filter(move |spell| spell.affects(pos))
+                    .map(|spell| spell.words.as_slice()),
+            ),
+        }
+    }
 }
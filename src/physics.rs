@@ -1,14 +1,24 @@
 use std::panic::{catch_unwind, AssertUnwindSafe};
 
-use eframe::epaint::{Pos2, Vec2};
+use eframe::epaint::{vec2, Pos2, Rect, Vec2};
+use enum_iterator::all;
 use itertools::Itertools;
-use rapier2d::{na::Unit, prelude::*};
+use rapier2d::{
+    crossbeam::channel::{unbounded, Receiver},
+    na::Unit,
+    prelude::*,
+};
+use tracing::{debug, error};
 
 use crate::{
     field::*,
-    math::{angle_diff, Convert},
+    math::{angle_diff, rotate, Convert},
     object::*,
-    world::{World, ABSOLUTE_ZERO, AIR_DENSITY_AT_GROUND_TEMP, GROUND_TEMP},
+    person::{ActiveSpells, PersonId},
+    world::{
+        Impact, World, WorldEvent, ABSOLUTE_ZERO, AIR_DENSITY_AT_GROUND_TEMP, DISPEL_THRESHOLD,
+        GROUND_TEMP,
+    },
 };
 
 pub struct PhysicsContext {
@@ -24,10 +34,15 @@ pub struct PhysicsContext {
     multibody_joints: MultibodyJointSet,
     ccd_solver: CCDSolver,
     pub queries: QueryPipeline,
+    event_collector: ChannelEventCollector,
+    contact_force_events: Receiver<ContactForceEvent>,
 }
 
 impl Default for PhysicsContext {
     fn default() -> Self {
+        // Only contact force events are enabled on colliders, so collision events are never sent
+        let (collision_send, _) = unbounded();
+        let (contact_force_send, contact_force_events) = unbounded();
         PhysicsContext {
             pipline: PhysicsPipeline::default(),
             gravity: vector!(0.0, 0.0),
@@ -41,6 +56,8 @@ impl Default for PhysicsContext {
             multibody_joints: MultibodyJointSet::default(),
             ccd_solver: CCDSolver::default(),
             queries: QueryPipeline::default(),
+            event_collector: ChannelEventCollector::new(collision_send, contact_force_send),
+            contact_force_events,
         }
     }
 }
@@ -59,14 +76,21 @@ impl PhysicsContext {
             &mut self.multibody_joints,
             &mut self.ccd_solver,
             &(),
-            &(),
+            &self.event_collector,
         );
+        self.update_queries();
+    }
+    /// Bring the query pipeline up to date with the current bodies and colliders
+    pub fn update_queries(&mut self) {
         self.queries
             .update(&self.islands, &self.bodies, &self.colliders);
     }
     pub fn dt(&self) -> f32 {
         self.integration_parameters.dt
     }
+    pub fn set_dt(&mut self, dt: f32) {
+        self.integration_parameters.dt = dt;
+    }
     pub fn remove_body(&mut self, handle: RigidBodyHandle) {
         self.bodies.remove(
             handle,
@@ -79,6 +103,50 @@ impl PhysicsContext {
     }
 }
 
+/// The force of wind per unit of wind speed per unit of area
+const WIND_DRAG: f32 = 1.0;
+/// The fraction of light that reaches past a fixed object
+const SHADOW_TRANSMITTANCE: f32 = 0.2;
+/// Contacts only do damage above this acceleration, so short falls are harmless
+const IMPACT_ACCELERATION_THRESHOLD: f32 = 500.0;
+/// How close a person must be to an impacting object to be hit by it
+const PERSON_IMPACT_RADIUS: f32 = 0.5;
+/// How quickly an anchor field below 1 slows objects
+const ANCHOR_DAMPING_RATE: f32 = 10.0;
+/// How strongly water slows down objects moving through it
+const WATER_DRAG: f32 = 5.0;
+/// How many points along each axis of a shape are sampled to find how much of it is under water
+const SUBMERSION_SAMPLES: usize = 5;
+/// How many points along each axis of a shape are sampled to find the field forces on it
+const FORCE_SAMPLES: usize = 2;
+
+/// How a vector output field pushes the objects in it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldPush {
+    /// The field is a force, the same for any mass but stronger on magnetic objects
+    Force,
+    /// The field is an acceleration, so the force is proportional to mass
+    Acceleration,
+}
+
+impl FieldPush {
+    /// How a kind of field pushes objects, or `None` if it doesn't
+    fn of(kind: VectorOutputFieldKind) -> Option<Self> {
+        match kind {
+            VectorOutputFieldKind::Gravity => Some(FieldPush::Acceleration),
+            VectorOutputFieldKind::Force => Some(FieldPush::Force),
+            VectorOutputFieldKind::Write => None,
+        }
+    }
+    /// Get the force on an object from the field's value
+    fn force(self, value: Vec2, mass: f32, props: &ObjectProperties) -> Vec2 {
+        match self {
+            FieldPush::Force => value * (1.0 + props.magnetic),
+            FieldPush::Acceleration => value * mass,
+        }
+    }
+}
+
 fn air_density_at_temp(temp: f32) -> f32 {
     (GROUND_TEMP - ABSOLUTE_ZERO) / (temp - ABSOLUTE_ZERO) * AIR_DENSITY_AT_GROUND_TEMP
 }
@@ -87,6 +155,13 @@ impl World {
     /// Run a physics step
     pub fn run_physics(&mut self) {
         puffin::profile_function!();
+        let pushes = all::<VectorOutputFieldKind>()
+            .filter_map(|kind| Some((kind, FieldPush::of(kind)?)))
+            .collect_vec();
+        let dt = self.physics.dt();
+        // The work each spell does this update, attributed from the forces it applies
+        let mut spell_work = Vec::new();
+        let carried = self.carried.map(|carried| carried.handle);
         // Set forces
         for &handle in self.objects.keys().collect_vec() {
             if !self.physics.bodies[handle].is_dynamic() {
@@ -95,11 +170,62 @@ impl World {
             let pos = self.objects[&handle].pr.pos;
             let gravity_acc =
                 self.sample_output_vector_field(VectorOutputFieldKind::Gravity, pos, true);
-            let field_force =
-                self.sample_output_vector_field(VectorOutputFieldKind::Force, pos, true);
             let order = self.sample_output_scalar_field(ScalarOutputFieldKind::Order, pos, true);
+            let anchor = self.sample_output_scalar_field(ScalarOutputFieldKind::Anchor, pos, true);
+            // 1 when unanchored and 0 when fully locked
+            let anchor_freedom = (1.0 - anchor.clamp(0.0, 1.0)).powf(dt * ANCHOR_DAMPING_RATE);
+            let wind = self.sample_input_vector_field(VectorInputFieldKind::Wind, pos);
             let temp = self.temperature_at(pos);
+            let mut volume = 0.0;
+            let mut submerged_volume = 0.0;
+            let mut displaced_fluid = 0.0;
+            for &handle in self.physics.bodies[handle].colliders() {
+                let collider = &self.physics.colliders[handle];
+                let (sub_volume, fluid) = self.submersion(collider);
+                volume += collider.volume();
+                submerged_volume += sub_volume;
+                displaced_fluid += fluid;
+            }
             let obj = &self.objects[&handle];
+            let mass = self.physics.bodies[handle].mass();
+            // Field forces are sampled across the object so that uneven fields can turn it.
+            // The torque is taken around the samples' center so that even fields don't.
+            let points = self.force_points(obj, *self.physics.bodies[handle].position());
+            let center: Vec2 = (points.iter())
+                .map(|&(point, weight)| point.coords * weight)
+                .sum::<Vector<Real>>()
+                .convert();
+            let mut field_force = Vec2::ZERO;
+            let mut field_torque = 0.0;
+            // The power of each spell's force on the object, from the velocity at each sample
+            let mut spell_power = Vec::new();
+            for &(point, weight) in &points {
+                let pos: Pos2 = point.convert();
+                let vel: Vec2 = self.physics.bodies[handle]
+                    .velocity_at_point(&point)
+                    .convert();
+                let mut force = Vec2::ZERO;
+                for &(kind, push) in &pushes {
+                    let mut from_spells = Vec2::ZERO;
+                    for (spell, value) in self.sample_vector_spells(kind, pos, true) {
+                        // The player's own force spells would fight their grip
+                        if carried == Some(handle)
+                            && kind == VectorOutputFieldKind::Force
+                            && spell.0 == PersonId::Player
+                        {
+                            continue;
+                        }
+                        from_spells += value;
+                        let spell_force = push.force(value, mass, &obj.def.props) * weight;
+                        spell_power.push((kind, spell, spell_force.dot(vel).abs()));
+                    }
+                    let value = Self::vector_output_from_spells(kind, from_spells);
+                    force += push.force(value, mass, &obj.def.props) * weight;
+                }
+                let arm = pos.to_vec2() - center;
+                field_force += force;
+                field_torque += arm.x * force.y - arm.y * force.x;
+            }
             let order_pos_diff = obj.ordered_pr.pos - obj.pr.pos;
             let body = &mut self.physics.bodies[handle];
             let order_force = if order.abs() > 0.0 {
@@ -109,43 +235,271 @@ impl World {
             } else {
                 Vec2::ZERO
             };
-            let (total_force, sensor) = if order_force.length() > 0.0 {
-                (order_force, order >= 1.0)
+            let (total_force, total_torque, sensor) = if order_force.length() > 0.0 {
+                (order_force, 0.0, order >= 1.0)
             } else {
-                let gravity_force = gravity_acc * body.mass();
-                let volume: f32 = body
-                    .colliders()
-                    .iter()
-                    .map(|&handle| self.physics.colliders[handle].volume())
-                    .sum();
-                let buoyant_force = -air_density_at_temp(temp) * volume * gravity_acc;
-                (field_force + gravity_force + buoyant_force, false)
+                // Field forces only do work when they are applied
+                spell_work.extend(
+                    (spell_power.into_iter())
+                        .filter(|(.., power)| power.is_finite())
+                        .map(|(kind, spell, power)| (kind, spell, power * anchor_freedom * dt)),
+                );
+                let air_volume = volume - submerged_volume;
+                let displaced_mass = air_density_at_temp(temp) * air_volume + displaced_fluid;
+                let buoyant_force = -displaced_mass * gravity_acc;
+                let wind_force = WIND_DRAG * air_volume * wind;
+                let water_drag_force = -WATER_DRAG * submerged_volume * obj.vel;
+                (
+                    field_force + buoyant_force + wind_force + water_drag_force,
+                    field_torque,
+                    false,
+                )
             };
             for &collider_handle in body.colliders() {
                 let collider = self.physics.colliders.get_mut(collider_handle).unwrap();
                 collider.set_sensor(sensor);
             }
+            if anchor_freedom < 1.0 {
+                let linvel = *body.linvel() * anchor_freedom;
+                let angvel = body.angvel() * anchor_freedom;
+                body.set_linvel(linvel, true);
+                body.set_angvel(angvel, true);
+            }
             body.reset_forces(true);
-            body.add_force(total_force.convert(), true);
             body.reset_torques(true);
+            // Non-finite forces would send the body and everything it touches to NaN
+            let force = total_force * anchor_freedom;
+            if force.is_finite() {
+                body.add_force(force.convert(), true);
+            }
+            let mut torque = total_torque * anchor_freedom;
             if order.abs() > 0.0 {
                 let angle = angle_diff(obj.pr.rot, obj.ordered_pr.rot);
-                let order_torque = order * angle;
-                body.add_torque(order_torque, true);
+                torque += order * angle;
+            }
+            if torque.is_finite() {
+                body.add_torque(torque, true);
+            }
+        }
+        // Each caster pays for the work done by their own spells
+        let mut charges = Vec::new();
+        for (kind, (person_id, i), work) in spell_work {
+            if let Some(person) = self.person_mut(person_id) {
+                person.active_spells.vectors[&kind][i].work += work;
+                charges.push((person_id, work));
             }
         }
+        self.charge_casters(charges);
+        self.hold_carried();
         // Step physics
         self.physics.step();
+        self.handle_impacts();
         // Set object positions from physics system
-        for obj in self.objects.values_mut() {
+        let mut non_finite = Vec::new();
+        for (handle, obj) in &mut self.objects {
             let body = self.physics.bodies.get(obj.body_handle).unwrap();
+            obj.prev_pr = obj.pr;
             obj.pr.pos = body.translation().convert();
             obj.vel = body
                 .velocity_at_point(&Point::from(*body.translation()))
                 .convert();
             obj.pr.rot = body.rotation().angle();
+            if !(obj.pr.pos.is_finite() && obj.pr.rot.is_finite()) {
+                non_finite.push(*handle);
+            }
+        }
+        for handle in non_finite {
+            self.recover_non_finite(handle);
+        }
+    }
+    /// Put an object whose position became non-finite back where it was,
+    /// and dispel the spells that could have pushed it there
+    fn recover_non_finite(&mut self, handle: RigidBodyHandle) {
+        let pr = self.objects[&handle].prev_pr;
+        // The body's own pose is no longer usable, so the points are placed where it was
+        let points = self.force_points(
+            &self.objects[&handle],
+            Isometry::new(pr.pos.convert(), pr.rot),
+        );
+        // Only spells that push objects, and push hard somewhere on the object, are suspect
+        let mut destabilized = Vec::new();
+        let mut words = Vec::new();
+        for person_id in self.person_ids() {
+            let Some(person) = self.person(person_id) else {
+                continue;
+            };
+            for (kind, spells) in &person.active_spells.vectors {
+                if FieldPush::of(*kind).is_none() {
+                    continue;
+                }
+                for (i, spell) in spells.iter().enumerate() {
+                    let suspect = points.iter().any(|&(point, _)| {
+                        let pos: Pos2 = point.convert();
+                        let value = spell.field.sample(self, pos, true);
+                        spell.affects(pos)
+                            && (!value.is_finite() || value.length() > DISPEL_THRESHOLD)
+                    });
+                    if suspect {
+                        destabilized.push((person_id, OutputFieldKind::from(*kind), i));
+                        words.push(&spell.words);
+                    }
+                }
+            }
+        }
+        error!(object = ?handle, last_pos = ?pr.pos, spells = ?words, "Object position became non-finite");
+        let obj = self.objects.get_mut(&handle).unwrap();
+        obj.pr = pr;
+        obj.vel = Vec2::ZERO;
+        let body = &mut self.physics.bodies[handle];
+        body.set_translation(pr.pos.convert(), true);
+        body.set_rotation(Rotation::new(pr.rot), true);
+        body.set_linvel(Vector::zeros(), true);
+        body.set_angvel(0.0, true);
+        // Remove in reverse so that the remaining indices stay valid
+        for &(person_id, kind, i) in destabilized.iter().rev() {
+            if let Some(person) = self.person_mut(person_id) {
+                person.active_spells.remove(kind, i);
+            }
+        }
+        for (caster, _, _) in destabilized.into_iter().dedup_by(|a, b| a.0 == b.0) {
+            self.events.push(WorldEvent::SpellsDestabilized { caster });
+        }
+    }
+    /// Get the points where fields push an object with its body at a pose,
+    /// spread over each of its shapes and weighted by how much of the object's mass they stand for
+    fn force_points(&self, obj: &Object, pose: Isometry<Real>) -> Vec<(Point<Real>, f32)> {
+        let mut points = Vec::new();
+        let colliders = (obj.foreground_handles.iter())
+            .chain(&obj.background_handles)
+            .map(|&handle| &self.physics.colliders[handle]);
+        for collider in colliders {
+            let aabb = collider.shape().compute_local_aabb();
+            let size = aabb.extents();
+            let position = pose * collider.position_wrt_parent().copied().unwrap_or_default();
+            for i in 0..FORCE_SAMPLES {
+                for j in 0..FORCE_SAMPLES {
+                    let t = vector![
+                        (i as f32 + 0.5) / FORCE_SAMPLES as f32,
+                        (j as f32 + 0.5) / FORCE_SAMPLES as f32
+                    ];
+                    let point = position * (aabb.mins + size.component_mul(&t));
+                    points.push((point, collider.mass()));
+                }
+            }
+        }
+        let total: f32 = points.iter().map(|(_, weight)| weight).sum();
+        if total > 0.0 {
+            for (_, weight) in &mut points {
+                *weight /= total;
+            }
+        } else {
+            // Objects without mass are pushed at their center
+            points = vec![(pose.translation.vector.into(), 1.0)];
         }
+        points
     }
+    /// Get the volume of a collider that is under water and the mass of the water it displaces
+    ///
+    /// This is approximated by sampling points in the collider's bounding box
+    fn submersion(&self, collider: &Collider) -> (f32, f32) {
+        let aabb = collider.compute_aabb();
+        let size = aabb.extents();
+        let mut inside = 0;
+        let mut submerged = 0;
+        let mut fluid = 0.0;
+        for i in 0..SUBMERSION_SAMPLES {
+            for j in 0..SUBMERSION_SAMPLES {
+                let t = vector![
+                    (i as f32 + 0.5) / SUBMERSION_SAMPLES as f32,
+                    (j as f32 + 0.5) / SUBMERSION_SAMPLES as f32
+                ];
+                let point = aabb.mins + size.component_mul(&t);
+                if !collider.shape().contains_point(collider.position(), &point) {
+                    continue;
+                }
+                inside += 1;
+                let density = self.fluid_density_at(point.convert());
+                if density > 0.0 {
+                    submerged += 1;
+                    fluid += density;
+                }
+            }
+        }
+        if inside == 0 {
+            return (0.0, 0.0);
+        }
+        let sample_volume = collider.volume() / inside as f32;
+        (submerged as f32 * sample_volume, fluid * sample_volume)
+    }
+    /// Damage objects and hit people with strong contacts from the last step
+    ///
+    /// Impacts from earlier steps are forgotten, so they don't pile up if nothing handles them
+    fn handle_impacts(&mut self) {
+        self.impacts.clear();
+        let dt = self.physics.dt();
+        while let Ok(event) = self.physics.contact_force_events.try_recv() {
+            let handles = [event.collider1, event.collider2];
+            let Some(bodies) = handles
+                .map(|handle| self.physics.colliders[handle].parent())
+                .into_iter()
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            // The lighter body feels the impact the most
+            let Some(mass) = (bodies.iter())
+                .map(|&handle| self.physics.bodies[handle].mass())
+                .filter(|&mass| mass > 0.0)
+                .min_by(f32::total_cmp)
+            else {
+                continue;
+            };
+            let excess_force = event.total_force_magnitude - IMPACT_ACCELERATION_THRESHOLD * mass;
+            if excess_force <= 0.0 {
+                continue;
+            }
+            let impulse = excess_force * dt;
+            for handle in &bodies {
+                if let Some(obj) = self.objects.get_mut(handle) {
+                    if obj.kind != ObjectKind::Ground {
+                        obj.damage += impulse;
+                    }
+                }
+            }
+            // People are hit by moving objects
+            for person_id in self.person_ids() {
+                let Some(person) = self.person(person_id) else {
+                    continue;
+                };
+                let pos = person.pos.convert();
+                let hit = handles.iter().any(|&handle| {
+                    let collider = &self.physics.colliders[handle];
+                    let dynamic = collider
+                        .parent()
+                        .is_some_and(|body| self.physics.bodies[body].is_dynamic());
+                    dynamic
+                        && collider
+                            .shape()
+                            .distance_to_point(collider.position(), &pos, true)
+                            <= PERSON_IMPACT_RADIUS
+                });
+                if hit {
+                    self.impacts.push(Impact { person_id, impulse });
+                }
+            }
+        }
+    }
+}
+
+/// Set the friction and restitution a shape asks for
+fn apply_material(mut collider: ColliderBuilder, shape: &OffsetShape) -> ColliderBuilder {
+    if let Some(friction) = shape.friction {
+        collider = collider.friction(friction);
+    }
+    if let Some(restitution) = shape.restitution {
+        collider = collider.restitution(restitution);
+    }
+    collider
 }
 
 fn graphical_shape_to_shared(shape: &GraphicalShape) -> SharedShape {
@@ -169,15 +523,83 @@ fn graphical_shape_to_shared(shape: &GraphicalShape) -> SharedShape {
 const OBJECT: Group = Group::GROUP_1;
 const BACKGROUND: Group = Group::GROUP_2;
 const GROUND: Group = Group::GROUP_3;
+const CHARACTER: Group = Group::GROUP_4;
+
+/// How far apart debris spawns
+const DEBRIS_SPACING: f32 = 0.2;
 
 impl World {
-    pub fn add_object_def(&mut self, pos: Pos2, def: ObjectDef) {
+    /// Make casters pay for work their spells did, ending the spells of anyone who runs out of mana
+    pub fn charge_casters(&mut self, charges: Vec<(PersonId, f32)>) {
+        let mut exhausted = Vec::new();
+        for (person_id, work) in charges {
+            let Some(person) = self.person_mut(person_id) else {
+                continue;
+            };
+            let milestone = person.do_work(work);
+            if person.mana <= 0.0 {
+                exhausted.push(person_id);
+            }
+            if let Some(total) = milestone {
+                self.events.push(WorldEvent::WorkDone {
+                    caster: person_id,
+                    total,
+                });
+            }
+        }
+        // Spells can't be sustained without mana
+        for caster in exhausted.into_iter().unique() {
+            if let Some(person) = self.person_mut(caster) {
+                person.active_spells = ActiveSpells::default();
+            }
+            debug!(person = ?caster, "Ran out of mana");
+            self.events.push(WorldEvent::ManaDepleted { caster });
+        }
+    }
+    /// Remove an object and replace it with its debris
+    pub fn break_object(&mut self, handle: RigidBodyHandle) {
+        let Some(obj) = self.objects.remove(&handle) else {
+            return;
+        };
+        let body = &self.physics.bodies[handle];
+        let linvel = *body.linvel();
+        let angvel = body.angvel();
+        self.physics.remove_body(handle);
+        self.events
+            .push(WorldEvent::ObjectBroken { pos: obj.pr.pos });
+        let count = obj.def.breaks_into.len();
+        for (i, name) in obj.def.breaks_into.iter().enumerate() {
+            let Some(def) = self.resources.objects.get(name).cloned() else {
+                error!("Unknown debris object {name}");
+                continue;
+            };
+            let offset = (i as f32 - (count - 1) as f32 / 2.0) * DEBRIS_SPACING;
+            let pos = obj.pr.pos + rotate(vec2(offset, 0.0), obj.pr.rot);
+            let debris = self.add_object(
+                ObjectKind::Object,
+                def.clone(),
+                |rb| {
+                    rb.translation(pos.convert())
+                        .rotation(obj.pr.rot)
+                        .linvel(linvel)
+                        .angvel(angvel)
+                },
+                |c| c,
+            );
+            // Debris keeps what the object had absorbed
+            let debris = self.objects.get_mut(&debris).unwrap();
+            debris.heat = obj.heat;
+            debris.entropy = debris.entropy.max(obj.entropy);
+        }
+        self.physics.update_queries();
+    }
+    pub fn add_object_def(&mut self, pos: Pos2, def: ObjectDef) -> RigidBodyHandle {
         self.add_object(
             ObjectKind::Object,
             def,
             |rb| rb.translation(pos.convert()),
             |c| c,
-        );
+        )
     }
     pub fn add_object(
         &mut self,
@@ -195,9 +617,22 @@ impl World {
         let rot = body.rotation().angle();
         let body_handle = self.physics.bodies.insert(body);
         // Create colliders
-        let foreground_groups = match kind {
-            ObjectKind::Object => InteractionGroups::new(OBJECT, OBJECT | GROUND),
-            ObjectKind::Ground => InteractionGroups::new(GROUND, OBJECT | BACKGROUND),
+        let (foreground_groups, background_groups) = match (kind, def.collision_group) {
+            (ObjectKind::Ground, _) => (
+                InteractionGroups::new(GROUND, OBJECT | BACKGROUND | CHARACTER),
+                InteractionGroups::new(BACKGROUND, BACKGROUND | GROUND),
+            ),
+            (ObjectKind::Object, CollisionGroup::Scenery) => (
+                InteractionGroups::new(OBJECT, OBJECT | GROUND | CHARACTER),
+                InteractionGroups::new(BACKGROUND, BACKGROUND | GROUND),
+            ),
+            (ObjectKind::Object, CollisionGroup::Character) => (
+                InteractionGroups::new(CHARACTER, OBJECT | GROUND),
+                InteractionGroups::new(BACKGROUND, BACKGROUND | GROUND),
+            ),
+            (ObjectKind::Object, CollisionGroup::Ethereal) => {
+                (InteractionGroups::none(), InteractionGroups::none())
+            }
         };
         let mut foreground_handles = Vec::new();
         let mut background_handles = Vec::new();
@@ -208,7 +643,8 @@ impl World {
                 .translation(offset_shape.offset.convert())
                 .density(offset_shape.density)
                 .collision_groups(foreground_groups)
-                .build();
+                .active_events(ActiveEvents::CONTACT_FORCE_EVENTS);
+            let collider = apply_material(collider, offset_shape).build();
             foreground_handles.push(self.physics.colliders.insert_with_parent(
                 collider,
                 body_handle,
@@ -221,8 +657,8 @@ impl World {
             let collider = build_collider(ColliderBuilder::new(shared_shape))
                 .translation(offset_shape.offset.convert())
                 .density(offset_shape.density)
-                .collision_groups(InteractionGroups::new(BACKGROUND, BACKGROUND | GROUND))
-                .build();
+                .collision_groups(background_groups);
+            let collider = apply_material(collider, offset_shape).build();
             background_handles.push(self.physics.colliders.insert_with_parent(
                 collider,
                 body_handle,
@@ -234,19 +670,31 @@ impl World {
         let object = Object {
             kind,
             heat: def.props.constant_heat.unwrap_or(GROUND_TEMP),
+            entropy: def.props.disorder,
+            damage: 0.0,
             def,
             pr: transform,
+            prev_pr: transform,
             ordered_pr: transform,
             vel: Vec2::ZERO,
             body_handle,
             foreground_handles,
             background_handles,
+            motion: None,
         };
         self.objects.insert(body_handle, object);
         body_handle
     }
+    /// Get the bounding box of all of an object's colliders
+    pub fn object_bounds(&self, obj: &Object) -> Option<Rect> {
+        (obj.foreground_handles.iter())
+            .chain(&obj.background_handles)
+            .map(|&handle| self.physics.colliders[handle].compute_aabb())
+            .map(|aabb| Rect::from_min_max(aabb.mins.convert(), aabb.maxs.convert()))
+            .reduce(Rect::union)
+    }
     pub fn get_light_at(&self, pos: Pos2) -> f32 {
-        let mut max = 0f32;
+        let mut total = 0.0;
         for light_obj in self.objects.values() {
             if light_obj.def.props.light == 0.0 {
                 continue;
@@ -269,7 +717,9 @@ impl World {
                     |handle, _| {
                         let body_handle = self.physics.colliders[handle].parent().unwrap();
                         let obj = &self.objects[&body_handle];
-                        if obj.background_handles.contains(&handle) {
+                        if obj.background_handles.contains(&handle)
+                            || !self.physics.bodies[body_handle].body_type().is_fixed()
+                        {
                             soft_count += 1;
                             true
                         } else {
@@ -279,13 +729,13 @@ impl World {
                     },
                 );
             }));
+            let mut intensity =
+                light_obj.def.props.light / (1.0 + dist.powi(2)) / (soft_count + 1) as f32;
             if hard {
-                continue;
+                intensity *= SHADOW_TRANSMITTANCE;
             }
-            let intensity =
-                light_obj.def.props.light / (1.0 + dist.powi(2)) / (soft_count + 1) as f32;
-            max = max.max(intensity);
+            total += intensity;
         }
-        max
+        total
     }
 }
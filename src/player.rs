@@ -1,27 +1,59 @@
 use std::collections::HashSet;
 
-use crate::{field::InputFieldKind, person::Person, word::Word};
+use enum_iterator::all;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    conduit::STARTING_CONDUIT_SLOTS, field::InputFieldKind, person::Person, quest::Quest,
+    spellbook::Spellbook, word::Word,
+};
+
+/// The player's max mana before any upgrades
+pub const PLAYER_BASE_MAX_MANA: f32 = 50.0;
 
 pub struct Player {
     pub person: Person,
     pub progression: Progression,
+    pub spellbook: Spellbook,
     pub name: String,
     pub gender: Gender,
+    /// The words said since the stack was last cleared, in order, for undoing
+    pub word_history: Vec<Word>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Gender {
     Male,
     Female,
     Enby,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Progression {
     pub known_words: HashSet<Word>,
     pub known_fields: HashSet<InputFieldKind>,
     pub mana_bar: bool,
+    #[serde(default)]
+    pub health_bar: bool,
     pub free: bool,
+    /// Whether stack items can be removed and reordered by hand
+    #[serde(default)]
+    pub rearrange: bool,
     pub conduit: bool,
+    /// Max mana gained from dialog and quests
+    #[serde(default)]
+    pub mana_bonus: f32,
+    /// How many conduit stones the player can have
+    #[serde(default = "starting_conduit_slots")]
+    pub conduit_slots: usize,
+    /// The dialog scenes that have been started by talking to npcs or by place triggers
+    pub seen_dialogs: HashSet<String>,
+    /// Flags set by dialog choices
+    #[serde(default)]
+    pub flags: HashSet<String>,
+    /// Quests that have been started, in the order they were started
+    #[serde(default)]
+    pub quests: Vec<Quest>,
 }
 
 #[allow(clippy::derivable_impls)]
@@ -31,19 +63,56 @@ impl Default for Progression {
             known_words: HashSet::new(),
             known_fields: HashSet::new(),
             mana_bar: false,
+            health_bar: false,
             free: false,
+            rearrange: false,
             conduit: false,
+            mana_bonus: 0.0,
+            conduit_slots: STARTING_CONDUIT_SLOTS,
+            seen_dialogs: HashSet::new(),
+            flags: HashSet::new(),
+            quests: Vec::new(),
         }
     }
 }
 
+impl Progression {
+    /// Know every word and field and have every ability
+    pub fn unlock_all(&mut self) {
+        self.known_words.extend(all::<Word>());
+        self.known_fields.extend(all::<InputFieldKind>());
+        self.mana_bar = true;
+        self.health_bar = true;
+        self.free = true;
+        self.rearrange = true;
+        self.conduit = true;
+    }
+}
+
+fn starting_conduit_slots() -> usize {
+    STARTING_CONDUIT_SLOTS
+}
+
 impl Player {
     pub fn new(name: String, gender: Gender) -> Self {
         Player {
-            person: Person::new(50.0),
+            person: Person::new(PLAYER_BASE_MAX_MANA),
             progression: Progression::default(),
+            spellbook: Spellbook::default(),
             name,
             gender,
+            word_history: Vec::new(),
         }
     }
+    /// Permanently raise the player's max mana
+    pub fn grant_max_mana(&mut self, mana: f32) {
+        self.set_mana_bonus(self.progression.mana_bonus + mana);
+        // The new mana is ready to use right away
+        self.person.mana += mana;
+    }
+    /// Set how much the player's max mana has been raised, which their max mana is derived from
+    pub fn set_mana_bonus(&mut self, bonus: f32) {
+        self.progression.mana_bonus = bonus;
+        self.person.max_mana = PLAYER_BASE_MAX_MANA + bonus;
+    }
 }
@@ -1,23 +1,33 @@
 use std::{
     cell::RefCell,
     cmp::Ordering,
+    collections::HashMap,
     f32::consts::PI,
     f64,
+    sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
 
+use derive_more::Display;
 use eframe::{
     egui::*,
-    epaint::{util::hash, Hsva},
+    epaint::{mutex::Mutex, util::hash},
 };
+use enum_iterator::Sequence;
+use once_cell::sync::Lazy;
 use rand::prelude::*;
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    color::Color,
+    color::{Color, Palette},
     math::{approach_one, round_to},
+    object::Region,
+    person::SpellBounds,
+    settings::settings,
     texture::textures,
     world::World,
+    world_view::WorldView,
 };
 
 pub struct FieldPlot<'w> {
@@ -26,14 +36,103 @@ pub struct FieldPlot<'w> {
     world_range: f32,
     size: f32,
     global_alpha: f32,
+    show_sensors: bool,
+    precision_scale: f32,
+    hover_readout: bool,
+    legend: bool,
+    palette: Palette,
+    world_view: bool,
+    particles: bool,
+    scalar_mode: ScalarPlotMode,
+    vector_mode: VectorPlotMode,
+}
+
+/// How scalar fields are drawn
+#[derive(Debug, Display, Default, Clone, Copy, PartialEq, Eq, Sequence, Serialize, Deserialize)]
+pub enum ScalarPlotMode {
+    /// A colored point at every sample
+    #[default]
+    Points,
+    /// Lines along which the field is constant
+    Contours,
+    #[display(fmt = "Points and contours")]
+    PointsAndContours,
+}
+
+/// How vector fields are drawn
+#[derive(Debug, Display, Default, Clone, Copy, PartialEq, Eq, Sequence, Serialize, Deserialize)]
+pub enum VectorPlotMode {
+    /// An arrow at every sample
+    #[default]
+    Arrows,
+    /// Lines that follow the field
+    Streamlines,
+}
+
+/// Plots this size are sampled at their field's precision for each point of width
+///
+/// Smaller plots are sampled less densely, and larger plots more coarsely
+const REFERENCE_PLOT_SIZE: f32 = 180.0;
+/// How much of each frame's time budget can be used before plot quality is reduced
+const PLOT_QUALITY_BUDGET: f32 = 0.8;
+/// How much plot quality changes at once
+const PLOT_QUALITY_STEP: f32 = 0.1;
+/// How many frames plot quality stays the same after changing,
+/// so that the slow frames spent resampling at the new quality don't change it again
+const PLOT_QUALITY_COOLDOWN: usize = 30;
+/// How many contour levels there are on each side of zero, spaced by half the color midpoint
+const CONTOUR_LEVELS: i32 = 4;
+/// How many points a contour needs to be labeled with its level
+const CONTOUR_LABEL_MIN_POINTS: usize = 8;
+/// How many streamlines are seeded along each side of a plot
+const STREAMLINE_SEEDS_PER_SIDE: usize = 7;
+/// How many steps a streamline takes in each direction from its seed
+const STREAMLINE_STEPS: usize = 30;
+/// How far a streamline moves each step, relative to the plot's range
+const STREAMLINE_STEP_RATIO: f32 = 0.03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlotSettings {
+    /// Whether to show the position and value under the cursor when hovering a field plot
+    pub hover_readout: bool,
+    /// Multiplies how densely every field is sampled
+    pub precision: f32,
+    /// The least that slow frames can reduce how densely fields are sampled
+    pub min_quality: f32,
+    /// The most that fast frames can increase how densely fields are sampled
+    pub max_quality: f32,
+    pub palette: Palette,
+    /// Whether to draw the world's objects in the density field instead of the place's background
+    pub world_view: bool,
+}
+
+impl Default for PlotSettings {
+    fn default() -> Self {
+        PlotSettings {
+            hover_readout: true,
+            precision: 1.0,
+            min_quality: 0.4,
+            max_quality: 1.0,
+            palette: Palette::default(),
+            world_view: false,
+        }
+    }
 }
 
 pub struct PlotData<V> {
     points: Vec<(f32, f32, V)>,
+    /// The unwiggled samples the points came from
+    samples: Arc<Vec<(Pos2, Vec2, V)>>,
+    /// The distance between samples
+    step: f32,
     center: Pos2,
     range: f32,
     point_radius: f32,
     global_alpha: f32,
+    palette: Palette,
+    scalar_mode: ScalarPlotMode,
+    vector_mode: VectorPlotMode,
 }
 
 pub trait FieldPlottable: Sync {
@@ -41,13 +140,21 @@ pub trait FieldPlottable: Sync {
     fn precision(&self) -> f32;
     fn color_midpoint(&self) -> f32;
     fn get_z(&self, world: &World, pos: Pos2) -> Self::Value;
-    fn get_color(&self, t: Self::Value) -> Color;
+    fn get_color(&self, palette: Palette, t: Self::Value) -> Color;
+    /// Circles outside of which the field's spells have no effect
+    fn bounds(&self, _world: &World) -> Vec<SpellBounds> {
+        Vec::new()
+    }
+    /// Identifies the field for caching samples, or `None` to resample every frame
+    fn cache_id(&self) -> Option<u64> {
+        None
+    }
     fn wiggle_delta(&self, point_radius: f32) -> f32 {
         wiggle_delta(point_radius, self.precision())
     }
 }
 
-pub trait Plottable: Sized + Send {
+pub trait Plottable: Sized + Clone + Send + Sync + 'static {
     fn cmp(&self, other: &Self) -> Ordering;
     fn plot(
         ui: &mut Ui,
@@ -55,9 +162,44 @@ pub trait Plottable: Sized + Send {
         field_plot: &impl FieldPlottable<Value = Self>,
         data: PlotData<Self>,
     );
+    /// Draw a key of what the plot's colors mean
+    fn legend(
+        ui: &mut Ui,
+        rect: Rect,
+        field_plot: &impl FieldPlottable<Value = Self>,
+        palette: Palette,
+        global_alpha: f32,
+    );
     fn format(&self, round: fn(f32) -> f32) -> String;
 }
 
+/// Draw legend text with a shadow so it is readable over the plot
+fn legend_text(ui: &Ui, pos: Pos2, anchor: Align2, text: &str, alpha: f32) {
+    let font_id = TextStyle::Small.resolve(ui.style());
+    let painter = ui.painter();
+    let shadow = Color32::from_black_alpha((alpha * 255.0) as u8);
+    painter.text(pos + vec2(1.0, 1.0), anchor, text, font_id.clone(), shadow);
+    let color = Color32::from_white_alpha((alpha * 255.0) as u8);
+    painter.text(pos, anchor, text, font_id, color);
+}
+
+/// Format a legend value with a couple of significant digits
+fn legend_value(x: f32) -> String {
+    if x == 0.0 {
+        return "0".into();
+    }
+    let digits = (1 - x.abs().log10().floor() as i32).max(0) as usize;
+    format!("{x:.digits$}")
+}
+
+/// Plot samples that are reused until the world or the plot's shape changes
+#[derive(Clone)]
+struct CachedSamples<V> {
+    key: u64,
+    /// Position, wiggle phases, and value of each point, sorted by value
+    samples: Arc<Vec<(Pos2, Vec2, V)>>,
+}
+
 fn wiggle_delta(point_radius: f32, precision: f32) -> f32 {
     point_radius * 0.1 * precision
 }
@@ -69,24 +211,71 @@ pub fn time() -> f64 {
         .as_secs_f64()
 }
 
-pub fn default_scalar_color(t: f32) -> Color {
-    let h = 0.9 * (1.0 - t);
-    let v = (2.0 * t - 1.0).abs().sqrt();
-    let s = v.powi(2);
-    Hsva::new(h, s, v, 1.0).into()
+/// Scales how densely every plot is sampled to keep frames fast
+struct PlotQuality {
+    quality: f32,
+    /// A running average of recent frame times
+    frame_time: Option<f32>,
+    /// How many more frames to wait before changing the quality again
+    cooldown: usize,
+}
+
+impl PlotQuality {
+    /// Step the quality down when frames take too long, and back up when there is time to spare
+    fn adapt(&mut self, frame_time: f32, budget: f32, min: f32, max: f32) {
+        let average = match self.frame_time {
+            Some(average) => average + (frame_time - average) * 0.05,
+            None => frame_time,
+        };
+        self.frame_time = Some(average);
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+            return;
+        }
+        let quality = if average > budget * PLOT_QUALITY_BUDGET {
+            self.quality - PLOT_QUALITY_STEP
+        } else if average < budget * PLOT_QUALITY_BUDGET * 0.5 {
+            self.quality + PLOT_QUALITY_STEP
+        } else {
+            self.quality
+        };
+        let quality = quality.clamp(min, max.max(min));
+        if quality != self.quality {
+            // Wait for the new quality to be reflected in frame times before changing it again
+            self.quality = quality;
+            self.cooldown = PLOT_QUALITY_COOLDOWN;
+        }
+    }
+}
+
+static PLOT_QUALITY: Lazy<Mutex<PlotQuality>> = Lazy::new(|| {
+    Mutex::new(PlotQuality {
+        quality: 1.0,
+        frame_time: None,
+        cooldown: 0,
+    })
+});
+
+/// Get how much plot sampling is currently scaled to keep frames fast
+pub fn plot_quality() -> f32 {
+    PLOT_QUALITY.lock().quality
 }
 
-pub fn simple_vector_color(t: Vec2, offset: f32) -> Color {
-    let t = (t - Vec2::splat(0.5)) * 2.0;
-    let s = t.length();
-    let v = 0.9 * t.length() + 0.1;
-    let h = (t.angle() + PI) / (2.0 * PI);
-    let h = (h + offset) % 1.0;
-    Hsva::new(h, s, v, 1.0).into()
+/// Adjust plot quality for how long the last frame took to draw, in seconds
+pub fn adapt_plot_quality(frame_time: f32) {
+    let settings = settings();
+    let budget = 1.0 / settings.fps_cap.unwrap_or(60) as f32;
+    let (min, max) = (settings.plot.min_quality, settings.plot.max_quality);
+    PLOT_QUALITY.lock().adapt(frame_time, budget, min, max);
 }
 
-pub fn default_vector_color(t: Vec2) -> Color {
-    simple_vector_color(t, 0.75)
+/// How many samples wide a plot should be for its size, before precision
+fn size_resolution(size: f32) -> f32 {
+    if size > REFERENCE_PLOT_SIZE {
+        (size * REFERENCE_PLOT_SIZE).sqrt()
+    } else {
+        size * size / REFERENCE_PLOT_SIZE
+    }
 }
 
 pub struct PlotResponse {
@@ -102,6 +291,66 @@ impl<'w> FieldPlot<'w> {
             world_range: range,
             size,
             global_alpha,
+            show_sensors: false,
+            precision_scale: 1.0,
+            hover_readout: true,
+            legend: false,
+            palette: Palette::default(),
+            world_view: false,
+            particles: false,
+            scalar_mode: ScalarPlotMode::default(),
+            vector_mode: VectorPlotMode::default(),
+        }
+    }
+    /// Color the plot with a palette other than the default
+    pub fn palette(self, palette: Palette) -> Self {
+        Self { palette, ..self }
+    }
+    /// Show what the plot's colors mean along its bottom edge
+    pub fn legend(self, legend: bool) -> Self {
+        Self { legend, ..self }
+    }
+    /// Show the position and value under the cursor when hovered
+    pub fn hover_readout(self, hover_readout: bool) -> Self {
+        Self {
+            hover_readout,
+            ..self
+        }
+    }
+    /// Sample the field more or less densely than its usual precision
+    pub fn precision_scale(self, precision_scale: f32) -> Self {
+        Self {
+            precision_scale,
+            ..self
+        }
+    }
+    /// Draw scalar fields with something other than points
+    pub fn scalar_mode(self, scalar_mode: ScalarPlotMode) -> Self {
+        Self {
+            scalar_mode,
+            ..self
+        }
+    }
+    /// Draw vector fields with something other than arrows
+    pub fn vector_mode(self, vector_mode: VectorPlotMode) -> Self {
+        Self {
+            vector_mode,
+            ..self
+        }
+    }
+    /// Draw the world's objects and people behind the field
+    pub fn world_view(self, world_view: bool) -> Self {
+        Self { world_view, ..self }
+    }
+    /// Draw the world's particles over the field
+    pub fn particles(self, particles: bool) -> Self {
+        Self { particles, ..self }
+    }
+    /// Outline the sensors of the current place
+    pub fn show_sensors(self, show_sensors: bool) -> Self {
+        Self {
+            show_sensors,
+            ..self
         }
     }
     pub fn show<F>(&self, ui: &mut Ui, field_plot: &F) -> PlotResponse
@@ -123,9 +372,79 @@ impl<'w> FieldPlot<'w> {
             Rect::from_min_max(Pos2::ZERO, pos2(1.0, 1.0)),
             panel_color,
         );
+        let world_tl = self.world_center + vec2(-self.world_range, self.world_range);
+        let ratio = rect.width() / (self.world_range * 2.0);
+        let to_screen = |pos: Pos2| {
+            let rel_pos = pos - world_tl;
+            rect.left_top() + vec2(rel_pos.x, -rel_pos.y) * ratio
+        };
+        // Draw objects
+        if self.world_view {
+            let painter = ui.painter_at(rect);
+            WorldView::new(
+                &painter,
+                self.world,
+                self.world_center,
+                self.world_range,
+                to_screen,
+                self.global_alpha,
+            )
+            .draw();
+        }
         // Plot data
-        let data = self.get_data(field_plot);
+        let data = self.get_data(ui.ctx(), field_plot);
         F::Value::plot(ui, rect, field_plot, data);
+        if self.legend {
+            F::Value::legend(ui, rect, field_plot, self.palette, self.global_alpha);
+        }
+        // Draw particles
+        if self.particles {
+            let painter = ui.painter_at(rect);
+            for particle in self.world.particles.iter() {
+                if particle.pos.distance(self.world_center) > self.world_range {
+                    continue;
+                }
+                let color = particle.color.with_a(particle.alpha() * self.global_alpha);
+                painter.circle_filled(to_screen(particle.pos), 1.5, color);
+            }
+        }
+        // Draw spell bounds
+        let stroke_color = Color32::from_white_alpha((self.global_alpha * 40.0) as u8);
+        for bounds in field_plot.bounds(self.world) {
+            let center = to_screen(bounds.center);
+            let stroke = Stroke::new(1.0, stroke_color);
+            ui.painter_at(rect)
+                .circle_stroke(center, bounds.radius * ratio, stroke);
+        }
+        // Draw sensors
+        if let Some(place) = (self.show_sensors)
+            .then(|| self.world.resources.places.get(&self.world.place))
+            .flatten()
+        {
+            let painter = ui.painter_at(rect);
+            let stroke = Stroke::new(1.0, Color32::from_rgb(255, 200, 0));
+            for sensor in &place.sensors {
+                let label_pos = match &sensor.region {
+                    Region::Rect { min, max } => {
+                        let screen_rect = Rect::from_two_pos(to_screen(*min), to_screen(*max));
+                        painter.rect_stroke(screen_rect, 0.0, stroke);
+                        screen_rect.center_top()
+                    }
+                    Region::Circle { center, radius } => {
+                        painter.circle_stroke(to_screen(*center), radius * ratio, stroke);
+                        to_screen(*center + vec2(0.0, *radius))
+                    }
+                };
+                let font_id = TextStyle::Small.resolve(ui.style());
+                painter.text(
+                    label_pos,
+                    Align2::CENTER_BOTTOM,
+                    &sensor.name,
+                    font_id,
+                    stroke.color,
+                );
+            }
+        }
         // Handle hovering
         let mut hovered_pos = None;
         if let Some(hpos) = response.hover_pos() {
@@ -134,7 +453,7 @@ impl<'w> FieldPlot<'w> {
             let pos =
                 world_tl + vec2(normalized_rect_pos.x, -normalized_rect_pos.y) * self.world_range;
             let relative_pos = pos - self.world_center;
-            if relative_pos.length() < self.world_range {
+            if relative_pos.length() < self.world_range && self.hover_readout {
                 let z = field_plot.get_z(self.world, pos);
                 let anchor = if relative_pos.y > self.world_range * 0.9 {
                     Align2::RIGHT_TOP
@@ -162,6 +481,8 @@ impl<'w> FieldPlot<'w> {
                     }
                 }
                 painter.text(hpos, anchor, text, font_id.clone(), Color32::WHITE);
+            }
+            if relative_pos.length() < self.world_range {
                 hovered_pos = Some(pos);
             }
         }
@@ -246,19 +567,17 @@ impl<'w> FieldPlot<'w> {
             hovered_pos: None,
         }
     }
-    fn get_data<F>(&self, field_plot: &F) -> PlotData<F::Value>
+    fn get_data<F>(&self, ctx: &Context, field_plot: &F) -> PlotData<F::Value>
     where
         F: FieldPlottable,
     {
         puffin::profile_function!();
         let time = time();
-        const SIZE_THRESHOLD: f32 = 180.0;
-        let adjusted_size = if self.size > SIZE_THRESHOLD {
-            self.size.sqrt() * SIZE_THRESHOLD.sqrt()
-        } else {
-            self.size
-        };
-        let resolution = (adjusted_size * field_plot.precision()) as usize;
+        let resolution = (size_resolution(self.size)
+            * field_plot.precision()
+            * self.precision_scale
+            * plot_quality())
+        .max(2.0) as usize;
         let step = 2.0 * self.world_range / resolution as f32;
         let point_radius = self.size / resolution as f32 * 0.5;
         let wiggle_delta = field_plot.wiggle_delta(point_radius);
@@ -266,39 +585,92 @@ impl<'w> FieldPlot<'w> {
             round_to(self.world_center.x, step),
             round_to(self.world_center.y, step),
         );
-        puffin::profile_scope!("point collection outer");
-        let mut points = Vec::with_capacity(resolution * resolution);
-        points.par_extend((0..resolution).par_bridge().flat_map(|i| {
-            puffin::profile_scope!("point collection inner");
-            let x = world_center.x - self.world_range + (i as f32) * step;
-            let rounded_x = round_to(x, step * 0.5);
-            (0..resolution).par_bridge().filter_map(move |j| {
-                let y = world_center.y - self.world_range + (j as f32) * step;
-                if pos2(x, y).distance(self.world_center) > self.world_range {
-                    return None;
-                }
-                let rounded_y = round_to(y, step * 0.5);
-                let mut rng = SmallRng::seed_from_u64(hash((
-                    (rounded_x * 1e6) as i64,
-                    (rounded_y * 1e6) as i64,
-                )));
-                let dxt = rng.gen::<f32>() + rounded_x - x;
-                let dyt = rng.gen::<f32>() + rounded_x - x;
-                let z = field_plot.get_z(self.world, pos2(rounded_x, rounded_y));
-                let dx = (time + dxt as f64 * f64::consts::TAU).sin() as f32 * wiggle_delta;
-                let dy = (time + dyt as f64 * f64::consts::TAU).sin() as f32 * wiggle_delta;
-                Some((x + dx, y + dy, z))
+        // Reuse samples if nothing they depend on has changed
+        let cache_id = (field_plot.cache_id()).map(|id| Id::new(("plot cache", id)));
+        let key = hash((
+            resolution,
+            world_center.x.to_bits(),
+            world_center.y.to_bits(),
+            self.world_range.to_bits(),
+            self.world.field_revision(),
+        ));
+        let cached = cache_id
+            .and_then(|id| ctx.data_mut(|data| data.get_temp::<CachedSamples<F::Value>>(id)))
+            .filter(|cached| cached.key == key);
+        let samples = if let Some(cached) = cached {
+            cached.samples
+        } else {
+            let samples = Arc::new(self.sample(field_plot, resolution, step, world_center));
+            if let Some(id) = cache_id {
+                let cached = CachedSamples {
+                    key,
+                    samples: samples.clone(),
+                };
+                ctx.data_mut(|data| data.insert_temp(id, cached));
+            }
+            samples
+        };
+        // Wiggle points
+        let points = samples
+            .iter()
+            .map(|(pos, phase, z)| {
+                let dx = (time + phase.x as f64 * f64::consts::TAU).sin() as f32 * wiggle_delta;
+                let dy = (time + phase.y as f64 * f64::consts::TAU).sin() as f32 * wiggle_delta;
+                (pos.x + dx, pos.y + dy, z.clone())
             })
-        }));
-        points.par_sort_by(|(_, _, a), (_, _, b)| a.cmp(b));
+            .collect();
         PlotData {
             points,
+            samples,
+            step,
             center: world_center,
             point_radius,
             range: self.world_range,
             global_alpha: self.global_alpha,
+            palette: self.palette,
+            scalar_mode: self.scalar_mode,
+            vector_mode: self.vector_mode,
         }
     }
+    fn sample<F>(
+        &self,
+        field_plot: &F,
+        resolution: usize,
+        step: f32,
+        world_center: Pos2,
+    ) -> Vec<(Pos2, Vec2, F::Value)>
+    where
+        F: FieldPlottable,
+    {
+        // Gather positions first so that sampling can be evenly split between threads
+        let mut positions = Vec::with_capacity(resolution * resolution);
+        for i in 0..resolution {
+            let x = world_center.x - self.world_range + (i as f32) * step;
+            for j in 0..resolution {
+                let y = world_center.y - self.world_range + (j as f32) * step;
+                if pos2(x, y).distance(self.world_center) <= self.world_range {
+                    positions.push(pos2(x, y));
+                }
+            }
+        }
+        puffin::profile_scope!("point collection");
+        let mut samples: Vec<_> = positions
+            .into_par_iter()
+            .map(|pos| {
+                let rounded = pos2(round_to(pos.x, step * 0.5), round_to(pos.y, step * 0.5));
+                let mut rng = SmallRng::seed_from_u64(hash((
+                    (rounded.x * 1e6) as i64,
+                    (rounded.y * 1e6) as i64,
+                )));
+                let dxt = rng.gen::<f32>() + rounded.x - pos.x;
+                let dyt = rng.gen::<f32>() + rounded.x - pos.x;
+                let z = field_plot.get_z(self.world, rounded);
+                (pos, vec2(dxt, dyt), z)
+            })
+            .collect();
+        samples.par_sort_by(|(_, _, a), (_, _, b)| a.cmp(b));
+        samples
+    }
 }
 
 impl Plottable for f32 {
@@ -320,11 +692,15 @@ impl Plottable for f32 {
         data: PlotData<Self>,
     ) {
         puffin::profile_function!("f32");
+        if data.scalar_mode == ScalarPlotMode::Contours {
+            plot_contours(ui, rect, field_plot, &data);
+            return;
+        }
         let midpoint = field_plot.color_midpoint();
         let painter = ui.painter();
         let world_tl = data.center + vec2(-data.range, data.range);
         let ratio = rect.width() / (data.range * 2.0);
-        for (x, y, z) in data.points {
+        for &(x, y, z) in &data.points {
             let t = approach_one(z, midpoint) * 0.5 + 0.5;
             let pos = pos2(x, y);
             let alpha = data.global_alpha
@@ -332,7 +708,7 @@ impl Plottable for f32 {
                     - (pos.distance(data.center) / data.range)
                         .powf(2.0)
                         .clamp(0.0, 1.0));
-            let color = field_plot.get_color(t).mul_a(alpha);
+            let color = field_plot.get_color(data.palette, t).mul_a(alpha);
             if color.a < 1.0 / 255.0 {
                 continue;
             }
@@ -340,9 +716,322 @@ impl Plottable for f32 {
             let point = rect.left_top() + vec2(rel_pos.x, -rel_pos.y) * ratio;
             painter.circle_filled(point, data.point_radius, color);
         }
+        if data.scalar_mode == ScalarPlotMode::PointsAndContours {
+            plot_contours(ui, rect, field_plot, &data);
+        }
+    }
+    fn legend(
+        ui: &mut Ui,
+        rect: Rect,
+        field_plot: &impl FieldPlottable<Value = Self>,
+        palette: Palette,
+        global_alpha: f32,
+    ) {
+        const SEGMENTS: usize = 32;
+        let midpoint = field_plot.color_midpoint();
+        let bar = Rect::from_center_size(
+            rect.center_bottom() - vec2(0.0, 20.0),
+            vec2(rect.width() * 0.6, 6.0),
+        );
+        let painter = ui.painter();
+        for i in 0..SEGMENTS {
+            let t = (i as f32 + 0.5) / SEGMENTS as f32;
+            let color = field_plot.get_color(palette, t).mul_a(global_alpha);
+            let x = bar.left() + bar.width() * i as f32 / SEGMENTS as f32;
+            let segment = Rect::from_min_size(
+                pos2(x, bar.top()),
+                vec2(bar.width() / SEGMENTS as f32 + 0.5, bar.height()),
+            );
+            painter.rect_filled(segment, 0.0, color);
+        }
+        // Ticks are where the color is an eighth of the way from each end
+        let extreme = -midpoint * (1.0 - 0.75f32).log2();
+        for (t, value) in [(0.125, -extreme), (0.5, 0.0), (0.875, extreme)] {
+            let pos = pos2(bar.left() + bar.width() * t, bar.bottom() + 1.0);
+            legend_text(
+                ui,
+                pos,
+                Align2::CENTER_TOP,
+                &legend_value(value),
+                global_alpha,
+            );
+        }
+    }
+}
+
+/// Cached samples put back on the grid they were taken on
+struct SampleGrid<V> {
+    /// The position of the bottom left of the grid
+    origin: Pos2,
+    step: f32,
+    width: usize,
+    /// Rows of values from the bottom up, or `None` where there is no valid sample
+    values: Vec<Option<V>>,
+}
+
+impl<V: Copy> SampleGrid<V> {
+    fn new(data: &PlotData<V>, is_valid: impl Fn(&V) -> bool) -> Self {
+        let origin = data.center - Vec2::splat(data.range);
+        let width = (2.0 * data.range / data.step).ceil() as usize + 1;
+        let mut values = vec![None; width * width];
+        for (pos, _, z) in data.samples.iter() {
+            let i = ((pos.x - origin.x) / data.step).round() as usize;
+            let j = ((pos.y - origin.y) / data.step).round() as usize;
+            if i < width && j < width && is_valid(z) {
+                values[j * width + i] = Some(*z);
+            }
+        }
+        SampleGrid {
+            origin,
+            step: data.step,
+            width,
+            values,
+        }
+    }
+    fn get(&self, i: usize, j: usize) -> Option<V> {
+        (i < self.width && j < self.width)
+            .then(|| self.values[j * self.width + i])
+            .flatten()
+    }
+    fn pos(&self, i: usize, j: usize) -> Pos2 {
+        self.origin + vec2(i as f32, j as f32) * self.step
+    }
+}
+
+impl SampleGrid<Vec2> {
+    /// Bilinearly interpolate the samples around a position
+    fn interpolate(&self, pos: Pos2) -> Option<Vec2> {
+        let rel = (pos - self.origin) / self.step;
+        if rel.x < 0.0 || rel.y < 0.0 {
+            return None;
+        }
+        let (i, j) = (rel.x as usize, rel.y as usize);
+        let (fx, fy) = (rel.x.fract(), rel.y.fract());
+        let bottom = self.get(i, j)? * (1.0 - fx) + self.get(i + 1, j)? * fx;
+        let top = self.get(i, j + 1)? * (1.0 - fx) + self.get(i + 1, j + 1)? * fx;
+        Some(bottom * (1.0 - fy) + top * fy)
+    }
+}
+
+/// A place where a contour crosses the grid, identified by the grid point it starts at and whether
+/// it goes up rather than right
+type Crossing = (usize, usize, bool);
+
+/// Trace the lines along which a scalar field equals a level using marching squares
+///
+/// Cells with a corner outside of the plot are skipped, so lines stop at the plot's edge
+fn contours(grid: &SampleGrid<f32>, level: f32) -> Vec<Vec<Pos2>> {
+    let mut positions = HashMap::new();
+    let mut segments = Vec::new();
+    for j in 0..grid.width.saturating_sub(1) {
+        for i in 0..grid.width - 1 {
+            let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+            let Some(values) = corners
+                .iter()
+                .map(|&(i, j)| grid.get(i, j))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            // Bottom, right, top, and left edges, each going from one corner to the next
+            let edges = [
+                (i, j, false),
+                (i + 1, j, true),
+                (i, j + 1, false),
+                (i, j, true),
+            ];
+            let mut crossed = Vec::with_capacity(4);
+            for (k, &edge) in edges.iter().enumerate() {
+                let (a, b) = (values[k], values[(k + 1) % 4]);
+                if (a >= level) == (b >= level) {
+                    continue;
+                }
+                let t = (level - a) / (b - a);
+                let (pa, pb) = (corners[k], corners[(k + 1) % 4]);
+                let pos = grid.pos(pa.0, pa.1).lerp(grid.pos(pb.0, pb.1), t);
+                positions.insert(edge, pos);
+                crossed.push(edge);
+            }
+            match crossed[..] {
+                [a, b] => segments.push((a, b)),
+                [bottom, right, top, left] => {
+                    // Saddles are resolved by which side of the level the cell's center is on
+                    let center = values.iter().sum::<f32>() / 4.0;
+                    if (center >= level) == (values[0] >= level) {
+                        segments.push((bottom, right));
+                        segments.push((top, left));
+                    } else {
+                        segments.push((left, bottom));
+                        segments.push((right, top));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    // Join segments that share a crossing into lines
+    let mut at_crossing: HashMap<Crossing, Vec<usize>> = HashMap::new();
+    for (s, &(a, b)) in segments.iter().enumerate() {
+        at_crossing.entry(a).or_default().push(s);
+        at_crossing.entry(b).or_default().push(s);
+    }
+    let mut used = vec![false; segments.len()];
+    let mut lines = Vec::new();
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let (a, b) = segments[start];
+        let mut ends = [vec![a], vec![b]];
+        for end in &mut ends {
+            let mut crossing = end[0];
+            while let Some(&next) = at_crossing[&crossing].iter().find(|&&s| !used[s]) {
+                used[next] = true;
+                let (c, d) = segments[next];
+                crossing = if c == crossing { d } else { c };
+                end.push(crossing);
+            }
+        }
+        let [mut back, front] = ends;
+        back.reverse();
+        back.extend(front);
+        lines.push(back.iter().map(|crossing| positions[crossing]).collect());
+    }
+    lines
+}
+
+/// Draw lines along which a scalar field is constant, each labeled with its value
+fn plot_contours(
+    ui: &mut Ui,
+    rect: Rect,
+    field_plot: &impl FieldPlottable<Value = f32>,
+    data: &PlotData<f32>,
+) {
+    puffin::profile_function!();
+    let grid = SampleGrid::new(data, |z| z.is_finite());
+    let midpoint = field_plot.color_midpoint();
+    let painter = ui.painter();
+    let world_tl = data.center + vec2(-data.range, data.range);
+    let ratio = rect.width() / (data.range * 2.0);
+    let to_screen = |pos: Pos2| {
+        let rel_pos = pos - world_tl;
+        rect.left_top() + vec2(rel_pos.x, -rel_pos.y) * ratio
+    };
+    let alpha_at = |pos: Pos2| {
+        data.global_alpha
+            * (1.0 - (pos.distance(data.center) / data.range).powf(2.0)).clamp(0.0, 1.0)
+    };
+    for k in -CONTOUR_LEVELS..=CONTOUR_LEVELS {
+        let level = k as f32 * midpoint * 0.5;
+        let color = field_plot.get_color(data.palette, approach_one(level, midpoint) * 0.5 + 0.5);
+        for line in contours(&grid, level) {
+            // Shadows keep the lines visible over points of the same color
+            for (shadow, width) in [(true, 3.0), (false, 1.5)] {
+                for (a, b) in line.iter().zip(&line[1..]) {
+                    let alpha = alpha_at(*a);
+                    let color = if shadow {
+                        Color32::from_black_alpha((alpha * 120.0) as u8)
+                    } else {
+                        color.mul_a(alpha).into()
+                    };
+                    painter.line_segment([to_screen(*a), to_screen(*b)], Stroke::new(width, color));
+                }
+            }
+            if line.len() >= CONTOUR_LABEL_MIN_POINTS {
+                let pos = line[line.len() / 2];
+                let text = legend_value(level);
+                legend_text(
+                    ui,
+                    to_screen(pos),
+                    Align2::CENTER_CENTER,
+                    &text,
+                    alpha_at(pos),
+                );
+            }
+        }
+    }
+}
+
+/// Draw lines that follow a vector field, fading in along the direction of flow
+fn plot_streamlines(
+    ui: &mut Ui,
+    rect: Rect,
+    field_plot: &impl FieldPlottable<Value = Vec2>,
+    data: PlotData<Vec2>,
+) {
+    puffin::profile_function!();
+    let grid = SampleGrid::new(&data, |z| z.is_finite());
+    let sample = |pos: Pos2| grid.interpolate(pos);
+    let origin = grid.origin;
+    let midpoint = field_plot.color_midpoint();
+    let painter = ui.painter();
+    let world_tl = data.center + vec2(-data.range, data.range);
+    let ratio = rect.width() / (data.range * 2.0);
+    let to_screen = |pos: Pos2| {
+        let rel_pos = pos - world_tl;
+        rect.left_top() + vec2(rel_pos.x, -rel_pos.y) * ratio
+    };
+    let seed_spacing = 2.0 * data.range / STREAMLINE_SEEDS_PER_SIDE as f32;
+    for i in 0..STREAMLINE_SEEDS_PER_SIDE {
+        for j in 0..STREAMLINE_SEEDS_PER_SIDE {
+            let seed = origin + vec2(i as f32 + 0.5, j as f32 + 0.5) * seed_spacing;
+            if seed.distance(data.center) > data.range {
+                continue;
+            }
+            let line = streamline(seed, data.range * STREAMLINE_STEP_RATIO, sample);
+            for (k, (a, b)) in line.iter().zip(&line[1..]).enumerate() {
+                let Some(z) = sample(*a) else {
+                    continue;
+                };
+                let t = z.normalized() * approach_one(z.length(), midpoint);
+                let fade = (k + 1) as f32 / line.len() as f32;
+                let alpha = data.global_alpha
+                    * fade
+                    * (1.0 - (a.distance(data.center) / data.range).powf(2.0)).clamp(0.0, 1.0);
+                let color = field_plot
+                    .get_color(data.palette, t * 0.5 + Vec2::splat(0.5))
+                    .mul_a(alpha);
+                if color.a < 1.0 / 255.0 {
+                    continue;
+                }
+                painter.line_segment([to_screen(*a), to_screen(*b)], Stroke::new(1.5, color));
+            }
+        }
     }
 }
 
+/// Follow a vector field upstream and downstream from a seed with fixed midpoint method steps
+///
+/// The points go in the direction of flow, and stop where the field can't be sampled or vanishes
+fn streamline(seed: Pos2, step: f32, sample: impl Fn(Pos2) -> Option<Vec2>) -> Vec<Pos2> {
+    let direction = |pos: Pos2, sign: f32| {
+        sample(pos)
+            .filter(|v| v.length() > 1e-6)
+            .map(|v| v.normalized() * sign)
+    };
+    let trace = |sign: f32| {
+        let mut points = Vec::new();
+        let mut pos = seed;
+        for _ in 0..STREAMLINE_STEPS {
+            let Some(v1) = direction(pos, sign) else {
+                break;
+            };
+            let Some(v2) = direction(pos + v1 * step * 0.5, sign) else {
+                break;
+            };
+            pos += v2 * step;
+            points.push(pos);
+        }
+        points
+    };
+    let mut line = trace(-1.0);
+    line.reverse();
+    line.push(seed);
+    line.extend(trace(1.0));
+    line
+}
+
 impl Plottable for Vec2 {
     fn cmp(&self, other: &Self) -> Ordering {
         let a = self.length();
@@ -355,7 +1044,8 @@ impl Plottable for Vec2 {
         }
     }
     fn format(&self, round: fn(f32) -> f32) -> String {
-        format!("({}, {})", round(self.x), round(self.y))
+        let (x, y, length) = (round(self.x), round(self.y), round(self.length()));
+        format!("({x}, {y}) |{length}|")
     }
     fn plot(
         ui: &mut Ui,
@@ -364,12 +1054,17 @@ impl Plottable for Vec2 {
         data: PlotData<Self>,
     ) {
         puffin::profile_function!("Vec2");
+        if data.vector_mode == VectorPlotMode::Streamlines {
+            plot_streamlines(ui, rect, field_plot, data);
+            return;
+        }
         let midpoint = field_plot.color_midpoint();
         let painter = ui.painter();
         let world_tl = data.center + vec2(-data.range, data.range);
         let ratio = rect.width() / (data.range * 2.0);
         for (x, y, z) in data.points {
-            let t = vec2(approach_one(z.x, midpoint), approach_one(z.y, midpoint));
+            // Squash the length rather than each component so equal lengths look equal
+            let t = z.normalized() * approach_one(z.length(), midpoint);
             let pos = pos2(x, y);
             let alpha = data.global_alpha
                 * (1.0
@@ -377,7 +1072,7 @@ impl Plottable for Vec2 {
                         .powf(2.0)
                         .clamp(0.0, 1.0));
             let color = field_plot
-                .get_color(t * 0.5 + Vec2::splat(0.5))
+                .get_color(data.palette, t * 0.5 + Vec2::splat(0.5))
                 .mul_a(alpha);
             if color.a < 1.0 / 255.0 {
                 continue;
@@ -392,4 +1087,102 @@ impl Plottable for Vec2 {
             );
         }
     }
+    fn legend(
+        ui: &mut Ui,
+        rect: Rect,
+        field_plot: &impl FieldPlottable<Value = Self>,
+        palette: Palette,
+        global_alpha: f32,
+    ) {
+        // An arrow with the color of a vector whose length is the midpoint
+        const ARROW_LENGTH: f32 = 20.0;
+        let midpoint = field_plot.color_midpoint();
+        let color = field_plot
+            .get_color(palette, vec2(0.5, 0.0) * 0.5 + Vec2::splat(0.5))
+            .mul_a(global_alpha);
+        let start = rect.center_bottom() - vec2(ARROW_LENGTH, 20.0);
+        ui.painter()
+            .arrow(start, vec2(ARROW_LENGTH, 0.0), Stroke::new(2.0, color));
+        let text = format!("|{}|", legend_value(midpoint));
+        let pos = start + vec2(ARROW_LENGTH + 4.0, 0.0);
+        legend_text(ui, pos, Align2::LEFT_CENTER, &text, global_alpha);
+    }
+}
+
+#[test]
+fn streamline_test() {
+    // Streamlines in a uniform field are straight and go with the flow
+    let line = streamline(Pos2::ZERO, 0.1, |_| Some(vec2(2.0, 0.0)));
+    assert_eq!(line.len(), STREAMLINE_STEPS * 2 + 1);
+    assert!(line.iter().all(|p| p.y.abs() < 1e-5));
+    assert!(line.first().unwrap().x < line.last().unwrap().x);
+    // Streamlines in a swirl stay close to their circle
+    let line = streamline(pos2(1.0, 0.0), 0.05, |p| Some(vec2(-p.y, p.x)));
+    assert!(line
+        .iter()
+        .all(|p| (p.to_vec2().length() - 1.0).abs() < 0.01));
+    // Streamlines stop where the field can't be sampled
+    let line = streamline(Pos2::ZERO, 0.1, |p| (p.x < 0.25).then_some(Vec2::X));
+    assert!(line.last().unwrap().x < 0.4);
+    assert_eq!(line.len(), STREAMLINE_STEPS + 3);
+}
+
+#[test]
+fn contours_test() {
+    // A cone's contours are circles
+    let width = 21;
+    let origin = pos2(-1.0, -1.0);
+    let step = 0.1;
+    let mut values = Vec::new();
+    for j in 0..width {
+        for i in 0..width {
+            let pos = origin + vec2(i as f32, j as f32) * step;
+            let distance = pos.to_vec2().length();
+            values.push((distance <= 1.0).then_some(distance));
+        }
+    }
+    let grid = SampleGrid {
+        origin,
+        step,
+        width,
+        values,
+    };
+    let lines = contours(&grid, 0.5);
+    assert_eq!(lines.len(), 1);
+    let line = &lines[0];
+    assert!(line
+        .iter()
+        .all(|p| (p.to_vec2().length() - 0.5).abs() < 0.02));
+    assert_eq!(line.first(), line.last());
+    // Contours stop at the edge of the samples instead of closing outside of them
+    let lines = contours(&grid, 0.98);
+    assert!(lines.iter().flatten().all(|p| p.to_vec2().length() <= 1.0));
+}
+
+#[test]
+fn plot_quality_test() {
+    let mut quality = PlotQuality {
+        quality: 1.0,
+        frame_time: None,
+        cooldown: 0,
+    };
+    // Quality steps down once, then waits for frame times to catch up
+    for _ in 0..PLOT_QUALITY_COOLDOWN {
+        quality.adapt(0.05, 1.0 / 60.0, 0.4, 1.0);
+    }
+    assert!((quality.quality - 0.9).abs() < 1e-5);
+    // Slow frames reduce quality down to the minimum
+    for _ in 0..500 {
+        quality.adapt(0.05, 1.0 / 60.0, 0.4, 1.0);
+    }
+    assert!((quality.quality - 0.4).abs() < 1e-5);
+    // Fast frames restore it up to the maximum
+    for _ in 0..500 {
+        quality.adapt(0.001, 1.0 / 60.0, 0.4, 1.0);
+    }
+    assert_eq!(quality.quality, 1.0);
+    // Small plots get far fewer samples for each point of width than large ones
+    assert!(size_resolution(100.0) / 100.0 < 0.6);
+    assert!(size_resolution(400.0) / 400.0 > size_resolution(100.0) / 100.0);
+    assert_eq!(size_resolution(REFERENCE_PLOT_SIZE), REFERENCE_PLOT_SIZE);
 }
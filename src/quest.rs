@@ -0,0 +1,163 @@
+use std::fs;
+
+use indexmap::IndexMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dialog::DialogEffect,
+    field::OutputFieldKind,
+    person::PersonId,
+    utils::{fatal_error, resources_path},
+    world::WorldEvent,
+};
+
+/// How a quest is described in `quests.yaml`
+#[derive(Debug, Deserialize)]
+pub struct QuestDef {
+    pub title: String,
+    pub steps: Vec<QuestStepDef>,
+    /// Effects applied when every step is done
+    #[serde(default)]
+    pub rewards: Vec<DialogEffect>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum QuestStepDef {
+    /// A step completed by dialog
+    Text(String),
+    /// A step completed when something happens in the world
+    Triggered { text: String, when: QuestTrigger },
+}
+
+impl QuestStepDef {
+    fn text(&self) -> &str {
+        match self {
+            QuestStepDef::Text(text) => text,
+            QuestStepDef::Triggered { text, .. } => text,
+        }
+    }
+    pub fn trigger(&self) -> Option<&QuestTrigger> {
+        match self {
+            QuestStepDef::Text(_) => None,
+            QuestStepDef::Triggered { when, .. } => Some(when),
+        }
+    }
+}
+
+/// Something happening in the world that completes a quest step or starts a dialog
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestTrigger {
+    /// The player casts a spell of a kind
+    Cast(OutputFieldKind),
+    BreakObject,
+    /// The player goes to a place
+    Enter(String),
+    /// The player enters a sensor of a place
+    EnterSensor(String),
+    /// The player's spells have done some total work, checked at each work milestone
+    Work(f32),
+}
+
+impl QuestTrigger {
+    pub fn matches(&self, event: &WorldEvent) -> bool {
+        match (self, event) {
+            (QuestTrigger::Cast(kind), WorldEvent::SpellCast { caster, kind: cast }) => {
+                *caster == PersonId::Player && kind == cast
+            }
+            (QuestTrigger::BreakObject, WorldEvent::ObjectBroken { .. }) => true,
+            (QuestTrigger::Enter(place), WorldEvent::PlaceEntered(entered)) => place == entered,
+            (QuestTrigger::EnterSensor(name), WorldEvent::SensorEntered { sensor, person_id }) => {
+                *person_id == PersonId::Player && name == sensor
+            }
+            (QuestTrigger::Work(work), WorldEvent::WorkDone { caster, total }) => {
+                *caster == PersonId::Player && total >= work
+            }
+            _ => false,
+        }
+    }
+}
+
+pub static QUESTS: Lazy<IndexMap<String, QuestDef>> = Lazy::new(|| {
+    load_quests().unwrap_or_else(|e| fatal_error(format!("Error loading quests: {e}")))
+});
+
+fn load_quests() -> anyhow::Result<IndexMap<String, QuestDef>> {
+    let yaml = fs::read_to_string(resources_path().join("quests.yaml"))?;
+    Ok(serde_yaml::from_str(&yaml)?)
+}
+
+/// A quest the player has started
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Quest {
+    pub id: String,
+    pub title: String,
+    pub steps: Vec<QuestStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestStep {
+    pub text: String,
+    pub done: bool,
+}
+
+impl Quest {
+    pub fn new(id: &str, def: &QuestDef) -> Self {
+        Quest {
+            id: id.into(),
+            title: def.title.clone(),
+            steps: (def.steps.iter())
+                .map(|step| QuestStep {
+                    text: step.text().into(),
+                    done: false,
+                })
+                .collect(),
+        }
+    }
+    pub fn is_complete(&self) -> bool {
+        self.steps.iter().all(|step| step.done)
+    }
+    /// Mark a step as done, returning whether this completed the quest
+    pub fn complete_step(&mut self, step: usize) -> bool {
+        let was_complete = self.is_complete();
+        if let Some(step) = self.steps.get_mut(step) {
+            step.done = true;
+        }
+        !was_complete && self.is_complete()
+    }
+}
+
+#[test]
+fn quest_test() {
+    let quests: std::collections::HashMap<String, QuestDef> = serde_yaml::from_str(
+        "errand:\n  title: An errand\n  steps: [Go, Return]\n  rewards: [give_word: Te]\n",
+    )
+    .unwrap();
+    let mut quest = Quest::new("errand", &quests["errand"]);
+    assert!(!quest.is_complete());
+    assert!(!quest.complete_step(1));
+    assert!(!quest.complete_step(5));
+    assert!(quest.complete_step(0));
+    // Completing a step again doesn't complete the quest again
+    assert!(!quest.complete_step(0));
+    assert!(load_quests().is_ok());
+    // Triggered steps
+    let def: QuestDef =
+        serde_yaml::from_str("title: Warmth\nsteps: [{text: Heat, when: {cast: Heat}}]\n").unwrap();
+    let trigger = def.steps[0].trigger().unwrap();
+    let cast = |caster| WorldEvent::SpellCast {
+        caster,
+        kind: crate::field::ScalarOutputFieldKind::Heat.into(),
+    };
+    assert!(trigger.matches(&cast(PersonId::Player)));
+    assert!(!trigger.matches(&cast(PersonId::Npc(crate::npc::NpcId::Leavy))));
+    let trigger: QuestTrigger = serde_yaml::from_str("work: 2000").unwrap();
+    let work = |total| WorldEvent::WorkDone {
+        caster: PersonId::Player,
+        total,
+    };
+    assert!(!trigger.matches(&work(1000.0)));
+    assert!(trigger.matches(&work(2000.0)));
+}
@@ -0,0 +1,319 @@
+use std::{
+    fs,
+    mem::take,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use eframe::epaint::pos2;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    game::Game,
+    save::Save,
+    settings::settings,
+    utils::data_path,
+    world::{Controls, PlayerInput, World},
+};
+
+/// The player's input for one or more updates in a row
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayStep {
+    /// How many updates in a row had this input
+    #[serde(default = "one")]
+    pub updates: u32,
+    pub controls: Controls,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<[f32; 2]>,
+    /// What the player did just before the first of the updates, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<PlayerInput>,
+}
+
+fn one() -> u32 {
+    1
+}
+
+/// A recording of everything the player did, from a saved starting point
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub save: Save,
+    pub seed: u64,
+    /// The length of each update in seconds
+    pub dt: f32,
+    pub steps: Vec<ReplayStep>,
+    /// Things the player did while recording that can't be played back
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unrecorded: Vec<String>,
+}
+
+impl Replay {
+    pub fn new(save: Save, seed: u64, dt: f32) -> Self {
+        Replay {
+            save,
+            seed,
+            dt,
+            steps: Vec::new(),
+            unrecorded: Vec::new(),
+        }
+    }
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let yaml = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        Ok(serde_yaml::from_str(&yaml)?)
+    }
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let yaml = serde_yaml::to_string(self)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, yaml).with_context(|| format!("Unable to write {}", path.display()))?;
+        Ok(())
+    }
+    /// Record the input for the next update
+    pub fn record(&mut self, world: &World, inputs: Vec<PlayerInput>) {
+        let step = ReplayStep {
+            updates: 1,
+            controls: world.controls.clone(),
+            target: (world.player.person.target).map(|target| [target.x, target.y]),
+            inputs,
+        };
+        // Updates with the same input and nothing new done are counted instead of repeated
+        if let Some(last) = self.steps.last_mut() {
+            if step.inputs.is_empty()
+                && last.controls == step.controls
+                && last.target == step.target
+            {
+                last.updates += 1;
+                return;
+            }
+        }
+        self.steps.push(step);
+    }
+}
+
+/// Feeds a replay's input to the world one update at a time
+pub struct Playback {
+    steps: Vec<ReplayStep>,
+    /// The index of the current step
+    step: usize,
+    /// How many updates of the current step have been played
+    played: u32,
+}
+
+impl Playback {
+    pub fn new(steps: Vec<ReplayStep>) -> Self {
+        Playback {
+            steps,
+            step: 0,
+            played: 0,
+        }
+    }
+    /// Set the world's input for the next update
+    ///
+    /// Returns `false` once the replay is over
+    pub fn play(&mut self, world: &mut World) -> bool {
+        while (self.steps.get(self.step)).is_some_and(|step| self.played >= step.updates) {
+            self.step += 1;
+            self.played = 0;
+        }
+        let Some(step) = self.steps.get(self.step) else {
+            return false;
+        };
+        if self.played == 0 {
+            for input in &step.inputs {
+                world.apply_input(input);
+            }
+        }
+        world.controls = step.controls.clone();
+        world.player.person.target = step.target.map(|[x, y]| pos2(x, y));
+        self.played += 1;
+        true
+    }
+}
+
+/// Whether the game is recording or playing back a replay
+pub enum ReplayMode {
+    Recording(Box<Replay>),
+    Playing { playback: Playback, dt: f32 },
+}
+
+impl ReplayMode {
+    /// The update length the replay was recorded with
+    pub fn dt(&self) -> f32 {
+        match self {
+            ReplayMode::Recording(replay) => replay.dt,
+            ReplayMode::Playing { dt, .. } => *dt,
+        }
+    }
+}
+
+fn replay_path() -> PathBuf {
+    data_path().join("replay.yaml")
+}
+
+/// A finished recording that was written
+pub struct Recorded {
+    pub path: PathBuf,
+    /// Things the player did while recording that can't be played back
+    pub unrecorded: Vec<String>,
+}
+
+/// Describe how writing a finished recording went
+pub fn recording_message(written: anyhow::Result<Recorded>) -> String {
+    match written {
+        Ok(recorded) if recorded.unrecorded.is_empty() => {
+            format!("Recorded to {}", recorded.path.display())
+        }
+        Ok(recorded) => format!(
+            "Recorded to {}, but {} can't be played back, so the replay may differ",
+            recorded.path.display(),
+            recorded.unrecorded.join(" and ")
+        ),
+        Err(e) => format!("Unable to write the recording: {e}"),
+    }
+}
+
+impl Game {
+    /// Rebuild the world from a save, the same way when recording and playing back
+    fn restart_from(&mut self, save: &Save, seed: u64) -> anyhow::Result<()> {
+        self.world = World::new(save.player());
+        self.restore_save(save)?;
+        self.world.reseed(seed);
+        self.world.inputs.clear();
+        Ok(())
+    }
+    /// Note that the player did something the replay being recorded can't play back
+    pub fn note_unrecorded(&mut self, what: &str) {
+        if let Some(ReplayMode::Recording(replay)) = &mut self.replay {
+            if !replay.unrecorded.iter().any(|noted| noted == what) {
+                warn!("The recording can't play back {what}");
+                replay.unrecorded.push(what.into());
+            }
+        }
+    }
+    /// Restart the current place from its saved state and record what the player does from there
+    pub fn start_recording(&mut self) -> anyhow::Result<()> {
+        if self.replay.is_some() {
+            bail!("A replay is already running");
+        }
+        let save = self.to_save();
+        let seed = thread_rng().gen();
+        self.restart_from(&save, seed)?;
+        let replay = Replay::new(save, seed, settings().tick_dt());
+        self.replay = Some(ReplayMode::Recording(replay.into()));
+        Ok(())
+    }
+    /// Stop recording and write the replay, returning where it was written
+    ///
+    /// Returns `None` if nothing was being recorded
+    pub fn finish_recording(&mut self) -> Option<anyhow::Result<Recorded>> {
+        match self.replay.take() {
+            Some(ReplayMode::Recording(replay)) => {
+                let path = replay_path();
+                Some(replay.write(&path).map(|_| Recorded {
+                    path,
+                    unrecorded: replay.unrecorded,
+                }))
+            }
+            playing => {
+                self.replay = playing;
+                None
+            }
+        }
+    }
+    /// Record or play back the player's input, then update the world once
+    pub fn update_world(&mut self) {
+        let inputs = take(&mut self.world.inputs);
+        let replay_over = match &mut self.replay {
+            Some(ReplayMode::Recording(replay)) => {
+                replay.record(&self.world, inputs);
+                false
+            }
+            Some(ReplayMode::Playing { playback, .. }) => !playback.play(&mut self.world),
+            None => false,
+        };
+        if replay_over {
+            self.replay = None;
+            self.ui_state.toast("The replay is over");
+        }
+        self.world.update();
+    }
+    /// Start a game that plays back a replay, then continues normally
+    pub fn from_replay(replay: Replay) -> anyhow::Result<Self> {
+        let mut game = Game::from_player(replay.save.player());
+        game.restart_from(&replay.save, replay.seed)?;
+        // Playing a replay must not overwrite the player's save
+        game.save_path = None;
+        if !replay.unrecorded.is_empty() {
+            game.ui_state.toast(format!(
+                "This replay may differ from the recording, which used {}",
+                replay.unrecorded.join(" and ")
+            ));
+        }
+        game.replay = Some(ReplayMode::Playing {
+            playback: Playback::new(replay.steps),
+            dt: replay.dt,
+        });
+        Ok(game)
+    }
+}
+
+#[test]
+fn replay_test() {
+    use crate::{
+        person::PersonId,
+        player::{Gender, Player},
+        word::Word,
+    };
+    let mut game = Game::from_player(Player::new("Test".into(), Gender::Enby));
+    game.start_recording().unwrap();
+    for i in 0..120 {
+        let controls = &mut game.world.controls;
+        controls.x_slider = Some((i as f32 / 20.0).sin());
+        controls.activation1 = i % 40 < 20;
+        // Stack changes made by hand are played back too
+        let world = &mut game.world;
+        match i {
+            0 => world.pick_up(pos2(-1.5, 0.2)).unwrap(),
+            80 => world.release_carried(2.0),
+            30 | 31 | 40 | 70 => world.say(PersonId::Player, Word::Se).unwrap(),
+            35 => assert!(world.undo_word()),
+            45 => world.swap_stack_items(0, 1),
+            50 => world.remove_stack_item(1),
+            60 => world.free_stack(),
+            _ => {}
+        }
+        game.update_world();
+    }
+    let Some(ReplayMode::Recording(replay)) = game.replay.take() else {
+        panic!("The game stopped recording");
+    };
+    assert!((replay.steps.iter()).any(|step| step.inputs == [PlayerInput::Say(Word::Se)]));
+    assert!((replay.steps.iter()).any(|step| step.inputs == [PlayerInput::Free]));
+    assert!((replay.steps.iter()).any(|step| step.inputs == [PlayerInput::Release(2.0)]));
+    assert!(replay.unrecorded.is_empty());
+    // Playing the replay back ends up in the same state
+    let mut played = Game::from_replay(*replay).unwrap();
+    for _ in 0..120 {
+        played.update_world();
+    }
+    let positions = |game: &Game| {
+        let objects = game
+            .world
+            .objects
+            .values()
+            .map(|obj| (obj.pr.pos, obj.pr.rot));
+        let people = game.world.people().map(|person| (person.pos, 0.0));
+        objects.chain(people).collect::<Vec<_>>()
+    };
+    assert_eq!(positions(&played), positions(&game));
+    let stack = |game: &Game| game.world.player.person.stack.words().collect::<Vec<_>>();
+    assert_eq!(stack(&played), stack(&game));
+    // Then the game continues normally
+    assert!(played.replay.is_some());
+    played.update_world();
+    assert!(played.replay.is_none());
+}
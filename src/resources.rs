@@ -0,0 +1,173 @@
+use std::{collections::HashMap, fmt::Display, fs};
+
+use anyhow::anyhow;
+use serde_yaml::Value;
+use tracing::warn;
+
+use crate::{
+    dialog::DIALOG_SCENES,
+    object::{ObjectDef, Place},
+    utils::resources_path,
+};
+
+/// Object and place definitions, reloadable while the game runs
+#[derive(Default)]
+pub struct Resources {
+    pub objects: HashMap<String, ObjectDef>,
+    pub places: HashMap<String, Place>,
+    pub errors: ResourceErrors,
+}
+
+/// An error in a resource file
+#[derive(Debug)]
+pub struct ResourceError {
+    /// The path of the file relative to the resources directory
+    pub file: String,
+    /// The line and column of the error, if known
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+/// Errors from loading resources, shown in the game instead of exiting
+#[derive(Debug, Default)]
+pub struct ResourceErrors {
+    pub errors: Vec<ResourceError>,
+}
+
+impl ResourceErrors {
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+    pub fn push(&mut self, file: impl Into<String>, error: impl Display) {
+        let file = file.into();
+        warn!(file, "{error}");
+        self.errors.push(ResourceError {
+            file,
+            location: None,
+            message: error.to_string(),
+        });
+    }
+    fn push_yaml(&mut self, file: impl Into<String>, error: serde_yaml::Error) {
+        let file = file.into();
+        warn!(file, "{error}");
+        let location = error.location().map(|loc| (loc.line(), loc.column()));
+        self.errors.push(ResourceError {
+            file,
+            location,
+            message: error.to_string(),
+        });
+    }
+}
+
+impl Resources {
+    /// Load all objects and places, keeping whatever loads successfully
+    pub fn load() -> Self {
+        let mut resources = Resources::default();
+        resources.load_objects();
+        resources.load_places();
+        resources
+    }
+    fn load_objects(&mut self) {
+        let yaml = fs::read_to_string(resources_path().join("objects.yaml"));
+        let yaml = yaml
+            .as_deref()
+            .unwrap_or(include_str!("../resources/objects.yaml"));
+        // Each object is read separately so that one bad object doesn't hide the rest
+        let values: HashMap<String, Value> = match serde_yaml::from_str(yaml) {
+            Ok(values) => values,
+            Err(e) => return self.errors.push_yaml("objects.yaml", e),
+        };
+        for (name, value) in values {
+            match serde_yaml::from_value(value) {
+                Ok(def) => {
+                    self.objects.insert(name, def);
+                }
+                Err(e) => self.errors.push("objects.yaml", format!("{name}: {e}")),
+            }
+        }
+        // Objects break into whatever debris exists
+        let names: Vec<String> = self.objects.keys().cloned().collect();
+        for (name, def) in &mut self.objects {
+            def.breaks_into.retain(|debris| {
+                let known = names.contains(debris);
+                if !known {
+                    let message = format!("{name}: Unknown debris object {debris}");
+                    self.errors.push("objects.yaml", message);
+                }
+                known
+            });
+        }
+    }
+    fn load_places(&mut self) {
+        let entries = match fs::read_dir(resources_path().join("places")) {
+            Ok(entries) => entries,
+            Err(e) => return self.errors.push("places", e),
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.is_file() || path.extension().is_none_or(|ext| ext != "yaml") {
+                continue;
+            }
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let file = format!("places/{name}.yaml");
+            let yaml = match fs::read_to_string(&path) {
+                Ok(yaml) => yaml,
+                Err(e) => {
+                    self.errors.push(file, e);
+                    continue;
+                }
+            };
+            let mut place: Place = match serde_yaml::from_str(&yaml) {
+                Ok(place) => place,
+                Err(e) => {
+                    self.errors.push_yaml(file, e);
+                    continue;
+                }
+            };
+            for po in &place.objects {
+                if !self.objects.contains_key(&po.name) {
+                    self.errors
+                        .push(&file, anyhow!("Unknown object {}", po.name));
+                }
+            }
+            // Npcs with unknown dialog can't be talked to
+            for npc in &mut place.npcs {
+                if let Some(dialog) = &npc.dialog {
+                    if !DIALOG_SCENES.contains_key(&dialog.scene) {
+                        let message =
+                            format!("Unknown dialog scene {} for {:?}", dialog.scene, npc.id);
+                        self.errors.push(&file, message);
+                        npc.dialog = None;
+                    }
+                }
+            }
+            if let Some(scene) =
+                (place.on_death.as_ref()).filter(|scene| !DIALOG_SCENES.contains_key(*scene))
+            {
+                self.errors
+                    .push(&file, anyhow!("Unknown death dialog scene {scene}"));
+            }
+            place.triggers.retain(|trigger| {
+                let known = DIALOG_SCENES.contains_key(&trigger.scene);
+                if !known {
+                    let message = format!("Unknown triggered dialog scene {}", trigger.scene);
+                    self.errors.push(&file, message);
+                }
+                known
+            });
+            self.places.insert(name, place);
+        }
+    }
+}
+
+#[test]
+fn resources_test() {
+    let resources = Resources::load();
+    assert!(resources.errors.is_empty(), "{:?}", resources.errors);
+    assert!(resources.places.contains_key("magician_house"));
+    // Yaml errors keep their location
+    let mut errors = ResourceErrors::default();
+    let e = serde_yaml::from_str::<Place>("bounds: [1, 2]").unwrap_err();
+    errors.push_yaml("places/test.yaml", e);
+    assert!(errors.errors[0].location.is_some());
+}
@@ -0,0 +1,378 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use eframe::epaint::{pos2, vec2};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    conduit::ConduitRack,
+    field::{Field, FieldKind, OutputFieldKind},
+    game::{FieldDisplay, Game, UiState},
+    person::{ActiveSpells, PersonId, SpellBounds},
+    player::{Gender, Player, Progression, PLAYER_BASE_MAX_MANA},
+    plot::{ScalarPlotMode, VectorPlotMode},
+    quest::QUESTS,
+    spellbook::Spellbook,
+    utils::data_path,
+    word::Word,
+};
+
+/// Incremented whenever the save format changes in a way old saves can't be read
+const SAVE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct Save {
+    pub version: u32,
+    pub name: String,
+    pub gender: Gender,
+    pub progression: Progression,
+    pub max_mana: f32,
+    /// All the work the player's spells have done, for work milestones
+    #[serde(default)]
+    pub total_work: f32,
+    pub rack: ConduitRack,
+    #[serde(default)]
+    pub spellbook: Spellbook,
+    pub place: String,
+    pub pos: [f32; 2],
+    /// The words on the player's stack
+    #[serde(default)]
+    pub stack: Vec<Word>,
+    /// Everyone's active spells
+    #[serde(default)]
+    pub active_spells: Vec<SavedSpell>,
+    /// The words of everyone's active spells, from older saves that didn't keep their fields
+    #[serde(default, skip_serializing)]
+    pub spells: Vec<(PersonId, Vec<Word>)>,
+}
+
+/// An active spell as it was when the game was saved
+#[derive(Serialize, Deserialize)]
+pub struct SavedSpell {
+    pub caster: PersonId,
+    pub kind: OutputFieldKind,
+    /// Recast on load to check that the spell can still be sustained
+    pub words: Vec<Word>,
+    /// The spell's field, kept so that sampled values don't change on load
+    pub field: Field,
+    pub bounds: Option<SpellBounds>,
+    pub lifetime: Option<f32>,
+}
+
+impl SavedSpell {
+    /// Put the saved field over the newest spell of the kind, which was just recast from the words
+    fn restore(&self, active_spells: &mut ActiveSpells) {
+        match (self.kind, &self.field) {
+            (OutputFieldKind::Scalar(kind), Field::Scalar(field)) => {
+                let spells = active_spells.scalars.get_mut(&kind);
+                if let Some(spell) = spells.and_then(|spells| spells.last_mut()) {
+                    spell.field = field.clone();
+                    spell.bounds = self.bounds;
+                    spell.lifetime = self.lifetime;
+                }
+            }
+            (OutputFieldKind::Vector(kind), Field::Vector(field)) => {
+                let spells = active_spells.vectors.get_mut(&kind);
+                if let Some(spell) = spells.and_then(|spells| spells.last_mut()) {
+                    spell.field = field.clone();
+                    spell.bounds = self.bounds;
+                    spell.lifetime = self.lifetime;
+                }
+            }
+            _ => warn!("Saved {} spell has the wrong type of field", self.kind),
+        }
+    }
+}
+
+pub fn default_save_path() -> PathBuf {
+    data_path().join("save.yaml")
+}
+
+pub fn save_exists() -> bool {
+    default_save_path().exists()
+}
+
+impl Game {
+    pub fn to_save(&self) -> Save {
+        let player = &self.world.player;
+        let pos = player.person.pos;
+        let mut active_spells = Vec::new();
+        for person_id in self.world.person_ids() {
+            let spells = &self.world.person(person_id).unwrap().active_spells;
+            for (kind, spells) in &spells.scalars {
+                active_spells.extend(spells.iter().map(|spell| SavedSpell {
+                    caster: person_id,
+                    kind: (*kind).into(),
+                    words: spell.words.clone(),
+                    field: spell.field.clone().into(),
+                    bounds: spell.bounds,
+                    lifetime: spell.lifetime,
+                }));
+            }
+            for (kind, spells) in &spells.vectors {
+                active_spells.extend(spells.iter().map(|spell| SavedSpell {
+                    caster: person_id,
+                    kind: (*kind).into(),
+                    words: spell.words.clone(),
+                    field: spell.field.clone().into(),
+                    bounds: spell.bounds,
+                    lifetime: spell.lifetime,
+                }));
+            }
+        }
+        Save {
+            version: SAVE_VERSION,
+            name: player.name.clone(),
+            gender: player.gender,
+            progression: player.progression.clone(),
+            max_mana: player.person.max_mana,
+            total_work: player.person.total_work,
+            rack: player.person.rack.clone(),
+            spellbook: player.spellbook.clone(),
+            place: self.world.place.clone(),
+            pos: [pos.x, pos.y],
+            stack: player.person.stack.words().collect(),
+            active_spells,
+            spells: Vec::new(),
+        }
+    }
+    pub fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.save_path else {
+            bail!("This session was started with developer options and doesn't save");
+        };
+        let yaml = serde_yaml::to_string(&self.to_save())?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, yaml).with_context(|| format!("Unable to write {}", path.display()))?;
+        Ok(())
+    }
+    pub fn load() -> anyhow::Result<Self> {
+        Game::load_from(&default_save_path())
+    }
+    pub fn load_from(path: &Path) -> anyhow::Result<Self> {
+        let yaml = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        // Check the version before trying to read the rest
+        let value: serde_yaml::Value = serde_yaml::from_str(&yaml)?;
+        let version = value.get("version").and_then(serde_yaml::Value::as_u64);
+        if version != Some(SAVE_VERSION as u64) {
+            let version = version.map_or_else(|| "unknown".into(), |v| v.to_string());
+            bail!(
+                "The save file is from an incompatible version of the game \
+                (save version {version}, expected {SAVE_VERSION})"
+            );
+        }
+        let save: Save = serde_yaml::from_value(value)?;
+        let mut game = Game::from_save(&save)?;
+        // Save back to the file the game came from
+        game.save_path = Some(path.to_owned());
+        Ok(game)
+    }
+    pub fn from_save(save: &Save) -> anyhow::Result<Self> {
+        let mut game = Game::from_player(save.player());
+        game.restore_save(save)?;
+        Ok(game)
+    }
+    /// Put the world's place, player, and spells as they were saved
+    pub fn restore_save(&mut self, save: &Save) -> anyhow::Result<()> {
+        self.world.load_place(&save.place)?;
+        self.world.player.person.pos = pos2(save.pos[0], save.pos[1]);
+        for quest in &save.progression.quests {
+            if !QUESTS.contains_key(&quest.id) {
+                warn!("Saved quest {} no longer exists", quest.id);
+            }
+        }
+        // Recast spells, dropping any that can no longer be sustained
+        let mut dropped = 0;
+        for saved in &save.active_spells {
+            if self.world.cast_words(saved.caster, &saved.words) {
+                if let Some(person) = self.world.person_mut(saved.caster) {
+                    saved.restore(&mut person.active_spells);
+                }
+            } else {
+                dropped += 1;
+            }
+        }
+        for (person_id, words) in &save.spells {
+            if !self.world.cast_words(*person_id, words) {
+                dropped += 1;
+            }
+        }
+        if dropped > 0 {
+            self.ui_state.toast(format!(
+                "{dropped} saved spell{} could not be sustained and faded",
+                if dropped == 1 { "" } else { "s" }
+            ));
+        }
+        if !self.world.cast_words(PersonId::Player, &save.stack) {
+            self.ui_state.toast("The saved stack could not be restored");
+        }
+        Ok(())
+    }
+}
+
+impl Save {
+    /// The player as they were saved, before their place and spells are restored
+    pub fn player(&self) -> Player {
+        let mut player = Player::new(self.name.clone(), self.gender);
+        player.progression = self.progression.clone();
+        // Older saves only have the max mana, with any upgrades already added
+        let bonus = (self.progression.mana_bonus).max(self.max_mana - PLAYER_BASE_MAX_MANA);
+        player.set_mana_bonus(bonus);
+        player.person.mana = player.person.max_mana;
+        player.person.total_work = self.total_work;
+        player.person.rack = self.rack.clone();
+        // Older saves had a fixed number of stones, some of them blank
+        (player.person.rack.conduits).retain(|stone| !stone.words.is_empty());
+        player.spellbook = self.spellbook.clone();
+        player
+    }
+}
+
+/// How a field display was arranged, saved between sessions
+#[derive(Serialize, Deserialize)]
+struct FieldLayout {
+    visible: bool,
+    pos: [f32; 2],
+    size: f32,
+    #[serde(default)]
+    center_offset: [f32; 2],
+    #[serde(default = "default_layout_zoom")]
+    zoom: f32,
+    #[serde(default = "default_layout_legend")]
+    legend: bool,
+    #[serde(default)]
+    scalar_mode: ScalarPlotMode,
+    #[serde(default)]
+    vector_mode: VectorPlotMode,
+}
+
+fn default_layout_zoom() -> f32 {
+    1.0
+}
+
+fn default_layout_legend() -> bool {
+    true
+}
+
+fn layout_path() -> PathBuf {
+    data_path().join("layout.yaml")
+}
+
+impl UiState {
+    pub fn save_layout(&self) -> anyhow::Result<()> {
+        let layout: IndexMap<FieldKind, FieldLayout> = (self.fields_display.iter())
+            .map(|(kind, display)| {
+                let layout = FieldLayout {
+                    visible: display.visible,
+                    pos: [display.pos.x, display.pos.y],
+                    size: display.size,
+                    center_offset: [display.center_offset.x, display.center_offset.y],
+                    zoom: display.zoom,
+                    legend: display.legend,
+                    scalar_mode: display.scalar_mode,
+                    vector_mode: display.vector_mode,
+                };
+                (*kind, layout)
+            })
+            .collect();
+        let yaml = serde_yaml::to_string(&layout)?;
+        let path = layout_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, yaml).with_context(|| format!("Unable to write {}", path.display()))?;
+        Ok(())
+    }
+    /// Arrange field displays as they were last session
+    ///
+    /// Displays for fields that no longer exist are skipped
+    pub fn load_layout(&mut self) {
+        let Ok(yaml) = fs::read_to_string(layout_path()) else {
+            return;
+        };
+        let Ok(serde_yaml::Value::Mapping(layout)) = serde_yaml::from_str(&yaml) else {
+            return;
+        };
+        for (kind, layout) in layout {
+            let (Ok(kind), Ok(layout)) = (
+                serde_yaml::from_value::<FieldKind>(kind),
+                serde_yaml::from_value::<FieldLayout>(layout),
+            ) else {
+                continue;
+            };
+            let display = FieldDisplay {
+                visible: layout.visible,
+                pos: vec2(layout.pos[0], layout.pos[1]),
+                size: layout.size,
+                center_offset: vec2(layout.center_offset[0], layout.center_offset[1]),
+                zoom: layout.zoom,
+                legend: layout.legend,
+                scalar_mode: layout.scalar_mode,
+                vector_mode: layout.vector_mode,
+            };
+            self.fields_display.insert(kind, display);
+        }
+    }
+}
+
+#[test]
+fn mana_bonus_save_test() {
+    let mut player = Player::new("Tester".into(), Gender::Enby);
+    player.grant_max_mana(5.0);
+    let mut save = Save {
+        version: SAVE_VERSION,
+        name: player.name.clone(),
+        gender: player.gender,
+        progression: player.progression.clone(),
+        max_mana: player.person.max_mana,
+        total_work: 0.0,
+        rack: ConduitRack::default(),
+        spellbook: Spellbook::default(),
+        place: String::new(),
+        pos: [0.0; 2],
+        stack: Vec::new(),
+        active_spells: Vec::new(),
+        spells: Vec::new(),
+    };
+    assert_eq!(save.player().person.max_mana, PLAYER_BASE_MAX_MANA + 5.0);
+    // Saves from before the bonus was tracked keep their max mana as a bonus
+    save.progression.mana_bonus = 0.0;
+    let mut player = save.player();
+    assert_eq!(player.progression.mana_bonus, 5.0);
+    player.grant_max_mana(5.0);
+    assert_eq!(player.person.max_mana, PLAYER_BASE_MAX_MANA + 10.0);
+}
+
+#[test]
+fn saved_spell_test() {
+    use crate::field::{VectorField, VectorOutputFieldKind};
+    use enum_iterator::all;
+    let mut game = Game::from_player(Player::new("Tester".into(), Gender::Enby));
+    game.world.player.person.total_work = 1500.0;
+    game.world
+        .player
+        .progression
+        .known_words
+        .extend(all::<Word>());
+    assert!(game
+        .world
+        .cast_words(PersonId::Player, &[Word::Ki, Word::Wu]));
+    // Stand in for a field its words wouldn't make again, like one with a sampled value
+    let force = VectorOutputFieldKind::Force;
+    game.world.player.person.active_spells.vectors[&force][0].field =
+        VectorField::Uniform(vec2(3.0, 4.0));
+    let yaml = serde_yaml::to_string(&game.to_save()).unwrap();
+    let save: Save = serde_yaml::from_str(&yaml).unwrap();
+    let loaded = Game::from_save(&save).unwrap();
+    assert_eq!(loaded.world.player.person.total_work, 1500.0);
+    let spells = &loaded.world.player.person.active_spells.vectors[&force];
+    assert_eq!(spells.len(), 1);
+    assert!(matches!(spells[0].field, VectorField::Uniform(v) if v == vec2(3.0, 4.0)));
+}
@@ -0,0 +1,113 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Context as _;
+use eframe::epaint::mutex::Mutex;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{dialog::DialogSettings, plot::PlotSettings, utils::data_path};
+
+/// Options the player can change from the pause menu
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Multiplies the pixels per point chosen from the window size
+    pub ui_scale: f32,
+    /// Whether to wait for the display to refresh, which only applies on startup
+    pub vsync: bool,
+    /// The most frames drawn per second
+    pub fps_cap: Option<u32>,
+    /// How long UI animations take in seconds, instead of each panel's own time
+    pub animation_time: Option<f32>,
+    pub show_fps: bool,
+    /// How many times the world updates per second
+    pub tick_rate: u32,
+    pub show_minimap: bool,
+    /// Whether spells give off particles
+    pub particles: bool,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    /// How hard thrown objects are pushed
+    pub throw_impulse: f32,
+    pub plot: PlotSettings,
+    pub dialog: DialogSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            ui_scale: 1.0,
+            vsync: true,
+            fps_cap: None,
+            animation_time: None,
+            show_fps: true,
+            tick_rate: 60,
+            show_minimap: true,
+            particles: true,
+            music_volume: 0.7,
+            sfx_volume: 1.0,
+            throw_impulse: 2.0,
+            plot: PlotSettings::default(),
+            dialog: DialogSettings::default(),
+        }
+    }
+}
+
+/// The world update rates the player can choose from
+pub const TICK_RATES: [u32; 3] = [30, 60, 120];
+
+impl Settings {
+    /// How long each world update is in seconds
+    pub fn tick_dt(&self) -> f32 {
+        1.0 / self.tick_rate.clamp(TICK_RATES[0], TICK_RATES[2]) as f32
+    }
+}
+
+static SETTINGS: Lazy<Mutex<Settings>> = Lazy::new(Default::default);
+
+pub fn settings() -> Settings {
+    *SETTINGS.lock()
+}
+
+pub fn set_settings(settings: Settings) {
+    *SETTINGS.lock() = settings;
+}
+
+fn settings_path() -> PathBuf {
+    data_path().join("settings.yaml")
+}
+
+/// Load the settings, using defaults for any that aren't in the file
+pub fn load_settings() {
+    let settings = fs::read_to_string(settings_path())
+        .ok()
+        .and_then(|yaml| {
+            serde_yaml::from_str(&yaml)
+                .map_err(|e| warn!("Unable to read settings: {e}"))
+                .ok()
+        })
+        .unwrap_or_default();
+    set_settings(settings);
+}
+
+pub fn save_settings() -> anyhow::Result<()> {
+    let yaml = serde_yaml::to_string(&settings())?;
+    let path = settings_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, yaml).with_context(|| format!("Unable to write {}", path.display()))?;
+    Ok(())
+}
+
+#[test]
+fn settings_test() {
+    // Settings missing from the file keep their defaults
+    let settings: Settings =
+        serde_yaml::from_str("ui_scale: 1.5\nplot:\n  precision: 2.0\n").unwrap();
+    assert_eq!(settings.ui_scale, 1.5);
+    assert_eq!(settings.plot.precision, 2.0);
+    assert!(settings.plot.hover_readout);
+    assert_eq!(settings.dialog, DialogSettings::default());
+}
@@ -0,0 +1,173 @@
+use eframe::epaint::Pos2;
+use rapier2d::prelude::*;
+
+use crate::{
+    math::Convert,
+    object::{ObjectDef, ObjectKind, PlacedMotion, PosRot},
+    person::{ActiveSpells, PersonId},
+    stack::Stack,
+    world::{Controls, World},
+};
+
+/// An in-memory copy of the state of the world that can be restored later
+pub struct WorldSnapshot {
+    place: String,
+    time: f32,
+    objects: Vec<ObjectSnapshot>,
+    people: Vec<(PersonId, PersonSnapshot)>,
+    mana_bonus: f32,
+    controls: Controls,
+    heat_grid: Vec<Vec<f32>>,
+    memory_grid: Vec<Vec<f32>>,
+}
+
+struct ObjectSnapshot {
+    def: ObjectDef,
+    pr: PosRot,
+    ordered_pr: PosRot,
+    linvel: Vector<Real>,
+    angvel: Real,
+    heat: f32,
+    entropy: f32,
+    damage: f32,
+    motion: Option<PlacedMotion>,
+}
+
+struct PersonSnapshot {
+    pos: Pos2,
+    mana: f32,
+    max_mana: f32,
+    total_work: f32,
+    health: f32,
+    stack: Stack,
+    active_spells: ActiveSpells,
+}
+
+impl World {
+    pub fn snapshot(&self) -> WorldSnapshot {
+        let objects = (self.objects.values())
+            .filter(|obj| obj.kind == ObjectKind::Object)
+            .map(|obj| {
+                let body = &self.physics.bodies[obj.body_handle];
+                ObjectSnapshot {
+                    def: obj.def.clone(),
+                    pr: obj.pr,
+                    ordered_pr: obj.ordered_pr,
+                    linvel: *body.linvel(),
+                    angvel: body.angvel(),
+                    heat: obj.heat,
+                    entropy: obj.entropy,
+                    damage: obj.damage,
+                    motion: obj.motion.clone(),
+                }
+            })
+            .collect();
+        let people = (self.person_ids().into_iter())
+            .filter_map(|person_id| {
+                let person = self.person(person_id)?;
+                Some((
+                    person_id,
+                    PersonSnapshot {
+                        pos: person.pos,
+                        mana: person.mana,
+                        max_mana: person.max_mana,
+                        total_work: person.total_work,
+                        health: person.health,
+                        stack: person.stack.clone(),
+                        active_spells: person.active_spells.clone(),
+                    },
+                ))
+            })
+            .collect();
+        WorldSnapshot {
+            place: self.place.clone(),
+            time: self.time,
+            objects,
+            people,
+            mana_bonus: self.player.progression.mana_bonus,
+            controls: self.controls.clone(),
+            heat_grid: self.heat_grid.clone(),
+            memory_grid: self.memory_grid.clone(),
+        }
+    }
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        if self.place != snapshot.place {
+            self.load_place_or_report(&snapshot.place);
+        }
+        // Rebuild the objects, since some may have broken since the snapshot
+        let handles: Vec<_> = (self.objects.iter())
+            .filter(|(_, obj)| obj.kind == ObjectKind::Object)
+            .map(|(handle, _)| *handle)
+            .collect();
+        for handle in handles {
+            self.objects.remove(&handle);
+            self.physics.remove_body(handle);
+        }
+        for snap in &snapshot.objects {
+            let handle = self.add_object(
+                ObjectKind::Object,
+                snap.def.clone(),
+                |rb| {
+                    rb.translation(snap.pr.pos.convert())
+                        .rotation(snap.pr.rot)
+                        .linvel(snap.linvel)
+                        .angvel(snap.angvel)
+                },
+                |c| c,
+            );
+            let obj = self.objects.get_mut(&handle).unwrap();
+            obj.ordered_pr = snap.ordered_pr;
+            obj.heat = snap.heat;
+            obj.entropy = snap.entropy;
+            obj.damage = snap.damage;
+            obj.motion = snap.motion.clone();
+        }
+        self.physics.update_queries();
+        // Restore people
+        for (person_id, snap) in &snapshot.people {
+            let Some(person) = self.person_mut(*person_id) else {
+                continue;
+            };
+            person.pos = snap.pos;
+            person.mana = snap.mana;
+            person.max_mana = snap.max_mana;
+            person.total_work = snap.total_work;
+            person.health = snap.health;
+            person.stack = snap.stack.clone();
+            person.active_spells = snap.active_spells.clone();
+        }
+        // The player's max mana follows their progression
+        self.player.set_mana_bonus(snapshot.mana_bonus);
+        self.time = snapshot.time;
+        self.controls = snapshot.controls.clone();
+        self.heat_grid = snapshot.heat_grid.clone();
+        self.memory_grid = snapshot.memory_grid.clone();
+        self.impacts.clear();
+        self.carried = None;
+    }
+}
+
+#[test]
+fn snapshot_test() {
+    use crate::player::{Gender, Player, PLAYER_BASE_MAX_MANA};
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let snapshot = world.snapshot();
+    let count = world.objects.len();
+    let total_y = |world: &World| -> f32 { world.objects.values().map(|obj| obj.pr.pos.y).sum() };
+    let y = total_y(&world);
+    // Break something and let things move
+    let (&handle, _) = (world.objects.iter())
+        .find(|(_, obj)| obj.def.durability.is_some())
+        .expect("No breakable objects");
+    world.objects.get_mut(&handle).unwrap().damage = f32::INFINITY;
+    for _ in 0..10 {
+        world.update();
+    }
+    world.player.grant_max_mana(5.0);
+    assert_ne!(world.objects.len(), count);
+    world.restore(&snapshot);
+    assert_eq!(world.objects.len(), count);
+    assert_eq!(world.player.progression.mana_bonus, 0.0);
+    assert_eq!(world.player.person.max_mana, PLAYER_BASE_MAX_MANA);
+    assert!((total_y(&world) - y).abs() < 1e-3);
+}
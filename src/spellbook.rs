@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+use crate::word::Word;
+
+/// Named spells the player has written down
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Spellbook {
+    pub entries: Vec<SpellbookEntry>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SpellbookEntry {
+    pub name: String,
+    pub words: Vec<Word>,
+    #[serde(default)]
+    pub note: String,
+}
@@ -1,14 +1,24 @@
+use eframe::epaint::{pos2, Pos2};
+
 use crate::{
     error::EidosError,
     field::*,
     function::*,
-    person::{ActiveSpell, ActiveSpells, PersonId},
+    person::{ActiveSpell, ActiveSpells, PersonId, SpellBounds, CONFINED_SPELL_RADIUS},
     word::Word,
+    world::World,
 };
 
 #[derive(Default, Clone)]
 pub struct Stack {
     stack: Vec<StackItem>,
+    /// Modifiers that have been said, with the words that made them
+    modifiers: Vec<(Modifier, Vec<Word>)>,
+    /// How many seconds the next spell will last, if a duration was said
+    duration: Option<f32>,
+    /// Whether items were removed or reordered by hand, in which case saying
+    /// the stack's words again may not rebuild it
+    rearranged: bool,
 }
 
 #[derive(Clone)]
@@ -61,14 +71,21 @@ where
 
 impl Stack {
     pub fn is_empty(&self) -> bool {
-        self.stack.is_empty()
+        self.stack.is_empty() && self.modifiers.is_empty()
     }
     pub fn len(&self) -> usize {
         self.stack.len()
     }
-    pub fn iter(&self) -> std::slice::Iter<StackItem> {
+    pub fn iter(&self) -> std::slice::Iter<'_, StackItem> {
         self.stack.iter()
     }
+    /// Words of modifiers that have been said but not yet consumed
+    pub fn modifier_words(&self) -> impl Iterator<Item = Word> + '_ {
+        (self.modifiers.iter()).flat_map(|(_, words)| words.iter().copied())
+    }
+    pub fn has_modifier(&self, modifier: Modifier) -> bool {
+        self.modifiers.iter().any(|(m, _)| *m == modifier)
+    }
     pub fn validate_function_use(&self, function: Function) -> Result<(), EidosError> {
         function.validate_use(self)
     }
@@ -84,12 +101,29 @@ impl Stack {
     }
     pub fn clear(&mut self) {
         self.stack.clear();
+        self.modifiers.clear();
+        self.duration = None;
+        self.rearranged = false;
+    }
+    pub fn is_rearranged(&self) -> bool {
+        self.rearranged
+    }
+    /// Remove an item by hand, along with its words
+    pub fn remove(&mut self, index: usize) -> StackItem {
+        self.rearranged = true;
+        self.stack.remove(index)
+    }
+    /// Swap two items by hand
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.rearranged = true;
+        self.stack.swap(a, b);
     }
     pub fn words(&self) -> impl Iterator<Item = Word> + '_ {
-        self.stack.iter().flat_map(|item| &item.words).copied()
+        (self.stack.iter().flat_map(|item| &item.words).copied()).chain(self.modifier_words())
     }
     pub fn say(
         &mut self,
+        world: &World,
         person_id: PersonId,
         word: Word,
         active_spells: Option<&mut ActiveSpells>,
@@ -104,6 +138,20 @@ impl Stack {
             },
             Function::WriteField(field_kind) => {
                 let item = self.pop();
+                let bounds = self.has_modifier(Modifier::Confine).then(|| SpellBounds {
+                    center: world
+                        .person(person_id)
+                        .map_or(Pos2::ZERO, |person| person.pos),
+                    radius: CONFINED_SPELL_RADIUS,
+                });
+                // Modifiers are part of the spell
+                let words: Vec<Word> = (item.words.into_iter())
+                    .chain(self.modifier_words())
+                    .chain([word])
+                    .collect();
+                let reserved = words.iter().map(Word::cost).sum();
+                let affects_caster = !self.has_modifier(Modifier::ExcludeSelf);
+                let lifetime = self.duration;
                 if let Some(active_spells) = active_spells {
                     match (field_kind, item.field) {
                         (OutputFieldKind::Vector(kind), Field::Vector(field)) => {
@@ -112,8 +160,10 @@ impl Stack {
                                 .entry(kind)
                                 .or_default()
                                 .push(ActiveSpell {
-                                    field,
-                                    words: item.words.into_iter().chain([word]).collect(),
+                                    bounds,
+                                    affects_caster,
+                                    lifetime,
+                                    ..ActiveSpell::new(field, words, reserved)
                                 });
                         }
                         (OutputFieldKind::Scalar(kind), Field::Scalar(field)) => {
@@ -122,8 +172,10 @@ impl Stack {
                                 .entry(kind)
                                 .or_default()
                                 .push(ActiveSpell {
-                                    field,
-                                    words: item.words.into_iter().chain([word]).collect(),
+                                    bounds,
+                                    affects_caster,
+                                    lifetime,
+                                    ..ActiveSpell::new(field, words, reserved)
                                 });
                         }
                         _ => unreachable!(),
@@ -158,6 +210,16 @@ impl Stack {
                     }
                 }
             }
+            Function::Combinator3(com3) => {
+                let c = self.pop();
+                let b = self.pop();
+                let a = self.pop();
+                let order = match com3 {
+                    Combinator3::Rot => [b, c, a],
+                    Combinator3::UnRot => [c, a, b],
+                };
+                self.stack.extend(order);
+            }
             Function::Un(op) => {
                 let a = self.pop();
                 let words = (a.words, word);
@@ -268,13 +330,107 @@ impl Stack {
                         }
                         _ => unreachable!(),
                     },
+                    BinOp::Rotate => match (a.field, b.field) {
+                        (Field::Vector(a), Field::Scalar(b)) => {
+                            self.push(words, VectorField::Rotate(a.into(), b.into()).reduce())
+                        }
+                        _ => unreachable!(),
+                    },
+                    BinOp::Dot => match (a.field, b.field) {
+                        (Field::Vector(a), Field::Vector(b)) => {
+                            self.push(words, ScalarField::Dot(a.into(), b.into()).reduce())
+                        }
+                        _ => unreachable!(),
+                    },
+                }
+            }
+            Function::Ternary(op) => {
+                let c = self.pop();
+                let b = self.pop();
+                let a = self.pop();
+                let words = ((a.words, b.words), c.words, word);
+                match op {
+                    TernOp::Select => match (a.field, b.field, c.field) {
+                        (Field::Scalar(a), Field::Scalar(b), Field::Scalar(c)) => self.push(
+                            words,
+                            ScalarField::Select(c.into(), a.into(), b.into()).reduce(),
+                        ),
+                        (Field::Vector(a), Field::Vector(b), Field::Scalar(c)) => self.push(
+                            words,
+                            VectorField::Select(c.into(), a.into(), b.into()).reduce(),
+                        ),
+                        _ => unreachable!(),
+                    },
+                    TernOp::Sample => match (a.field, b.field, c.field) {
+                        (Field::Scalar(a), Field::Scalar(b), Field::Scalar(c)) => {
+                            let pos = pos2(b.uniform().unwrap(), c.uniform().unwrap());
+                            self.push(words, ScalarField::Uniform(a.sample(world, pos, true)))
+                        }
+                        _ => unreachable!(),
+                    },
                 }
             }
             Function::Variable(var) => match var {
                 Variable::Scalar => self.push(word, ScalarField::Variable),
                 Variable::Vector => self.push(word, VectorField::Variable),
             },
+            Function::Modifier(Modifier::Duration) => {
+                let item = self.pop();
+                let Field::Scalar(field) = item.field else {
+                    unreachable!()
+                };
+                // Durations said together add up
+                let seconds = field.uniform().unwrap().max(0.0);
+                *self.duration.get_or_insert(0.0) += seconds;
+                (self.modifiers).push((Modifier::Duration, (item.words, word).into_words()));
+            }
+            Function::Modifier(modifier) => self.modifiers.push((modifier, vec![word])),
+            // The world applies the dispel itself
+            Function::Dispel => {
+                self.modifiers.clear();
+                self.duration = None;
+            }
         }
         Ok(())
     }
 }
+
+#[test]
+fn complexity_limit_test() {
+    use crate::player::{Gender, Player};
+    let world = World::new(Player::new("Test".into(), Gender::Enby));
+    let mut stack = Stack::default();
+    stack.say(&world, PersonId::Player, Word::Se, None).unwrap();
+    // Doubling a field with itself eventually becomes too complex
+    let mut doublings = 0;
+    loop {
+        stack.say(&world, PersonId::Player, Word::Mo, None).unwrap();
+        match stack.say(&world, PersonId::Player, Word::Ma, None) {
+            Ok(()) => doublings += 1,
+            Err(EidosError::TooComplex { .. }) => break,
+            Err(err) => panic!("{err}"),
+        }
+    }
+    assert_eq!(doublings, 6);
+    assert!(stack
+        .iter()
+        .all(|item| item.field.complexity() <= MAX_FIELD_COMPLEXITY));
+}
+
+#[test]
+fn rearrange_test() {
+    use crate::player::{Gender, Player};
+    let world = World::new(Player::new("Test".into(), Gender::Enby));
+    let mut stack = Stack::default();
+    stack.say(&world, PersonId::Player, Word::Se, None).unwrap();
+    stack.say(&world, PersonId::Player, Word::Te, None).unwrap();
+    assert!(!stack.is_rearranged());
+    // Items keep their own words when moved or removed
+    stack.swap(0, 1);
+    assert!(stack.words().eq([Word::Te, Word::Se]));
+    stack.remove(0);
+    assert!(stack.words().eq([Word::Se]));
+    assert!(stack.is_rearranged());
+    stack.clear();
+    assert!(!stack.is_rearranged());
+}
@@ -1,5 +1,5 @@
 use std::{
-    env::{current_dir, current_exe},
+    env::{current_dir, current_exe, var_os},
     path::PathBuf,
     process::exit,
 };
@@ -26,6 +26,21 @@ pub fn resources_path() -> PathBuf {
     fatal_error("Unable to find resources directory")
 }
 
+/// The directory where the game stores data like saves
+pub fn data_path() -> PathBuf {
+    let base = if cfg!(windows) {
+        var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    base.unwrap_or_else(|| current_dir().map_err(fatal_error).unwrap())
+        .join("eidos")
+}
+
 pub fn fatal_error(message: impl ToString) -> ! {
     fatal_error_impl(message.to_string())
 }
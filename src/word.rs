@@ -1,5 +1,9 @@
-use std::{env, fs};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+};
 
+use anyhow::bail;
 use derive_more::{Display, From};
 use enum_iterator::{all, cardinality, Sequence};
 use itertools::Itertools;
@@ -8,7 +12,12 @@ use rand::prelude::*;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::{color::Color, field::*, function::*, utils::resources_path};
+use crate::{
+    color::Color,
+    field::*,
+    function::*,
+    utils::{fatal_error, resources_path},
+};
 
 #[derive(
     Debug,
@@ -64,6 +73,18 @@ pub enum Word {
     Ko,
     /// Read
     Re,
+    /// Noise
+    Ha,
+    /// Time
+    He,
+    /// Wind
+    Ba,
+    /// Velocity
+    Be,
+    /// Proximity
+    Hi,
+    /// Material
+    Bo,
 
     // Outputs
     /// Gravity
@@ -84,8 +105,16 @@ pub enum Word {
     Ma,
     /// Multiply
     Sa,
+    /// Power
+    Fe,
+    /// Modulo
+    Fi,
     /// Negate
     Na,
+    /// Absolute value
+    Po,
+    /// Sign
+    Pa,
     /// Min
     Mi,
     /// Max
@@ -94,14 +123,44 @@ pub enum Word {
     Ra,
     /// Magnitude
     Va,
+    /// X component
+    Fo,
+    /// Y component
+    Fu,
+    /// Dot product
+    Da,
+    /// Normalize
+    De,
+    /// Counterspell
+    Du,
+    /// Include self
+    Di,
+    /// Confine
+    Bi,
+    /// Exclude self
+    Bu,
+    /// Duration
+    Do,
     /// Sqrt
     La,
-    /// Derivative
+    /// Gradient
     We,
     /// Sine
     Wa,
+    /// Cosine
+    So,
+    /// Divergence
+    Ne,
+    /// Curl
+    Ni,
     /// Index
     Ka,
+    /// Select
+    Pu,
+    /// Sample
+    Fa,
+    /// Rotate
+    Wo,
 
     // Controls
     /// Horizontal slider
@@ -126,6 +185,10 @@ pub enum Word {
     Ru,
     /// Over
     Vo,
+    /// Rot
+    Ho,
+    /// Un-rot
+    Hu,
 }
 
 impl Word {
@@ -148,6 +211,11 @@ impl Word {
             Lu => ScalarInputFieldKind::Temperature.into(),
             Ko => ScalarInputFieldKind::Disorder.into(),
             Re => ScalarInputFieldKind::Memory.into(),
+            Ha => ScalarInputFieldKind::Noise.into(),
+            He => ScalarInputFieldKind::Time.into(),
+            Bo => ScalarInputFieldKind::Material.into(),
+            Ba => VectorInputFieldKind::Wind.into(),
+            Be => VectorInputFieldKind::Velocity.into(),
             Vu => VectorOutputFieldKind::Gravity.into(),
             Wu => VectorOutputFieldKind::Force.into(),
             Lo => ScalarOutputFieldKind::Heat.into(),
@@ -156,23 +224,45 @@ impl Word {
             Ri => VectorOutputFieldKind::Write.into(),
             Ma => HomoBinOp::Add.into(),
             Sa => HeteroBinOp::Mul.into(),
+            Fe => HeteroBinOp::Pow.into(),
+            Fi => HeteroBinOp::Mod.into(),
             Na => MathUnOp::Neg.into(),
+            Po => MathUnOp::Abs.into(),
+            Pa => MathUnOp::Sign.into(),
             Mi => HomoBinOp::Min.into(),
             Me => HomoBinOp::Max.into(),
             Va => ToScalarOp::Magnitude.into(),
+            Fo => ToScalarOp::XComponent.into(),
+            Fu => ToScalarOp::YComponent.into(),
+            Da => BinOp::Dot.into(),
+            De => VectorUnVectorOp::Normalize.into(),
+            Du => Function::Dispel,
+            Di => Modifier::IncludeSelf.into(),
+            Bi => Modifier::Confine.into(),
+            Bu => Modifier::ExcludeSelf.into(),
+            Do => Modifier::Duration.into(),
             Ra => ScalarUnOp::Reciprocal.into(),
             La => ScalarUnOp::Sqrt.into(),
-            We => ScalarUnVectorOp::Derivative.into(),
+            We => ScalarUnVectorOp::Gradient.into(),
             Wa => ScalarUnOp::Sin.into(),
+            So => ScalarUnOp::Cos.into(),
+            Ne => VectorUnScalarOp::Divergence.into(),
+            Ni => VectorUnScalarOp::Curl.into(),
             Ka => BinOp::Index.into(),
+            Wo => BinOp::Rotate.into(),
+            Pu => TernOp::Select.into(),
+            Fa => TernOp::Sample.into(),
             No => Combinator1::Drop.into(),
             Mo => Combinator1::Duplicate.into(),
             Ru => Combinator2::Swap.into(),
             Vo => Combinator2::Over.into(),
+            Ho => Combinator3::Rot.into(),
+            Hu => Combinator3::UnRot.into(),
             Le => ControlKind::XSlider.into(),
             Li => ControlKind::YSlider.into(),
             Pe => Nullary::TargetX.into(),
             Pi => Nullary::TargetY.into(),
+            Hi => Nullary::Proximity.into(),
             Ve => ControlKind::Activation1.into(),
             Vi => ControlKind::Activation2.into(),
         }
@@ -181,6 +271,12 @@ impl Word {
         !matches!(self.function(), Function::Variable(_))
     }
     pub fn cost(&self) -> f32 {
+        WORD_DEFS
+            .get(self)
+            .and_then(|def| def.cost)
+            .unwrap_or_else(|| self.default_cost())
+    }
+    fn default_cost(&self) -> f32 {
         use Word::*;
         match self {
             To => 0.0,
@@ -192,11 +288,16 @@ impl Word {
             Li => 2.0,
             Pe => 3.0,
             Pi => 3.0,
-            No | Ru | Vo => 0.0,
+            Wa | So => 1.5,
+            We | Ne | Ni | Fa => 2.0,
+            No | Ru | Vo | Ho | Hu => 0.0,
             _ => 1.0,
         }
     }
     pub fn text_color(&self) -> Option<Color> {
+        if let Some([r, g, b]) = WORD_DEFS.get(self).and_then(|def| def.color) {
+            return Some(Color::rgb(r, g, b));
+        }
         Some(match self.function() {
             Function::ReadField(_) => Color::rgb(0.7, 0.7, 1.0),
             Function::Nullary(
@@ -206,15 +307,18 @@ impl Word {
                 Color::rgb(0.5, 1.0, 1.0)
             }
             Function::Nullary(Nullary::X | Nullary::Y) => Color::rgb(1.0, 0.2, 0.5),
-            Function::Nullary(Nullary::TargetX | Nullary::TargetY) | Function::Control(_) => {
-                Color::rgb(1.0, 1.0, 0.3)
-            }
+            Function::Nullary(Nullary::TargetX | Nullary::TargetY | Nullary::Proximity)
+            | Function::Control(_) => Color::rgb(1.0, 1.0, 0.3),
             Function::Un(_) => Color::rgb(0.4, 1.0, 0.5),
-            Function::Bin(_) => Color::rgb(1.0, 0.5, 1.0),
+            Function::Bin(_) | Function::Ternary(_) => Color::rgb(1.0, 0.5, 1.0),
             Function::Variable(_) => Color::rgb(1.0, 0.7, 0.7),
             _ => return None,
         })
     }
+    /// A sentence about the word for new players
+    pub fn description(&self) -> Option<&'static str> {
+        WORD_DESCRIPTIONS.get(self).map(String::as_str)
+    }
 }
 
 struct Genotype {
@@ -298,7 +402,9 @@ static REFERENCE_SPELLS: &[&[Word]] = &[
     &[Te, Ki, Sa, Vu],
     &[Ti, Pe, Mo, Sa, Pi, Mo, Sa, Ma, Ma, Ra],
     &[Te, Li, Ma, Wi, Na, Ma, Ki, Sa, Vu],
-    &[Mo, We, Sa], // mul by derivative
+    &[Mo, We, Sa], // mul by gradient
+    &[We, Ne], // laplacian
+    &[Ku, Ni], // curl of vector variable
     &[Te, Ta, Ma], // 10 + 5
     &[Ta, Tu, Ma], // 5 + 2
     &[Ta, Ti, Ma], // 5 + 1
@@ -317,13 +423,37 @@ static REFERENCE_SPELLS: &[&[Word]] = &[
     &[Ki, Si, Sa], &[Si, Ki, Sa], // y vector field
     &[Ke, Pe, Na, Sa], // mouse x vector field
     &[Ki, Pi, Na, Sa], // mouse y vector field
+    &[Ki, Ti, Hi, Ma, Ra, Sa, Vu], // gravity falling off with range
     &[To, Mi], // min(0,v)
     &[To, Me], // max(0,v)
+    &[Se, Po], // |x|
+    &[Se, Pa], // sign of x
+    &[Se, Tu, Fi, Ti, Ru, Ma], // repeating stripes
+    &[Su, Tu, Fe], // distance squared
+    &[Ro, We, Mo, Fu, Ki, Sa, Ru, Fo, Ke, Sa, Ma], // density gradient x and y swapped
+    &[Wu, Vu, Da], // alignment of force with gravity
+    &[Pe, Ke, Sa, Pi, Ki, Sa, Ma, De, Wu], // constant push toward the target
+    &[Du], // counterspell
+    &[Di, Du], // counterspell including own spells
+    &[Bi, Ke, Wu], // push right near the caster
+    &[Bu, Ki, Wu], // push up everywhere but the caster
+    &[Te, Do, Ki, Wu], // push up for ten seconds
+    &[Se, Wa], // vertical stripes
+    &[Si, So], // horizontal stripes
+    &[Ki, Ke, Ro, Pu], // j vector inside objects, i vector outside
+    &[Lu, Ti, Tu, Fa], // temperature at a fixed point
+    &[Ke, Ti, Wo], // rotated i vector
+    &[Ki, Ra, Wo], // rotated j vector
     &[Ro], // in from density field
     &[Wi], // in from elevation field
     &[Lu], // in from temperature field
     &[Ko], // in from disorder field
     &[Re], // in from memory field
+    &[Ha], // in from noise field
+    &[Bo], // in from material field
+    &[He, Wa, Ki, Sa, Vu], // pulsing gravity
+    &[Ba, Na, Wu], // shelter from the wind
+    &[Be, Na, Wu], // brake moving objects
     &[Sa, Lo], &[Ma, Lo], // out to heat field
     &[Sa, Mu], &[Ma, Mu], // out to order field
     &[Sa, Nu], &[Ma, Nu], // out to anchor field
@@ -335,14 +465,22 @@ static GROUPS: &[&[Word]] = &[
     &[To, Ti, Tu, Ta, Te], // number constants
     &[Se, Si], // x, y scalar fields
     &[Ke, Ki], // i, j basis vector fields
-    &[Pe, Pi], // mouse x, y scalar fields
+    &[Pe, Pi, Hi], // caster-relative scalar fields
+    &[Pu, Fa], // ternary functions
     &[Su, Ku], // distance and angle fields
     &[Le, Li], // sliders
-    &[Ro, Wi, Lu, Ko, Re], // input fields
+    &[Ro, Wi, Lu, Ko, Re, Ha, He, Ba, Be, Bo], // input fields
     &[Lo, Mu, Nu, Vu, Wu, Ri], // output fields
-    &[No, Ru, Vo], // stack manipulation
+    &[No, Ru, Vo, Ho, Hu], // stack manipulation
     &[Mi, Me], // min, max
+    &[Na, Po, Pa, De], // sign manipulation
+    &[We, Ne, Ni], // vector calculus
+    &[Wa, So], // trigonometry
+    &[Sa, Fe, Fi], // multiplicative operators
+    &[Va, Fo, Fu, Da], // vector to scalar
     &[Re, Ri], // read, write
+    &[Du, Di], // counterspell
+    &[Di, Bi, Bu, Do], // modifiers
     &[Ve, Vi], // mouse clicks
 ];
 
@@ -391,17 +529,98 @@ impl Phenotype {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+struct WordsFile {
+    grid: Vec<Vec<String>>,
+    #[serde(default)]
+    words: HashMap<String, WordDef>,
+}
+
+/// Overrides for a word's properties
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct WordDef {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cost: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<[f32; 3]>,
+}
+
+static WORDS_FILE: Lazy<WordsFile> = Lazy::new(|| {
+    let yaml = fs::read_to_string(resources_path().join("words.yaml"));
+    let yaml = yaml
+        .as_deref()
+        .unwrap_or(include_str!("../resources/words.yaml"));
+    match serde_yaml::from_str(yaml) {
+        Ok(file) => file,
+        Err(e) => fatal_error(format!("Unable to read words file: {e}")),
+    }
+});
+
 pub static WORD_GRID: Lazy<Vec<Vec<Word>>> = Lazy::new(|| {
-    let path = resources_path().join("word_grid.yaml");
-    if !env::args().any(|arg| arg == "regen_grid") {
-        if let Some(grid) = fs::read_to_string(&path)
-            .ok()
-            .and_then(|yaml| serde_yaml::from_str(&yaml).ok())
-        {
-            return grid;
+    if env::args().any(|arg| arg == "regen_grid") {
+        return regen_grid();
+    }
+    load_grid(&WORDS_FILE.grid)
+        .unwrap_or_else(|e| fatal_error(format!("Unable to read words file: {e}")))
+});
+
+static WORD_DESCRIPTIONS: Lazy<HashMap<Word, String>> = Lazy::new(|| {
+    let yaml = fs::read_to_string(resources_path().join("word_descriptions.yaml"));
+    let yaml = yaml
+        .as_deref()
+        .unwrap_or(include_str!("../resources/word_descriptions.yaml"));
+    match serde_yaml::from_str(yaml) {
+        Ok(descriptions) => descriptions,
+        Err(e) => fatal_error(format!("Unable to read word descriptions: {e}")),
+    }
+});
+
+static WORD_DEFS: Lazy<HashMap<Word, WordDef>> = Lazy::new(|| {
+    load_defs(&WORDS_FILE.words)
+        .unwrap_or_else(|e| fatal_error(format!("Unable to read words file: {e}")))
+});
+
+fn word_by_name(name: &str) -> Option<Word> {
+    all::<Word>().find(|word| word.to_string() == name)
+}
+
+fn load_grid(names: &[Vec<String>]) -> anyhow::Result<Vec<Vec<Word>>> {
+    let mut seen = HashSet::new();
+    let mut grid = Vec::new();
+    for (i, row) in names.iter().enumerate() {
+        let mut grid_row = Vec::new();
+        for (j, name) in row.iter().enumerate() {
+            let (row, col) = (i + 1, j + 1);
+            let Some(word) = word_by_name(name) else {
+                bail!("Unknown word {name:?} at row {row}, column {col}");
+            };
+            if !seen.insert(word) {
+                bail!("Duplicate word {name} at row {row}, column {col}");
+            }
+            grid_row.push(word);
         }
+        grid.push(grid_row);
     }
+    Ok(grid)
+}
+
+fn load_defs(defs: &HashMap<String, WordDef>) -> anyhow::Result<HashMap<Word, WordDef>> {
+    let mut words = HashMap::new();
+    for (name, def) in defs {
+        let Some(word) = word_by_name(name) else {
+            bail!("Unknown word {name:?} in word definitions");
+        };
+        if let Some(cost) = def.cost {
+            if !cost.is_finite() || cost < 0.0 {
+                bail!("Cost of {name} must be a non-negative number, but it is {cost}");
+            }
+        }
+        words.insert(word, *def);
+    }
+    Ok(words)
+}
 
+fn regen_grid() -> Vec<Vec<Word>> {
     let mut population = Vec::new();
     let mut rng = SmallRng::seed_from_u64(0);
     for _ in 0..100_000 {
@@ -435,7 +654,60 @@ pub static WORD_GRID: Lazy<Vec<Vec<Word>>> = Lazy::new(|| {
         println!();
     }
 
-    let _ = fs::write(path, serde_yaml::to_string(&final_grid).unwrap());
+    let file = WordsFile {
+        grid: (final_grid.iter())
+            .map(|row| row.iter().map(Word::to_string).collect())
+            .collect(),
+        ..WORDS_FILE.clone()
+    };
+    let path = resources_path().join("words.yaml");
+    let _ = fs::write(path, serde_yaml::to_string(&file).unwrap());
 
     final_grid
-});
+}
+
+/// Encode words as text that can be shared
+pub fn spell_code(words: &[Word]) -> String {
+    words.iter().join("-")
+}
+
+/// Decode words from a spell code
+pub fn parse_spell_code(code: &str) -> anyhow::Result<Vec<Word>> {
+    let code = code.trim();
+    if code.is_empty() {
+        return Ok(Vec::new());
+    }
+    (code.split('-'))
+        .map(|name| {
+            let name = name.trim();
+            all::<Word>()
+                .find(|word| word.to_string().eq_ignore_ascii_case(name))
+                .ok_or_else(|| anyhow::anyhow!("Unknown word {name:?}"))
+        })
+        .collect()
+}
+
+#[test]
+fn words_file_test() {
+    let grid_words: HashSet<Word> = WORD_GRID.iter().flatten().copied().collect();
+    assert_eq!(grid_words.len(), cardinality::<Word>());
+    assert_eq!(To.cost(), 0.0);
+    assert_eq!(Te.cost(), 10.0);
+    assert_eq!(Ma.cost(), 1.0);
+}
+
+#[test]
+fn spell_code_test() {
+    for spell in REFERENCE_SPELLS {
+        let code = spell_code(spell);
+        assert_eq!(parse_spell_code(&code).unwrap(), *spell);
+    }
+    assert!(parse_spell_code("Te-Xy").is_err());
+}
+
+#[test]
+fn word_descriptions_test() {
+    for word in all::<Word>() {
+        assert!(word.description().is_some(), "{word} has no description");
+    }
+}
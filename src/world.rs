@@ -1,41 +1,212 @@
-use std::{f32::consts::PI, iter::once};
+use std::{
+    collections::hash_map::DefaultHasher,
+    f32::consts::PI,
+    hash::{Hash, Hasher},
+    iter::once,
+    mem::{replace, take},
+    panic::{catch_unwind, AssertUnwindSafe},
+};
 
-use eframe::{egui::*, epaint::ahash::HashMap};
+use anyhow::bail;
+use eframe::{
+    egui::*,
+    epaint::ahash::{HashMap, HashSet},
+};
+use enum_iterator::all;
+use indexmap::IndexMap;
 use itertools::Itertools;
+use rand::prelude::*;
 use rapier2d::prelude::*;
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
 
 use crate::{
+    carry::Carried,
+    color::Color,
+    error::EidosError,
     field::*,
-    math::{angle_diff, go_to},
-    npc::{Npc, NpcId},
+    function::{Function, Modifier},
+    math::{angle_diff, go_to, value_noise, Convert},
+    npc::{Movement, Npc, NpcDialog, NpcId, SpellTrigger},
     object::*,
-    person::{Person, PersonId},
+    particle::{Particle, Particles},
+    person::{ActiveSpells, Person, PersonId, SpellBounds},
     physics::PhysicsContext,
     player::Player,
+    resources::Resources,
+    stack::Stack,
+    word::Word,
 };
 
 pub struct World {
     pub player: Player,
-    pub npcs: HashMap<NpcId, Npc>,
-    pub objects: HashMap<RigidBodyHandle, Object>,
+    pub npcs: IndexMap<NpcId, Npc>,
+    pub objects: IndexMap<RigidBodyHandle, Object>,
     pub min_bound: Pos2,
     pub max_bound: Pos2,
     pub heat_grid: Vec<Vec<f32>>,
+    /// Time not yet spent on heat steps
+    heat_ticker: f32,
     pub memory_grid: Vec<Vec<f32>>,
+    /// Memory grids of places that have been left, by place name
+    pub place_memories: HashMap<String, Vec<Vec<f32>>>,
+    pub place: String,
+    pub wind: Wind,
     pub physics: PhysicsContext,
     pub controls: Controls,
+    pub noise_seed: u64,
+    /// Randomness that affects the simulation, seeded so that replays play out the same
+    rng: SmallRng,
+    /// What the player did since the game last took it, for recording replays
+    pub inputs: Vec<PlayerInput>,
+    pub time: f32,
+    /// People hit by objects, waiting to be handled
+    pub impacts: Vec<Impact>,
+    /// The index of the transition region the player is in
+    transition_region: Option<usize>,
+    /// The object the player is holding
+    pub carried: Option<Carried>,
+    /// Things that have happened since the game last handled events
+    pub events: Vec<WorldEvent>,
+    pub resources: Resources,
+    /// The sensors of the current place that each person is in, by sensor index
+    sensor_occupants: HashSet<(usize, PersonId)>,
+    pub particles: Particles,
+    /// How far between their previous and current transforms objects are when rendering
+    ///
+    /// This is `None` while updating, so that the simulation never depends on the frame rate
+    pub render_alpha: Option<f32>,
+}
+
+/// Something that happened in the world that the game may react to
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldEvent {
+    /// A person wrote a spell to an output field
+    SpellCast {
+        caster: PersonId,
+        kind: OutputFieldKind,
+    },
+    ObjectBroken {
+        pos: Pos2,
+    },
+    /// A person dispelled other spells
+    SpellsDispelled {
+        caster: PersonId,
+    },
+    /// A person's spells sent an object out of bounds of the numbers and were dispelled
+    SpellsDestabilized {
+        caster: PersonId,
+    },
+    /// Some of a person's spells ran out of time
+    SpellsExpired {
+        caster: PersonId,
+    },
+    /// A person's spells did more work than they had mana for, and all of their spells ended
+    ManaDepleted {
+        caster: PersonId,
+    },
+    /// The total work a person's spells have done passed another [`WORK_MILESTONE`](crate::person::WORK_MILESTONE)
+    WorkDone {
+        caster: PersonId,
+        total: f32,
+    },
+    /// The player died and came back at the place's spawn point
+    PlayerDied,
+    /// The player went to another place
+    PlaceEntered(String),
+    SensorEntered {
+        sensor: String,
+        person_id: PersonId,
+    },
+    SensorExited {
+        sensor: String,
+        person_id: PersonId,
+    },
+}
+
+/// Something the player did that changes the simulation, recorded for replays
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerInput {
+    Say(Word),
+    /// Put the words of a conduit stone or spellbook entry on the stack
+    Push(Vec<Word>),
+    Free,
+    Undo,
+    RemoveStackItem(usize),
+    SwapStackItems(usize, usize),
+    Dispel {
+        kind: OutputFieldKind,
+        index: usize,
+    },
+    /// Pick up the object at a position
+    PickUp([f32; 2]),
+    /// Let go of the carried object with a throw impulse
+    Release(f32),
 }
 
-const HEAT_GRID_RESOLUTION: f32 = 0.25;
-const MEMORY_GRID_RESOLUTION: f32 = 0.25;
+/// A person being hit by an object
+#[derive(Debug, Clone, Copy)]
+pub struct Impact {
+    pub person_id: PersonId,
+    pub impulse: f32,
+}
+
+pub const HEAT_GRID_RESOLUTION: f32 = 0.25;
+pub const MEMORY_GRID_RESOLUTION: f32 = 0.25;
 pub const GROUND_TEMP: f32 = -3.0;
 pub const ABSOLUTE_ZERO: f32 = -(20.0 + GROUND_TEMP + 273.15);
 pub const TEMP_DROP_PER_METER: f32 = 6.5 / 1000.0;
 pub const GRAVITY: Vec2 = vec2(0.0, -10.0);
 pub const AIR_DENSITY_AT_GROUND_TEMP: f32 = 0.001279176;
+/// The most flammability can speed up heating, which keeps objects from overshooting
+const MAX_HEAT_SOAK: f32 = 50.0;
+/// The most any one spell can add to the magic field at a point
+const MAX_SPELL_MAGIC: f32 = 10.0;
+/// The fraction of the wind that gets through fixed objects
+const WIND_IN_FIXED: f32 = 0.1;
+/// How quickly objects' entropy rises on its own
+const ENTROPY_RATE: f32 = 0.01;
+/// The entropy past which objects no longer become more disordered on their own
+const MAX_NATURAL_ENTROPY: f32 = 1.0;
+/// How much work it takes to remove one unit of entropy with Order
+const ORDER_WORK_PER_ENTROPY: f32 = 1000.0;
+/// The magnitude above which a spell is dispelled by a counterspell or a destabilized object
+pub const DISPEL_THRESHOLD: f32 = 0.01;
+/// The temperature above which objects take damage
+const BURN_TEMP: f32 = 100.0;
+/// Damage per second per degree above the burn temperature
+const BURN_RATE: f32 = 0.01;
+/// How fast npcs walk in meters per second
+const NPC_WALK_SPEED: f32 = 1.0;
+/// How tall people are, used to block npcs with objects and to draw people
+pub const PERSON_HEIGHT: f32 = 1.6;
+/// How wide people are
+pub const PERSON_WIDTH: f32 = 0.5;
+/// How strong an active vector output field has to be to emit motes
+const MOTE_MIN_MAGNITUDE: f32 = 0.5;
+/// How fast motes drift along the field that emitted them
+const MOTE_SPEED: f32 = 0.5;
+const MOTE_LIFETIME: f32 = 1.5;
+/// How long each heat step is, independent of the physics step
+const HEAT_DT: f32 = 1.0 / 60.0;
+/// The temperature above which people are hurt
+const PERSON_BURN_TEMP: f32 = 60.0;
+/// Health lost per second per degree above the burn temperature
+const PERSON_BURN_RATE: f32 = 0.5;
+/// Health lost per unit of impulse from being hit by an object
+const IMPACT_DAMAGE: f32 = 0.5;
+/// The fraction of their max mana the player has after respawning
+const RESPAWN_MANA_FRACTION: f32 = 0.5;
+/// How close npcs following the player get
+const NPC_FOLLOW_DISTANCE: f32 = 1.5;
+/// How long npcs try to walk into something before picking a new goal
+const NPC_GIVE_UP_TIME: f32 = 2.0;
+/// How far under water a person's feet can be before they can no longer draw mana
+const MAX_MANA_SUBMERSION: f32 = 1.5;
 
-#[derive(Default)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Controls {
     pub x_slider: Option<f32>,
     pub y_slider: Option<f32>,
@@ -59,43 +230,52 @@ impl World {
         // Init world
         let mut world = World {
             player,
-            npcs: HashMap::default(),
+            npcs: IndexMap::default(),
             physics: PhysicsContext::default(),
             min_bound: Pos2::ZERO,
             max_bound: Pos2::ZERO,
             heat_grid: Vec::new(),
+            heat_ticker: 0.0,
             memory_grid: Vec::new(),
-            objects: HashMap::default(),
+            place_memories: HashMap::default(),
+            place: String::new(),
+            wind: Wind::default(),
+            objects: IndexMap::default(),
             controls: Controls::default(),
+            noise_seed: 0,
+            rng: SmallRng::seed_from_u64(0),
+            inputs: Vec::new(),
+            time: 0.0,
+            impacts: Vec::new(),
+            transition_region: None,
+            carried: None,
+            events: Vec::new(),
+            resources: Resources::load(),
+            sensor_occupants: HashSet::default(),
+            particles: Particles::default(),
+            render_alpha: None,
         };
+        world.reseed(thread_rng().gen());
         // Place
-        world.load_place("magician_house");
+        world.load_place_or_report("magician_house");
         world
     }
-    #[track_caller]
-    pub fn person(&self, person_id: PersonId) -> &Person {
+    /// Seed the noise field and the simulation's randomness
+    pub fn reseed(&mut self, seed: u64) {
+        self.noise_seed = seed;
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+    /// Get a person, if they are in the current place
+    pub fn person(&self, person_id: PersonId) -> Option<&Person> {
         match person_id {
-            PersonId::Player => &self.player.person,
-            PersonId::Npc(npc_id) => {
-                if let Some(npc) = self.npcs.get(&npc_id) {
-                    &npc.person
-                } else {
-                    panic!("No npc with id {npc_id:?}");
-                }
-            }
+            PersonId::Player => Some(&self.player.person),
+            PersonId::Npc(npc_id) => self.npcs.get(&npc_id).map(|npc| &npc.person),
         }
     }
-    #[track_caller]
-    pub fn person_mut(&mut self, person_id: PersonId) -> &mut Person {
+    pub fn person_mut(&mut self, person_id: PersonId) -> Option<&mut Person> {
         match person_id {
-            PersonId::Player => &mut self.player.person,
-            PersonId::Npc(npc_id) => {
-                if let Some(npc) = self.npcs.get_mut(&npc_id) {
-                    &mut npc.person
-                } else {
-                    panic!("No npc with id {npc_id:?}");
-                }
-            }
+            PersonId::Player => Some(&mut self.player.person),
+            PersonId::Npc(npc_id) => self.npcs.get_mut(&npc_id).map(|npc| &mut npc.person),
         }
     }
     pub fn max_rect(&self) -> Rect {
@@ -120,6 +300,7 @@ impl ShapeLayer {
     }
 }
 
+#[derive(Clone, Copy)]
 struct FoundObject<'a> {
     obj: &'a Object,
     shape: &'a OffsetShape,
@@ -127,67 +308,117 @@ struct FoundObject<'a> {
 }
 
 impl World {
-    fn find_obj_filtered_at_impl(
-        &self,
+    /// Find an object by testing every shape of some objects,
+    /// with the point moved into each object's space
+    fn scan_objects_at<'a>(
+        &'a self,
         p: Pos2,
+        objects: impl IntoIterator<Item = &'a Object>,
         filter: impl Fn(&Object, &RigidBody) -> bool,
-        transform_point: fn(&Object, Pos2) -> Pos2,
-    ) -> Option<FoundObject> {
-        puffin::profile_function!();
-        let mut min_layer = ShapeLayer::Far;
-        let mut best = None;
-        for obj in self.objects.values() {
+        transform_point: impl Fn(&Object, Pos2) -> Pos2,
+    ) -> Option<FoundObject<'a>> {
+        let mut best: Option<FoundObject> = None;
+        for obj in objects {
             if !filter(obj, &self.physics.bodies[obj.body_handle]) {
                 continue;
             }
             let transformed_point = transform_point(obj, p);
-            if let Some(shape) = obj
-                .def
-                .shapes
-                .iter()
-                .find(|shape| shape.contains(transformed_point))
-            {
+            let contains = |shape: &&OffsetShape| shape.contains(transformed_point);
+            if let Some(shape) = obj.def.shapes.iter().find(contains) {
                 return Some(FoundObject {
                     obj,
                     shape,
                     layer: ShapeLayer::Foreground,
                 });
-            } else if let Some(shape) = obj
-                .def
-                .background
-                .iter()
-                .find(|shape| shape.contains(transformed_point))
-            {
-                if min_layer > ShapeLayer::Background {
-                    min_layer = ShapeLayer::Background;
-                    best = Some((obj, shape));
-                }
-            } else if let Some(shape) = obj
-                .def
-                .far
-                .iter()
-                .find(|shape| shape.contains(transformed_point))
-            {
-                min_layer = ShapeLayer::Far;
-                best = Some((obj, shape));
+            }
+            let found = if let Some(shape) = obj.def.background.iter().find(contains) {
+                (shape, ShapeLayer::Background)
+            } else if let Some(shape) = obj.def.far.iter().find(contains) {
+                (shape, ShapeLayer::Far)
+            } else {
+                continue;
+            };
+            if best.is_none_or(|best| found.1 < best.layer) {
+                best = Some(FoundObject {
+                    obj,
+                    shape: found.0,
+                    layer: found.1,
+                });
             }
         }
-        best.map(|(obj, shape)| FoundObject {
-            obj,
-            shape,
-            layer: min_layer,
-        })
+        best
     }
     fn find_object_filtered_at(
         &self,
         p: Pos2,
         filter: impl Fn(&Object, &RigidBody) -> bool,
-    ) -> Option<FoundObject> {
-        self.find_obj_filtered_at_impl(p, filter, Object::transform_point)
+    ) -> Option<FoundObject<'_>> {
+        puffin::profile_function!();
+        // Colliders are at the objects' current transforms, so objects that moved are
+        // scanned at their rendered transforms when rendering between steps
+        let rendered_moved = |obj: &Object| self.render_alpha.is_some() && obj.moved();
+        let mut best: Option<FoundObject> = None;
+        // Foreground and background shapes have colliders, so the query pipeline can find them
+        self.physics.queries.intersections_with_point(
+            &self.physics.bodies,
+            &self.physics.colliders,
+            &p.convert(),
+            QueryFilter::default(),
+            |handle| {
+                let Some(body_handle) = self.physics.colliders[handle].parent() else {
+                    return true;
+                };
+                let Some(obj) = self.objects.get(&body_handle) else {
+                    return true;
+                };
+                if !filter(obj, &self.physics.bodies[body_handle]) || rendered_moved(obj) {
+                    return true;
+                }
+                if let Some(i) = obj.foreground_handles.iter().position(|&h| h == handle) {
+                    best = Some(FoundObject {
+                        obj,
+                        shape: &obj.def.shapes[i],
+                        layer: ShapeLayer::Foreground,
+                    });
+                    false
+                } else {
+                    if let Some(i) = obj.background_handles.iter().position(|&h| h == handle) {
+                        best = Some(FoundObject {
+                            obj,
+                            shape: &obj.def.background[i],
+                            layer: ShapeLayer::Background,
+                        });
+                    }
+                    true
+                }
+            },
+        );
+        if let Some(alpha) = self.render_alpha {
+            let moved = self.objects.values().filter(|obj| obj.moved());
+            let render_point = |obj: &Object, p| obj.render_pr(alpha).transform_point(p);
+            if let Some(found) = self.scan_objects_at(p, moved, &filter, render_point) {
+                if best.is_none_or(|best| found.layer < best.layer) {
+                    best = Some(found);
+                }
+            }
+        }
+        if best.is_some() {
+            return best;
+        }
+        // Far shapes have no colliders
+        let with_far =
+            (self.objects.values()).filter(|obj| !obj.def.far.is_empty() && !rendered_moved(obj));
+        self.scan_objects_at(p, with_far, filter, Object::transform_point)
     }
-    fn find_object_at(&self, p: Pos2) -> Option<FoundObject> {
+    fn find_object_at(&self, p: Pos2) -> Option<FoundObject<'_>> {
         self.find_object_filtered_at(p, |_, _| true)
     }
+    /// Get the dynamic object whose foreground is at a position
+    pub fn dynamic_object_at(&self, p: Pos2) -> Option<RigidBodyHandle> {
+        self.find_object_filtered_at(p, |_, body| body.is_dynamic())
+            .filter(|found| matches!(found.layer, ShapeLayer::Foreground))
+            .map(|found| found.obj.body_handle)
+    }
     pub fn sample_scalar_field(
         &self,
         kind: ScalarFieldKind,
@@ -218,6 +449,51 @@ impl World {
             }
         }
     }
+    /// Get the magic at a position, optionally leaving out the spells of one person
+    pub fn magic_at(&self, pos: Pos2, allow_recursion: bool, excluded: Option<PersonId>) -> f32 {
+        let mul = if let Some(found) = self.find_object_at(pos) {
+            if let ShapeLayer::Foreground = found.layer {
+                return found.obj.def.props.magic;
+            } else {
+                found.layer.multiplier()
+            }
+        } else {
+            1.0
+        };
+        if !allow_recursion {
+            return 1.0;
+        }
+        let mut sum = 0.0;
+        for person_id in self.person_ids_iter() {
+            if excluded == Some(person_id) {
+                continue;
+            }
+            let Some(person) = self.person(person_id) else {
+                continue;
+            };
+            for spell in person.active_spells.scalars.values().flatten() {
+                if !spell.affects(pos) {
+                    continue;
+                }
+                sum += spell
+                    .field
+                    .sample(self, pos, false)
+                    .abs()
+                    .min(MAX_SPELL_MAGIC);
+            }
+            for spell in person.active_spells.vectors.values().flatten() {
+                if !spell.affects(pos) {
+                    continue;
+                }
+                sum += spell
+                    .field
+                    .sample(self, pos, false)
+                    .length()
+                    .min(MAX_SPELL_MAGIC);
+            }
+        }
+        sum * mul
+    }
     pub fn sample_input_scalar_field(
         &self,
         kind: ScalarInputFieldKind,
@@ -229,64 +505,49 @@ impl World {
             ScalarInputFieldKind::Density => self
                 .find_object_at(pos)
                 .map(|found| found.shape.density * found.layer.multiplier())
-                .unwrap_or(0.0),
+                .unwrap_or_else(|| self.fluid_density_at(pos)),
             ScalarInputFieldKind::Elevation => {
-                let mut test = pos;
-                while test.y > 0.0 {
-                    puffin::profile_scope!("elevation test");
-                    if self
-                        .find_object_filtered_at(test, |_, body| body.body_type().is_fixed())
-                        .is_some()
-                    {
-                        return pos.y - test.y;
-                    }
-                    test.y -= 0.25;
+                // The ground plane is always at 0
+                if pos.y <= 0.0 {
+                    return pos.y;
                 }
-                pos.y
-            }
-            ScalarInputFieldKind::Magic => {
-                let mul = if let Some(found) = self.find_object_at(pos) {
-                    if let ShapeLayer::Foreground = found.layer {
-                        return found.obj.def.props.magic;
-                    } else {
-                        found.layer.multiplier()
-                    }
-                } else {
-                    1.0
+                let ray = Ray::new(pos.convert(), vector![0.0, -1.0]);
+                let is_fixed = |_, collider: &Collider| {
+                    collider
+                        .parent()
+                        .is_some_and(|handle| self.physics.bodies[handle].is_fixed())
                 };
-                if !allow_recursion {
-                    return 1.0;
-                }
-                let mut sum = 0.0;
-                for person in self.people() {
-                    for spell in person.active_spells.scalars.values().flatten() {
-                        sum += spell.field.sample(self, pos, false).abs();
-                    }
-                    for spell in person.active_spells.vectors.values().flatten() {
-                        sum += spell.field.sample(self, pos, false).length();
-                    }
-                }
-                sum * mul
+                let filter = QueryFilter::default().predicate(&is_fixed);
+                catch_unwind(AssertUnwindSafe(|| {
+                    self.physics.queries.cast_ray(
+                        &self.physics.bodies,
+                        &self.physics.colliders,
+                        &ray,
+                        pos.y,
+                        true,
+                        filter,
+                    )
+                }))
+                .ok()
+                .flatten()
+                .map_or(pos.y, |(_, toi)| toi)
             }
+            ScalarInputFieldKind::Magic => self.magic_at(pos, allow_recursion, None),
             ScalarInputFieldKind::Light => self.get_light_at(pos),
             ScalarInputFieldKind::Temperature => {
                 if let Some(found) = self.find_object_at(pos) {
                     return found.obj.heat;
                 }
-                let i = ((pos.x - self.min_bound.x) / HEAT_GRID_RESOLUTION + 0.5) as usize;
-                let j = ((pos.y - self.min_bound.y) / HEAT_GRID_RESOLUTION + 0.5) as usize;
-                self.heat_grid
-                    .get(i)
-                    .and_then(|col| col.get(j))
-                    .copied()
-                    .unwrap_or_else(|| ambient_temp_at(pos.y))
+                self.temperature_at(pos)
             }
             ScalarInputFieldKind::Disorder => {
                 if let Some(found) = self.find_object_at(pos) {
                     found.obj.pr.pos.distance(found.obj.ordered_pr.pos)
                         + angle_diff(found.obj.pr.rot, found.obj.ordered_pr.rot).abs() / PI
-                } else if let Some(found) = self.find_obj_filtered_at_impl(
+                        + found.obj.entropy
+                } else if let Some(found) = self.scan_objects_at(
                     pos,
+                    self.objects.values(),
                     |_, _| true,
                     Object::transform_point_as_ordered,
                 ) {
@@ -305,10 +566,31 @@ impl World {
                     .copied()
                     .unwrap_or(0.0)
             }
+            ScalarInputFieldKind::Noise => value_noise(self.noise_seed, pos),
+            ScalarInputFieldKind::Time => self.time,
+            ScalarInputFieldKind::Material => self
+                .find_object_at(pos)
+                .map_or(0.0, |found| found.obj.def.props.material()),
         }
     }
-    pub fn sample_input_vector_field(&self, kind: VectorInputFieldKind, _pos: Pos2) -> Vec2 {
-        match kind {}
+    pub fn sample_input_vector_field(&self, kind: VectorInputFieldKind, pos: Pos2) -> Vec2 {
+        match kind {
+            VectorInputFieldKind::Wind => {
+                let wind = self.wind.at(self.time);
+                if self
+                    .find_object_filtered_at(pos, |_, body| body.body_type().is_fixed())
+                    .is_some()
+                {
+                    wind * WIND_IN_FIXED
+                } else {
+                    wind
+                }
+            }
+            VectorInputFieldKind::Velocity => self
+                .find_object_at(pos)
+                .map(|found| (*self.physics.bodies[found.obj.body_handle].linvel()).convert())
+                .unwrap_or_default(),
+        }
     }
     pub fn sample_output_scalar_field(
         &self,
@@ -317,12 +599,30 @@ impl World {
         allow_recursion: bool,
     ) -> f32 {
         puffin::profile_function!(kind.to_string());
-        self.people()
-            .filter_map(|person| person.active_spells.scalars.get(&kind))
-            .flatten()
-            .map(|spell| spell.field.sample(self, pos, allow_recursion))
+        (self.sample_scalar_spells(kind, pos, allow_recursion))
+            .map(|(_, value)| value)
             .sum()
     }
+    /// Sample each of the spells of a scalar output field that have an effect at a position
+    ///
+    /// Each value comes with its spell's caster and index among their spells of the kind
+    pub fn sample_scalar_spells(
+        &self,
+        kind: ScalarOutputFieldKind,
+        pos: Pos2,
+        allow_recursion: bool,
+    ) -> impl Iterator<Item = ((PersonId, usize), f32)> + '_ {
+        self.person_ids_iter().flat_map(move |person_id| {
+            let person = self.person(person_id);
+            let spells = person.and_then(|person| person.active_spells.scalars.get(&kind));
+            (spells.into_iter().flatten().enumerate())
+                .filter(move |(_, spell)| spell.affects_from(person.unwrap(), pos))
+                .map(move |(i, spell)| {
+                    let value = spell.field.sample(self, pos, allow_recursion);
+                    ((person_id, i), value)
+                })
+        })
+    }
     pub fn sample_output_vector_field(
         &self,
         kind: VectorOutputFieldKind,
@@ -330,21 +630,550 @@ impl World {
         allow_recursion: bool,
     ) -> Vec2 {
         puffin::profile_function!(kind.to_string());
-        let from_spells = self
-            .people()
-            .filter_map(|person| person.active_spells.vectors.get(&kind))
-            .flatten()
-            .fold(Vec2::ZERO, |acc, spell| {
-                acc + spell.field.sample(self, pos, allow_recursion)
-            });
+        let from_spells = (self.sample_vector_spells(kind, pos, allow_recursion))
+            .fold(Vec2::ZERO, |acc, (_, value)| acc + value);
+        Self::vector_output_from_spells(kind, from_spells)
+    }
+    /// Sample each of the spells of a vector output field that have an effect at a position
+    ///
+    /// Each value comes with its spell's caster and index among their spells of the kind
+    pub fn sample_vector_spells(
+        &self,
+        kind: VectorOutputFieldKind,
+        pos: Pos2,
+        allow_recursion: bool,
+    ) -> impl Iterator<Item = ((PersonId, usize), Vec2)> + '_ {
+        self.person_ids_iter().flat_map(move |person_id| {
+            let person = self.person(person_id);
+            let spells = person.and_then(|person| person.active_spells.vectors.get(&kind));
+            (spells.into_iter().flatten().enumerate())
+                .filter(move |(_, spell)| spell.affects_from(person.unwrap(), pos))
+                .map(move |(i, spell)| {
+                    let value = spell.field.sample(self, pos, allow_recursion);
+                    ((person_id, i), value)
+                })
+        })
+    }
+    /// Get a vector output field's value from the sum of its spells' values
+    pub fn vector_output_from_spells(kind: VectorOutputFieldKind, from_spells: Vec2) -> Vec2 {
         match kind {
             VectorOutputFieldKind::Gravity => from_spells + GRAVITY,
             VectorOutputFieldKind::Force => from_spells,
             VectorOutputFieldKind::Write => vec2(from_spells.x.abs(), from_spells.y),
         }
     }
+    pub fn say(&mut self, person_id: PersonId, word: Word) -> Result<(), EidosError> {
+        // Words sample objects where they are, not where they are drawn between steps
+        let render_alpha = self.render_alpha.take();
+        let res = self.say_impl(person_id, word);
+        self.render_alpha = render_alpha;
+        res
+    }
+    fn say_impl(&mut self, person_id: PersonId, word: Word) -> Result<(), EidosError> {
+        if person_id == PersonId::Player && !self.word_history_matches() {
+            // The stack was changed some other way, so start the history from it
+            self.player.word_history = self.player.person.stack.words().collect();
+        }
+        let Some(person) = self.person_mut(person_id) else {
+            return Ok(());
+        };
+        let mut stack = take(&mut person.stack);
+        let include_self = stack.has_modifier(Modifier::IncludeSelf);
+        // Only take the active spells when writing so that sampling still sees them
+        let res = if let Function::WriteField(_) = word.function() {
+            let mut active_spells = take(&mut person.active_spells);
+            let res = stack.say(self, person_id, word, Some(&mut active_spells));
+            if let Some(person) = self.person_mut(person_id) {
+                person.active_spells = active_spells;
+            }
+            res
+        } else {
+            stack.say(self, person_id, word, None)
+        };
+        if let Some(person) = self.person_mut(person_id) {
+            person.stack = stack;
+        }
+        if let Err(e) = &res {
+            debug!(person = ?person_id, ?word, "Unable to say word: {e}");
+        }
+        if res.is_ok() && person_id == PersonId::Player {
+            match word.function() {
+                // Committed spells and dispels can't be undone
+                Function::WriteField(_) | Function::Dispel => self.player.word_history.clear(),
+                _ => self.player.word_history.push(word),
+            }
+        }
+        if res.is_ok() {
+            if person_id == PersonId::Player {
+                self.inputs.push(PlayerInput::Say(word));
+            }
+            self.emit_spell_particles(person_id, word);
+            match word.function() {
+                Function::Dispel => self.dispel(person_id, include_self),
+                Function::WriteField(kind) => self.events.push(WorldEvent::SpellCast {
+                    caster: person_id,
+                    kind,
+                }),
+                _ => {}
+            }
+        }
+        res
+    }
+    /// Emit sparks from a person saying a word, and a burst when it casts a spell
+    fn emit_spell_particles(&mut self, person_id: PersonId, word: Word) {
+        let Some(person) = self.person(person_id) else {
+            return;
+        };
+        let hands = person.pos + vec2(0.0, PERSON_HEIGHT * 0.6);
+        if let Function::WriteField(_) = word.function() {
+            let color = Color::rgb(1.0, 0.8, 0.4);
+            self.particles.burst(hands, 30, 3.0, 1.0, color);
+        } else {
+            let color = Color::rgb(0.7, 0.85, 1.0);
+            self.particles.burst(hands, 6, 1.0, 0.5, color);
+        }
+    }
+    /// Emit motes where active vector output spells are strong
+    ///
+    /// Each spell is sampled at one random point in its bounds per update
+    fn emit_motes(&mut self) {
+        if !self.particles.enabled {
+            return;
+        }
+        let spells = (self.people())
+            .flat_map(|person| &person.active_spells.vectors)
+            .flat_map(|(kind, spells)| spells.iter().map(|spell| (*kind, spell.bounds)))
+            .collect_vec();
+        let mut rng = thread_rng();
+        let rect = self.max_rect();
+        let mut motes = Vec::new();
+        for (kind, bounds) in spells {
+            let pos = match bounds {
+                Some(SpellBounds { center, radius }) => {
+                    let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                    let distance = radius * rng.gen::<f32>().sqrt();
+                    center + vec2(angle.cos(), angle.sin()) * distance
+                }
+                None => pos2(
+                    rng.gen_range(rect.min.x..=rect.max.x),
+                    rng.gen_range(rect.min.y..=rect.max.y),
+                ),
+            };
+            let field = self.sample_output_vector_field(kind, pos, true);
+            if field.length().is_nan() || field.length() < MOTE_MIN_MAGNITUDE {
+                continue;
+            }
+            let color = match kind {
+                VectorOutputFieldKind::Gravity => Color::rgb(0.7, 0.5, 1.0),
+                VectorOutputFieldKind::Force => Color::rgb(1.0, 0.6, 0.3),
+                VectorOutputFieldKind::Write => Color::rgb(0.5, 1.0, 0.6),
+            };
+            motes.push(Particle {
+                pos,
+                vel: field.normalized() * MOTE_SPEED,
+                age: 0.0,
+                lifetime: MOTE_LIFETIME,
+                color,
+            });
+        }
+        for mote in motes {
+            self.particles.emit(mote);
+        }
+    }
+    /// Rebuild the player's stack by saying words on an empty one
+    fn replay_player_words(&self, words: &[Word]) -> Option<Stack> {
+        let mut stack = Stack::default();
+        for word in words {
+            stack.say(self, PersonId::Player, *word, None).ok()?;
+        }
+        Some(stack)
+    }
+    /// Whether saying the player's word history rebuilds their current stack
+    fn word_history_matches(&self) -> bool {
+        self.replay_player_words(&self.player.word_history)
+            .is_some_and(|stack| stack.words().eq(self.player.person.stack.words()))
+    }
+    /// Whether the player's last word can be undone
+    pub fn can_undo_word(&self) -> bool {
+        !self.player.word_history.is_empty() && self.word_history_matches()
+    }
+    /// Take back the player's last word, returning whether there was one to undo
+    ///
+    /// Mana is released because the word is no longer on the stack.
+    /// Revealing a field the first time its word is said is not undone.
+    pub fn undo_word(&mut self) -> bool {
+        if !self.can_undo_word() {
+            return false;
+        }
+        let history = &self.player.word_history;
+        let Some(stack) = self.replay_player_words(&history[..history.len() - 1]) else {
+            return false;
+        };
+        self.player.person.stack = stack;
+        self.player.word_history.pop();
+        self.inputs.push(PlayerInput::Undo);
+        true
+    }
+    /// Say words onto a copy of the player's stack without casting anything,
+    /// stopping at the first word that can't be said
+    pub fn preview_player_words(&self, words: &[Word]) -> (Stack, Result<(), EidosError>) {
+        let mut stack = self.player.person.stack.clone();
+        let mut res = Ok(());
+        for word in words {
+            res = stack.say(self, PersonId::Player, *word, None);
+            if res.is_err() {
+                break;
+            }
+        }
+        (stack, res)
+    }
+    /// Put words on the player's stack the way conduit stones and spellbook entries do,
+    /// returning whether they could all be said
+    pub fn push_player_words(&mut self, words: &[Word]) -> bool {
+        let (stack, res) = self.preview_player_words(words);
+        if res.is_ok() {
+            self.player.person.stack = stack;
+            self.inputs.push(PlayerInput::Push(words.to_vec()));
+        }
+        res.is_ok()
+    }
+    /// Clear the player's stack
+    pub fn free_stack(&mut self) {
+        self.player.person.stack.clear();
+        self.inputs.push(PlayerInput::Free);
+    }
+    /// Take an item off the player's stack by hand
+    pub fn remove_stack_item(&mut self, i: usize) {
+        let stack = &mut self.player.person.stack;
+        if i < stack.len() {
+            stack.remove(i);
+            self.inputs.push(PlayerInput::RemoveStackItem(i));
+        }
+    }
+    /// Swap two items of the player's stack by hand
+    pub fn swap_stack_items(&mut self, a: usize, b: usize) {
+        let stack = &mut self.player.person.stack;
+        if a < stack.len() && b < stack.len() {
+            stack.swap(a, b);
+            self.inputs.push(PlayerInput::SwapStackItems(a, b));
+        }
+    }
+    /// Dispel one of the player's spells by hand
+    pub fn dispel_player_spell(&mut self, kind: OutputFieldKind, index: usize) {
+        let active_spells = &mut self.player.person.active_spells;
+        if index < active_spells.spell_words(kind).count() {
+            active_spells.remove(kind, index);
+            self.inputs.push(PlayerInput::Dispel { kind, index });
+        }
+    }
+    /// Do something the player did again, as when playing back a replay
+    pub fn apply_input(&mut self, input: &PlayerInput) {
+        match input {
+            // Only inputs that worked are recorded
+            PlayerInput::Say(word) => {
+                let _ = self.say(PersonId::Player, *word);
+            }
+            PlayerInput::Push(words) => {
+                self.push_player_words(words);
+            }
+            PlayerInput::Free => self.free_stack(),
+            PlayerInput::Undo => {
+                self.undo_word();
+            }
+            PlayerInput::RemoveStackItem(i) => self.remove_stack_item(*i),
+            PlayerInput::SwapStackItems(a, b) => self.swap_stack_items(*a, *b),
+            PlayerInput::Dispel { kind, index } => self.dispel_player_spell(*kind, *index),
+            PlayerInput::PickUp([x, y]) => {
+                if let Err(e) = self.pick_up(pos2(*x, *y)) {
+                    warn!("Unable to pick up an object again: {e}");
+                }
+            }
+            PlayerInput::Release(throw_impulse) => self.release_carried(*throw_impulse),
+        }
+    }
+    /// Get the closest npc the player can talk to
+    pub fn npc_dialog_in_range(&self) -> Option<(NpcId, &NpcDialog)> {
+        let player = &self.player;
+        (self.npcs.iter())
+            .filter_map(|(&npc_id, npc)| {
+                let dialog = npc.dialog.as_ref()?;
+                let dist = npc.person.pos.distance(player.person.pos);
+                let seen = dialog.once && player.progression.seen_dialogs.contains(&dialog.scene);
+                (dist <= dialog.radius && !seen).then_some((npc_id, dialog, dist))
+            })
+            .min_by(|(.., a), (.., b)| a.total_cmp(b))
+            .map(|(npc_id, dialog, _)| (npc_id, dialog))
+    }
+    /// Walk npcs toward their goals
+    fn move_npcs(&mut self) {
+        let dt = self.physics.dt();
+        let player_pos = self.player.person.pos;
+        let mut steps = Vec::new();
+        for (&npc_id, npc) in &mut self.npcs {
+            let pos = npc.person.pos;
+            // Pick a goal
+            let reached = |goal: Pos2| (goal.x - pos.x).abs() < NPC_WALK_SPEED * dt;
+            match npc.movement {
+                Movement::Idle => npc.goal = None,
+                Movement::Wander { radius } => {
+                    if npc.goal.is_none_or(reached) {
+                        let x = npc.home.x + self.rng.gen_range(-radius..=radius);
+                        npc.goal = Some(pos2(x, pos.y));
+                    }
+                }
+                Movement::WalkTo { pos: goal } => {
+                    npc.goal = Some(goal).filter(|&goal| !reached(goal));
+                    if npc.goal.is_none() {
+                        npc.movement = Movement::Idle;
+                    }
+                }
+                Movement::FollowPlayer => {
+                    npc.goal =
+                        Some(player_pos).filter(|goal| goal.distance(pos) > NPC_FOLLOW_DISTANCE);
+                }
+            }
+            if let Some(goal) = npc.goal {
+                let dx = (goal.x - pos.x).clamp(-NPC_WALK_SPEED * dt, NPC_WALK_SPEED * dt);
+                steps.push((npc_id, pos + vec2(dx, 0.0)));
+            }
+        }
+        for (npc_id, next) in steps {
+            // Fixed foreground objects block walking
+            let blocked = [PERSON_HEIGHT / 2.0, PERSON_HEIGHT]
+                .into_iter()
+                .any(|height| {
+                    let p = next + vec2(0.0, height);
+                    self.find_object_filtered_at(p, |_, body| body.is_fixed())
+                        .is_some_and(|found| matches!(found.layer, ShapeLayer::Foreground))
+                });
+            let npc = self.npcs.get_mut(&npc_id).unwrap();
+            if !blocked {
+                npc.person.pos = next;
+                npc.stuck_time = 0.0;
+                continue;
+            }
+            npc.stuck_time += dt;
+            if npc.stuck_time > NPC_GIVE_UP_TIME {
+                npc.stuck_time = 0.0;
+                npc.goal = None;
+                if let Movement::WalkTo { .. } = npc.movement {
+                    npc.movement = Movement::Idle;
+                }
+            }
+        }
+    }
+    /// Cast the npc spells whose triggers have started being met
+    fn run_npc_spells(&mut self) {
+        let player = &self.player.person;
+        let mut to_cast = Vec::new();
+        for (&npc_id, npc) in &mut self.npcs {
+            for spell in &mut npc.spells {
+                let met = match spell.script.trigger {
+                    SpellTrigger::PlayerWithin(dist) => player.pos.distance(npc.person.pos) <= dist,
+                    SpellTrigger::PlayerCasting(kind) => {
+                        player.active_spells.spell_words(kind).len() > 0
+                    }
+                    SpellTrigger::Every(period) => self.time - spell.last_cast >= period,
+                };
+                if met && !spell.triggered {
+                    to_cast.push((npc_id, spell.script.words.clone()));
+                    spell.last_cast = self.time;
+                }
+                spell.triggered = met;
+            }
+        }
+        for (npc_id, words) in to_cast {
+            self.cast_npc_spell(npc_id, &words);
+        }
+    }
+    /// Say the words of an npc's spell, stopping if the npc runs out of mana
+    fn cast_npc_spell(&mut self, npc_id: NpcId, words: &[Word]) {
+        let person_id = PersonId::Npc(npc_id);
+        let Some(person) = self.person_mut(person_id) else {
+            return;
+        };
+        // Casting the same spell again replaces it
+        if let Some(Function::WriteField(kind)) = words.last().map(Word::function) {
+            let existing =
+                (person.active_spells.spell_words(kind)).position(|spell| spell == words);
+            if let Some(i) = existing {
+                person.active_spells.remove(kind, i);
+            }
+        }
+        self.cast_words(person_id, words);
+    }
+    /// Say a sequence of words, stopping and clearing the stack if one can't be said
+    ///
+    /// Returns whether all the words were said
+    pub fn cast_words(&mut self, person_id: PersonId, words: &[Word]) -> bool {
+        for &word in words {
+            if self.person(person_id).is_none() {
+                return false;
+            }
+            if self.available_mana(person_id) < word.cost() || self.say(person_id, word).is_err() {
+                if let Some(person) = self.person_mut(person_id) {
+                    person.stack.clear();
+                }
+                return false;
+            }
+        }
+        true
+    }
+    /// Remove the active spells that are present at the caster's target
+    fn dispel(&mut self, caster: PersonId, include_self: bool) {
+        let Some(target) = self.person(caster).and_then(|person| person.target) else {
+            return;
+        };
+        let mut dispelled = Vec::new();
+        for person_id in self.person_ids() {
+            if person_id == caster && !include_self {
+                continue;
+            }
+            let Some(person) = self.person(person_id) else {
+                continue;
+            };
+            let active_spells = &person.active_spells;
+            for (kind, spells) in &active_spells.scalars {
+                for (i, spell) in spells.iter().enumerate() {
+                    if spell.affects(target)
+                        && spell.field.sample(self, target, true).abs() > DISPEL_THRESHOLD
+                    {
+                        dispelled.push((person_id, OutputFieldKind::from(*kind), i));
+                    }
+                }
+            }
+            for (kind, spells) in &active_spells.vectors {
+                for (i, spell) in spells.iter().enumerate() {
+                    if spell.affects(target)
+                        && spell.field.sample(self, target, true).length() > DISPEL_THRESHOLD
+                    {
+                        dispelled.push((person_id, OutputFieldKind::from(*kind), i));
+                    }
+                }
+            }
+        }
+        if !dispelled.is_empty() {
+            self.events.push(WorldEvent::SpellsDispelled { caster });
+        }
+        // Remove in reverse so that the remaining indices stay valid
+        for (person_id, kind, i) in dispelled.into_iter().rev() {
+            if let Some(person) = self.person_mut(person_id) {
+                person.active_spells.remove(kind, i);
+            }
+        }
+    }
+    /// Remove the spells whose durations have run out, which releases their mana
+    fn expire_spells(&mut self) {
+        let dt = self.physics.dt();
+        for person_id in self.person_ids() {
+            let Some(person) = self.person_mut(person_id) else {
+                continue;
+            };
+            if person.active_spells.expire(dt) > 0 {
+                debug!(person = ?person_id, "Spells expired");
+                self.events
+                    .push(WorldEvent::SpellsExpired { caster: person_id });
+            }
+        }
+    }
+    /// Regenerate people's mana, faster where there is more magic around them
+    fn regen_mana(&mut self) {
+        let dt = self.physics.dt();
+        for person_id in self.person_ids() {
+            let rate = self.mana_regen_rate(person_id);
+            if let Some(person) = self.person_mut(person_id) {
+                person.regen_mana(rate * dt);
+            }
+        }
+    }
+    /// Get the magic around a person that doesn't come from their own spells
+    fn ambient_magic(&self, person_id: PersonId) -> f32 {
+        let Some(person) = self.person(person_id) else {
+            return 0.0;
+        };
+        let chest = person.pos + vec2(0.0, PERSON_HEIGHT / 2.0);
+        self.magic_at(chest, true, Some(person_id))
+    }
+    /// Get how much mana a person regains per second where they stand
+    ///
+    /// People too deep under water regain none
+    pub fn mana_regen_rate(&self, person_id: PersonId) -> f32 {
+        let Some(person) = self.person(person_id) else {
+            return 0.0;
+        };
+        if self.submersion_depth(person.pos) > MAX_MANA_SUBMERSION {
+            return 0.0;
+        }
+        person.mana_regen_rate(self.ambient_magic(person_id))
+    }
+    /// Hurt the player by heat, impacts, and falling, and respawn them if they die
+    ///
+    /// Npcs have health too, but nothing hurts them yet
+    fn hurt_player(&mut self) {
+        let pos = self.player.person.pos;
+        let temp = self.temperature_at(pos + vec2(0.0, PERSON_HEIGHT / 2.0));
+        let mut damage = (temp - PERSON_BURN_TEMP).max(0.0) * PERSON_BURN_RATE * self.physics.dt();
+        for impact in self.impacts.drain(..) {
+            if impact.person_id == PersonId::Player {
+                damage += impact.impulse * IMPACT_DAMAGE;
+            }
+        }
+        let fell = (self.resources.places.get(&self.place))
+            .is_some_and(|place| pos.y < place.bounds.bottom);
+        let person = &mut self.player.person;
+        person.hurt(damage);
+        if fell {
+            person.health = 0.0;
+        }
+        if person.is_dead() {
+            self.respawn_player();
+        }
+    }
+    /// Bring the player back at the place's spawn point with some mana and nothing cast
+    pub fn respawn_player(&mut self) {
+        info!(place = self.place, "The player died");
+        let spawn = (self.resources.places.get(&self.place)).map_or(Pos2::ZERO, Place::spawn);
+        let person = &mut self.player.person;
+        person.pos = spawn;
+        person.health = person.max_health;
+        person.mana = person.max_mana * RESPAWN_MANA_FRACTION;
+        person.stack.clear();
+        person.active_spells = ActiveSpells::default();
+        self.player.word_history.clear();
+        self.carried = None;
+        self.transition_region = self.player_transition_region();
+        self.events.push(WorldEvent::PlayerDied);
+    }
+    /// A hash of everything besides objects and grids that field values depend on
+    ///
+    /// Time only advances when the world updates, so this changes on every update
+    pub fn field_revision(&self) -> u64 {
+        let controls = &self.controls;
+        let mut hasher = DefaultHasher::new();
+        self.time.to_bits().hash(&mut hasher);
+        self.place.hash(&mut hasher);
+        if self.objects.values().any(Object::moved) {
+            self.render_alpha.map(f32::to_bits).hash(&mut hasher);
+        }
+        controls.x_slider.map(f32::to_bits).hash(&mut hasher);
+        controls.y_slider.map(f32::to_bits).hash(&mut hasher);
+        (controls.activation1, controls.activation2).hash(&mut hasher);
+        for person in self.people() {
+            (person.pos.x.to_bits(), person.pos.y.to_bits()).hash(&mut hasher);
+            person
+                .target
+                .map(|target| (target.x.to_bits(), target.y.to_bits()))
+                .hash(&mut hasher);
+            for words in
+                all::<OutputFieldKind>().flat_map(|kind| person.active_spells.spell_words(kind))
+            {
+                words.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
     pub fn people(&self) -> impl Iterator<Item = &Person> {
-        self.person_ids_iter().map(|id| self.person(id))
+        self.person_ids_iter().filter_map(|id| self.person(id))
     }
     pub fn person_ids_iter(&self) -> impl Iterator<Item = PersonId> + '_ {
         once(PersonId::Player).chain(self.npcs.keys().copied().map(PersonId::Npc))
@@ -361,8 +1190,23 @@ fn ambient_temp_at(y: f32) -> f32 {
 
 impl World {
     pub fn update(&mut self) {
+        self.render_alpha = None;
+        // Move between places
+        self.check_transitions();
+        self.check_sensors();
+        // Let npcs move and cast
+        self.move_npcs();
+        self.run_npc_spells();
         // Run physics
+        self.move_platforms();
         self.run_physics();
+        self.time += self.physics.dt();
+        self.expire_spells();
+        self.regen_mana();
+        self.hurt_player();
+        // Update particles
+        self.emit_motes();
+        self.particles.update(self.physics.dt());
         // Update memory
         for i in 0..self.memory_grid.len() {
             for j in 0..self.memory_grid[i].len() {
@@ -377,6 +1221,74 @@ impl World {
                 }
             }
         }
+        // Update heat at its own rate, so that it spreads the same at any tick rate
+        self.heat_ticker += self.physics.dt();
+        while self.heat_ticker >= HEAT_DT {
+            self.update_heat();
+            self.heat_ticker -= HEAT_DT;
+        }
+        // Apply anchoring
+        for handle in self.objects.keys().copied().collect_vec() {
+            let pos = self.objects[&handle].pr.pos;
+            let anchoring = self.physics.dt()
+                * self.sample_output_scalar_field(ScalarOutputFieldKind::Anchor, pos, true);
+            let obj = self.objects.get_mut(&handle).unwrap();
+            obj.ordered_pr.pos.x = go_to(obj.ordered_pr.pos.x, obj.pr.pos.x, anchoring);
+            obj.ordered_pr.pos.y = go_to(obj.ordered_pr.pos.y, obj.pr.pos.y, anchoring);
+            obj.ordered_pr.rot = go_to(obj.ordered_pr.rot, obj.pr.rot, anchoring);
+        }
+        // Update entropy
+        let mut order_work = Vec::new();
+        for handle in self.objects.keys().copied().collect_vec() {
+            let pos = self.objects[&handle].pr.pos;
+            let spells =
+                (self.sample_scalar_spells(ScalarOutputFieldKind::Order, pos, true)).collect_vec();
+            let order: f32 = spells.iter().map(|(_, value)| value).sum();
+            let dt = self.physics.dt();
+            let obj = self.objects.get_mut(&handle).unwrap();
+            if obj.entropy < MAX_NATURAL_ENTROPY {
+                obj.entropy = (obj.entropy + ENTROPY_RATE * dt).min(MAX_NATURAL_ENTROPY);
+            }
+            if order > 0.0 {
+                let before = obj.entropy;
+                obj.entropy = go_to(obj.entropy, 0.0, order * dt);
+                // The ordering spells share the work by how much order they bring
+                let removed = before - obj.entropy;
+                let ordering: f32 = spells.iter().map(|(_, value)| value.max(0.0)).sum();
+                for (spell, value) in spells {
+                    if value > 0.0 {
+                        let work = removed * value / ordering * ORDER_WORK_PER_ENTROPY;
+                        order_work.push((spell, work));
+                    }
+                }
+            }
+        }
+        let mut charges = Vec::new();
+        for ((person_id, i), work) in order_work {
+            if let Some(person) = self.person_mut(person_id) {
+                person.active_spells.scalars[&ScalarOutputFieldKind::Order][i].work += work;
+                charges.push((person_id, work));
+            }
+        }
+        self.charge_casters(charges);
+        // Burn hot objects, except ones that are heat sources themselves
+        let dt = self.physics.dt();
+        for obj in self.objects.values_mut() {
+            if obj.def.props.constant_heat.is_none() && obj.heat > BURN_TEMP {
+                obj.damage += (obj.heat - BURN_TEMP) * BURN_RATE * dt;
+            }
+        }
+        // Break damaged objects
+        let broken = (self.objects.iter())
+            .filter(|(_, obj)| obj.def.durability.is_some_and(|d| obj.damage > d))
+            .map(|(handle, _)| *handle)
+            .collect_vec();
+        for handle in broken {
+            self.break_object(handle);
+        }
+    }
+    /// Apply heat pressure and spread heat between objects and grid cells
+    fn update_heat(&mut self) {
         // Apply heat pressure
         for i in 0..self.heat_grid.len() {
             for j in 0..self.heat_grid[i].len() {
@@ -398,7 +1310,11 @@ impl World {
             let i = ((obj.pr.pos.x - self.min_bound.x) / HEAT_GRID_RESOLUTION + 0.5) as usize;
             let j = ((obj.pr.pos.y - self.min_bound.y) / HEAT_GRID_RESOLUTION + 0.5) as usize;
             if let Some(cell_heat) = self.heat_grid.get_mut(i).and_then(|col| col.get_mut(j)) {
-                let diff = (obj.heat - *cell_heat) * 0.01;
+                let mut diff = (obj.heat - *cell_heat) * 0.01;
+                // Flammable objects soak up heat faster, but never past the air around them
+                if diff < 0.0 {
+                    diff *= (1.0 + obj.def.props.flammable).min(MAX_HEAT_SOAK);
+                }
                 *cell_heat += diff;
                 obj.heat -= diff;
             }
@@ -442,25 +1358,47 @@ impl World {
             })
             .collect();
         self.heat_grid = new_grid;
-        // Apply anchoring
-        for handle in self.objects.keys().copied().collect_vec() {
-            let pos = self.objects[&handle].pr.pos;
-            let anchoring = self.physics.dt()
-                * self.sample_output_scalar_field(ScalarOutputFieldKind::Anchor, pos, true);
-            let obj = self.objects.get_mut(&handle).unwrap();
-            obj.ordered_pr.pos.x = go_to(obj.ordered_pr.pos.x, obj.pr.pos.x, anchoring);
-            obj.ordered_pr.pos.y = go_to(obj.ordered_pr.pos.y, obj.pr.pos.y, anchoring);
-            obj.ordered_pr.rot = go_to(obj.ordered_pr.rot, obj.pr.rot, anchoring);
+    }
+    /// Get the density of the water at a position, or 0 if there is none
+    pub fn fluid_density_at(&self, pos: Pos2) -> f32 {
+        (self.resources.places.get(&self.place))
+            .and_then(|place| place.water.iter().find(|water| water.region.contains(pos)))
+            .map_or(0.0, |water| water.density)
+    }
+    /// Get how deep under water a position is, or 0 if it is not in water
+    pub fn submersion_depth(&self, pos: Pos2) -> f32 {
+        (self.resources.places.get(&self.place))
+            .and_then(|place| place.water.iter().find_map(|water| water.depth(pos)))
+            .unwrap_or(0.0)
+    }
+    /// Get how much mana a person can draw on to say words
+    ///
+    /// People too deep under water can't draw any
+    pub fn available_mana(&self, person_id: PersonId) -> f32 {
+        let Some(person) = self.person(person_id) else {
+            return 0.0;
+        };
+        if self.submersion_depth(person.pos) > MAX_MANA_SUBMERSION {
+            return 0.0;
         }
+        person.capped_mana()
     }
+    /// Get the temperature of the air at a position, interpolated between heat grid cells
     pub fn temperature_at(&self, pos: Pos2) -> f32 {
-        let i = ((pos.x - self.min_bound.x) / HEAT_GRID_RESOLUTION + 0.5) as usize;
-        let j = ((pos.y - self.min_bound.y) / HEAT_GRID_RESOLUTION + 0.5) as usize;
-        self.heat_grid
-            .get(i)
-            .and_then(|col| col.get(j))
-            .copied()
-            .unwrap_or_else(|| ambient_temp_at(pos.y))
+        let x = ((pos.x - self.min_bound.x) / HEAT_GRID_RESOLUTION).max(0.0);
+        let y = ((pos.y - self.min_bound.y) / HEAT_GRID_RESOLUTION).max(0.0);
+        let (i, j) = (x as usize, y as usize);
+        let cell = |i: usize, j: usize| self.heat_grid.get(i).and_then(|col| col.get(j)).copied();
+        let Some(bottom_left) = cell(i, j) else {
+            return ambient_temp_at(pos.y);
+        };
+        let bottom_right = cell(i + 1, j).unwrap_or(bottom_left);
+        let top_left = cell(i, j + 1).unwrap_or(bottom_left);
+        let top_right = cell(i + 1, j + 1).unwrap_or(bottom_right);
+        let (tx, ty) = (x.fract(), y.fract());
+        let bottom = lerp(bottom_left..=bottom_right, tx);
+        let top = lerp(top_left..=top_right, tx);
+        lerp(bottom..=top, ty)
     }
     fn heat_grid_width(&self) -> usize {
         ((self.max_bound.x - self.min_bound.x) / HEAT_GRID_RESOLUTION).ceil() as usize
@@ -474,19 +1412,186 @@ impl World {
     fn memory_grid_height(&self) -> usize {
         ((self.max_bound.y - self.min_bound.y) / MEMORY_GRID_RESOLUTION).ceil() as usize
     }
-    pub fn load_place(&mut self, place_name: &str) {
-        let Some(place) = PLACES.get(place_name) else {
+    /// The transition region of the current place the player is in
+    pub fn player_transition_region(&self) -> Option<usize> {
+        let place = self.resources.places.get(&self.place)?;
+        let pos = self.player.person.pos;
+        (place.transitions.iter()).position(|transition| transition.region.contains(pos))
+    }
+    /// Go to another place if the player has entered a transition region
+    fn check_transitions(&mut self) {
+        let region = self.player_transition_region();
+        let entered = region.filter(|_| self.transition_region.is_none());
+        self.transition_region = region;
+        let Some(i) = entered else {
             return;
         };
+        let transition = self.resources.places[&self.place].transitions[i].clone();
+        if !self.load_place_or_report(&transition.to) {
+            return;
+        }
+        self.player.person.pos = transition.spawn;
+        self.events.push(WorldEvent::PlaceEntered(transition.to));
+        // Appearing inside a region should not immediately leave again
+        self.transition_region = self.player_transition_region();
+    }
+    /// Add a place's objects from their definitions
+    fn place_objects(&mut self, place: &Place) {
+        for po in &place.objects {
+            let Some(object) = self.resources.objects.get(&po.name).cloned() else {
+                continue;
+            };
+            if let Some(repli) = &po.replication {
+                for i in 0..repli.right {
+                    for j in 0..repli.up {
+                        let pos =
+                            po.pos + vec2(repli.spacing.x * i as f32, repli.spacing.y * j as f32);
+                        let handle = self.add_object_def(pos, object.clone());
+                        self.set_motion(handle, pos, &po.motion);
+                    }
+                }
+            } else {
+                let handle = self.add_object_def(po.pos, object);
+                self.set_motion(handle, po.pos, &po.motion);
+            }
+        }
+    }
+    fn set_motion(&mut self, handle: RigidBodyHandle, origin: Pos2, motion: &Option<Motion>) {
+        let Some(motion) = motion else {
+            return;
+        };
+        let obj = self.objects.get_mut(&handle).unwrap();
+        obj.motion = Some(PlacedMotion {
+            origin,
+            motion: motion.clone(),
+            start_time: self.time,
+        });
+    }
+    /// Move platforms to where they should be after the next physics step
+    ///
+    /// This uses world time so that platforms stay in phase with physics while paused
+    fn move_platforms(&mut self) {
+        let next_time = self.time + self.physics.dt();
+        for obj in self.objects.values() {
+            let Some(placed) = &obj.motion else {
+                continue;
+            };
+            let pos = placed.origin + placed.motion.offset(next_time - placed.start_time);
+            let body = &mut self.physics.bodies[obj.body_handle];
+            body.set_next_kinematic_translation(pos.convert());
+        }
+    }
+    /// Reload objects and places from their files and rebuild the current place's objects
+    ///
+    /// People, the ground, and the heat and memory grids are kept.
+    /// Changes to a place's bounds apply the next time it is entered.
+    pub fn reload_resources(&mut self) {
+        self.resources = Resources::load();
+        let Some(place) = self.resources.places.get(&self.place).cloned() else {
+            let file = format!("places/{}.yaml", self.place);
+            self.resources
+                .errors
+                .push(file, "The current place could not be reloaded");
+            return;
+        };
+        let handles = (self.objects.iter())
+            .filter(|(_, obj)| obj.kind == ObjectKind::Object)
+            .map(|(handle, _)| *handle)
+            .collect_vec();
+        for handle in handles {
+            self.objects.remove(&handle);
+            self.physics.remove_body(handle);
+        }
+        self.place_objects(&place);
+        self.physics.update_queries();
+        self.wind = place.wind;
+        self.impacts.clear();
+        self.carried = None;
+    }
+    /// Report people entering and leaving the current place's sensors
+    fn check_sensors(&mut self) {
+        let Some(place) = self.resources.places.get(&self.place) else {
+            return;
+        };
+        for person_id in self.person_ids() {
+            let Some(pos) = self.person(person_id).map(|person| person.pos) else {
+                continue;
+            };
+            for (i, sensor) in place.sensors.iter().enumerate() {
+                let inside = sensor.region.contains(pos);
+                let sensor = sensor.name.clone();
+                if inside && self.sensor_occupants.insert((i, person_id)) {
+                    self.events
+                        .push(WorldEvent::SensorEntered { sensor, person_id });
+                } else if !inside && self.sensor_occupants.remove(&(i, person_id)) {
+                    self.events
+                        .push(WorldEvent::SensorExited { sensor, person_id });
+                }
+            }
+        }
+    }
+    /// Load a place, adding any error to the resource errors
+    ///
+    /// Returns whether the place was loaded
+    pub fn load_place_or_report(&mut self, place_name: &str) -> bool {
+        match self.load_place(place_name) {
+            Ok(()) => true,
+            Err(e) => {
+                let file = format!("places/{place_name}.yaml");
+                self.resources.errors.push(file, e);
+                false
+            }
+        }
+    }
+    pub fn load_place(&mut self, place_name: &str) -> anyhow::Result<()> {
+        let Some(place) = self.resources.places.get(place_name).cloned() else {
+            bail!("Unknown place {place_name}");
+        };
+        info!(place = place_name, "Loading place");
+        // Check objects before changing anything
+        if let Some(po) =
+            (place.objects.iter()).find(|po| !self.resources.objects.contains_key(&po.name))
+        {
+            bail!("Unknown object {} in {place_name}", po.name);
+        }
+        // Remember the old place's memory
+        let old_place = replace(&mut self.place, place_name.into());
+        if !old_place.is_empty() {
+            self.place_memories
+                .insert(old_place, take(&mut self.memory_grid));
+        }
         // Set bounds
         self.min_bound.x = place.bounds.left;
         self.max_bound.x = place.bounds.right;
         self.min_bound.y = place.bounds.bottom;
         self.max_bound.y = place.bounds.top;
-        // Remove old objects
-        for (handle, _) in self.objects.drain() {
+        self.wind = place.wind.clone();
+        // Place the player
+        self.player.person.pos = place.spawn();
+        // Remove old objects and people
+        for (handle, _) in self.objects.drain(..) {
             self.physics.remove_body(handle);
         }
+        self.npcs.clear();
+        self.impacts.clear();
+        self.carried = None;
+        self.sensor_occupants.clear();
+        self.particles.clear();
+        // Add npcs
+        for pn in &place.npcs {
+            let mut npc = Npc::new(pn.id);
+            npc.person.pos = pn.pos;
+            npc.home = pn.pos;
+            npc.movement = pn.movement;
+            if let Some(max_mana) = pn.max_mana {
+                npc.person.max_mana = max_mana;
+            }
+            npc.dialog = pn.dialog.clone();
+            for spell in &mut npc.spells {
+                spell.last_cast = self.time;
+            }
+            self.npcs.insert(pn.id, npc);
+        }
         // Add objects
         // Ground
         self.add_object(
@@ -499,21 +1604,8 @@ impl World {
             |rb| rb,
             |c| c.restitution(0.5),
         );
-        // Place objects
-        for po in &place.objects {
-            let object = OBJECTS[&po.name].clone();
-            if let Some(repli) = &po.replication {
-                for i in 0..repli.right {
-                    for j in 0..repli.up {
-                        let pos =
-                            po.pos + vec2(repli.spacing.x * i as f32, repli.spacing.y * j as f32);
-                        self.add_object_def(pos, object.clone());
-                    }
-                }
-            } else {
-                self.add_object_def(po.pos, object);
-            }
-        }
+        self.place_objects(&place);
+        self.physics.update_queries();
         // Init heat grid
         self.heat_grid = vec![vec![GROUND_TEMP; self.heat_grid_height()]; self.heat_grid_width()];
         for col in self.heat_grid.iter_mut() {
@@ -523,6 +1615,747 @@ impl World {
             }
         }
         // Init memory grid
-        self.memory_grid = vec![vec![0.0; self.memory_grid_height()]; self.memory_grid_width()];
+        self.memory_grid = self.place_memories.remove(place_name).unwrap_or_else(|| {
+            vec![vec![0.0; self.memory_grid_height()]; self.memory_grid_width()]
+        });
+        Ok(())
+    }
+}
+
+#[test]
+fn find_object_test() {
+    use crate::player::{Gender, Player};
+    let world = World::new(Player::new("Test".into(), Gender::Enby));
+    let density = |x: f32, y: f32| {
+        world.sample_input_scalar_field(ScalarInputFieldKind::Density, pos2(x, y), true)
+    };
+    // The ground is found before the first physics step
+    assert_eq!(density(0.0, -1.0), 3.0);
+    assert_eq!(density(0.0, 1000.0), 0.0);
+    // Every object's foreground shapes are found at its center
+    for obj in world.objects.values() {
+        if obj.kind == ObjectKind::Ground {
+            continue;
+        }
+        let found = world.find_object_at(obj.pr.pos);
+        assert!(found.is_some(), "Nothing found at {:?}", obj.pr.pos);
+    }
+}
+
+#[test]
+fn ordered_disorder_test() {
+    use crate::player::{Gender, Player};
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let handle = (world.objects.iter())
+        .find(|(_, obj)| obj.def.ty.is_dynamic() && obj.pr.pos.x < -3.0)
+        .map(|(handle, _)| *handle)
+        .unwrap();
+    let ordered = world.objects[&handle].ordered_pr.pos;
+    // Move the object straight up, away from where it belongs
+    let moved = ordered + vec2(0.0, 10.0);
+    (world.physics.bodies[handle]).set_translation(moved.convert(), true);
+    world.update();
+    let moved = world.objects[&handle].pr.pos;
+    let entropy = world.objects[&handle].entropy;
+    let disorder = |pos| world.sample_input_scalar_field(ScalarInputFieldKind::Disorder, pos, true);
+    // The object is found where it is, and its absence is felt where it belongs
+    assert!(
+        (disorder(moved) - 10.0 - entropy).abs() < 0.1,
+        "{}",
+        disorder(moved)
+    );
+    assert!(
+        (disorder(ordered) + 10.0).abs() < 0.1,
+        "{}",
+        disorder(ordered)
+    );
+}
+
+/// Compare serial and parallel sampling with `cargo test --release -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn magic_sampling_bench() {
+    use std::time::Instant;
+
+    use crate::player::{Gender, Player};
+    let world = World::new(Player::new("Test".into(), Gender::Enby));
+    let rect = world.max_rect();
+    let positions = (0..100)
+        .flat_map(|i| (0..100).map(move |j| (i, j)))
+        .map(|(i, j)| rect.min + rect.size() * vec2(i as f32, j as f32) / 100.0)
+        .collect_vec();
+    let sample =
+        |pos: &Pos2| world.sample_input_scalar_field(ScalarInputFieldKind::Magic, *pos, true);
+    let start = Instant::now();
+    let serial: Vec<f32> = positions.iter().map(sample).collect();
+    let serial_time = start.elapsed();
+    let start = Instant::now();
+    let parallel: Vec<f32> = positions.par_iter().map(sample).collect();
+    let parallel_time = start.elapsed();
+    assert_eq!(serial, parallel);
+    println!("serial: {serial_time:?}, parallel: {parallel_time:?}");
+}
+
+#[test]
+fn settling_is_harmless_test() {
+    use crate::player::{Gender, Player};
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let health = world.player.person.health;
+    for _ in 0..300 {
+        world.update();
+    }
+    for obj in world.objects.values() {
+        assert_eq!(obj.damage, 0.0, "{:?} was damaged", obj.pr.pos);
+    }
+    assert_eq!(world.player.person.health, health);
+}
+
+#[test]
+fn breaking_test() {
+    use crate::player::{Gender, Player};
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let count = world.objects.len();
+    let (&handle, obj) = (world.objects.iter())
+        .find(|(_, obj)| obj.def.durability.is_some())
+        .expect("No breakable objects");
+    let debris = obj.def.breaks_into.len();
+    world.objects.get_mut(&handle).unwrap().damage = f32::INFINITY;
+    world.update();
+    assert!(!world.objects.contains_key(&handle));
+    assert_eq!(world.objects.len(), count - 1 + debris);
+}
+
+#[test]
+fn reload_resources_test() {
+    use crate::player::Gender;
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    world.player.person.pos = pos2(1.0, 2.0);
+    let object_count = world.objects.len();
+    let body_count = world.physics.bodies.len();
+    world.reload_resources();
+    assert!(world.resources.errors.is_empty());
+    assert_eq!(world.objects.len(), object_count);
+    assert_eq!(world.physics.bodies.len(), body_count);
+    assert_eq!(world.player.person.pos, pos2(1.0, 2.0));
+    assert!(world.npcs.contains_key(&NpcId::Leavy));
+}
+
+#[test]
+fn collision_group_test() {
+    use crate::player::Gender;
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let yaml = "type: Dynamic\nshapes: [{shape: {box: [0.4, 0.4]}, friction: 0.9}]\n";
+    let solid: ObjectDef = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(solid.collision_group, CollisionGroup::Scenery);
+    assert_eq!(solid.shapes[0].friction, Some(0.9));
+    let ethereal = ObjectDef {
+        collision_group: CollisionGroup::Ethereal,
+        ..solid.clone()
+    };
+    let find = |world: &World, y: f32| {
+        (world.objects.iter())
+            .find(|(_, obj)| obj.pr.pos.x == 30.0 && obj.pr.pos.y == y)
+            .map(|(handle, _)| *handle)
+            .unwrap()
+    };
+    world.add_object_def(pos2(30.0, 1.0), solid);
+    let solid = find(&world, 1.0);
+    world.add_object_def(pos2(30.0, 2.0), ethereal);
+    let ethereal = find(&world, 2.0);
+    for _ in 0..120 {
+        world.update();
+    }
+    // Ethereal objects fall through the ground and everything else
+    assert!(world.objects[&solid].pr.pos.y > 0.0);
+    assert!(world.objects[&ethereal].pr.pos.y < -1.0);
+}
+
+#[test]
+fn material_test() {
+    use crate::player::Gender;
+    let world = World::new(Player::new("Test".into(), Gender::Enby));
+    let flammable = (world.objects.values())
+        .find(|obj| obj.def.props.flammable > 0.0)
+        .expect("No flammable objects");
+    let sample = |pos| world.sample_input_scalar_field(ScalarInputFieldKind::Material, pos, true);
+    assert_eq!(sample(flammable.pr.pos), flammable.def.props.material());
+    assert_eq!(sample(pos2(0.0, 4.0)), 0.0);
+}
+
+#[test]
+fn platform_test() {
+    use crate::player::Gender;
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    world.load_place("platform_test").unwrap();
+    let cube = (world.objects.iter())
+        .find(|(_, obj)| obj.def.ty.is_dynamic())
+        .map(|(handle, _)| *handle)
+        .unwrap();
+    let start = world.objects[&cube].pr.pos.y;
+    // A quarter of the elevator's period brings it to the top
+    let steps = (2.0 / world.physics.dt()) as usize;
+    for _ in 0..steps {
+        world.update();
+    }
+    let lift = world.objects[&cube].pr.pos.y - start;
+    assert!((lift - 2.0).abs() < 0.2, "The cube was lifted {lift}");
+    // Platforms start from where they were placed when the place is entered later,
+    // rather than jumping to where their motion would have taken them
+    world.time = 2.0;
+    world.load_place("platform_test").unwrap();
+    world.update();
+    for obj in world.objects.values().filter(|obj| obj.motion.is_some()) {
+        let speed = world.physics.bodies[obj.body_handle].linvel().norm();
+        assert!(speed < 2.0, "A platform moved at {speed}");
+    }
+}
+
+#[test]
+fn interpolation_test() {
+    use crate::player::Gender;
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    world.load_place("platform_test").unwrap();
+    let cube = (world.objects.iter())
+        .find(|(_, obj)| obj.def.ty.is_dynamic())
+        .map(|(handle, _)| *handle)
+        .unwrap();
+    for _ in 0..30 {
+        world.update();
+    }
+    // A point just inside where the cube is now, but outside where it was
+    let obj = &world.objects[&cube];
+    let motion = obj.pr.pos - obj.prev_pr.pos;
+    assert!(motion.length() > 0.01, "The cube only moved {motion:?}");
+    let point = obj.pr.pos + motion.normalized() * 0.19;
+    let density =
+        |world: &World| world.sample_input_scalar_field(ScalarInputFieldKind::Density, point, true);
+    assert!(density(&world) > 0.0);
+    world.render_alpha = Some(0.0);
+    assert_eq!(density(&world), 0.0);
+    world.render_alpha = Some(1.0);
+    assert!(density(&world) > 0.0);
+    // Updates sample the current transforms
+    world.render_alpha = Some(0.0);
+    world.update();
+    assert_eq!(world.render_alpha, None);
+}
+
+#[test]
+fn interpolated_say_test() {
+    use crate::player::Gender;
+    use Word::*;
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    world.load_place("platform_test").unwrap();
+    let cube = (world.objects.iter())
+        .find(|(_, obj)| obj.def.ty.is_dynamic())
+        .map(|(handle, _)| *handle)
+        .unwrap();
+    // Move the cube far enough that it is drawn halfway somewhere else
+    let body = &mut world.physics.bodies[cube];
+    body.set_translation(vector![3.0, 5.0], true);
+    body.set_linvel(Vector::zeros(), true);
+    world.update();
+    // Sample density where the cube is now
+    world.render_alpha = Some(0.5);
+    for word in [Ro, Tu, Ti, Ma, Ta, Fa] {
+        world.say(PersonId::Player, word).unwrap();
+    }
+    assert_eq!(world.render_alpha, Some(0.5));
+    let sampled = world.player.person.stack.iter().last().unwrap();
+    let Field::Scalar(field) = &sampled.field else {
+        panic!("Sampled a vector");
+    };
+    assert!(field.uniform().unwrap() > 0.0);
+}
+
+#[test]
+fn tick_rate_test() {
+    use crate::player::Gender;
+    // Heat spreads as far in a second at any tick rate
+    let heat_after_a_second = |dt: f32| {
+        let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+        world.load_place("platform_test").unwrap();
+        world.physics.set_dt(dt);
+        world.heat_grid[36][24] += 100.0;
+        for _ in 0..(1.0 / dt).round() as usize {
+            world.update();
+        }
+        world.heat_grid[36][25]
+    };
+    let slow = heat_after_a_second(1.0 / 30.0);
+    let fast = heat_after_a_second(1.0 / 120.0);
+    assert!(
+        (slow - fast).abs() < 0.01,
+        "{slow} at 30 Hz, {fast} at 120 Hz"
+    );
+}
+
+#[test]
+fn sensor_test() {
+    use crate::player::Gender;
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    world.load_place("platform_test").unwrap();
+    let sensor_events = |world: &mut World| {
+        (world.events.drain(..))
+            .filter(|event| {
+                matches!(
+                    event,
+                    WorldEvent::SensorEntered { .. } | WorldEvent::SensorExited { .. }
+                )
+            })
+            .count()
+    };
+    world.update();
+    assert_eq!(sensor_events(&mut world), 0);
+    // Entering and leaving are each reported once
+    world.player.person.pos = pos2(-4.0, 1.0);
+    world.update();
+    world.update();
+    assert!(matches!(
+        world.events.first(),
+        Some(WorldEvent::SensorEntered { sensor, person_id: PersonId::Player }) if sensor == "left_side"
+    ));
+    assert_eq!(sensor_events(&mut world), 1);
+    world.player.person.pos = pos2(0.0, 1.0);
+    world.update();
+    world.update();
+    assert!(matches!(
+        world.events.first(),
+        Some(WorldEvent::SensorExited { .. })
+    ));
+    assert_eq!(sensor_events(&mut world), 1);
+}
+
+#[test]
+fn water_test() {
+    use crate::player::Gender;
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    world.load_place("pond_test").unwrap();
+    let find = |world: &World, x: f32| {
+        (world.objects.iter())
+            .find(|(_, obj)| obj.pr.pos.x == x)
+            .map(|(handle, _)| *handle)
+            .unwrap()
+    };
+    let cube = find(&world, -2.0);
+    let vase = find(&world, 3.0);
+    let steps = (10.0 / world.physics.dt()) as usize;
+    for _ in 0..steps {
+        world.update();
+    }
+    // Light objects settle at the surface and heavy ones sink
+    let cube = &world.objects[&cube];
+    assert!(
+        (cube.pr.pos.y - 1.88).abs() < 0.05,
+        "The cube is at {}",
+        cube.pr.pos.y
+    );
+    assert!(
+        cube.vel.length() < 0.05,
+        "The cube is moving at {}",
+        cube.vel.length()
+    );
+    assert!(world.objects[&vase].pr.pos.y < 0.5);
+    // Water has density and deep water keeps people from drawing mana
+    let density =
+        world.sample_input_scalar_field(ScalarInputFieldKind::Density, pos2(0.0, 1.0), true);
+    assert_eq!(density, 1.0);
+    world.player.person.pos = pos2(0.0, 1.0);
+    assert!(world.available_mana(PersonId::Player) > 0.0);
+    world.player.person.pos = pos2(0.0, 0.0);
+    assert_eq!(world.available_mana(PersonId::Player), 0.0);
+}
+
+#[test]
+fn undo_word_test() {
+    use crate::player::{Gender, Player};
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    assert!(!world.can_undo_word());
+    world.say(PersonId::Player, Word::Se).unwrap();
+    world.say(PersonId::Player, Word::Mo).unwrap();
+    let reserved = world.player.person.reserved_mana();
+    assert!(world.undo_word());
+    assert!(world.player.person.stack.words().eq([Word::Se]));
+    assert!(world.player.person.reserved_mana() < reserved);
+    // Changing the stack another way restarts the history from it
+    world.player.person.stack.clear();
+    assert!(!world.can_undo_word());
+    world.say(PersonId::Player, Word::Se).unwrap();
+    assert_eq!(world.player.word_history, [Word::Se]);
+    assert!(world.undo_word());
+    assert_eq!(world.player.person.stack.words().count(), 0);
+}
+
+#[test]
+fn non_finite_test() {
+    use crate::{
+        person::ActiveSpell,
+        player::{Gender, Player},
+    };
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let pos = pos2(1.0, 1.0);
+    assert_eq!(
+        ScalarField::Uniform(f32::INFINITY).sample(&world, pos, true),
+        MAX_FIELD_VALUE
+    );
+    assert_eq!(
+        ScalarField::Uniform(f32::NAN).sample(&world, pos, true),
+        0.0
+    );
+    // An object that goes to NaN is put back and the spells pushing it are dispelled
+    world.player.progression.known_words.extend(all::<Word>());
+    world.say(PersonId::Player, Word::Ki).unwrap();
+    world.say(PersonId::Player, Word::Vu).unwrap();
+    let gravity = OutputFieldKind::from(VectorOutputFieldKind::Gravity);
+    assert_eq!(
+        world.player.person.active_spells.spell_words(gravity).len(),
+        1
+    );
+    // Spells that don't push objects are left alone
+    (world.player.person.active_spells.scalars)
+        .entry(ScalarOutputFieldKind::Heat)
+        .or_default()
+        .push(ActiveSpell::new(ScalarField::Uniform(1.0), Vec::new(), 0.0));
+    let heat = OutputFieldKind::from(ScalarOutputFieldKind::Heat);
+    let (&handle, _) = (world.objects.iter())
+        .find(|(_, obj)| obj.def.ty.is_dynamic())
+        .unwrap();
+    world.update();
+    world.events.clear();
+    let pr = world.objects[&handle].pr;
+    (world.physics.bodies[handle]).set_linvel(vector![f32::NAN, 0.0], true);
+    world.update();
+    assert_eq!(world.objects[&handle].pr, pr);
+    assert_eq!(
+        world.player.person.active_spells.spell_words(gravity).len(),
+        0
+    );
+    assert_eq!(world.player.person.active_spells.spell_words(heat).len(), 1);
+    assert!(world.events.iter().any(|event| matches!(
+        event,
+        WorldEvent::SpellsDestabilized {
+            caster: PersonId::Player
+        }
+    )));
+    world.update();
+    assert!(world.objects[&handle].pr.pos.is_finite());
+}
+
+#[test]
+fn force_torque_test() {
+    use crate::{
+        function::{HeteroBinOp, TypedBinOp},
+        player::{Gender, Player},
+    };
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let (&handle, _) = (world.objects.iter())
+        .find(|(_, obj)| obj.def.ty.is_dynamic())
+        .unwrap();
+    // Lift the object into the air so that nothing else turns it
+    world.lift_test_object(handle, 0.0);
+    world.update();
+    // A field pushing left harder the higher up it is turns the object counterclockwise
+    let shear = VectorField::BinSV(
+        TypedBinOp::Hetero(HeteroBinOp::Mul),
+        ScalarField::Y,
+        VectorField::Uniform(vec2(-10.0, 0.0)).into(),
+    );
+    world.push_test_force(shear, None);
+    assert!(world.physics.bodies[handle].angvel().abs() < 1e-4);
+    world.update();
+    assert!(world.physics.bodies[handle].angvel() > 0.0);
+}
+
+#[test]
+fn field_push_test() {
+    use crate::player::{Gender, Player};
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let mass = |world: &World, handle: RigidBodyHandle| world.physics.bodies[handle].mass();
+    let dynamic = (world.objects.iter())
+        .filter(|(_, obj)| obj.def.ty.is_dynamic())
+        .map(|(handle, _)| *handle)
+        .sorted_by(|&a, &b| mass(&world, a).total_cmp(&mass(&world, b)))
+        .collect_vec();
+    let (light, heavy) = (dynamic[0], *dynamic.last().unwrap());
+    assert!(mass(&world, heavy) > mass(&world, light) * 1.5);
+    // Lift the objects into the air, apart from each other
+    let lifted = [(-5.0, light), (5.0, heavy)];
+    for (x, handle) in lifted {
+        world.lift_test_object(handle, x);
+    }
+    world.update();
+    world.push_test_force(VectorField::Uniform(vec2(10.0, 0.0)), None);
+    // Measure a single step of the spell from standing still
+    for (x, handle) in lifted {
+        world.lift_test_object(handle, x);
+    }
+    world.update();
+    let vel = |handle: RigidBodyHandle| *world.physics.bodies[handle].linvel();
+    // Gravity accelerates everything the same, while force moves lighter objects more
+    let close = |a: f32, b: f32| (a - b).abs() < 0.01 * a.abs().max(b.abs());
+    assert!(close(vel(light).y, vel(heavy).y));
+    let momentum = |handle: RigidBodyHandle| vel(handle).x * mass(&world, handle);
+    assert!(close(momentum(light), momentum(heavy)));
+    assert!(vel(light).x > vel(heavy).x);
+}
+
+#[test]
+fn exclude_self_test() {
+    use crate::player::{Gender, Player};
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    world.player.progression.known_words.extend(all::<Word>());
+    let chest = world.player.person.pos + vec2(0.0, PERSON_HEIGHT / 2.0);
+    let away = chest + vec2(5.0, 0.0);
+    let force = |world: &World, pos| {
+        world.sample_output_vector_field(VectorOutputFieldKind::Force, pos, true)
+    };
+    for word in [Word::Bu, Word::Ki, Word::Wu] {
+        world.say(PersonId::Player, word).unwrap();
+    }
+    let kind = OutputFieldKind::from(VectorOutputFieldKind::Force);
+    assert!(!world.player.person.active_spells.affects_caster(kind, 0));
+    assert_eq!(force(&world, chest), Vec2::ZERO);
+    assert_eq!(force(&world, away), Vec2::Y);
+    // Without the modifier the spell affects the caster too
+    for word in [Word::Ki, Word::Wu] {
+        world.say(PersonId::Player, word).unwrap();
+    }
+    assert!(world.player.person.active_spells.affects_caster(kind, 1));
+    assert_eq!(force(&world, chest), Vec2::Y);
+}
+
+#[test]
+fn spell_duration_test() {
+    use crate::{
+        conduit::ConduitStone,
+        player::{Gender, Player},
+    };
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    world.player.progression.known_words.extend(all::<Word>());
+    let force = OutputFieldKind::from(VectorOutputFieldKind::Force);
+    // Conduit stones replay the duration along with the rest of the spell
+    let mut stone = ConduitStone::default();
+    stone.etch([Word::Tu, Word::Do, Word::Ki, Word::Wu]);
+    assert!(world.cast_words(PersonId::Player, &stone.words));
+    let active_spells = &world.player.person.active_spells;
+    let words = active_spells.spell_words(force).next().unwrap().to_vec();
+    assert_eq!(words, [Word::Ki, Word::Tu, Word::Do, Word::Wu]);
+    assert_eq!(active_spells.lifetime(force, 0), Some(2.0));
+    // So do the spell's own words
+    assert!(world.cast_words(PersonId::Player, &words));
+    assert_eq!(
+        world.player.person.active_spells.lifetime(force, 1),
+        Some(2.0)
+    );
+    assert!(world.player.person.reserved_mana() > 0.0);
+    // Both spells fade after two seconds
+    let spell_count = |world: &World| world.player.person.active_spells.spell_words(force).len();
+    let mut updates = 0usize;
+    while spell_count(&world) == 2 {
+        world.update();
+        updates += 1;
+    }
+    assert_eq!(spell_count(&world), 0);
+    let expected = (2.0 / world.physics.dt()).round() as usize;
+    assert!(updates.abs_diff(expected) <= 1);
+    assert_eq!(world.player.person.reserved_mana(), 0.0);
+    assert!(world.events.iter().any(|event| matches!(
+        event,
+        WorldEvent::SpellsExpired {
+            caster: PersonId::Player
+        }
+    )));
+}
+
+#[test]
+fn spell_work_test() {
+    use crate::{
+        person::WORK_MANA_MULTIPLIER,
+        player::{Gender, Player},
+    };
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let handle = (world.objects.iter())
+        .find(|(_, obj)| obj.def.ty.is_dynamic())
+        .map(|(handle, _)| *handle)
+        .unwrap();
+    world.lift_test_object(handle, 0.0);
+    // One spell pushes the object sideways while it is in the air,
+    // and another is confined far away from everything
+    for bounds in [
+        Some(SpellBounds {
+            center: pos2(0.0, 20.0),
+            radius: 10.0,
+        }),
+        Some(SpellBounds {
+            center: pos2(1e4, 1e4),
+            radius: 1.0,
+        }),
+    ] {
+        world.push_test_force(VectorField::Uniform(vec2(10.0, 0.0)), bounds);
+    }
+    // Reserving all of the player's mana keeps regeneration from hiding the cost
+    let max_mana = world.player.person.max_mana;
+    world.player.person.active_spells.vectors[&VectorOutputFieldKind::Force][0].reserved = max_mana;
+    for _ in 0..30 {
+        world.update();
+    }
+    let force = OutputFieldKind::from(VectorOutputFieldKind::Force);
+    let work = world.player.person.active_spells.work(force, 0);
+    assert_eq!(world.player.person.active_spells.work(force, 1), 0.0);
+    // The pushing spell's work went into the object's sideways motion
+    let body = &world.physics.bodies[handle];
+    let energy = 0.5 * body.mass() * body.linvel().x.powi(2);
+    assert!((work - energy).abs() < energy * 0.2);
+    // The caster paid for it
+    let person = &world.player.person;
+    let spent = person.max_mana - person.mana;
+    assert!((spent - work * WORK_MANA_MULTIPLIER).abs() < 1e-3);
+    // Running out of mana ends the caster's spells
+    world.player.person.mana = 1e-6;
+    world.events.clear();
+    world.update();
+    assert!(world.player.person.active_spells.is_empty());
+    assert!(world.events.iter().any(|event| matches!(
+        event,
+        WorldEvent::ManaDepleted {
+            caster: PersonId::Player
+        }
+    )));
+    // Mana comes back once nothing is sustained
+    world.update();
+    assert!(world.player.person.mana > 1e-6);
+}
+
+#[test]
+fn player_death_test() {
+    use crate::player::{Gender, Player};
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    world.player.progression.known_words.extend(all::<Word>());
+    let spawn = world.player.person.pos;
+    // Heat hurts
+    for col in &mut world.heat_grid {
+        col.fill(200.0);
+    }
+    world.update();
+    let health = world.player.person.health;
+    assert!(health < world.player.person.max_health);
+    assert!(!world.player.person.is_dead());
+    // Falling out of the place is deadly
+    for word in [Word::Ki, Word::Wu, Word::Ti] {
+        world.say(PersonId::Player, word).unwrap();
+    }
+    world.player.person.pos = spawn + vec2(1.0, -100.0);
+    world.update();
+    assert!(world.events.contains(&WorldEvent::PlayerDied));
+    let person = &world.player.person;
+    assert_eq!(person.pos, spawn);
+    assert_eq!(person.health, person.max_health);
+    assert!(person.mana < person.max_mana);
+    assert!(person.stack.is_empty());
+    let force = OutputFieldKind::from(VectorOutputFieldKind::Force);
+    assert_eq!(person.active_spells.spell_words(force).len(), 0);
+}
+
+#[test]
+fn mana_regen_test() {
+    use crate::{
+        person::ActiveSpell,
+        player::{Gender, Player},
+    };
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    let heat = |amount| ActiveSpell::new(ScalarField::Uniform(amount), Vec::new(), 0.0);
+    let leavy = &mut world.npcs.get_mut(&NpcId::Leavy).unwrap().person;
+    leavy.active_spells = ActiveSpells::default();
+    world.player.person.mana = 0.0;
+    let base = world.mana_regen_rate(PersonId::Player);
+    assert!(base > 0.0);
+    world.update();
+    assert!(world.player.person.mana > 0.0);
+    // Standing in another caster's spell regenerates faster
+    let leavy = &mut world.npcs.get_mut(&NpcId::Leavy).unwrap().person;
+    (leavy.active_spells.scalars)
+        .entry(ScalarOutputFieldKind::Heat)
+        .or_default()
+        .push(heat(5.0));
+    let boosted = world.mana_regen_rate(PersonId::Player);
+    assert!(boosted > base);
+    // Sustaining a spell only lets a trickle through, and the player's own spells don't boost it
+    (world.player.person.active_spells.scalars)
+        .entry(ScalarOutputFieldKind::Heat)
+        .or_default()
+        .push(heat(5.0));
+    let trickle = world.mana_regen_rate(PersonId::Player);
+    assert!(trickle > 0.0 && trickle < boosted);
+    world.player.person.active_spells.scalars[&ScalarOutputFieldKind::Heat][0].reserved =
+        world.player.person.max_mana;
+    assert_eq!(world.mana_regen_rate(PersonId::Player), 0.0);
+    // Nothing regenerates too deep under water
+    world.player.person.active_spells = ActiveSpells::default();
+    world.load_place("pond_test").unwrap();
+    world.player.person.pos = pos2(0.0, 1.9);
+    assert!(world.mana_regen_rate(PersonId::Player) > 0.0);
+    world.player.person.pos = pos2(0.0, 0.2);
+    assert_eq!(world.mana_regen_rate(PersonId::Player), 0.0);
+    let mana = world.player.person.mana;
+    world.regen_mana();
+    assert_eq!(world.player.person.mana, mana);
+}
+
+#[test]
+fn order_cost_test() {
+    use crate::{
+        person::{ActiveSpell, WORK_MANA_MULTIPLIER},
+        player::{Gender, Player},
+    };
+    let mut world = World::new(Player::new("Test".into(), Gender::Enby));
+    for obj in world.objects.values_mut() {
+        obj.entropy = 1.0;
+    }
+    (world.player.person.active_spells.scalars)
+        .entry(ScalarOutputFieldKind::Order)
+        .or_default()
+        .push(ActiveSpell::new(
+            ScalarField::Uniform(1.0),
+            Vec::new(),
+            world.player.person.max_mana,
+        ));
+    let entropy = |world: &World| world.objects.values().map(|obj| obj.entropy).sum::<f32>();
+    let before = entropy(&world);
+    world.update();
+    // The caster paid for the entropy their spell removed
+    let removed = before - entropy(&world);
+    assert!(removed > 0.0);
+    let order = OutputFieldKind::from(ScalarOutputFieldKind::Order);
+    let work = world.player.person.active_spells.work(order, 0);
+    assert!(work > 0.0);
+    let person = &world.player.person;
+    let spent = person.max_mana - person.mana;
+    assert!((spent - work * WORK_MANA_MULTIPLIER).abs() < 1e-3);
+}
+
+#[cfg(test)]
+mod test_helpers {
+    use super::*;
+    use crate::person::ActiveSpell;
+
+    impl World {
+        /// Lift a dynamic object into the air, still and upright
+        pub fn lift_test_object(&mut self, handle: RigidBodyHandle, x: f32) {
+            let body = &mut self.physics.bodies[handle];
+            body.set_translation(vector![x, 20.0], true);
+            body.set_rotation(Rotation::new(0.0), true);
+            body.set_linvel(Vector::zeros(), true);
+            body.set_angvel(0.0, true);
+        }
+        /// Give the player a force spell
+        pub fn push_test_force(&mut self, field: VectorField, bounds: Option<SpellBounds>) {
+            (self.player.person.active_spells.vectors)
+                .entry(VectorOutputFieldKind::Force)
+                .or_default()
+                .push(ActiveSpell {
+                    bounds,
+                    ..ActiveSpell::new(field, Vec::new(), 0.0)
+                });
+        }
     }
 }
@@ -0,0 +1,200 @@
+use std::f32::consts::{PI, TAU};
+
+use eframe::egui::*;
+
+use crate::{
+    image::image_texture,
+    math::rotate,
+    object::{GraphicalShape, Object, ObjectKind, OffsetShape},
+    person::Person,
+    world::{ShapeLayer, World, PERSON_HEIGHT, PERSON_WIDTH},
+};
+
+/// How many segments make up a full circle
+const CIRCLE_SEGMENTS: usize = 32;
+const GROUND_COLOR: [u8; 3] = [110, 90, 70];
+const OBJECT_COLOR: [u8; 3] = [150, 150, 150];
+pub const PLAYER_COLOR: Color32 = Color32::from_rgb(90, 170, 255);
+pub const NPC_COLOR: Color32 = Color32::from_rgb(255, 170, 60);
+
+/// Draws the objects and people of the world as shapes
+pub struct WorldView<'a, F> {
+    painter: &'a Painter,
+    world: &'a World,
+    /// Maps a world position to a screen position
+    to_screen: F,
+    /// How far a half-space has to extend to cover the view
+    extent: f32,
+    global_alpha: f32,
+}
+
+impl<'a, F> WorldView<'a, F>
+where
+    F: Fn(Pos2) -> Pos2,
+{
+    pub fn new(
+        painter: &'a Painter,
+        world: &'a World,
+        view_center: Pos2,
+        view_range: f32,
+        to_screen: F,
+        global_alpha: f32,
+    ) -> Self {
+        // Half-spaces are centered on their object, which may be far from the view
+        let farthest = (world.objects.values())
+            .map(|obj| obj.pr.pos.distance(view_center))
+            .fold(0.0, f32::max);
+        WorldView {
+            painter,
+            world,
+            to_screen,
+            extent: view_range * 2.0 + farthest,
+            global_alpha,
+        }
+    }
+    pub fn draw(&self) {
+        puffin::profile_function!();
+        // Far shapes are drawn first and faintest, then background, then foreground
+        // The ground goes behind everything else in the same layer
+        let mut objects: Vec<&Object> = self.world.objects.values().collect();
+        objects.sort_by_key(|obj| obj.kind != ObjectKind::Ground);
+        for (layer, opacity) in [
+            (ShapeLayer::Far, 0.3),
+            (ShapeLayer::Background, 0.6),
+            (ShapeLayer::Foreground, 1.0),
+        ] {
+            for obj in &objects {
+                let shapes = match layer {
+                    ShapeLayer::Foreground => &obj.def.shapes,
+                    ShapeLayer::Background => &obj.def.background,
+                    ShapeLayer::Far => &obj.def.far,
+                };
+                for shape in shapes {
+                    self.draw_object_shape(obj, shape, opacity);
+                }
+            }
+        }
+        for npc in self.world.npcs.values() {
+            self.draw_person(&npc.person, NPC_COLOR);
+        }
+        self.draw_person(&self.world.player.person, PLAYER_COLOR);
+    }
+    fn draw_object_shape(&self, obj: &Object, shape: &OffsetShape, opacity: f32) {
+        let pr = (self.world.render_alpha).map_or(obj.pr, |alpha| obj.render_pr(alpha));
+        let local = outline(&shape.shape, self.extent);
+        let screen: Vec<Pos2> = (local.iter())
+            .map(|&p| (self.to_screen)(pr.pos + rotate(shape.offset + p, pr.rot)))
+            .collect();
+        let [r, g, b] = obj.def.color.unwrap_or(match obj.kind {
+            ObjectKind::Ground => GROUND_COLOR,
+            ObjectKind::Object => OBJECT_COLOR,
+        });
+        let alpha = opacity * self.global_alpha;
+        let fill = Color32::from_rgb(r, g, b).gamma_multiply(alpha);
+        let stroke = Stroke::new(1.0, Color32::from_black_alpha((alpha * 160.0) as u8));
+        // Half-spaces are too large to stretch an image over
+        let texture = (obj.def.texture.as_deref())
+            .filter(|_| !matches!(shape.shape, GraphicalShape::HalfSpace(_)))
+            .and_then(|name| image_texture(self.painter.ctx(), name));
+        if let Some(texture_id) = texture {
+            self.painter
+                .add(textured_polygon(texture_id, &local, &screen, alpha));
+            self.painter.add(Shape::closed_line(screen, stroke));
+        } else {
+            self.painter
+                .add(Shape::convex_polygon(screen, fill, stroke));
+        }
+    }
+    /// People are drawn as capsules standing at their position, outlined more strongly than objects
+    fn draw_person(&self, person: &Person, color: Color32) {
+        let shape = GraphicalShape::capsule_wh(PERSON_WIDTH, PERSON_HEIGHT);
+        let center = person.pos + vec2(0.0, PERSON_HEIGHT / 2.0);
+        let screen = (outline(&shape, self.extent).into_iter())
+            .map(|p| (self.to_screen)(center + p))
+            .collect();
+        let fill = color.gamma_multiply(0.5 * self.global_alpha);
+        let stroke = Stroke::new(2.0, color.gamma_multiply(self.global_alpha));
+        self.painter
+            .add(Shape::convex_polygon(screen, fill, stroke));
+    }
+}
+
+/// The points around the edge of a shape, relative to its center
+fn outline(shape: &GraphicalShape, extent: f32) -> Vec<Vec2> {
+    match shape {
+        GraphicalShape::Circle(radius) => {
+            let mut points = arc(Vec2::ZERO, *radius, 0.0, TAU, CIRCLE_SEGMENTS);
+            points.pop();
+            points
+        }
+        GraphicalShape::Box(size) => {
+            let half = *size / 2.0;
+            vec![
+                vec2(-half.x, -half.y),
+                vec2(half.x, -half.y),
+                half,
+                vec2(-half.x, half.y),
+            ]
+        }
+        GraphicalShape::HalfSpace(normal) => {
+            // The solid side is opposite the normal, cut off past the edge of the view
+            let normal = normal.normalized();
+            let along = normal.rot90() * extent;
+            let inside = -normal * extent;
+            vec![-along, -along + inside, along + inside, along]
+        }
+        GraphicalShape::Capsule {
+            half_height,
+            radius,
+        } => {
+            let segments = CIRCLE_SEGMENTS / 2;
+            let mut points = arc(vec2(0.0, *half_height), *radius, 0.0, PI, segments);
+            points.extend(arc(vec2(0.0, -*half_height), *radius, PI, TAU, segments));
+            points
+        }
+    }
+}
+
+/// Points along an arc, including both ends
+fn arc(center: Vec2, radius: f32, start: f32, end: f32, segments: usize) -> Vec<Vec2> {
+    (0..=segments)
+        .map(|i| {
+            let angle = start + (end - start) * i as f32 / segments as f32;
+            center + vec2(angle.cos(), angle.sin()) * radius
+        })
+        .collect()
+}
+
+/// A convex polygon with an image stretched over its bounds
+fn textured_polygon(texture_id: TextureId, local: &[Vec2], screen: &[Pos2], alpha: f32) -> Mesh {
+    let bounds = Rect::from_points(&local.iter().map(|p| p.to_pos2()).collect::<Vec<_>>());
+    let color = Color32::WHITE.gamma_multiply(alpha);
+    let mut mesh = Mesh::with_texture(texture_id);
+    for (p, &pos) in local.iter().zip(screen) {
+        // Images are stored top to bottom, but the world's y points up
+        let uv = pos2(
+            (p.x - bounds.min.x) / bounds.width(),
+            (bounds.max.y - p.y) / bounds.height(),
+        );
+        mesh.vertices.push(epaint::Vertex { pos, uv, color });
+    }
+    for i in 1..local.len().saturating_sub(1) as u32 {
+        mesh.add_triangle(0, i, i + 1);
+    }
+    mesh
+}
+
+#[test]
+fn outline_test() {
+    // Capsules are closed by the straight sides between their caps
+    let capsule = outline(&GraphicalShape::capsule_wh(1.0, 3.0), 10.0);
+    assert!(capsule.iter().all(|p| p.x.abs() <= 0.5 + 1e-5));
+    assert!(capsule.iter().any(|p| (p.y - 1.5).abs() < 1e-5));
+    assert!(capsule.iter().any(|p| (p.y + 1.5).abs() < 1e-5));
+    // Half-spaces cover the side opposite their normal
+    let half_space = GraphicalShape::HalfSpace(Vec2::Y);
+    let points = outline(&half_space, 10.0);
+    assert!(points.iter().all(|p| p.y <= 0.0));
+    let center = points.iter().fold(Vec2::ZERO, |sum, p| sum + *p) / points.len() as f32;
+    assert!(half_space.contains(center.to_pos2()));
+}